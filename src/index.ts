@@ -71,6 +71,17 @@ const loadProject = (path: string): boolean => {
     }
 }
 
+// the same as loading a file, but with the project.json already in hand
+const loadProjectJson = (json: string): boolean => {
+    try {
+        resolve(vm.loadProject(json));
+        return true;
+    // eslint-disable-next-line @typescript-eslint/no-unused-vars
+    } catch (error: any) {
+        return false;
+    }
+}
+
 const saveProject = (path: string): boolean => {
     try {
         resolve(vm.saveProjectSb3().then(
@@ -160,20 +171,18 @@ const detachBlock = (id: string) => {
 }
 
 // the changeBlock routines were also split apart
-// TODO: value is an object!
-// { id?: string, name: string, value: string, variableType?: string }
-const changeField = (id: string, name: string, value: string) => {
-    // VARIABLE, LIST, or BROADCAST_OPTION, or variably named dropdown inputs
+const changeField = (id: string, name: string, value: string, dataId: string | null) => {
+    // VARIABLE, LIST, and BROADCAST_OPTION are looked up by id, and the VM fills in the name
     (vm.runtime.getEditingTarget()?.blocks as any).changeBlock({
         id,
         element: 'field',
         name,
-        value
+        value: dataId ?? value
     });
 }
 
-// todo: is this properly implemented?
-const changeMutation = (id: string, value: any) => {
+// the VM parses the mutation out of an XML string, e.g. `<mutation proccode="..."></mutation>`
+const changeMutation = (id: string, value: string) => {
     (vm.runtime.getEditingTarget()?.blocks as any).changeBlock({
         id,
         element: 'mutation',
@@ -181,12 +190,42 @@ const changeMutation = (id: string, value: any) => {
     });
 }
 
+// clones share their original's blocks, so only list the originals.
+// the VM keeps the stage first.
+const originalTargets = (): any[] =>
+    vm.runtime.targets.filter((target: any) => target.isOriginal)
+
+const getTargets = (): { name: string, isStage: boolean, costumes: string[], sounds: string[], variables: string[] }[] =>
+    originalTargets().map(target => ({
+        name: target.getName(),
+        isStage: target.isStage,
+        costumes: target.getCostumes().map((costume: any) => costume.name),
+        sounds: target.getSounds().map((sound: any) => sound.name),
+        variables: Object.values(target.variables)
+            .filter((variable: any) => variable.type == "")
+            .map((variable: any) => variable.name),
+    }))
+
+const getEditingTarget = (): number =>
+    originalTargets().indexOf(vm.runtime.getEditingTarget())
+
+const setEditingTarget = (index: number) => {
+    const target = originalTargets()[index];
+    if (target) {
+        vm.setEditingTarget(target.id);
+    }
+}
+
 const getAllBlocks = (): Record<string, object> => 
     vm.runtime.getEditingTarget()?.blocks._blocks ?? {}
 
 const getVariablesOfType = (type: "" | "list" | "broadcast_msg"): Record<string, string> => {
     const output: Record<string, string> = {}
-    const vars = vm.runtime.getEditingTarget()?.variables ?? {};
+    // globals live on the stage, and are shadowed by locals of the same id
+    const vars = {
+        ...(vm.runtime.getTargetForStage()?.variables ?? {}),
+        ...(vm.runtime.getEditingTarget()?.variables ?? {}),
+    };
     Object.keys(vars).forEach(key => {
         if (vars[key].type == type) {
             output[key] = vars[key].name;
@@ -195,8 +234,53 @@ const getVariablesOfType = (type: "" | "list" | "broadcast_msg"): Record<string,
     return output
 }
 
+// the same project.json that ends up in the .sb3
+const getProjectJson = (): string => vm.toJSON()
+
+// locals belong to the editing target, everything else to the stage
+const createVariable = (type: "" | "list" | "broadcast_msg", id: string, name: string, isLocal: boolean, isCloud: boolean) => {
+    const target = isLocal ? vm.runtime.getEditingTarget() : vm.runtime.getTargetForStage();
+    (target as any)?.createVariable(id, name, type, isCloud);
+}
+
+// the VM only renames the owner's own fields, so every target's blocks are gone through here
+const renameVariable = (id: string, name: string) => {
+    for (const target of originalTargets()) {
+        if (target.variables[id]) {
+            target.renameVariable(id, name);
+        }
+        for (const block of Object.values(target.blocks._blocks) as any[]) {
+            for (const field of Object.values(block.fields) as any[]) {
+                if (field.id === id) {
+                    field.value = name;
+                }
+            }
+        }
+    }
+}
+
+// blocks still referring to it are left alone
+const deleteVariable = (id: string) => {
+    for (const target of originalTargets()) {
+        if (target.variables[id]) {
+            target.deleteVariable(id);
+        }
+    }
+}
+
+// the VM loads extensions a project's blocks use by itself, so this includes those
+const getExtensions = (): string[] =>
+    Array.from(vm.extensionManager._loadedExtensions.keys())
+
+const enableExtension = (id: string) => {
+    if (!vm.extensionManager.isExtensionLoaded(id)) {
+        vm.extensionManager.loadExtensionIdSync(id);
+    }
+}
+
 const API = {
     loadProject,
+    loadProjectJson,
     saveProject,
     createBlock,
     deleteBlock,
@@ -205,8 +289,17 @@ const API = {
     detachBlock,
     changeField,
     changeMutation,
+    getTargets,
+    getEditingTarget,
+    setEditingTarget,
     getAllBlocks,
     getVariablesOfType,
+    getProjectJson,
+    createVariable,
+    renameVariable,
+    deleteVariable,
+    getExtensions,
+    enableExtension,
 }
 
 const main = async () => {
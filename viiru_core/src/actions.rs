@@ -0,0 +1,1112 @@
+//! Everything the editor can do from its main view, by name. Keys are bound to these through
+//! the config file, and the help pane lists them, so anything else driving the editor should
+//! go through [`perform`] as well.
+
+use crossterm::event::KeyCode;
+
+use crate::{
+    backend::VariableType,
+    config::Config,
+    extensions::EXTENSIONS,
+    jump_to_reference, paste_text,
+    result::{ViiruError, ViiruResult},
+    runtime::{DataPrompt, ReferenceList, Runtime, State, ToolboxSearch},
+    scratchblocks,
+    spec::Shape,
+    ui::{copy_to_system_clipboard, Screen},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Quit,
+    ForceQuit,
+    Open,
+    Write,
+    Undo,
+    Redo,
+    CustomBlock,
+    Copy,
+    Paste,
+    Breakpoint,
+    Lint,
+    Data,
+    Extensions,
+    FindReferences,
+    GoToDefinition,
+    Run,
+    Step,
+    StepOver,
+    Continue,
+    Stop,
+    Restart,
+    Help,
+    CommandLine,
+    MoveLeft,
+    MoveDown,
+    MoveUp,
+    MoveRight,
+    ScrollLeft,
+    ScrollDown,
+    ScrollUp,
+    ScrollRight,
+    ToggleToolbox,
+    SearchToolbox,
+    ToggleTargets,
+    Stamp,
+    Delete,
+    PickUp,
+    CreateVariable,
+    CreateLocalVariable,
+    CreateCloudVariable,
+    CreateList,
+    CreateLocalList,
+    CreateBroadcast,
+    RenameData,
+}
+
+pub struct ActionInfo {
+    pub action: Action,
+    /// what the config file calls it
+    pub name: &'static str,
+    /// shown in the help pane
+    pub description: &'static str,
+}
+
+/// every action, in the order the help pane lists them
+pub const ACTIONS: &[ActionInfo] = &[
+    ActionInfo {
+        action: Action::Quit,
+        name: "quit",
+        description: "quit, warning about unsaved changes",
+    },
+    ActionInfo {
+        action: Action::ForceQuit,
+        name: "force-quit",
+        description: "quit without saving",
+    },
+    ActionInfo {
+        action: Action::Open,
+        name: "open",
+        description: "open a project file",
+    },
+    ActionInfo {
+        action: Action::Write,
+        name: "write",
+        description: "write the project to a file",
+    },
+    ActionInfo {
+        action: Action::Undo,
+        name: "undo",
+        description: "undo the last change",
+    },
+    ActionInfo {
+        action: Action::Redo,
+        name: "redo",
+        description: "redo the last undone change",
+    },
+    ActionInfo {
+        action: Action::CustomBlock,
+        name: "custom-block",
+        description: "create a custom block, or edit the hovered one",
+    },
+    ActionInfo {
+        action: Action::Copy,
+        name: "copy",
+        description: "copy the hovered script as text",
+    },
+    ActionInfo {
+        action: Action::Paste,
+        name: "paste",
+        description: "paste the last copied script",
+    },
+    ActionInfo {
+        action: Action::Breakpoint,
+        name: "breakpoint",
+        description: "toggle a breakpoint on the hovered block",
+    },
+    ActionInfo {
+        action: Action::Lint,
+        name: "lint",
+        description: "check the project for likely mistakes",
+    },
+    ActionInfo {
+        action: Action::Data,
+        name: "data",
+        description: "list the variables, lists and broadcasts",
+    },
+    ActionInfo {
+        action: Action::Extensions,
+        name: "extensions",
+        description: "list the extensions",
+    },
+    ActionInfo {
+        action: Action::FindReferences,
+        name: "find-references",
+        description: "list the uses of the hovered variable, list or broadcast",
+    },
+    ActionInfo {
+        action: Action::GoToDefinition,
+        name: "go-to-definition",
+        description: "jump to the hats receiving the hovered broadcast",
+    },
+    ActionInfo {
+        action: Action::Run,
+        name: "run",
+        description: "click the green flag",
+    },
+    ActionInfo {
+        action: Action::Step,
+        name: "step",
+        description: "while paused, run a single block",
+    },
+    ActionInfo {
+        action: Action::StepOver,
+        name: "step-over",
+        description: "while paused, run a C-block's body or a custom block in one go",
+    },
+    ActionInfo {
+        action: Action::Continue,
+        name: "continue",
+        description: "while paused, carry on running",
+    },
+    ActionInfo {
+        action: Action::Stop,
+        name: "stop",
+        description: "stop the running project",
+    },
+    ActionInfo {
+        action: Action::Restart,
+        name: "restart",
+        description: "click the green flag again while running",
+    },
+    ActionInfo {
+        action: Action::Help,
+        name: "help",
+        description: "list every action and its keys",
+    },
+    ActionInfo {
+        action: Action::CommandLine,
+        name: "command-line",
+        description: "type a command, like :w or :goto 0 0",
+    },
+    ActionInfo {
+        action: Action::MoveLeft,
+        name: "move-left",
+        description: "move the cursor left",
+    },
+    ActionInfo {
+        action: Action::MoveDown,
+        name: "move-down",
+        description: "move the cursor down",
+    },
+    ActionInfo {
+        action: Action::MoveUp,
+        name: "move-up",
+        description: "move the cursor up",
+    },
+    ActionInfo {
+        action: Action::MoveRight,
+        name: "move-right",
+        description: "move the cursor right",
+    },
+    ActionInfo {
+        action: Action::ScrollLeft,
+        name: "scroll-left",
+        description: "scroll the view left",
+    },
+    ActionInfo {
+        action: Action::ScrollDown,
+        name: "scroll-down",
+        description: "scroll the view down",
+    },
+    ActionInfo {
+        action: Action::ScrollUp,
+        name: "scroll-up",
+        description: "scroll the view up",
+    },
+    ActionInfo {
+        action: Action::ScrollRight,
+        name: "scroll-right",
+        description: "scroll the view right",
+    },
+    ActionInfo {
+        action: Action::ToggleToolbox,
+        name: "toggle-toolbox",
+        description: "switch between the toolbox and the scripts",
+    },
+    ActionInfo {
+        action: Action::SearchToolbox,
+        name: "search-toolbox",
+        description: "filter the toolbox by the blocks' text",
+    },
+    ActionInfo {
+        action: Action::ToggleTargets,
+        name: "toggle-targets",
+        description: "list the stage and sprites",
+    },
+    ActionInfo {
+        action: Action::Stamp,
+        name: "stamp",
+        description: "pick up a copy of the hovered block",
+    },
+    ActionInfo {
+        action: Action::Delete,
+        name: "delete",
+        description: "delete the held or hovered block",
+    },
+    ActionInfo {
+        action: Action::PickUp,
+        name: "pick-up",
+        description: "pick up, drop, edit or spawn whatever is under the cursor",
+    },
+    ActionInfo {
+        action: Action::CreateVariable,
+        name: "create-variable",
+        description: "in the data pane, create a variable for all sprites",
+    },
+    ActionInfo {
+        action: Action::CreateLocalVariable,
+        name: "create-local-variable",
+        description: "in the data pane, create a variable for this sprite only",
+    },
+    ActionInfo {
+        action: Action::CreateCloudVariable,
+        name: "create-cloud-variable",
+        description: "in the data pane, create a cloud variable",
+    },
+    ActionInfo {
+        action: Action::CreateList,
+        name: "create-list",
+        description: "in the data pane, create a list for all sprites",
+    },
+    ActionInfo {
+        action: Action::CreateLocalList,
+        name: "create-local-list",
+        description: "in the data pane, create a list for this sprite only",
+    },
+    ActionInfo {
+        action: Action::CreateBroadcast,
+        name: "create-broadcast",
+        description: "in the data pane, create a broadcast",
+    },
+    ActionInfo {
+        action: Action::RenameData,
+        name: "rename-data",
+        description: "in the data pane, rename the selected entry",
+    },
+];
+
+impl Action {
+    pub fn from_name(name: &str) -> Option<Action> {
+        ACTIONS
+            .iter()
+            .find(|info| info.name == name)
+            .map(|info| info.action)
+    }
+
+    pub fn info(self) -> &'static ActionInfo {
+        ACTIONS.iter().find(|info| info.action == self).unwrap()
+    }
+}
+
+/// Opens the project file, saying how it went in the status bar
+pub fn open_project(runtime: &mut Runtime, path: &str) -> ViiruResult {
+    runtime.status_message = match runtime.load_project(path) {
+        Ok(true) => {
+            runtime.project_path = Some(path.to_string());
+            format!("Opened project file {path}")
+        }
+        Ok(false) => format!("Failed to open project file at {path}"),
+        Err(err) if err.is_file_error() => format!("Failed to open project file at {path}: {err}"),
+        Err(err) => return Err(err),
+    };
+    Ok(())
+}
+
+/// Writes the project file unless in read-only mode, returning whether it was written
+pub fn write_project(runtime: &mut Runtime, path: &str) -> ViiruResult<bool> {
+    if runtime.readonly {
+        runtime.status_message = "Read-only mode, not writing".into();
+        return Ok(false);
+    }
+    let written = match runtime.save_project(path) {
+        Err(err) if err.is_file_error() => {
+            runtime.status_message = format!("Failed to write project file to {path}: {err}");
+            return Ok(false);
+        }
+        written => written?,
+    };
+    if written {
+        runtime.status_message = format!("Saved project into {path}");
+        runtime.project_path = Some(path.to_string());
+    } else {
+        runtime.status_message = format!("Failed to write project file to {path}");
+    }
+    Ok(written)
+}
+
+/// The list panes and dropdown menus share the movement and pick-up actions. Moving left
+/// closes them, as does the action that opened them.
+fn perform_in_pane(
+    action: Action,
+    runtime: &mut Runtime,
+    screen: &mut Screen,
+) -> ViiruResult<Outcome> {
+    match (action, runtime.state) {
+        (Action::MoveDown, _) => move_pane_cursor(runtime, 1),
+        (Action::MoveUp, _) => move_pane_cursor(runtime, -1),
+        (Action::MoveRight | Action::PickUp, State::Diagnostics) => {
+            let diagnostic = runtime.diagnostics.get(runtime.diagnostic_cursor);
+            if let Some(block_id) = diagnostic.and_then(|d| d.block_id.clone()) {
+                runtime.jump_to_block(&block_id);
+            }
+        }
+        (Action::MoveRight | Action::PickUp, State::Extensions) => {
+            let extension = &EXTENSIONS[runtime.extension_cursor];
+            if runtime.is_extension_enabled(&extension.id) {
+                runtime.status_message = format!("{} is already enabled", extension.name);
+            } else {
+                runtime.enable_extension(&extension.id)?;
+                runtime.status_message = format!("Added {} blocks to the toolbox", extension.name);
+                runtime.state = State::Toolbox;
+            }
+        }
+        (Action::MoveRight | Action::PickUp, State::References) => {
+            let reference = runtime
+                .reference_list
+                .as_ref()
+                .and_then(|list| list.references.get(list.cursor).cloned());
+            if let Some(reference) = reference {
+                jump_to_reference(screen, runtime, &reference)?;
+            }
+        }
+        (Action::MoveRight | Action::PickUp, State::Dropdown) => {
+            runtime.commit_dropdown()?;
+            runtime.state = State::Move;
+        }
+        (Action::SearchToolbox, State::Dropdown) => {
+            if let Some(menu) = &mut runtime.dropdown_menu {
+                menu.typing = true;
+            }
+        }
+        (Action::Delete, State::Data) => {
+            let selected = runtime
+                .data_list
+                .as_ref()
+                .and_then(|list| list.entries.get(list.cursor))
+                .cloned();
+            if let Some(entry) = selected {
+                let uses = runtime.uses_of_data(&entry)?;
+                if uses.is_empty() {
+                    runtime.delete_data(&entry.id)?;
+                    runtime.status_message = format!("Deleted `{}`", entry.name);
+                } else {
+                    runtime.status_message = match runtime.config.keys_for(Action::Delete).first() {
+                        Some(key) => format!(
+                            "`{}` is still in use, {key} to delete it anyway",
+                            entry.name
+                        ),
+                        None => format!("`{}` is still in use", entry.name),
+                    };
+                    runtime.reference_list = Some(ReferenceList {
+                        title: format!("uses of `{}`", entry.name),
+                        name: entry.name,
+                        references: uses,
+                        cursor: 0,
+                        deleting: Some(entry.id),
+                    });
+                    runtime.state = State::References;
+                }
+            }
+        }
+        (
+            Action::CreateVariable
+            | Action::CreateLocalVariable
+            | Action::CreateCloudVariable
+            | Action::CreateList
+            | Action::CreateLocalList
+            | Action::CreateBroadcast,
+            State::Data,
+        ) => {
+            let variable_type = match action {
+                Action::CreateList | Action::CreateLocalList => VariableType::List,
+                Action::CreateBroadcast => VariableType::Broadcast,
+                _ => VariableType::Scalar,
+            };
+            runtime.data_prompt = Some(DataPrompt::Create {
+                variable_type,
+                is_local: matches!(
+                    action,
+                    Action::CreateLocalVariable | Action::CreateLocalList
+                ),
+                is_cloud: action == Action::CreateCloudVariable,
+            });
+            runtime.last_action = Some(Action::Data);
+            runtime.state = State::Command;
+        }
+        (Action::RenameData, State::Data) => {
+            let selected = runtime
+                .data_list
+                .as_ref()
+                .and_then(|list| list.entries.get(list.cursor))
+                .cloned();
+            let Some(entry) = selected else {
+                return Ok(Outcome::Unchanged);
+            };
+            runtime.command_buffer = entry.name.clone();
+            runtime.data_prompt = Some(DataPrompt::Rename(entry));
+            runtime.last_action = Some(Action::Data);
+            runtime.state = State::Command;
+        }
+        (Action::Delete, State::References) => {
+            let Some(list) = runtime
+                .reference_list
+                .take_if(|list| list.deleting.is_some())
+            else {
+                return Ok(Outcome::Unchanged);
+            };
+            runtime.status_message = format!("Deleted `{}`", list.name);
+            runtime.delete_data(&list.deleting.unwrap())?;
+            runtime.state = State::Data;
+        }
+        (Action::MoveLeft, _)
+        | (Action::Lint, State::Diagnostics)
+        | (Action::Extensions, State::Extensions)
+        | (Action::Help, State::Help)
+        | (Action::FindReferences, State::References) => close_pane(runtime)?,
+        _ => return Ok(Outcome::Unchanged),
+    }
+    Ok(Outcome::Redraw)
+}
+
+/// The arrow keys, Enter and Esc work in the panes whatever the bindings are
+pub fn pane_action(state: State, code: KeyCode) -> Option<Action> {
+    if !matches!(
+        state,
+        State::Diagnostics
+            | State::Extensions
+            | State::Help
+            | State::References
+            | State::Data
+            | State::Dropdown
+    ) {
+        return None;
+    }
+    match code {
+        KeyCode::Down => Some(Action::MoveDown),
+        KeyCode::Up => Some(Action::MoveUp),
+        KeyCode::Enter => Some(Action::PickUp),
+        KeyCode::Esc => Some(Action::MoveLeft),
+        _ => None,
+    }
+}
+
+fn move_pane_cursor(runtime: &mut Runtime, delta: isize) {
+    // stays within the list
+    let step = |cursor: usize, len: usize| {
+        cursor
+            .saturating_add_signed(delta)
+            .min(len.saturating_sub(1))
+    };
+    match runtime.state {
+        State::Diagnostics => {
+            runtime.diagnostic_cursor = step(runtime.diagnostic_cursor, runtime.diagnostics.len());
+        }
+        State::Extensions => {
+            runtime.extension_cursor = step(runtime.extension_cursor, EXTENSIONS.len());
+        }
+        State::Help => runtime.help_scroll = step(runtime.help_scroll, ACTIONS.len()),
+        State::References => {
+            if let Some(list) = &mut runtime.reference_list {
+                list.cursor = step(list.cursor, list.references.len());
+            }
+        }
+        State::Data => {
+            if let Some(list) = &mut runtime.data_list {
+                list.cursor = step(list.cursor, list.entries.len());
+            }
+        }
+        State::Dropdown => {
+            if let Some(menu) = &mut runtime.dropdown_menu {
+                menu.move_cursor(delta);
+            }
+        }
+        _ => (),
+    }
+}
+
+fn close_pane(runtime: &mut Runtime) -> ViiruResult {
+    match runtime.state {
+        // a warning about deleting goes back to the variables it came from
+        State::References => {
+            let list = runtime.reference_list.take();
+            if list.is_some_and(|list| list.deleting.is_some()) {
+                runtime.load_data_list()?;
+                runtime.state = State::Data;
+                return Ok(());
+            }
+        }
+        State::Data => runtime.data_list = None,
+        State::Dropdown => runtime.dropdown_menu = None,
+        _ => (),
+    }
+    runtime.state = State::Move;
+    Ok(())
+}
+
+/// lists whichever of stop and restart are still bound to a key
+fn running_message(config: &Config) -> String {
+    let hints: Vec<_> = [(Action::Stop, "stop"), (Action::Restart, "start over")]
+        .into_iter()
+        .filter_map(|(action, what)| {
+            let key = config.keys_for(action).into_iter().next()?;
+            Some(format!("{key} to {what}"))
+        })
+        .collect();
+    if hints.is_empty() {
+        "Running".into()
+    } else {
+        format!("Running. {}", hints.join(", "))
+    }
+}
+
+/// what the editor should do after an action
+pub enum Outcome {
+    Quit,
+    Redraw,
+    Unchanged,
+}
+
+/// Does whatever the action means in the current state, which is often nothing
+pub fn perform(action: Action, runtime: &mut Runtime, screen: &mut Screen) -> ViiruResult<Outcome> {
+    if let State::Diagnostics
+    | State::Extensions
+    | State::Help
+    | State::References
+    | State::Data
+    | State::Dropdown = runtime.state
+    {
+        return perform_in_pane(action, runtime, screen);
+    }
+    let mut needs_refresh = false;
+    // the command line needs to know what it's being asked for
+    runtime.last_action = Some(action);
+    match action {
+        Action::Quit => {
+            if runtime.is_dirty() {
+                runtime.status_message = match runtime.config.keys_for(Action::ForceQuit).first() {
+                    Some(key) => format!("Unsaved changes. ({key} to force)"),
+                    None => "Unsaved changes".into(),
+                };
+                needs_refresh = true;
+            } else {
+                return Ok(Outcome::Quit);
+            }
+        }
+        Action::ForceQuit => {
+            return Ok(Outcome::Quit);
+        }
+        Action::Open => {
+            runtime.state = State::Command;
+            needs_refresh = true;
+        }
+        Action::Write => {
+            runtime.command_buffer = runtime.project_path.clone().unwrap_or_default();
+            runtime.state = State::Command;
+            needs_refresh = true;
+        }
+        Action::Undo => {
+            if let State::Move = runtime.state {
+                runtime.status_message = match runtime.undo() {
+                    Ok(true) => "Undid last change".into(),
+                    Ok(false) => "Nothing to undo".into(),
+                    Err(ViiruError::MissingBlock(_)) => {
+                        "The blocks have changed since, so the undo history was cleared".into()
+                    }
+                    Err(err) => return Err(err),
+                };
+                needs_refresh = true;
+            }
+        }
+        Action::Redo => {
+            if let State::Move = runtime.state {
+                runtime.status_message = match runtime.redo() {
+                    Ok(true) => "Redid last change".into(),
+                    Ok(false) => "Nothing to redo".into(),
+                    Err(ViiruError::MissingBlock(_)) => {
+                        "The blocks have changed since, so the undo history was cleared".into()
+                    }
+                    Err(err) => return Err(err),
+                };
+                needs_refresh = true;
+            }
+        }
+        Action::CustomBlock => {
+            if let State::Move = runtime.state {
+                // edit the hovered custom block, or make a new one
+                runtime.editing_definition = runtime
+                    .block_positions
+                    .get(&(runtime.cursor_x, runtime.cursor_y))
+                    .and_then(|a| runtime.definition_of(a.last().unwrap()));
+                runtime.command_buffer = runtime
+                    .editing_definition
+                    .as_ref()
+                    .and_then(|id| runtime.prototype_of(id))
+                    .and_then(|id| runtime.blocks[&id].mutation.as_ref())
+                    .map(|mutation| mutation.signature())
+                    .unwrap_or_default();
+                runtime.state = State::Command;
+                needs_refresh = true;
+            }
+        }
+        Action::Copy => {
+            if let State::Move = runtime.state {
+                if let Some(a) = runtime
+                    .block_positions
+                    .get(&(runtime.cursor_x, runtime.cursor_y))
+                {
+                    let selected = runtime.movable_block(a.last().unwrap());
+                    runtime.clipboard = scratchblocks::export_script(runtime, &selected);
+                    copy_to_system_clipboard(&runtime.clipboard)?;
+                    runtime.status_message = "Copied script as text".into();
+                    needs_refresh = true;
+                }
+            }
+        }
+        Action::Paste => {
+            if let State::Move = runtime.state {
+                paste_text(runtime, &runtime.clipboard.clone())?;
+                needs_refresh = true;
+            }
+        }
+        Action::Breakpoint => {
+            if let State::Move = runtime.state {
+                if let Some(a) = runtime
+                    .block_positions
+                    .get(&(runtime.cursor_x, runtime.cursor_y))
+                {
+                    let block_id = a.last().unwrap().clone();
+                    runtime.status_message = match runtime.toggle_breakpoint(&block_id) {
+                        Some(true) => "Breakpoint set".into(),
+                        Some(false) => "Breakpoint removed".into(),
+                        None => "Nothing under the hat block to pause at".into(),
+                    };
+                    needs_refresh = true;
+                }
+            }
+        }
+        Action::Lint => {
+            if let State::Move = runtime.state {
+                runtime.enable_lint()?;
+                runtime.state = State::Diagnostics;
+                needs_refresh = true;
+            }
+        }
+        Action::Data => {
+            if let State::Move = runtime.state {
+                runtime.load_data_list()?;
+                runtime.state = State::Data;
+                needs_refresh = true;
+            }
+        }
+        Action::Help => {
+            if let State::Move | State::Toolbox = runtime.state {
+                runtime.help_scroll = 0;
+                runtime.state = State::Help;
+                needs_refresh = true;
+            }
+        }
+        Action::CommandLine => {
+            if let State::Move = runtime.state {
+                runtime.command_buffer.clear();
+                runtime.completion = None;
+                runtime.history_cursor = None;
+                runtime.state = State::Command;
+                needs_refresh = true;
+            }
+        }
+        Action::Extensions => {
+            if let State::Move = runtime.state {
+                runtime.state = State::Extensions;
+                needs_refresh = true;
+            }
+        }
+        Action::FindReferences | Action::GoToDefinition => {
+            if let State::Move = runtime.state {
+                let is_definition = action == Action::GoToDefinition;
+                let hovered = runtime
+                    .block_positions
+                    .get(&(runtime.cursor_x, runtime.cursor_y))
+                    .map(|ids| ids.last().unwrap().clone());
+                let found = match hovered {
+                    Some(block_id) => runtime.find_references(&block_id, is_definition)?,
+                    None => None,
+                };
+                match found {
+                    None if is_definition => {
+                        runtime.status_message =
+                            "Not a broadcast, so there's nothing to go to".into();
+                    }
+                    None => {
+                        runtime.status_message = "Not a variable, list or broadcast".into();
+                    }
+                    // a single receiver is jumped to right away
+                    Some(list) if is_definition && list.references.len() == 1 => {
+                        jump_to_reference(screen, runtime, &list.references[0])?;
+                    }
+                    Some(list) => {
+                        runtime.status_message =
+                            format!("{} {}", list.references.len(), list.title);
+                        runtime.reference_list = Some(list);
+                        runtime.state = State::References;
+                    }
+                }
+                needs_refresh = true;
+            }
+        }
+        Action::Run => {
+            if let State::Move = runtime.state {
+                runtime.start_running()?;
+                runtime.status_message = running_message(&runtime.config);
+                needs_refresh = true;
+            }
+        }
+        Action::Step | Action::StepOver | Action::Continue => {
+            let paused = runtime
+                .interpreter
+                .as_mut()
+                .filter(|interpreter| interpreter.paused.is_some());
+            if let (State::Run, Some(interpreter)) = (runtime.state, paused) {
+                match action {
+                    Action::Step => interpreter.step_block(),
+                    Action::StepOver => interpreter.step_over(),
+                    _ => interpreter.resume(),
+                }
+                runtime.status_message = running_message(&runtime.config);
+                needs_refresh = true;
+            }
+        }
+        Action::Stop => {
+            if let State::Run = runtime.state {
+                runtime.stop_running();
+                runtime.status_message = "Stopped".into();
+                needs_refresh = true;
+            }
+        }
+        Action::Restart => {
+            if let (State::Run, Some(interpreter)) = (runtime.state, &mut runtime.interpreter) {
+                interpreter.green_flag();
+                needs_refresh = true;
+            }
+        }
+        Action::MoveLeft => match runtime.state {
+            State::Move | State::Hold => {
+                runtime.move_x(-1)?;
+                if runtime.cursor_x - runtime.scroll_x == runtime.viewport.y_min - 1 {
+                    runtime.scroll_x -= 1;
+                }
+                needs_refresh = true;
+            }
+            _ => (),
+        },
+        Action::MoveDown => match runtime.state {
+            State::Move | State::Hold => {
+                runtime.move_y(1)?;
+                if runtime.cursor_y - runtime.scroll_y == runtime.viewport.y_max {
+                    runtime.scroll_y += 1;
+                }
+                needs_refresh = true;
+            }
+            State::Targets => {
+                runtime.target_cursor =
+                    (runtime.target_cursor + 1).min(runtime.targets.len().saturating_sub(1));
+                needs_refresh = true;
+            }
+            State::Toolbox if runtime.toolbox_search.is_some() => {
+                runtime.move_toolbox_search(1);
+                needs_refresh = true;
+            }
+            State::Toolbox => {
+                runtime.toolbox_cursor =
+                    (runtime.toolbox_cursor + 1).min(runtime.toolbox.len() - 1);
+                while runtime.toolbox_cursor > runtime.toolbox_visible_max {
+                    runtime.toolbox_scroll =
+                        (runtime.toolbox_scroll + 1).min(runtime.toolbox.len() - 1);
+                    let vox = runtime.viewport_offset_x;
+                    let voy = runtime.viewport_offset_y;
+                    screen.draw_toolbox(runtime, vox, voy, true)?;
+                }
+                needs_refresh = true;
+            }
+            _ => (),
+        },
+        Action::MoveUp => match runtime.state {
+            State::Move | State::Hold => {
+                runtime.move_y(-1)?;
+                if runtime.cursor_y - runtime.scroll_y < runtime.viewport.y_min {
+                    runtime.scroll_y -= 1;
+                }
+                needs_refresh = true;
+            }
+            State::Targets => {
+                runtime.target_cursor = runtime.target_cursor.saturating_sub(1);
+                needs_refresh = true;
+            }
+            State::Toolbox if runtime.toolbox_search.is_some() => {
+                runtime.move_toolbox_search(-1);
+                needs_refresh = true;
+            }
+            State::Toolbox => {
+                runtime.toolbox_cursor = runtime.toolbox_cursor.saturating_sub(1);
+                if runtime.toolbox_cursor == runtime.toolbox_scroll - 1 {
+                    runtime.toolbox_scroll = runtime.toolbox_cursor;
+                }
+                needs_refresh = true;
+            }
+            _ => (),
+        },
+        Action::MoveRight => match runtime.state {
+            State::Move | State::Hold => {
+                runtime.move_x(1)?;
+                if runtime.cursor_x - runtime.scroll_x == runtime.viewport.x_max {
+                    runtime.scroll_x += 1;
+                }
+                needs_refresh = true;
+            }
+            _ => (),
+        },
+        Action::ScrollLeft => match runtime.state {
+            State::Move | State::Hold => {
+                runtime.scroll_x -= 1;
+                runtime.move_x(-1)?;
+                needs_refresh = true;
+            }
+            _ => (),
+        },
+        Action::ScrollDown => match runtime.state {
+            State::Move | State::Hold => {
+                runtime.scroll_y += 1;
+                runtime.move_y(1)?;
+                needs_refresh = true;
+            }
+            State::Toolbox if runtime.toolbox_search.is_some() => {
+                runtime.move_toolbox_search(1);
+                needs_refresh = true;
+            }
+            State::Toolbox => {
+                runtime.toolbox_cursor =
+                    (runtime.toolbox_cursor + 1).min(runtime.toolbox.len() - 1);
+                runtime.toolbox_scroll =
+                    (runtime.toolbox_scroll + 1).min(runtime.toolbox.len() - 1);
+                needs_refresh = true;
+            }
+            _ => (),
+        },
+        Action::ScrollUp => match runtime.state {
+            State::Move | State::Hold => {
+                runtime.scroll_y -= 1;
+                runtime.move_y(-1)?;
+                needs_refresh = true;
+            }
+            State::Toolbox if runtime.toolbox_search.is_some() => {
+                runtime.move_toolbox_search(-1);
+                needs_refresh = true;
+            }
+            State::Toolbox => {
+                runtime.toolbox_cursor = runtime.toolbox_cursor.saturating_sub(1);
+                runtime.toolbox_scroll = runtime.toolbox_scroll.saturating_sub(1);
+                needs_refresh = true;
+            }
+            _ => (),
+        },
+        Action::ScrollRight => match runtime.state {
+            State::Move | State::Hold => {
+                runtime.scroll_x += 1;
+                runtime.move_x(1)?;
+                needs_refresh = true;
+            }
+            _ => (),
+        },
+        Action::ToggleToolbox => match runtime.state {
+            State::Move => {
+                runtime.state = State::Toolbox;
+                needs_refresh = true;
+            }
+            State::Toolbox => {
+                runtime.close_toolbox_search();
+                runtime.state = State::Move;
+                needs_refresh = true;
+            }
+            _ => (),
+        },
+        Action::SearchToolbox => {
+            if let State::Toolbox = runtime.state {
+                // searching again edits the last query
+                let search = runtime.toolbox_search.get_or_insert(ToolboxSearch {
+                    query: String::new(),
+                    typing: true,
+                    cursor: 0,
+                });
+                search.typing = true;
+                needs_refresh = true;
+            }
+        }
+        Action::ToggleTargets => match runtime.state {
+            State::Move | State::Toolbox => {
+                runtime.target_cursor = runtime.editing_target;
+                runtime.state = State::Targets;
+                needs_refresh = true;
+            }
+            State::Targets => {
+                runtime.state = State::Move;
+                needs_refresh = true;
+            }
+            _ => (),
+        },
+        Action::Stamp => match runtime.state {
+            State::Move => {
+                if let Some(a) = runtime
+                    .block_positions
+                    .get(&(runtime.cursor_x, runtime.cursor_y))
+                {
+                    let selected = runtime.movable_block(a.last().unwrap());
+                    let stamp_id = runtime.stamp_block(&selected, true)?;
+                    runtime.put_to_cursor(&stamp_id)?;
+                    needs_refresh = true;
+                    runtime.state = State::Hold;
+                }
+            }
+            State::Hold => {
+                if let Some(cursor_id) = runtime.cursor_block.clone() {
+                    runtime.stamp_block(&cursor_id, true)?;
+                    needs_refresh = true;
+                }
+            }
+            _ => (),
+        },
+        Action::Delete => match runtime.state {
+            State::Move => {
+                if let Some(a) = runtime
+                    .block_positions
+                    .get(&(runtime.cursor_x, runtime.cursor_y))
+                {
+                    let selected = runtime.movable_block(a.last().unwrap());
+                    runtime.delete_block(&selected)?;
+                    needs_refresh = true;
+                }
+            }
+            State::Hold => {
+                if let Some(cursor_id) = runtime.cursor_block.take() {
+                    runtime.delete_block(&cursor_id)?;
+                    needs_refresh = true;
+                    runtime.state = State::Move;
+                }
+            }
+            _ => (),
+        },
+        Action::PickUp => {
+            // interaction!
+            match runtime.state {
+                State::Move => {
+                    // dropdowns can be grabbed as well, when there's nothing to choose from
+                    let dropdown_id = runtime
+                        .dropdown_points
+                        .get(&(runtime.cursor_x, runtime.cursor_y))
+                        .cloned();
+                    if dropdown_id.is_some_and(|block_id| runtime.open_dropdown(&block_id)) {
+                        runtime.state = State::Dropdown;
+                        needs_refresh = true;
+                    } else if let Some(a) = runtime
+                        .block_positions
+                        .get(&(runtime.cursor_x, runtime.cursor_y))
+                    {
+                        let mut selected = a.last().unwrap().clone();
+                        if runtime.is_prototype_argument(&selected) {
+                            // arguments are taken out as copies
+                            selected = runtime.stamp_block(&selected, true)?;
+                        } else {
+                            selected = runtime.movable_block(&selected);
+                        }
+                        runtime.put_to_cursor(&selected)?;
+                        needs_refresh = true;
+                        runtime.state = State::Hold;
+                    } else if let Some(shadow_id) = runtime
+                        .writable_points
+                        .get(&(runtime.cursor_x, runtime.cursor_y))
+                    {
+                        runtime.editing_shadow = shadow_id.clone();
+                        runtime.state = State::Inline;
+                        runtime.status_message = "Editing field".into();
+                        needs_refresh = true;
+                    }
+                }
+                State::Hold => {
+                    if let Some(drop_point) = runtime.current_drop_point() {
+                        let cursor_id = runtime.cursor_block.take().unwrap();
+                        let parent_id = drop_point.id;
+                        let fits = if drop_point.is_above {
+                            runtime.insert_above(&cursor_id, &parent_id)?
+                        } else if let Shape::Stack = drop_point.shape {
+                            runtime.insert_stack(
+                                &cursor_id,
+                                &parent_id,
+                                drop_point.input.as_deref(),
+                            )?
+                        } else {
+                            let input_name = drop_point.input.unwrap();
+                            // chuck existing inputs away to the right somewhere
+                            if let Some(existing_id) = runtime.blocks[&parent_id].inputs
+                                [&input_name]
+                                .block_id
+                                .clone()
+                            {
+                                runtime.detach_block(&existing_id)?;
+                                // TODO: pick a more reasonable position
+                                runtime.slide_block_by(&existing_id, 1, 1)?;
+                            }
+                            runtime.attach_input(&cursor_id, &parent_id, &input_name, false)?;
+                            true
+                        };
+                        if !fits {
+                            runtime.status_message = "Nothing can go below a cap block".into();
+                        }
+                    } else {
+                        runtime.cursor_block.take().unwrap();
+                    }
+                    needs_refresh = true;
+                    runtime.state = State::Move;
+                }
+                State::Targets => {
+                    runtime.switch_target(runtime.target_cursor)?;
+                    runtime.status_message = format!("Editing {}", runtime.editing_target_name());
+                    needs_refresh = true;
+                    runtime.state = State::Move;
+                }
+                State::Toolbox => {
+                    let Some(toolbox_id) = runtime.toolbox_selection() else {
+                        return Ok(Outcome::Unchanged);
+                    };
+                    let spawned_id = runtime.stamp_block(&toolbox_id, true)?;
+                    runtime.put_to_cursor(&spawned_id)?;
+                    runtime.slide_block_to(&spawned_id, runtime.cursor_x, runtime.cursor_y)?;
+                    needs_refresh = true;
+                    runtime.state = State::Hold;
+                }
+                _ => (),
+            }
+        }
+        // only do anything in the data pane
+        Action::CreateVariable
+        | Action::CreateLocalVariable
+        | Action::CreateCloudVariable
+        | Action::CreateList
+        | Action::CreateLocalList
+        | Action::CreateBroadcast
+        | Action::RenameData => (),
+    }
+    Ok(if needs_refresh {
+        Outcome::Redraw
+    } else {
+        Outcome::Unchanged
+    })
+}
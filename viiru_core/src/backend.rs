@@ -0,0 +1,543 @@
+use std::collections::HashMap;
+
+use crate::{
+    block::{Block, Mutation},
+    extensions::used_extensions,
+    opcodes::BLOCKS,
+    project::{List, Project, Target, Variable},
+    result::ViiruResult,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum VariableType {
+    Scalar,
+    List,
+    Broadcast,
+}
+
+#[derive(Clone)]
+pub struct TargetInfo {
+    pub name: String,
+    pub is_stage: bool,
+    /// names, in order; the stage's costumes are its backdrops
+    pub costumes: Vec<String>,
+    pub sounds: Vec<String>,
+    /// names of its own variables, which for the stage are the globals
+    pub variables: Vec<String>,
+}
+
+/// Everything the editor needs from whatever holds the project on its behalf.
+/// This mirrors the `API` object passed in from `src/index.ts`.
+pub trait ProjectBackend {
+    /// replaces the current project with an empty one
+    fn new_project(&mut self) -> ViiruResult<()>;
+    /// returns false if the project could not be loaded, or the error if it's known
+    fn load_project(&mut self, path: &str) -> ViiruResult<bool>;
+    /// returns false if the project could not be saved, or the error if it's known
+    fn save_project(&mut self, path: &str) -> ViiruResult<bool>;
+    fn create_block(&mut self, opcode: &str, is_shadow: bool, id: &str) -> ViiruResult<()>;
+    /// deletes the block along with its inputs and the rest of its stack
+    fn delete_block(&mut self, id: &str) -> ViiruResult<()>;
+    /// coordinates are in cells
+    fn slide_block(&mut self, id: &str, x: i32, y: i32) -> ViiruResult<()>;
+    /// attaches to the input slot if one is given, otherwise below the parent
+    fn attach_block(
+        &mut self,
+        id: &str,
+        parent_id: &str,
+        input_name: Option<&str>,
+        is_shadow: bool,
+    ) -> ViiruResult<()>;
+    fn detach_block(&mut self, id: &str) -> ViiruResult<()>;
+    fn change_field(
+        &mut self,
+        block_id: &str,
+        field_name: &str,
+        text: &str,
+        data_id: Option<&str>,
+    ) -> ViiruResult<()>;
+    /// the stage comes first, followed by every sprite
+    fn get_targets(&mut self) -> ViiruResult<Vec<TargetInfo>>;
+    /// index into `get_targets`
+    fn get_editing_target(&mut self) -> ViiruResult<usize>;
+    /// all block operations apply to the editing target
+    fn set_editing_target(&mut self, index: usize) -> ViiruResult<()>;
+    fn change_mutation(&mut self, block_id: &str, mutation: &Mutation) -> ViiruResult<()>;
+    fn get_all_blocks(&mut self) -> ViiruResult<HashMap<String, Block>>;
+    /// id -> name, including globals when editing a sprite
+    fn get_variables_of_type(
+        &mut self,
+        variable_type: VariableType,
+    ) -> ViiruResult<HashMap<String, String>>;
+    /// a copy of the whole project as it is right now, without assets
+    fn get_project(&mut self) -> ViiruResult<Project>;
+    /// locals belong to the editing target, everything else to the stage
+    fn create_variable(
+        &mut self,
+        variable_type: VariableType,
+        id: &str,
+        name: &str,
+        is_local: bool,
+        is_cloud: bool,
+    ) -> ViiruResult<()>;
+    /// also renames every field referring to it, in every target
+    fn rename_variable(&mut self, id: &str, name: &str) -> ViiruResult<()>;
+    /// blocks still referring to it are left alone
+    fn delete_variable(&mut self, id: &str) -> ViiruResult<()>;
+    /// ids of the extensions loaded into the project, including the ones its blocks use
+    fn get_extensions(&mut self) -> ViiruResult<Vec<String>>;
+    fn enable_extension(&mut self, id: &str) -> ViiruResult<()>;
+}
+
+/// Holds the project in plain Rust data structures, reading and writing `.sb3` files natively.
+#[derive(Default)]
+pub struct MemoryBackend {
+    project: Project,
+    editing_target: usize,
+    blocks: HashMap<String, Block>,
+    /// blocks of the other targets edited so far, kept decoded so their ids stay the same
+    decoded: HashMap<usize, HashMap<String, Block>>,
+}
+
+fn top_level_of(blocks: &HashMap<String, Block>) -> Vec<String> {
+    blocks
+        .iter()
+        .filter(|(_, block)| block.parent_id.is_none())
+        .map(|(id, _)| id.clone())
+        .collect()
+}
+
+fn rename_fields(blocks: &mut HashMap<String, Block>, id: &str, name: &str) {
+    for field in blocks
+        .values_mut()
+        .flat_map(|block| block.fields.values_mut())
+    {
+        if field.id.as_deref() == Some(id) {
+            field.value = name.to_string();
+        }
+    }
+}
+
+impl MemoryBackend {
+    /// writes the blocks of every decoded target back into the project
+    fn store_blocks(&mut self) {
+        let decoded = self.decoded.iter().map(|(index, blocks)| (*index, blocks));
+        for (index, blocks) in decoded.chain([(self.editing_target, &self.blocks)]) {
+            if let Some(target) = self.project.targets.get_mut(index) {
+                target.encode_blocks(blocks, &top_level_of(blocks));
+            }
+        }
+    }
+
+    fn delete_blocks_recursively(&mut self, id: &str) {
+        let Some(block) = self.blocks.remove(id) else {
+            return;
+        };
+        if let Some(next_id) = &block.next_id {
+            self.delete_blocks_recursively(next_id);
+        }
+        for input in block.inputs.values() {
+            if let Some(id) = &input.block_id {
+                self.delete_blocks_recursively(id);
+            }
+            if let Some(id) = &input.shadow_id {
+                self.delete_blocks_recursively(id);
+            }
+        }
+    }
+}
+
+impl ProjectBackend for MemoryBackend {
+    fn new_project(&mut self) -> ViiruResult<()> {
+        self.project = Project::empty();
+        self.editing_target = self.project.default_editing_target();
+        self.blocks.clear();
+        self.decoded.clear();
+        Ok(())
+    }
+
+    fn load_project(&mut self, path: &str) -> ViiruResult<bool> {
+        let project = Project::load(path)?;
+        // there's nothing to show the editor
+        let editing_target = project.default_editing_target();
+        let Some(target) = project.targets.get(editing_target) else {
+            return Ok(false);
+        };
+        self.blocks = target.decode_blocks();
+        self.decoded.clear();
+        self.project = project;
+        self.editing_target = editing_target;
+        // older editors don't always list every extension their blocks need
+        for id in used_extensions(&self.project) {
+            self.enable_extension(&id)?;
+        }
+        Ok(true)
+    }
+
+    fn save_project(&mut self, path: &str) -> ViiruResult<bool> {
+        self.store_blocks();
+        // like scratch, extensions without any blocks in the project aren't written out
+        let used = used_extensions(&self.project);
+        let enabled = std::mem::replace(&mut self.project.extensions, used);
+        let saved = self.project.save(path);
+        self.project.extensions = enabled;
+        saved?;
+        Ok(true)
+    }
+
+    fn create_block(&mut self, opcode: &str, is_shadow: bool, id: &str) -> ViiruResult<()> {
+        let mut block = Block {
+            opcode: opcode.to_string(),
+            is_shadow,
+            ..Default::default()
+        };
+        if let Some(spec) = BLOCKS.get(opcode) {
+            for input_name in spec.input_names() {
+                block.inputs.insert(input_name.clone(), Default::default());
+            }
+            for field_name in spec.field_names() {
+                block.fields.insert(field_name.clone(), Default::default());
+            }
+        }
+        self.blocks.insert(id.to_string(), block);
+        Ok(())
+    }
+
+    fn delete_block(&mut self, id: &str) -> ViiruResult<()> {
+        self.detach_block(id)?;
+        self.delete_blocks_recursively(id);
+        Ok(())
+    }
+
+    fn slide_block(&mut self, id: &str, x: i32, y: i32) -> ViiruResult<()> {
+        if let Some(block) = self.blocks.get_mut(id) {
+            block.x = x;
+            block.y = y;
+        }
+        Ok(())
+    }
+
+    fn attach_block(
+        &mut self,
+        id: &str,
+        parent_id: &str,
+        input_name: Option<&str>,
+        is_shadow: bool,
+    ) -> ViiruResult<()> {
+        let Some(parent) = self.blocks.get_mut(parent_id) else {
+            return Ok(());
+        };
+        if let Some(input_name) = input_name {
+            let input = parent.inputs.entry(input_name.to_string()).or_default();
+            if is_shadow {
+                input.shadow_id = Some(id.to_string());
+            } else {
+                input.block_id = Some(id.to_string());
+            }
+        } else {
+            parent.next_id = Some(id.to_string());
+        }
+        if let Some(block) = self.blocks.get_mut(id) {
+            block.parent_id = Some(parent_id.to_string());
+        }
+        Ok(())
+    }
+
+    fn detach_block(&mut self, id: &str) -> ViiruResult<()> {
+        let Some(parent_id) = self.blocks.get_mut(id).and_then(|b| b.parent_id.take()) else {
+            return Ok(());
+        };
+        if let Some(parent) = self.blocks.get_mut(&parent_id) {
+            if parent.next_id.as_deref() == Some(id) {
+                parent.next_id = None;
+            }
+            for input in parent.inputs.values_mut() {
+                if input.block_id.as_deref() == Some(id) {
+                    input.block_id = None;
+                }
+                if input.shadow_id.as_deref() == Some(id) {
+                    input.shadow_id = None;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn change_field(
+        &mut self,
+        block_id: &str,
+        field_name: &str,
+        text: &str,
+        data_id: Option<&str>,
+    ) -> ViiruResult<()> {
+        if let Some(block) = self.blocks.get_mut(block_id) {
+            let field = block.fields.entry(field_name.to_string()).or_default();
+            field.value = text.to_string();
+            field.id = data_id.map(str::to_string);
+        }
+        Ok(())
+    }
+
+    fn change_mutation(&mut self, block_id: &str, mutation: &Mutation) -> ViiruResult<()> {
+        if let Some(block) = self.blocks.get_mut(block_id) {
+            block.mutation = Some(mutation.clone());
+        }
+        Ok(())
+    }
+
+    fn get_targets(&mut self) -> ViiruResult<Vec<TargetInfo>> {
+        Ok(self
+            .project
+            .targets
+            .iter()
+            .map(|target| TargetInfo {
+                name: target.name.clone(),
+                is_stage: target.is_stage,
+                costumes: target.costumes.iter().map(|c| c.name.clone()).collect(),
+                sounds: target.sounds.iter().map(|s| s.name.clone()).collect(),
+                variables: target.variables.values().map(|v| v.name.clone()).collect(),
+            })
+            .collect())
+    }
+
+    fn get_editing_target(&mut self) -> ViiruResult<usize> {
+        Ok(self.editing_target)
+    }
+
+    fn set_editing_target(&mut self, index: usize) -> ViiruResult<()> {
+        if index == self.editing_target || index >= self.project.targets.len() {
+            return Ok(());
+        }
+        let blocks = match self.decoded.remove(&index) {
+            Some(blocks) => blocks,
+            None => self.project.targets[index].decode_blocks(),
+        };
+        let previous = std::mem::replace(&mut self.blocks, blocks);
+        self.decoded.insert(self.editing_target, previous);
+        self.editing_target = index;
+        Ok(())
+    }
+
+    fn get_all_blocks(&mut self) -> ViiruResult<HashMap<String, Block>> {
+        Ok(self.blocks.clone())
+    }
+
+    fn get_variables_of_type(
+        &mut self,
+        variable_type: VariableType,
+    ) -> ViiruResult<HashMap<String, String>> {
+        let names_of = |target: &Target| match variable_type {
+            VariableType::Scalar => target.variable_names(),
+            VariableType::List => target.list_names(),
+            VariableType::Broadcast => target.broadcast_names(),
+        };
+        let mut names = HashMap::new();
+        // globals live on the stage
+        if let Some(stage) = self.project.targets.iter().find(|t| t.is_stage) {
+            names.extend(names_of(stage));
+        }
+        if let Some(target) = self.project.targets.get(self.editing_target) {
+            names.extend(names_of(target));
+        }
+        Ok(names)
+    }
+
+    fn get_project(&mut self) -> ViiruResult<Project> {
+        self.store_blocks();
+        // the project isn't Clone, but it does go through json just fine
+        Ok(serde_json::from_value(serde_json::to_value(
+            &self.project,
+        )?)?)
+    }
+
+    fn create_variable(
+        &mut self,
+        variable_type: VariableType,
+        id: &str,
+        name: &str,
+        is_local: bool,
+        is_cloud: bool,
+    ) -> ViiruResult<()> {
+        let owner = if is_local {
+            self.project.targets.get_mut(self.editing_target)
+        } else {
+            self.project.targets.iter_mut().find(|t| t.is_stage)
+        };
+        let Some(owner) = owner else {
+            return Ok(());
+        };
+        let (id, name) = (id.to_string(), name.to_string());
+        match variable_type {
+            VariableType::Scalar => {
+                let value = 0.into();
+                owner.variables.insert(
+                    id,
+                    Variable {
+                        name,
+                        value,
+                        is_cloud,
+                    },
+                );
+            }
+            VariableType::List => {
+                owner.lists.insert(
+                    id,
+                    List {
+                        name,
+                        items: vec![],
+                    },
+                );
+            }
+            VariableType::Broadcast => {
+                owner.broadcasts.insert(id, name);
+            }
+        }
+        Ok(())
+    }
+
+    fn rename_variable(&mut self, id: &str, name: &str) -> ViiruResult<()> {
+        for (i, target) in self.project.targets.iter_mut().enumerate() {
+            if let Some(variable) = target.variables.get_mut(id) {
+                variable.name = name.to_string();
+            }
+            if let Some(list) = target.lists.get_mut(id) {
+                list.name = name.to_string();
+            }
+            if let Some(broadcast) = target.broadcasts.get_mut(id) {
+                *broadcast = name.to_string();
+            }
+            // blocks that are kept decoded are renamed there, the rest as they were loaded
+            if let Some(blocks) = self.decoded.get_mut(&i) {
+                rename_fields(blocks, id, name);
+            } else if i != self.editing_target {
+                target.rename_references(id, name);
+            }
+        }
+        rename_fields(&mut self.blocks, id, name);
+        for monitor in &mut self.project.monitors {
+            if monitor.get("id").and_then(|id| id.as_str()) != Some(id) {
+                continue;
+            }
+            if let Some(params) = monitor.get_mut("params").and_then(|p| p.as_object_mut()) {
+                for value in params.values_mut() {
+                    *value = name.into();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn delete_variable(&mut self, id: &str) -> ViiruResult<()> {
+        for target in &mut self.project.targets {
+            target.variables.shift_remove(id);
+            target.lists.shift_remove(id);
+            target.broadcasts.shift_remove(id);
+        }
+        self.project
+            .monitors
+            .retain(|monitor| monitor.get("id").and_then(|id| id.as_str()) != Some(id));
+        Ok(())
+    }
+
+    fn get_extensions(&mut self) -> ViiruResult<Vec<String>> {
+        Ok(self.project.extensions.clone())
+    }
+
+    fn enable_extension(&mut self, id: &str) -> ViiruResult<()> {
+        if !self.project.extensions.iter().any(|enabled| enabled == id) {
+            self.project.extensions.push(id.to_string());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::env::temp_dir;
+
+    use super::*;
+    use crate::{result::ViiruError, runtime::Runtime};
+
+    pub(crate) const SPRITE: usize = 1;
+    pub(crate) const STAGE: usize = 0;
+
+    pub(crate) fn new_runtime() -> Runtime<'static> {
+        let mut runtime = Runtime::new(Box::new(MemoryBackend::default()));
+        runtime.new_project().unwrap();
+        runtime
+    }
+
+    /// a flag hat with a move block under it
+    pub(crate) fn create_script(runtime: &mut Runtime) -> (String, String) {
+        let hat_id = runtime
+            .create_single_block("event_whenflagclicked")
+            .unwrap();
+        let (move_id, _) = runtime.create_block_template("motion_movesteps").unwrap();
+        runtime.attach_next(&move_id, &hat_id).unwrap();
+        runtime.slide_block_to(&hat_id, 3, 4).unwrap();
+        runtime.checkpoint();
+        (hat_id, move_id)
+    }
+
+    /// writes the project out and opens it again in a fresh editor
+    pub(crate) fn round_trip(runtime: &mut Runtime, name: &str) -> Runtime<'static> {
+        let path = temp_dir().join(format!("viiru-{name}-{}.sb3", std::process::id()));
+        let path = path.to_str().unwrap();
+        assert!(runtime.save_project(path).unwrap());
+        let mut loaded = new_runtime();
+        assert!(loaded.load_project(path).unwrap());
+        let _ = std::fs::remove_file(path);
+        loaded
+    }
+
+    #[test]
+    fn broken_files_say_what_is_wrong() {
+        let path = temp_dir().join(format!("viiru-broken-{}.sb3", std::process::id()));
+        std::fs::write(&path, "not a zip").unwrap();
+        let mut runtime = new_runtime();
+        let loaded = runtime.load_project(path.to_str().unwrap());
+        let _ = std::fs::remove_file(&path);
+        assert!(matches!(loaded, Err(ViiruError::ZipError(_))));
+    }
+
+    #[test]
+    fn scripts_survive_a_round_trip() {
+        let mut runtime = new_runtime();
+        let (hat_id, move_id) = create_script(&mut runtime);
+        let loaded = round_trip(&mut runtime, "scripts");
+
+        let hat = &loaded.blocks[&hat_id];
+        assert_eq!(hat.opcode, "event_whenflagclicked");
+        assert_eq!((hat.x, hat.y), (3, 4));
+        assert_eq!(hat.next_id.as_deref(), Some(move_id.as_str()));
+        let steps = &loaded.blocks[&move_id].inputs["STEPS"];
+        assert_eq!(steps.block_id, None);
+        let number = &loaded.blocks[steps.shadow_id.as_ref().unwrap()];
+        assert!(number.is_shadow);
+        assert_eq!(number.fields["NUM"].value, "10");
+    }
+
+    #[test]
+    fn created_shadows_stay_shadows() {
+        let mut runtime = new_runtime();
+        let shadow_id = runtime.create_single_block("math_number").unwrap();
+        let block_id = runtime.create_single_block("motion_xposition").unwrap();
+        let loaded = round_trip(&mut runtime, "shadows");
+
+        assert!(loaded.blocks[&shadow_id].is_shadow);
+        assert!(!loaded.blocks[&block_id].is_shadow);
+    }
+
+    #[test]
+    fn deleting_takes_the_rest_of_the_stack() {
+        let mut runtime = new_runtime();
+        let (hat_id, move_id) = create_script(&mut runtime);
+        runtime.delete_block(&hat_id).unwrap();
+        runtime.checkpoint();
+        assert!(!runtime.blocks.contains_key(&move_id));
+
+        let loaded = round_trip(&mut runtime, "delete");
+        assert!(!loaded.blocks.contains_key(&hat_id));
+        assert!(!loaded.blocks.contains_key(&move_id));
+    }
+}
@@ -10,8 +10,12 @@ pub struct Block {
     pub opcode: String,
     pub parent_id: Option<String>,
     pub next_id: Option<String>,
+    /// shadows fill an input until something is dropped over them
+    pub is_shadow: bool,
     pub inputs: HashMap<String, Input>,
     pub fields: HashMap<String, Field>,
+    /// only custom block prototypes and calls carry one
+    pub mutation: Option<Mutation>,
 }
 
 impl Block {
@@ -69,13 +73,120 @@ impl Block {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Default)]
 pub struct Input {
     pub shadow_id: Option<String>,
     pub block_id: Option<String>,
 }
 
-#[derive(Clone)]
+/// Describes a custom block. Arguments are `%s` (string or number) or `%b` (boolean)
+/// in the proccode, and each one is the input named by its id.
+#[derive(Clone, Default, PartialEq)]
+pub struct Mutation {
+    pub proccode: String,
+    pub argument_ids: Vec<String>,
+    /// only stored on the prototype
+    pub argument_names: Vec<String>,
+    /// only stored on the prototype
+    pub argument_defaults: Vec<String>,
+    /// run without screen refresh
+    pub warp: bool,
+}
+
+impl Mutation {
+    /// whether each argument is a boolean, in order
+    pub fn argument_kinds(&self) -> Vec<bool> {
+        self.proccode
+            .split('%')
+            .skip(1)
+            .filter_map(|part| match part.chars().next() {
+                Some('s' | 'n') => Some(false),
+                Some('b') => Some(true),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// the inverse of `parse_signature`, for editing an existing custom block
+    pub fn signature(&self) -> String {
+        let mut names = self.argument_names.iter();
+        let mut words = vec![];
+        for word in self.proccode.split(' ') {
+            let mut name = || names.next().cloned().unwrap_or_default();
+            match word {
+                "%s" | "%n" => words.push(format!("({})", name())),
+                "%b" => words.push(format!("<{}>", name())),
+                _ => words.push(word.to_string()),
+            }
+        }
+        if self.warp {
+            words.push("!".to_string());
+        }
+        words.join(" ")
+    }
+}
+
+pub struct Signature {
+    pub proccode: String,
+    /// names, and whether each argument is a boolean
+    pub arguments: Vec<(String, bool)>,
+    pub warp: bool,
+}
+
+/// Parses a custom block signature such as `jump (height) <fast> !`, where `(name)` is a string or
+/// number argument, `<name>` is a boolean argument and a trailing `!` runs it without screen refresh.
+pub fn parse_signature(signature: &str) -> Option<Signature> {
+    let mut signature = signature.trim();
+    let warp = signature.ends_with('!');
+    if warp {
+        signature = signature[..signature.len() - 1].trim_end();
+    }
+    let mut proccode = vec![];
+    let mut arguments = vec![];
+    let mut rest = signature;
+    loop {
+        rest = rest.trim_start();
+        if rest.is_empty() {
+            break;
+        }
+        let closing = match rest.chars().next() {
+            Some('(') => Some(')'),
+            Some('<') => Some('>'),
+            _ => None,
+        };
+        if let Some(closing) = closing {
+            let end = rest.find(closing)?;
+            let name = rest[1..end].trim();
+            if name.is_empty() {
+                return None;
+            }
+            let is_boolean = closing == '>';
+            proccode.push(if is_boolean { "%b" } else { "%s" }.to_string());
+            arguments.push((name.to_string(), is_boolean));
+            rest = &rest[end + 1..];
+        } else {
+            let end = rest.find([' ', '(', '<']).unwrap_or(rest.len());
+            let word = &rest[..end];
+            // stray brackets and percent signs would confuse the proccode
+            if word.contains([')', '>', '%']) {
+                return None;
+            }
+            proccode.push(word.to_string());
+            rest = &rest[end..];
+        }
+    }
+    // a block needs at least some text to be told apart from others
+    if !proccode.iter().any(|part| !part.starts_with('%')) {
+        return None;
+    }
+    Some(Signature {
+        proccode: proccode.join(" "),
+        arguments,
+        warp,
+    })
+}
+
+#[derive(Clone, Default)]
 pub struct Field {
     pub value: String,
     pub id: Option<String>,
@@ -2,7 +2,179 @@ use std::collections::HashMap;
 
 use neon::{prelude::*, types::function::Arguments};
 
-use crate::block::{Block, Field, Input};
+use crate::{
+    backend::{ProjectBackend, TargetInfo, VariableType},
+    block::{Block, Field, Input, Mutation},
+    project::{decode_mutation, Project},
+    result::ViiruResult,
+};
+
+/// Defers everything to the scratch-vm instance living on the Node.js side
+pub struct NeonBackend<'js, 'a> {
+    pub cx: &'a mut FunctionContext<'js>,
+    pub api: Handle<'js, JsObject>,
+}
+
+impl ProjectBackend for NeonBackend<'_, '_> {
+    fn new_project(&mut self) -> ViiruResult<()> {
+        // scratch-vm has no notion of an empty project, so it's handed the one we'd save
+        let json = serde_json::to_string(&Project::empty())?;
+        load_project_json(self.cx, self.api, &json)?;
+        Ok(())
+    }
+
+    fn load_project(&mut self, path: &str) -> ViiruResult<bool> {
+        Ok(load_project(self.cx, self.api, path)?.value(self.cx))
+    }
+
+    fn save_project(&mut self, path: &str) -> ViiruResult<bool> {
+        Ok(save_project(self.cx, self.api, path)?.value(self.cx))
+    }
+
+    fn create_block(&mut self, opcode: &str, is_shadow: bool, id: &str) -> ViiruResult<()> {
+        create_block(self.cx, self.api, opcode, is_shadow, Some(id))?;
+        Ok(())
+    }
+
+    fn delete_block(&mut self, id: &str) -> ViiruResult<()> {
+        delete_block(self.cx, self.api, id)?;
+        Ok(())
+    }
+
+    fn slide_block(&mut self, id: &str, x: i32, y: i32) -> ViiruResult<()> {
+        slide_block(self.cx, self.api, id, x, y)?;
+        Ok(())
+    }
+
+    fn attach_block(
+        &mut self,
+        id: &str,
+        parent_id: &str,
+        input_name: Option<&str>,
+        is_shadow: bool,
+    ) -> ViiruResult<()> {
+        attach_block(self.cx, self.api, id, parent_id, input_name, is_shadow)?;
+        Ok(())
+    }
+
+    fn detach_block(&mut self, id: &str) -> ViiruResult<()> {
+        detach_block(self.cx, self.api, id)?;
+        Ok(())
+    }
+
+    fn change_field(
+        &mut self,
+        block_id: &str,
+        field_name: &str,
+        text: &str,
+        data_id: Option<&str>,
+    ) -> ViiruResult<()> {
+        change_field(self.cx, self.api, block_id, field_name, text, data_id)?;
+        Ok(())
+    }
+
+    fn change_mutation(&mut self, block_id: &str, mutation: &Mutation) -> ViiruResult<()> {
+        change_mutation(self.cx, self.api, block_id, mutation)?;
+        Ok(())
+    }
+
+    fn get_targets(&mut self) -> ViiruResult<Vec<TargetInfo>> {
+        let targets: Handle<JsArray> = get_targets(self.cx, self.api)?;
+        let length = targets.len(self.cx);
+        let mut output = vec![];
+        for i in 0..length {
+            let target: Handle<JsObject> = targets.get(self.cx, i)?;
+            let name = str_value(self.cx, target, "name")?;
+            let is_stage: Handle<JsBoolean> = target.get(self.cx, "isStage")?;
+            let costumes = str_array_value(self.cx, target, "costumes")?;
+            let sounds = str_array_value(self.cx, target, "sounds")?;
+            let variables = str_array_value(self.cx, target, "variables")?;
+            output.push(TargetInfo {
+                name,
+                is_stage: is_stage.value(self.cx),
+                costumes,
+                sounds,
+                variables,
+            });
+        }
+        Ok(output)
+    }
+
+    fn get_editing_target(&mut self) -> ViiruResult<usize> {
+        let index = get_editing_target(self.cx, self.api)?.value(self.cx);
+        // the VM reports -1 if nothing is being edited
+        Ok(index.max(0.0) as usize)
+    }
+
+    fn set_editing_target(&mut self, index: usize) -> ViiruResult<()> {
+        set_editing_target(self.cx, self.api, index)?;
+        Ok(())
+    }
+
+    fn get_all_blocks(&mut self) -> ViiruResult<HashMap<String, Block>> {
+        let blocks: Handle<JsObject> = get_all_blocks(self.cx, self.api)?;
+        Ok(map_each_value(self.cx, blocks, |cx, obj| {
+            to_block(cx, obj)
+        })?)
+    }
+
+    fn get_variables_of_type(
+        &mut self,
+        variable_type: VariableType,
+    ) -> ViiruResult<HashMap<String, String>> {
+        let vars: Handle<JsObject> = get_variables_of_type(self.cx, self.api, variable_type)?;
+        Ok(map_each_value(self.cx, vars, |cx, obj| {
+            Ok(string_of(cx, obj))
+        })?)
+    }
+
+    fn get_project(&mut self) -> ViiruResult<Project> {
+        let json = get_project_json(self.cx, self.api)?.value(self.cx);
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    fn create_variable(
+        &mut self,
+        variable_type: VariableType,
+        id: &str,
+        name: &str,
+        is_local: bool,
+        is_cloud: bool,
+    ) -> ViiruResult<()> {
+        create_variable(
+            self.cx,
+            self.api,
+            variable_type,
+            id,
+            name,
+            is_local,
+            is_cloud,
+        )?;
+        Ok(())
+    }
+
+    fn rename_variable(&mut self, id: &str, name: &str) -> ViiruResult<()> {
+        rename_variable(self.cx, self.api, id, name)?;
+        Ok(())
+    }
+
+    fn delete_variable(&mut self, id: &str) -> ViiruResult<()> {
+        delete_variable(self.cx, self.api, id)?;
+        Ok(())
+    }
+
+    fn get_extensions(&mut self) -> ViiruResult<Vec<String>> {
+        let extensions = get_extensions(self.cx, self.api)?;
+        Ok((0..extensions.len(self.cx))
+            .map(|i| str_index(self.cx, extensions, i))
+            .collect::<NeonResult<_>>()?)
+    }
+
+    fn enable_extension(&mut self, id: &str) -> ViiruResult<()> {
+        enable_extension(self.cx, self.api, id)?;
+        Ok(())
+    }
+}
 
 pub fn string_of(cx: &mut FunctionContext, s: Handle<JsString>) -> String {
     String::from_utf16(&s.to_utf16(cx)).unwrap()
@@ -17,6 +189,17 @@ fn str_value<'js>(
     Ok(string_of(cx, handle))
 }
 
+fn str_array_value<'js>(
+    cx: &mut FunctionContext<'js>,
+    object: Handle<'js, JsObject>,
+    key: &str,
+) -> NeonResult<Vec<String>> {
+    let array: Handle<JsArray> = object.get(cx, key)?;
+    (0..array.len(cx))
+        .map(|i| str_index(cx, array, i))
+        .collect()
+}
+
 // funny that this is the exact same implementation, but `.get` isn't polymorphic over self
 fn str_index<'js>(
     cx: &mut FunctionContext<'js>,
@@ -82,6 +265,10 @@ pub fn to_block<'js>(
     let opcode = str_value(cx, object, "opcode")?;
     let parent_id = optional_str_value(cx, object, "parent")?;
     let next_id = optional_str_value(cx, object, "next")?;
+    let shadow_value = object.get_value(cx, "shadow")?;
+    let is_shadow = shadow_value
+        .downcast::<JsBoolean, _>(cx)
+        .is_ok_and(|shadow| shadow.value(cx));
 
     let inputs_obj: Handle<JsObject> = object.get(cx, "inputs")?;
     let inputs = map_each_value(cx, inputs_obj, |cx, input| {
@@ -101,20 +288,45 @@ pub fn to_block<'js>(
         Ok(Field { value, id })
     })?;
 
+    // only custom blocks have mutations we care about
+    let mutation_value = object.get_value(cx, "mutation")?;
+    let mutation = if let Ok(mutation_obj) = mutation_value.downcast::<JsObject, _>(cx) {
+        let mut attributes = serde_json::Map::new();
+        for key in [
+            "proccode",
+            "argumentids",
+            "argumentnames",
+            "argumentdefaults",
+            "warp",
+        ] {
+            let value = mutation_obj.get_value(cx, key)?;
+            if let Ok(value) = value.downcast::<JsString, _>(cx) {
+                attributes.insert(key.into(), string_of(cx, value).into());
+            } else if let Ok(value) = value.downcast::<JsBoolean, _>(cx) {
+                attributes.insert(key.into(), value.value(cx).into());
+            }
+        }
+        decode_mutation(&serde_json::Value::Object(attributes))
+    } else {
+        None
+    };
+
     // 50px per cell
     let x = optional_num_value_or_zero(cx, object, "x")? / 50.0;
     let y = optional_num_value_or_zero(cx, object, "y")? / 50.0;
 
     Ok(Block {
-        x: x as i32,
-        y: y as i32,
+        x: x.round() as i32,
+        y: y.round() as i32,
         offset_x: 0,
         offset_y: 0,
         opcode,
         parent_id,
         next_id,
+        is_shadow,
         inputs,
         fields,
+        mutation,
     })
 }
 
@@ -154,6 +366,16 @@ pub fn load_project<'js>(
     Ok(result)
 }
 
+pub fn load_project_json<'js>(
+    cx: &mut FunctionContext<'js>,
+    api: Handle<JsObject>,
+    json: &str,
+) -> JsResult<'js, JsBoolean> {
+    let args = args!(cx; cx.string(json));
+    let result = api_call(cx, api, "loadProjectJson", args)?;
+    Ok(result)
+}
+
 pub fn save_project<'js>(
     cx: &mut FunctionContext<'js>,
     api: Handle<JsObject>,
@@ -249,7 +471,54 @@ pub fn change_field<'js>(
     api_call(cx, api, "changeField", args)
 }
 
-// todo: ChangeMutation(String, ())
+// the VM parses mutations from scratch-blocks' XML
+pub fn change_mutation<'js>(
+    cx: &mut FunctionContext<'js>,
+    api: Handle<JsObject>,
+    block_id: &str,
+    mutation: &Mutation,
+) -> JsResult<'js, JsUndefined> {
+    let escape = |s: &str| {
+        s.replace('&', "&amp;")
+            .replace('"', "&quot;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+    };
+    let list = |list: &Vec<String>| escape(&serde_json::to_string(list).unwrap());
+    let xml = format!(
+        r#"<mutation proccode="{}" argumentids="{}" argumentnames="{}" argumentdefaults="{}" warp="{}"></mutation>"#,
+        escape(&mutation.proccode),
+        list(&mutation.argument_ids),
+        list(&mutation.argument_names),
+        list(&mutation.argument_defaults),
+        mutation.warp,
+    );
+    let args = args!(cx; cx.string(block_id), cx.string(xml));
+    api_call(cx, api, "changeMutation", args)
+}
+
+pub fn get_targets<'js>(
+    cx: &mut FunctionContext<'js>,
+    api: Handle<JsObject>,
+) -> JsResult<'js, JsArray> {
+    api_call(cx, api, "getTargets", ())
+}
+
+pub fn get_editing_target<'js>(
+    cx: &mut FunctionContext<'js>,
+    api: Handle<JsObject>,
+) -> JsResult<'js, JsNumber> {
+    api_call(cx, api, "getEditingTarget", ())
+}
+
+pub fn set_editing_target<'js>(
+    cx: &mut FunctionContext<'js>,
+    api: Handle<JsObject>,
+    index: usize,
+) -> JsResult<'js, JsUndefined> {
+    let args = args!(cx; cx.number(index as f64));
+    api_call(cx, api, "setEditingTarget", args)
+}
 
 pub fn get_all_blocks<'js>(
     cx: &mut FunctionContext<'js>,
@@ -258,10 +527,13 @@ pub fn get_all_blocks<'js>(
     api_call(cx, api, "getAllBlocks", ())
 }
 
-pub enum VariableType {
-    Scalar,
-    List,
-    Broadcast,
+/// the VM's name for each type
+fn variable_type_name(variable_type: VariableType) -> &'static str {
+    match variable_type {
+        VariableType::Scalar => "",
+        VariableType::List => "list",
+        VariableType::Broadcast => "broadcast_msg",
+    }
 }
 
 pub fn get_variables_of_type<'js>(
@@ -269,11 +541,67 @@ pub fn get_variables_of_type<'js>(
     api: Handle<JsObject>,
     variable_type: VariableType,
 ) -> JsResult<'js, JsObject> {
-    let s = match variable_type {
-        VariableType::Scalar => "",
-        VariableType::List => "list",
-        VariableType::Broadcast => "broadcast_msg",
-    };
-    let args = args!(cx; cx.string(s));
+    let args = args!(cx; cx.string(variable_type_name(variable_type)));
     api_call(cx, api, "getVariablesOfType", args)
 }
+
+pub fn get_project_json<'js>(
+    cx: &mut FunctionContext<'js>,
+    api: Handle<JsObject>,
+) -> JsResult<'js, JsString> {
+    api_call(cx, api, "getProjectJson", ())
+}
+
+pub fn create_variable<'js>(
+    cx: &mut FunctionContext<'js>,
+    api: Handle<JsObject>,
+    variable_type: VariableType,
+    id: &str,
+    name: &str,
+    is_local: bool,
+    is_cloud: bool,
+) -> JsResult<'js, JsUndefined> {
+    let args = args!(cx;
+        cx.string(variable_type_name(variable_type)),
+        cx.string(id),
+        cx.string(name),
+        cx.boolean(is_local),
+        cx.boolean(is_cloud),
+    );
+    api_call(cx, api, "createVariable", args)
+}
+
+pub fn rename_variable<'js>(
+    cx: &mut FunctionContext<'js>,
+    api: Handle<JsObject>,
+    id: &str,
+    name: &str,
+) -> JsResult<'js, JsUndefined> {
+    let args = args!(cx; cx.string(id), cx.string(name));
+    api_call(cx, api, "renameVariable", args)
+}
+
+pub fn delete_variable<'js>(
+    cx: &mut FunctionContext<'js>,
+    api: Handle<JsObject>,
+    id: &str,
+) -> JsResult<'js, JsUndefined> {
+    let args = args!(cx; cx.string(id));
+    api_call(cx, api, "deleteVariable", args)
+}
+
+pub fn get_extensions<'js>(
+    cx: &mut FunctionContext<'js>,
+    api: Handle<JsObject>,
+) -> JsResult<'js, JsArray> {
+    api_call(cx, api, "getExtensions", ())
+}
+
+pub fn enable_extension<'js>(
+    cx: &mut FunctionContext<'js>,
+    api: Handle<JsObject>,
+    id: &str,
+) -> JsResult<'js, JsUndefined> {
+    let args = args!(cx; cx.string(id));
+    api_call(cx, api, "enableExtension", args)
+}
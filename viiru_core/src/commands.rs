@@ -0,0 +1,546 @@
+//! The `:` command line, for whatever needs an argument: `:e path`, `:w [path]`, `:wq`, `:q!`,
+//! `:target Sprite1`, `:goto x y`, `:find text` and `:set option=value`. Every action can be
+//! run by name as well, like `:toggle-toolbox`.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    actions::{open_project, perform, write_project, Action, Outcome, ACTIONS},
+    opcodes::BLOCKS,
+    references::Reference,
+    result::ViiruResult,
+    runtime::{ReferenceList, Runtime, State},
+    scratchblocks::block_text,
+    spec::Shape,
+    ui::Screen,
+    util::state_dir,
+};
+
+/// how many commands are remembered between sessions
+const HISTORY_LENGTH: usize = 500;
+
+/// what a command's argument is, for completing it
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Argument {
+    Nothing,
+    Path,
+    Target,
+    Option,
+    Text,
+}
+
+const COMMANDS: &[(&str, Argument)] = &[
+    ("e", Argument::Path),
+    ("e!", Argument::Path),
+    ("w", Argument::Path),
+    ("wq", Argument::Path),
+    ("q", Argument::Nothing),
+    ("q!", Argument::Nothing),
+    ("target", Argument::Target),
+    ("goto", Argument::Text),
+    ("find", Argument::Text),
+    ("set", Argument::Option),
+];
+
+/// the settings `:set` can change
+const OPTIONS: &[&str] = &["readonly", "status_height", "toolbox_width"];
+
+/// Tab cycles through everything the word under completion could be
+pub struct Completion {
+    /// the command line up to the word being completed
+    pub prefix: String,
+    pub candidates: Vec<String>,
+    pub index: usize,
+}
+
+/// Runs a command line, which has already left `State::Command`
+pub fn execute(runtime: &mut Runtime, screen: &mut Screen, line: &str) -> ViiruResult<Outcome> {
+    let line = line.trim();
+    let (name, argument) = match line.split_once(char::is_whitespace) {
+        Some((name, argument)) => (name, argument.trim()),
+        None => (line, ""),
+    };
+    let path = (!argument.is_empty())
+        .then(|| argument.to_string())
+        .or_else(|| runtime.project_path.clone());
+    match name {
+        "" => (),
+        "e" | "e!" if argument.is_empty() => {
+            runtime.status_message = "Usage: :e path".into();
+        }
+        "e" if runtime.is_dirty() => {
+            runtime.status_message = "Unsaved changes. (:e! to discard them)".into();
+        }
+        "e" | "e!" => open_project(runtime, argument)?,
+        "w" | "wq" if path.is_none() => {
+            runtime.status_message = "No file name. (:w path)".into();
+        }
+        "w" => {
+            write_project(runtime, &path.unwrap())?;
+        }
+        "wq" => {
+            if write_project(runtime, &path.unwrap())? {
+                return Ok(Outcome::Quit);
+            }
+        }
+        "q" if runtime.is_dirty() => {
+            runtime.status_message = "Unsaved changes. (:q! to force)".into();
+        }
+        "q" | "q!" => return Ok(Outcome::Quit),
+        "target" => match find_target(runtime, argument) {
+            Some(index) => {
+                runtime.switch_target(index)?;
+                runtime.status_message = format!("Editing {}", runtime.editing_target_name());
+            }
+            None => runtime.status_message = format!("No sprite called `{argument}`"),
+        },
+        "goto" => {
+            let coordinates: Vec<_> = argument.split_whitespace().map(str::parse).collect();
+            match coordinates[..] {
+                [Ok(x), Ok(y)] => runtime.jump_to(x, y),
+                _ => runtime.status_message = "Usage: :goto x y".into(),
+            }
+        }
+        "find" if argument.is_empty() => {
+            runtime.status_message = "Usage: :find text".into();
+        }
+        "find" => {
+            let list = find_text(runtime, argument);
+            runtime.status_message = format!("{} {}", list.references.len(), list.title);
+            runtime.reference_list = Some(list);
+            runtime.state = State::References;
+        }
+        "set" => set_option(runtime, argument),
+        _ => match Action::from_name(name) {
+            Some(action) => return perform(action, runtime, screen),
+            None => runtime.status_message = format!("Not a command: {name}"),
+        },
+    }
+    Ok(Outcome::Redraw)
+}
+
+/// an exact name first, ignoring case, and then the only one starting with it
+fn find_target(runtime: &Runtime, name: &str) -> Option<usize> {
+    let name = name.to_lowercase();
+    let names: Vec<_> = runtime
+        .targets
+        .iter()
+        .map(|target| target.name.to_lowercase())
+        .collect();
+    if let Some(index) = names.iter().position(|target| *target == name) {
+        return Some(index);
+    }
+    let mut starting = names
+        .iter()
+        .enumerate()
+        .filter(|(_, t)| t.starts_with(&name));
+    match (starting.next(), starting.next()) {
+        (Some((index, _)), None) => Some(index),
+        _ => None,
+    }
+}
+
+/// Every block in the editing target's scripts whose text contains the search, ignoring case.
+/// Reporters are part of the text of the block holding them, so only the innermost match counts.
+fn find_text(runtime: &Runtime, text: &str) -> ReferenceList {
+    let needle = text.to_lowercase();
+    let matches = |id: &str| block_text(runtime, id).to_lowercase().contains(&needle);
+    let mut tops: Vec<_> = runtime
+        .top_level
+        .iter()
+        .filter(|id| runtime.blocks.contains_key(*id))
+        .collect();
+    tops.sort_by_key(|id| (runtime.blocks[*id].y, runtime.blocks[*id].x));
+    let mut references = vec![];
+    for top_id in tops {
+        let mut pending = vec![top_id.clone()];
+        while let Some(id) = pending.pop() {
+            let block = &runtime.blocks[&id];
+            // shadows are already covered by the text of their parent
+            let mut input_names: Vec<_> = block
+                .inputs
+                .iter()
+                .filter(|(_, input)| input.block_id.is_some() && input.block_id != input.shadow_id)
+                .map(|(name, _)| name)
+                .collect();
+            input_names.sort();
+            let children: Vec<_> = input_names
+                .into_iter()
+                .filter_map(|name| block.inputs[name].block_id.clone())
+                .collect();
+            let is_reporter = |id: &String| {
+                BLOCKS
+                    .get(&runtime.blocks[id].opcode)
+                    .map(|spec| spec.shape)
+                    != Some(Shape::Stack)
+            };
+            if matches(&id)
+                && !children
+                    .iter()
+                    .filter(|id| is_reporter(id))
+                    .any(|id| matches(id))
+            {
+                references.push(Reference {
+                    target: runtime.editing_target,
+                    block_id: id.clone(),
+                    opcode: block.opcode.clone(),
+                    role: "contains",
+                });
+            }
+            pending.extend(block.next_id.iter().cloned());
+            pending.extend(children.into_iter().rev());
+        }
+    }
+    ReferenceList {
+        title: format!("blocks containing `{text}`"),
+        name: text.to_string(),
+        references,
+        cursor: 0,
+        deleting: None,
+    }
+}
+
+fn set_option(runtime: &mut Runtime, argument: &str) {
+    let Some((option, value)) = argument.split_once('=') else {
+        runtime.status_message = format!("Usage: :set option=value ({})", OPTIONS.join(", "));
+        return;
+    };
+    let (option, value) = (option.trim(), value.trim());
+    match option {
+        "readonly" => match value.parse() {
+            Ok(readonly) => runtime.readonly = readonly,
+            Err(_) => runtime.status_message = "readonly is either true or false".into(),
+        },
+        "toolbox_width" | "status_height" => {
+            let Ok(size) = value.parse::<u16>() else {
+                runtime.status_message = format!("{option} takes a number");
+                return;
+            };
+            // the scripts need at least a column and a row to themselves
+            let largest = if option == "toolbox_width" {
+                runtime.window_cols as i32 - runtime.viewport_offset_x - 1
+            } else {
+                runtime.window_rows as i32 - runtime.viewport_offset_y - 1
+            };
+            if size as i32 > largest {
+                runtime.status_message =
+                    format!("{option} can be at most {} in this window", largest.max(0));
+                return;
+            }
+            if option == "toolbox_width" {
+                runtime.config.toolbox_width = size as i32;
+                runtime.toolbox_width = size as i32;
+            } else {
+                runtime.config.status_height = size as i32;
+                runtime.status_height = size as i32;
+            }
+            runtime.set_viewport(runtime.window_cols, runtime.window_rows);
+        }
+        _ => runtime.status_message = format!("No option called {option}"),
+    }
+}
+
+/// Completes the word at the end of the command line, or moves on to the next candidate
+pub fn complete(runtime: &mut Runtime) {
+    if let Some(completion) = &mut runtime.completion {
+        completion.index = (completion.index + 1) % completion.candidates.len();
+        runtime.command_buffer = format!(
+            "{}{}",
+            completion.prefix, completion.candidates[completion.index]
+        );
+        return;
+    }
+    let buffer = runtime.command_buffer.clone();
+    let (prefix, word, candidates) = match buffer.split_once(' ') {
+        None => {
+            let names = COMMANDS
+                .iter()
+                .map(|(name, _)| *name)
+                .chain(ACTIONS.iter().map(|info| info.name));
+            let candidates = names.filter(|name| name.starts_with(&buffer));
+            (
+                String::new(),
+                buffer.as_str(),
+                candidates.map(String::from).collect(),
+            )
+        }
+        Some((name, argument)) => {
+            let kind = COMMANDS
+                .iter()
+                .find(|(command, _)| *command == name)
+                .map_or(Argument::Nothing, |(_, kind)| *kind);
+            let candidates = argument_candidates(runtime, kind, argument);
+            (format!("{name} "), argument, candidates)
+        }
+    };
+    let mut candidates: Vec<_> = candidates
+        .into_iter()
+        .filter(|candidate| candidate.starts_with(word) && candidate != word)
+        .collect();
+    candidates.sort();
+    candidates.dedup();
+    if candidates.is_empty() {
+        return;
+    }
+    runtime.command_buffer = format!("{prefix}{}", candidates[0]);
+    // a single candidate is simply filled in
+    if candidates.len() > 1 {
+        runtime.completion = Some(Completion {
+            prefix,
+            candidates,
+            index: 0,
+        });
+    }
+}
+
+fn argument_candidates(runtime: &Runtime, kind: Argument, argument: &str) -> Vec<String> {
+    match kind {
+        Argument::Path => path_candidates(argument),
+        Argument::Target => runtime
+            .targets
+            .iter()
+            .map(|target| target.name.clone())
+            .collect(),
+        Argument::Option => OPTIONS.iter().map(|option| format!("{option}=")).collect(),
+        Argument::Nothing | Argument::Text => vec![],
+    }
+}
+
+/// the files and directories next to the path, with directories ending in a slash
+fn path_candidates(path: &str) -> Vec<String> {
+    let (dir, file) = match path.rfind('/') {
+        Some(slash) => (&path[..=slash], &path[slash + 1..]),
+        None => ("", path),
+    };
+    let Ok(entries) = fs::read_dir(if dir.is_empty() { "." } else { dir }) else {
+        return vec![];
+    };
+    entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            // hidden files only when asked for
+            if name.starts_with('.') && !file.starts_with('.') {
+                return None;
+            }
+            let is_dir = entry.file_type().is_ok_and(|kind| kind.is_dir());
+            Some(format!("{dir}{name}{}", if is_dir { "/" } else { "" }))
+        })
+        .collect()
+}
+
+fn history_path() -> Option<PathBuf> {
+    state_dir().map(|dir| dir.join("history"))
+}
+
+/// the commands run in earlier sessions, oldest first
+pub fn load_history() -> Vec<String> {
+    history_path()
+        .map(|path| read_history(&path))
+        .unwrap_or_default()
+}
+
+fn read_history(path: &Path) -> Vec<String> {
+    let Ok(text) = fs::read_to_string(path) else {
+        return vec![];
+    };
+    text.lines().map(String::from).collect()
+}
+
+/// Remembers a command, both for this session and the next ones
+pub fn add_to_history(runtime: &mut Runtime, line: &str) {
+    if !remember(&mut runtime.command_history, line) {
+        return;
+    }
+    if let Some(path) = history_path() {
+        write_history(&path, &runtime.command_history);
+    }
+}
+
+/// adds the line unless it's blank or the same as the last one, returning whether it was added
+fn remember(history: &mut Vec<String>, line: &str) -> bool {
+    if line.trim().is_empty() || history.last().is_some_and(|last| last == line) {
+        return false;
+    }
+    history.push(line.to_string());
+    let overflow = history.len().saturating_sub(HISTORY_LENGTH);
+    history.drain(..overflow);
+    true
+}
+
+fn write_history(path: &Path, history: &[String]) {
+    // losing the history isn't worth interrupting anyone over
+    if let Some(dir) = path.parent() {
+        let _ = fs::create_dir_all(dir);
+    }
+    let _ = fs::write(path, history.join("\n") + "\n");
+}
+
+/// Steps through the history, with `None` being the line that was being typed
+pub fn browse_history(runtime: &mut Runtime, older: bool) {
+    let len = runtime.command_history.len();
+    let index = match (runtime.history_cursor, older) {
+        (None, true) if len > 0 => Some(len - 1),
+        (None, _) => return,
+        (Some(index), true) => Some(index.saturating_sub(1)),
+        (Some(index), false) if index + 1 < len => Some(index + 1),
+        (Some(_), false) => None,
+    };
+    if runtime.history_cursor.is_none() {
+        runtime.history_draft = runtime.command_buffer.clone();
+    }
+    runtime.history_cursor = index;
+    runtime.command_buffer = match index {
+        Some(index) => runtime.command_history[index].clone(),
+        None => std::mem::take(&mut runtime.history_draft),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env::temp_dir;
+
+    use super::*;
+    use crate::backend::{
+        tests::{new_runtime, SPRITE, STAGE},
+        VariableType,
+    };
+
+    fn run(runtime: &mut Runtime, line: &str) {
+        let mut screen = Screen::new(80, 24);
+        execute(runtime, &mut screen, line).unwrap();
+    }
+
+    /// types the text into the command line and presses tab
+    fn completed(runtime: &mut Runtime, text: &str) -> String {
+        runtime.completion = None;
+        runtime.command_buffer = text.into();
+        complete(runtime);
+        runtime.command_buffer.clone()
+    }
+
+    #[test]
+    fn tab_cycles_through_commands() {
+        let mut runtime = new_runtime();
+        assert_eq!(completed(&mut runtime, "ta"), "target");
+        assert_eq!(completed(&mut runtime, "wr"), "write");
+        // w itself is complete already, so it goes on to the longer ones
+        assert_eq!(completed(&mut runtime, "w"), "wq");
+        complete(&mut runtime);
+        assert_eq!(runtime.command_buffer, "write");
+        complete(&mut runtime);
+        assert_eq!(runtime.command_buffer, "wq");
+        assert_eq!(completed(&mut runtime, "nonsense"), "nonsense");
+    }
+
+    #[test]
+    fn tab_completes_arguments_by_kind() {
+        let mut runtime = new_runtime();
+        assert_eq!(completed(&mut runtime, "target Spr"), "target Sprite1");
+        assert_eq!(completed(&mut runtime, "set tool"), "set toolbox_width=");
+        // text is searched for as it is, so there's nothing to complete it to
+        runtime
+            .create_data(VariableType::Scalar, "score", false, false)
+            .unwrap();
+        assert_eq!(completed(&mut runtime, "find sc"), "find sc");
+        assert_eq!(completed(&mut runtime, "q sc"), "q sc");
+    }
+
+    #[test]
+    fn arguments_are_split_off_at_the_first_space() {
+        let mut runtime = new_runtime();
+        run(&mut runtime, "  goto   3   -4  ");
+        assert_eq!((runtime.cursor_x, runtime.cursor_y), (3, -4));
+        run(&mut runtime, "goto 3");
+        assert_eq!(runtime.status_message, "Usage: :goto x y");
+
+        run(&mut runtime, "target  stage ");
+        assert_eq!(runtime.editing_target, STAGE);
+        run(&mut runtime, "target sprite");
+        assert_eq!(runtime.editing_target, SPRITE);
+        run(&mut runtime, "target nobody");
+        assert_eq!(runtime.status_message, "No sprite called `nobody`");
+
+        run(&mut runtime, "find ");
+        assert_eq!(runtime.status_message, "Usage: :find text");
+        run(&mut runtime, "find move steps");
+        let list = runtime.reference_list.as_ref().unwrap();
+        assert_eq!(list.title, "blocks containing `move steps`");
+        assert!(runtime.state == State::References);
+
+        run(&mut runtime, "fly");
+        assert_eq!(runtime.status_message, "Not a command: fly");
+    }
+
+    #[test]
+    fn sizes_have_to_leave_room_for_the_scripts() {
+        let mut runtime = new_runtime();
+        runtime.set_viewport(80, 24);
+        run(&mut runtime, "set toolbox_width=30");
+        assert_eq!(runtime.viewport.x_max, 50);
+
+        run(&mut runtime, "set toolbox_width=80");
+        assert_eq!(
+            runtime.status_message,
+            "toolbox_width can be at most 79 in this window"
+        );
+        assert_eq!(runtime.viewport.x_max, 50);
+        run(&mut runtime, "set status_height=24");
+        assert_eq!(
+            runtime.status_message,
+            "status_height can be at most 23 in this window"
+        );
+        run(&mut runtime, "set status_height=tall");
+        assert_eq!(runtime.status_message, "status_height takes a number");
+        run(&mut runtime, "set status_height = 4");
+        assert_eq!(runtime.viewport.y_max, 20);
+    }
+
+    #[test]
+    fn history_skips_blanks_and_repeats() {
+        let mut history = vec![];
+        assert!(remember(&mut history, "w"));
+        assert!(!remember(&mut history, "w"));
+        assert!(!remember(&mut history, "   "));
+        assert!(remember(&mut history, "q"));
+        assert!(remember(&mut history, "w"));
+        assert_eq!(history, ["w", "q", "w"]);
+
+        for n in 0..HISTORY_LENGTH {
+            remember(&mut history, &format!("goto {n} 0"));
+        }
+        assert_eq!(history.len(), HISTORY_LENGTH);
+        assert_eq!(history[0], "goto 0 0");
+    }
+
+    #[test]
+    fn history_is_kept_between_sessions() {
+        let dir = temp_dir().join(format!("viiru-history-{}", std::process::id()));
+        let path = dir.join("state").join("history");
+        assert!(read_history(&path).is_empty());
+        let history = vec!["e project.sb3".to_string(), "goto 3 4".to_string()];
+        write_history(&path, &history);
+        assert_eq!(read_history(&path), history);
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn browsing_the_history_keeps_what_was_typed() {
+        let mut runtime = new_runtime();
+        runtime.command_history = vec!["w".into(), "q".into()];
+        runtime.command_buffer = "go".into();
+        browse_history(&mut runtime, true);
+        assert_eq!(runtime.command_buffer, "q");
+        browse_history(&mut runtime, true);
+        browse_history(&mut runtime, true);
+        assert_eq!(runtime.command_buffer, "w");
+        browse_history(&mut runtime, false);
+        assert_eq!(runtime.command_buffer, "q");
+        browse_history(&mut runtime, false);
+        assert_eq!(runtime.command_buffer, "go");
+        assert_eq!(runtime.history_cursor, None);
+    }
+}
@@ -0,0 +1,568 @@
+//! User settings from `~/.config/viiru/config.toml`: key bindings, layout sizes and colours.
+//! Everything is optional, and missing settings keep their defaults.
+
+use std::{collections::HashMap, fs, io, path::Path};
+
+use crossterm::{
+    event::{KeyCode, KeyEvent, KeyModifiers},
+    style::Color,
+};
+use indexmap::IndexMap;
+use serde::Deserialize;
+
+use crate::{
+    actions::Action,
+    result::{ViiruError, ViiruResult},
+    util::{config_dir, parse_rgb},
+};
+
+/// the bindings of the editor, which the config file adds to or replaces
+const DEFAULT_KEYS: &[(&str, &str)] = &[
+    ("q", "quit"),
+    ("Q", "force-quit"),
+    ("o", "open"),
+    ("w", "write"),
+    ("u", "undo"),
+    ("ctrl-r", "redo"),
+    ("c", "custom-block"),
+    ("y", "copy"),
+    ("p", "paste"),
+    ("b", "breakpoint"),
+    ("e", "lint"),
+    ("v", "data"),
+    ("x", "extensions"),
+    ("f", "find-references"),
+    ("d", "go-to-definition"),
+    ("g", "run"),
+    ("h", "move-left"),
+    ("j", "move-down"),
+    ("k", "move-up"),
+    ("l", "move-right"),
+    ("H", "scroll-left"),
+    ("J", "scroll-down"),
+    ("K", "scroll-up"),
+    ("L", "scroll-right"),
+    ("t", "toggle-toolbox"),
+    ("T", "toggle-targets"),
+    ("s", "stamp"),
+    ("D", "delete"),
+    ("space", "pick-up"),
+    ("?", "help"),
+    (":", "command-line"),
+    ("/", "search-toolbox"),
+];
+
+/// the bindings used while a running project is paused, which can reuse the editor's keys
+const DEFAULT_DEBUGGER_KEYS: &[(&str, &str)] =
+    &[("n", "step"), ("o", "step-over"), ("c", "continue")];
+
+/// the bindings used while a project runs, ahead of the keys passed on to it
+const DEFAULT_RUN_KEYS: &[(&str, &str)] = &[("esc", "stop"), ("ctrl-g", "restart")];
+
+/// the bindings of the data pane, ahead of the editor's
+const DEFAULT_DATA_KEYS: &[(&str, &str)] = &[
+    ("v", "create-variable"),
+    ("V", "create-local-variable"),
+    ("C", "create-cloud-variable"),
+    ("l", "create-list"),
+    ("L", "create-local-list"),
+    ("b", "create-broadcast"),
+    ("r", "rename-data"),
+];
+
+pub type KeyBinding = (KeyCode, KeyModifiers);
+
+pub struct Config {
+    pub keys: HashMap<KeyBinding, Action>,
+    pub debugger_keys: HashMap<KeyBinding, Action>,
+    pub run_keys: HashMap<KeyBinding, Action>,
+    pub data_keys: HashMap<KeyBinding, Action>,
+    pub toolbox_width: i32,
+    pub status_height: i32,
+    pub colors: Theme,
+}
+
+/// the colours of the editor itself, as opposed to those of blocks
+pub struct Theme {
+    /// the frame around the viewport
+    pub border: Color,
+    /// the picked entry of a dropdown menu
+    pub selection: Color,
+    /// anything the linter found
+    pub lint: Color,
+    pub breakpoint: Color,
+    /// the block a paused project is about to run
+    pub paused: Color,
+    /// behind dropdowns whose costume or sound is gone
+    pub missing: Color,
+    /// the dots marking out the viewport
+    pub grid: Color,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            border: Color::Rgb {
+                r: 0x7f,
+                g: 0x3e,
+                b: 0xcf,
+            },
+            selection: Color::Rgb {
+                r: 0x7f,
+                g: 0x3e,
+                b: 0xcf,
+            },
+            lint: Color::Rgb {
+                r: 255,
+                g: 165,
+                b: 0,
+            },
+            breakpoint: Color::Red,
+            paused: Color::Yellow,
+            missing: Color::DarkRed,
+            grid: Color::DarkGrey,
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        let bindings = |defaults: &[(&str, &str)]| {
+            defaults
+                .iter()
+                .map(|(key, action)| (parse_key(key).unwrap(), Action::from_name(action).unwrap()))
+                .collect()
+        };
+        Config {
+            keys: bindings(DEFAULT_KEYS),
+            debugger_keys: bindings(DEFAULT_DEBUGGER_KEYS),
+            run_keys: bindings(DEFAULT_RUN_KEYS),
+            data_keys: bindings(DEFAULT_DATA_KEYS),
+            toolbox_width: 45,
+            status_height: 5,
+            colors: Theme::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Reads the config file at `path`, or the usual one if there isn't one given. Only the
+    /// usual one may be missing. Bindings that make no sense are left out and described in
+    /// the returned warning, since a typo there shouldn't keep the editor from starting.
+    pub fn load(path: Option<&Path>) -> ViiruResult<(Config, Option<String>)> {
+        let (path, text) = match path {
+            Some(path) => {
+                let text = fs::read_to_string(path).map_err(|err| {
+                    ViiruError::ConfigError(format!("could not read {}: {err}", path.display()))
+                })?;
+                (path.to_path_buf(), text)
+            }
+            None => {
+                let Some(path) = config_dir().map(|dir| dir.join("config.toml")) else {
+                    return Ok((Config::default(), None));
+                };
+                match fs::read_to_string(&path) {
+                    Ok(text) => (path, text),
+                    Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                        return Ok((Config::default(), None))
+                    }
+                    Err(err) => return Err(err.into()),
+                }
+            }
+        };
+        let (config, warnings) = Config::parse(&text).map_err(|err| {
+            ViiruError::ConfigError(format!("{}: {}", path.display(), err.trim_end()))
+        })?;
+        let warning =
+            (!warnings.is_empty()).then(|| format!("{}: {}", path.display(), warnings.join(", ")));
+        Ok((config, warning))
+    }
+
+    /// the settings of a config file on top of the defaults, along with anything left out
+    fn parse(text: &str) -> Result<(Config, Vec<String>), String> {
+        let mut config = Config::default();
+        let file: ConfigFile = toml::from_str(text).map_err(|err| err.to_string())?;
+
+        let mut warnings = vec![];
+        bind_keys(&mut config.keys, &file.keys, &mut warnings);
+        bind_keys(
+            &mut config.debugger_keys,
+            &file.debugger_keys,
+            &mut warnings,
+        );
+        bind_keys(&mut config.run_keys, &file.run_keys, &mut warnings);
+        bind_keys(&mut config.data_keys, &file.data_keys, &mut warnings);
+        if let Some(width) = file.toolbox_width {
+            config.toolbox_width = width as i32;
+        }
+        if let Some(height) = file.status_height {
+            config.status_height = height as i32;
+        }
+        let colors = &mut config.colors;
+        let overrides = [
+            (&mut colors.border, file.colors.border),
+            (&mut colors.selection, file.colors.selection),
+            (&mut colors.lint, file.colors.lint),
+            (&mut colors.breakpoint, file.colors.breakpoint),
+            (&mut colors.paused, file.colors.paused),
+            (&mut colors.missing, file.colors.missing),
+            (&mut colors.grid, file.colors.grid),
+        ];
+        for (color, value) in overrides {
+            if let Some(ColorDef(value)) = value {
+                *color = value;
+            }
+        }
+        Ok((config, warnings))
+    }
+
+    /// the keys bound to an action, written the way the config file does
+    pub fn keys_for(&self, action: Action) -> Vec<String> {
+        let mut keys: Vec<_> = self
+            .keys
+            .iter()
+            .chain(&self.debugger_keys)
+            .chain(&self.run_keys)
+            .chain(&self.data_keys)
+            .filter(|(_, bound)| **bound == action)
+            .map(|(binding, _)| key_name(*binding))
+            .collect();
+        // shortest first, so that plain keys come before ones with modifiers
+        keys.sort_by_key(|key| (key.len(), key.clone()));
+        keys
+    }
+
+    pub fn action_for(&self, event: &KeyEvent) -> Option<Action> {
+        self.keys.get(&binding_of(event)).copied()
+    }
+
+    /// what a key does while a running project is paused
+    pub fn debugger_action_for(&self, event: &KeyEvent) -> Option<Action> {
+        self.debugger_keys.get(&binding_of(event)).copied()
+    }
+
+    /// what a key does while a project is running, if it isn't passed on to the project
+    pub fn run_action_for(&self, event: &KeyEvent) -> Option<Action> {
+        self.run_keys.get(&binding_of(event)).copied()
+    }
+
+    /// what a key does in the data pane, before the editor's bindings are looked at
+    pub fn data_action_for(&self, event: &KeyEvent) -> Option<Action> {
+        self.data_keys.get(&binding_of(event)).copied()
+    }
+}
+
+fn binding_of(event: &KeyEvent) -> KeyBinding {
+    let mut modifiers = event.modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT);
+    // shifted letters come through as capitals already
+    if !matches!(event.code, KeyCode::Char(_)) {
+        modifiers |= event.modifiers & KeyModifiers::SHIFT;
+    }
+    (event.code, modifiers)
+}
+
+/// applies a table of bindings from the config file on top of the defaults
+fn bind_keys(
+    keys: &mut HashMap<KeyBinding, Action>,
+    table: &IndexMap<String, String>,
+    warnings: &mut Vec<String>,
+) {
+    for (key, action_name) in table {
+        let Some(binding) = parse_key(key) else {
+            warnings.push(format!("unknown key \"{key}\""));
+            continue;
+        };
+        match action_name.as_str() {
+            // lets a default binding be taken away without a replacement
+            "none" => {
+                keys.remove(&binding);
+            }
+            _ => match Action::from_name(action_name) {
+                Some(action) => {
+                    keys.insert(binding, action);
+                }
+                None => warnings.push(format!("unknown action \"{action_name}\" for {key}")),
+            },
+        }
+    }
+}
+
+/// Keys are written like `a`, `A`, `space`, `ctrl-r` or `alt-left`
+pub fn parse_key(key: &str) -> Option<KeyBinding> {
+    let mut modifiers = KeyModifiers::NONE;
+    let mut rest = key;
+    loop {
+        if let Some(after) = rest.strip_prefix("ctrl-") {
+            modifiers |= KeyModifiers::CONTROL;
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("alt-") {
+            modifiers |= KeyModifiers::ALT;
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("shift-").filter(|after| after.len() > 1) {
+            modifiers |= KeyModifiers::SHIFT;
+            rest = after;
+        } else {
+            break;
+        }
+    }
+    let code = match rest {
+        "space" => KeyCode::Char(' '),
+        "enter" => KeyCode::Enter,
+        "esc" => KeyCode::Esc,
+        "tab" => KeyCode::Tab,
+        "backspace" => KeyCode::Backspace,
+        "delete" => KeyCode::Delete,
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "pageup" => KeyCode::PageUp,
+        "pagedown" => KeyCode::PageDown,
+        _ => {
+            let mut chars = rest.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => KeyCode::Char(c),
+                (Some('f'), Some(_)) => KeyCode::F(rest[1..].parse().ok()?),
+                _ => return None,
+            }
+        }
+    };
+    Some((code, modifiers))
+}
+
+/// the opposite of [`parse_key`]
+pub fn key_name((code, modifiers): KeyBinding) -> String {
+    let mut name = String::new();
+    if modifiers.contains(KeyModifiers::CONTROL) {
+        name.push_str("ctrl-");
+    }
+    if modifiers.contains(KeyModifiers::ALT) {
+        name.push_str("alt-");
+    }
+    if modifiers.contains(KeyModifiers::SHIFT) {
+        name.push_str("shift-");
+    }
+    match code {
+        KeyCode::Char(' ') => name.push_str("space"),
+        KeyCode::Char(c) => name.push(c),
+        KeyCode::F(n) => name.push_str(&format!("f{n}")),
+        code => name.push_str(match code {
+            KeyCode::Enter => "enter",
+            KeyCode::Esc => "esc",
+            KeyCode::Tab => "tab",
+            KeyCode::Backspace => "backspace",
+            KeyCode::Delete => "delete",
+            KeyCode::Up => "up",
+            KeyCode::Down => "down",
+            KeyCode::Left => "left",
+            KeyCode::Right => "right",
+            KeyCode::Home => "home",
+            KeyCode::End => "end",
+            KeyCode::PageUp => "pageup",
+            KeyCode::PageDown => "pagedown",
+            _ => "?",
+        }),
+    }
+    name
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    /// key to action name
+    #[serde(default)]
+    keys: IndexMap<String, String>,
+    #[serde(default)]
+    debugger_keys: IndexMap<String, String>,
+    #[serde(default)]
+    run_keys: IndexMap<String, String>,
+    #[serde(default)]
+    data_keys: IndexMap<String, String>,
+    toolbox_width: Option<u16>,
+    status_height: Option<u16>,
+    #[serde(default)]
+    colors: ColorsFile,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct ColorsFile {
+    border: Option<ColorDef>,
+    selection: Option<ColorDef>,
+    lint: Option<ColorDef>,
+    breakpoint: Option<ColorDef>,
+    paused: Option<ColorDef>,
+    missing: Option<ColorDef>,
+    grid: Option<ColorDef>,
+}
+
+/// either `#RRGGBB` or one of the terminal's own colours, like `dark_grey`
+#[derive(Deserialize)]
+#[serde(try_from = "String")]
+struct ColorDef(Color);
+
+impl TryFrom<String> for ColorDef {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        if let Some(hex) = s.strip_prefix('#') {
+            if hex.len() == 6 && hex.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Ok(ColorDef(parse_rgb(hex).into()));
+            }
+        } else if let Ok(color) = Color::try_from(s.as_str()) {
+            return Ok(ColorDef(color));
+        }
+        Err(format!(
+            "expected a colour like \"#7F3ECF\" or \"dark_grey\", found \"{s}\""
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(key: &str) -> KeyEvent {
+        let (code, modifiers) = parse_key(key).unwrap();
+        KeyEvent::new(code, modifiers)
+    }
+
+    #[test]
+    fn key_names_round_trip() {
+        for key in [
+            "a",
+            "A",
+            ":",
+            "space",
+            "enter",
+            "esc",
+            "tab",
+            "pagedown",
+            "f5",
+            "ctrl-r",
+            "alt-left",
+            "shift-tab",
+            "ctrl-alt-x",
+        ] {
+            assert_eq!(key_name(parse_key(key).unwrap()), key);
+        }
+        assert_eq!(
+            parse_key("ctrl-space"),
+            Some((KeyCode::Char(' '), KeyModifiers::CONTROL))
+        );
+        // capitals already say shift
+        assert_eq!(parse_key("shift-a"), None);
+        for key in ["", "ctrl-", "hyper-x", "fx", "spacebar"] {
+            assert_eq!(parse_key(key), None, "{key}");
+        }
+    }
+
+    #[test]
+    fn shifted_letters_match_their_capitals() {
+        let config = Config::default();
+        let event = KeyEvent::new(KeyCode::Char('D'), KeyModifiers::SHIFT);
+        assert_eq!(config.action_for(&event), Some(Action::Delete));
+    }
+
+    #[test]
+    fn files_add_to_and_replace_the_defaults() {
+        let (config, warnings) = Config::parse(
+            r##"
+            toolbox_width = 30
+
+            [keys]
+            ctrl-s = "write"
+            u = "redo"
+
+            [debugger_keys]
+            s = "step"
+
+            [colors]
+            border = "#102030"
+            grid = "dark_cyan"
+            "##,
+        )
+        .unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!(config.toolbox_width, 30);
+        assert_eq!(config.status_height, 5);
+        assert_eq!(config.action_for(&press("ctrl-s")), Some(Action::Write));
+        assert_eq!(config.action_for(&press("w")), Some(Action::Write));
+        assert_eq!(config.action_for(&press("u")), Some(Action::Redo));
+        assert_eq!(config.keys_for(Action::Redo), ["u", "ctrl-r"]);
+        assert_eq!(config.debugger_action_for(&press("s")), Some(Action::Step));
+        assert_eq!(config.debugger_action_for(&press("n")), Some(Action::Step));
+        assert_eq!(
+            config.colors.border,
+            Color::Rgb {
+                r: 0x10,
+                g: 0x20,
+                b: 0x30
+            }
+        );
+        assert_eq!(config.colors.grid, Color::DarkCyan);
+    }
+
+    #[test]
+    fn none_unbinds_a_default_key() {
+        let (config, warnings) = Config::parse(
+            r#"
+            [keys]
+            t = "none"
+            [run_keys]
+            esc = "none"
+            "#,
+        )
+        .unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!(config.action_for(&press("t")), None);
+        assert!(config.keys_for(Action::ToggleToolbox).is_empty());
+        assert_eq!(config.run_action_for(&press("esc")), None);
+        assert_eq!(
+            config.run_action_for(&press("ctrl-g")),
+            Some(Action::Restart)
+        );
+    }
+
+    #[test]
+    fn unknown_keys_and_actions_are_warned_about() {
+        let (config, warnings) = Config::parse(
+            r#"
+            [keys]
+            z = "fly"
+            hyper-x = "quit"
+            ctrl-q = "force-quit"
+
+            [data_keys]
+            n = "create-variable"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            warnings,
+            ["unknown action \"fly\" for z", "unknown key \"hyper-x\""]
+        );
+        assert_eq!(config.action_for(&press("z")), None);
+        assert_eq!(config.action_for(&press("ctrl-q")), Some(Action::ForceQuit));
+        assert_eq!(
+            config.data_action_for(&press("n")),
+            Some(Action::CreateVariable)
+        );
+    }
+
+    #[test]
+    fn unknown_settings_are_errors() {
+        let error = Config::parse("toolbox_widht = 30").err().unwrap();
+        assert!(error.contains("unknown field `toolbox_widht`"), "{error}");
+        let error = Config::parse("[colors]\nborders = \"red\"").err().unwrap();
+        assert!(error.contains("unknown field `borders`"), "{error}");
+        let error = Config::parse("[colors]\nborder = \"#12345\"")
+            .err()
+            .unwrap();
+        assert!(error.contains("expected a colour"), "{error}");
+        let error = Config::parse("status_height = -1").err().unwrap();
+        assert!(error.contains("status_height"), "{error}");
+    }
+}
@@ -0,0 +1,587 @@
+//! Block definitions, written in TOML (or JSON) rather than code. The built-in ones under
+//! `blocks/` are embedded in the binary, and any more files in the user's
+//! `~/.config/viiru/blocks/` are added on top, so that extension blocks can be defined
+//! without recompiling. See `blocks/pen.toml` for an example.
+
+use std::{collections::HashSet, fmt, fs, path::Path, sync::OnceLock};
+
+use serde::{
+    de::{self, value::MapAccessDeserializer, MapAccess, Visitor},
+    Deserialize, Deserializer,
+};
+
+use crate::{
+    extensions::Extension,
+    result::{ViiruError, ViiruResult},
+    spec::{DefaultValue, DropdownOption, Fragment, Shape, Spec},
+    util::{config_dir, parse_rgb},
+};
+
+/// core categories first, then the extensions in the order they're listed in
+const BUILT_IN: &[(&str, &str)] = &[
+    ("motion.toml", include_str!("../blocks/motion.toml")),
+    ("looks.toml", include_str!("../blocks/looks.toml")),
+    ("sound.toml", include_str!("../blocks/sound.toml")),
+    ("events.toml", include_str!("../blocks/events.toml")),
+    ("control.toml", include_str!("../blocks/control.toml")),
+    ("sensing.toml", include_str!("../blocks/sensing.toml")),
+    ("operators.toml", include_str!("../blocks/operators.toml")),
+    ("variables.toml", include_str!("../blocks/variables.toml")),
+    ("lists.toml", include_str!("../blocks/lists.toml")),
+    ("procedures.toml", include_str!("../blocks/procedures.toml")),
+    ("shadows.toml", include_str!("../blocks/shadows.toml")),
+    ("pen.toml", include_str!("../blocks/pen.toml")),
+    ("music.toml", include_str!("../blocks/music.toml")),
+    (
+        "video_sensing.toml",
+        include_str!("../blocks/video_sensing.toml"),
+    ),
+    (
+        "text2speech.toml",
+        include_str!("../blocks/text2speech.toml"),
+    ),
+    ("translate.toml", include_str!("../blocks/translate.toml")),
+];
+
+static USER_FILES: OnceLock<Vec<BlockFile>> = OnceLock::new();
+
+/// the blocks of one definition file, ready to use
+#[derive(Clone)]
+pub struct BlockFile {
+    pub extension: Option<Extension>,
+    pub blocks: Vec<(String, Spec)>,
+}
+
+/// Every definition file in load order, so later blocks replace earlier ones with the same opcode
+pub fn block_files() -> impl Iterator<Item = BlockFile> {
+    built_in_files().chain(USER_FILES.get().into_iter().flatten().cloned())
+}
+
+fn built_in_files() -> impl Iterator<Item = BlockFile> {
+    BUILT_IN.iter().map(|(name, text)| match parse(name, text) {
+        Ok(file) => file,
+        Err(err) => panic!("built-in block definitions are broken: {err}"),
+    })
+}
+
+/// Reads the user's own definition files. Has to happen before any block is looked up,
+/// since the specs are only put together once.
+pub fn load_user_definitions() -> ViiruResult {
+    let Some(dir) = config_dir().map(|dir| dir.join("blocks")) else {
+        return Ok(());
+    };
+    let files = load_dir(&dir)?;
+    // menus may come from any file, including the built-in ones
+    let opcodes: HashSet<_> = built_in_files()
+        .chain(files.iter().map(|(_, file)| file.clone()))
+        .flat_map(|file| file.blocks)
+        .map(|(opcode, _)| opcode)
+        .collect();
+    for (name, file) in &files {
+        for (opcode, spec) in &file.blocks {
+            if let Some(menu) = menus_of(spec).find(|menu| !opcodes.contains(*menu)) {
+                return Err(ViiruError::DefinitionError(format!(
+                    "{name}: {opcode} uses the menu {menu}, which isn't defined anywhere"
+                )));
+            }
+        }
+    }
+    let _ = USER_FILES.set(files.into_iter().map(|(_, file)| file).collect());
+    Ok(())
+}
+
+fn load_dir(dir: &Path) -> ViiruResult<Vec<(String, BlockFile)>> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Ok(vec![]);
+    };
+    let mut paths: Vec<_> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "toml" || extension == "json")
+        })
+        .collect();
+    // so that files overriding each other do so predictably
+    paths.sort();
+    paths
+        .iter()
+        .map(|path| {
+            let name = path.display().to_string();
+            let text = fs::read_to_string(path)?;
+            let file = parse(&name, &text)?;
+            Ok((name, file))
+        })
+        .collect()
+}
+
+fn menus_of(spec: &Spec) -> impl Iterator<Item = &String> {
+    spec.lines.iter().flatten().filter_map(|frag| match frag {
+        Fragment::StrumberInput(_, Some(DefaultValue::Block(menu))) => Some(menu),
+        _ => None,
+    })
+}
+
+/// Parses a definition file, as TOML unless its name ends in `.json`
+pub fn parse(name: &str, text: &str) -> ViiruResult<BlockFile> {
+    let file: FileDef = if name.ends_with(".json") {
+        serde_json::from_str(text).map_err(|err| err.to_string())
+    } else {
+        toml::from_str(text).map_err(|err| err.to_string().trim_end().to_string())
+    }
+    .map_err(|message| ViiruError::DefinitionError(format!("{name}: {message}")))?;
+
+    let mut seen = HashSet::new();
+    let mut blocks = vec![];
+    for block in file.blocks {
+        if !seen.insert(block.opcode.clone()) {
+            return Err(ViiruError::DefinitionError(format!(
+                "{name}: {} is defined twice",
+                block.opcode
+            )));
+        }
+        // menus have colours of their own, a shade darker than their category
+        let defaults = match (&file.menu_colors, block.shadow) {
+            (Some(menu_colors), true) => menu_colors,
+            _ => &file.colors,
+        };
+        let color = |pick: fn(&ColorsDef) -> Option<Color>, which: &str| {
+            pick(&block.colors)
+                .or_else(|| pick(defaults))
+                .map(|Color(rgb)| rgb)
+                .ok_or_else(|| {
+                    ViiruError::DefinitionError(format!(
+                        "{name}: {} has no {which} colour, and neither does the file",
+                        block.opcode
+                    ))
+                })
+        };
+        let spec = Spec {
+            shape: block.shape,
+            is_shadow: block.shadow,
+            is_hat: block.hat,
+            block_color: color(|colors| colors.block, "block")?,
+            text_color: color(|colors| colors.text, "text")?,
+            alt_color: color(|colors| colors.alt, "alt")?,
+            static_dropdown_options: block.options,
+            lines: block.lines,
+        };
+        blocks.push((block.opcode, spec));
+    }
+    let extension = file.extension.map(|extension| Extension {
+        id: extension.id,
+        name: extension.name,
+        blocks: blocks.iter().map(|(opcode, _)| opcode.clone()).collect(),
+    });
+    Ok(BlockFile { extension, blocks })
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FileDef {
+    /// makes the file's blocks an extension, added to the toolbox from the extensions pane
+    extension: Option<ExtensionDef>,
+    #[serde(default)]
+    colors: ColorsDef,
+    menu_colors: Option<ColorsDef>,
+    #[serde(default)]
+    blocks: Vec<BlockDef>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ExtensionDef {
+    id: String,
+    name: String,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct ColorsDef {
+    block: Option<Color>,
+    text: Option<Color>,
+    alt: Option<Color>,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(try_from = "String")]
+struct Color((u8, u8, u8));
+
+impl TryFrom<String> for Color {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.strip_prefix('#') {
+            Some(hex) if hex.len() == 6 && hex.chars().all(|c| c.is_ascii_hexdigit()) => {
+                Ok(Color(parse_rgb(hex)))
+            }
+            _ => Err(format!("expected a colour like \"#4C97FF\", found \"{s}\"")),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(remote = "Shape", rename_all = "lowercase")]
+enum ShapeDef {
+    #[serde(rename = "reporter")]
+    Circle,
+    #[serde(rename = "boolean")]
+    Hexagon,
+    Stack,
+}
+
+fn stack() -> Shape {
+    Shape::Stack
+}
+
+#[derive(Deserialize)]
+#[serde(try_from = "RawBlockDef")]
+struct BlockDef {
+    opcode: String,
+    shape: Shape,
+    hat: bool,
+    shadow: bool,
+    colors: ColorsDef,
+    lines: Vec<Vec<Fragment>>,
+    options: Option<Vec<DropdownOption>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawBlockDef {
+    opcode: String,
+    #[serde(with = "ShapeDef", default = "stack")]
+    shape: Shape,
+    #[serde(default)]
+    hat: bool,
+    #[serde(default)]
+    shadow: bool,
+    #[serde(default)]
+    colors: ColorsDef,
+    line: Option<Vec<FragmentDef>>,
+    lines: Option<Vec<Vec<FragmentDef>>>,
+    /// the choices of the block's dropdown, when they never change
+    options: Option<Vec<OptionDef>>,
+}
+
+impl TryFrom<RawBlockDef> for BlockDef {
+    type Error = String;
+
+    fn try_from(raw: RawBlockDef) -> Result<Self, Self::Error> {
+        let opcode = raw.opcode;
+        let lines = match (raw.line, raw.lines) {
+            (Some(line), None) => vec![line],
+            (None, Some(lines)) if !lines.is_empty() => lines,
+            (Some(_), Some(_)) => {
+                return Err(format!("{opcode} has both `line` and `lines`, pick one"))
+            }
+            _ => return Err(format!("{opcode} needs a `line` or `lines` to show")),
+        };
+        if lines.iter().any(Vec::is_empty) {
+            return Err(format!("{opcode} has an empty line"));
+        }
+        let options = raw.options.map(|options| {
+            options
+                .into_iter()
+                .map(|OptionDef(value, display)| DropdownOption {
+                    value,
+                    display,
+                    id: None,
+                })
+                .collect::<Vec<_>>()
+        });
+        let dropdowns = lines
+            .iter()
+            .flatten()
+            .filter(|FragmentDef(frag)| matches!(frag, Fragment::Dropdown(..)))
+            .count();
+        match (&options, dropdowns) {
+            (Some(_), 0) => return Err(format!("{opcode} has options but no dropdown")),
+            (Some(_), 2..) => {
+                return Err(format!("{opcode} has options but more than one dropdown"))
+            }
+            _ => (),
+        }
+        let lines = lines
+            .into_iter()
+            .map(|line| {
+                line.into_iter()
+                    .map(|FragmentDef(frag)| match frag {
+                        Fragment::Dropdown(field, _) => Fragment::Dropdown(field, options.clone()),
+                        frag => frag,
+                    })
+                    .collect()
+            })
+            .collect();
+        Ok(BlockDef {
+            opcode,
+            shape: raw.shape,
+            hat: raw.hat,
+            shadow: raw.shadow,
+            colors: raw.colors,
+            lines,
+            options,
+        })
+    }
+}
+
+/// Either plain text, or a table with one key saying what kind of slot it is
+struct FragmentDef(Fragment);
+
+impl<'de> Deserialize<'de> for FragmentDef {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct FragmentVisitor;
+
+        impl<'de> Visitor<'de> for FragmentVisitor {
+            type Value = FragmentDef;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("text or a slot like { input = \"X\" }")
+            }
+
+            fn visit_str<E: de::Error>(self, s: &str) -> Result<Self::Value, E> {
+                Ok(FragmentDef(Fragment::Text(s.to_string())))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+                let slot = SlotDef::deserialize(MapAccessDeserializer::new(map))?;
+                slot.into_fragment()
+                    .map(FragmentDef)
+                    .map_err(de::Error::custom)
+            }
+        }
+
+        deserializer.deserialize_any(FragmentVisitor)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SlotDef {
+    /// takes a string or number, or a reporter
+    input: Option<String>,
+    boolean: Option<String>,
+    /// the mouth of a C-block
+    substack: Option<String>,
+    /// lines up the inputs of `if`-like blocks
+    align: Option<String>,
+    dropdown: Option<String>,
+    field: Option<String>,
+    writable: Option<String>,
+    /// the colour picker of colour shadows
+    swatch: Option<String>,
+    icon: Option<Icon>,
+    expander: Option<bool>,
+    // an input's default
+    number: Option<f64>,
+    text: Option<String>,
+    menu: Option<String>,
+    colour: Option<Color>,
+    blank: Option<bool>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Icon {
+    Flag,
+    Clockwise,
+    Anticlockwise,
+}
+
+impl SlotDef {
+    fn into_fragment(self) -> Result<Fragment, String> {
+        let defaults = [
+            self.number.map(|x| DefaultValue::Num(x, true)),
+            self.text.map(DefaultValue::Str),
+            self.menu.map(DefaultValue::Block),
+            self.colour.map(|Color(rgb)| DefaultValue::Color(rgb)),
+            // todo: distinguish between more number types
+            self.blank
+                .filter(|blank| *blank)
+                .map(|_| DefaultValue::Num(0.0, false)),
+        ];
+        let mut defaults = defaults.into_iter().flatten();
+        let default = defaults.next();
+        if defaults.next().is_some() {
+            return Err("an input can only have one default".into());
+        }
+        let kinds = [
+            self.input
+                .map(|name| Fragment::StrumberInput(name, default.clone())),
+            self.boolean.map(Fragment::BooleanInput),
+            self.substack.map(Fragment::BlockInput),
+            self.align.map(Fragment::AlignmentPoint),
+            self.dropdown.map(|name| Fragment::Dropdown(name, None)),
+            self.field.map(Fragment::FieldText),
+            self.writable.map(Fragment::WritableFieldText),
+            self.swatch.map(Fragment::CustomColour),
+            self.icon.map(|icon| match icon {
+                Icon::Flag => Fragment::Flag,
+                Icon::Clockwise => Fragment::Clockwise,
+                Icon::Anticlockwise => Fragment::Anticlockwise,
+            }),
+            self.expander
+                .filter(|expander| *expander)
+                .map(|_| Fragment::Expander),
+        ];
+        let mut kinds = kinds.into_iter().flatten();
+        let (Some(frag), None) = (kinds.next(), kinds.next()) else {
+            return Err(
+                "expected exactly one of input, boolean, substack, align, dropdown, \
+                 field, writable, swatch, icon or expander"
+                    .into(),
+            );
+        };
+        if default.is_some() && !matches!(frag, Fragment::StrumberInput(..)) {
+            return Err("only an input can have a default".into());
+        }
+        Ok(frag)
+    }
+}
+
+/// A dropdown option, which shows its value unless given a `display` text
+struct OptionDef(String, String);
+
+impl<'de> Deserialize<'de> for OptionDef {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(deny_unknown_fields)]
+        struct Labelled {
+            value: String,
+            display: String,
+        }
+
+        struct OptionVisitor;
+
+        impl<'de> Visitor<'de> for OptionVisitor {
+            type Value = OptionDef;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("an option like \"on\" or { value = \"1\", display = \"(1) Piano\" }")
+            }
+
+            fn visit_str<E: de::Error>(self, s: &str) -> Result<Self::Value, E> {
+                Ok(OptionDef(s.to_string(), s.to_string()))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+                let Labelled { value, display } =
+                    Labelled::deserialize(MapAccessDeserializer::new(map))?;
+                Ok(OptionDef(value, display))
+            }
+        }
+
+        deserializer.deserialize_any(OptionVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::opcodes::BLOCKS;
+
+    /// What every built-in spec looked like when the definitions moved out of the old spec
+    /// DSL, one line per opcode. Regenerate it with `describe` when a block changes on purpose.
+    const BUILT_IN_SPECS: &str = include_str!("../tests/built_in_specs.txt");
+
+    /// a spec in a line, close to how it's written in the definition files
+    fn describe(opcode: &str, spec: &Spec) -> String {
+        let (r, g, b) = spec.block_color;
+        let mut description = format!("{opcode} {:?} #{r:02x}{g:02x}{b:02x}", spec.shape);
+        if spec.is_hat {
+            description.push_str(" hat");
+        }
+        if spec.is_shadow {
+            description.push_str(" shadow");
+        }
+        let options = |options: &[DropdownOption]| {
+            let options: Vec<_> = options
+                .iter()
+                .map(|option| match &option.display {
+                    display if *display == option.value => option.value.clone(),
+                    display => format!("{}={display}", option.value),
+                })
+                .collect();
+            options.join(",")
+        };
+        for (i, line) in spec.lines.iter().enumerate() {
+            description.push_str(if i == 0 { ":" } else { " /" });
+            // the old DSL sometimes split text that reads the same
+            let mut text = String::new();
+            for frag in line {
+                if let Fragment::Text(s) = frag {
+                    text.push_str(s);
+                    continue;
+                }
+                if !text.is_empty() {
+                    description.push_str(&format!(" {:?}", std::mem::take(&mut text)));
+                }
+                description.push(' ');
+                description.push_str(&match frag {
+                    Fragment::StrumberInput(name, None) => format!("({name})"),
+                    Fragment::StrumberInput(name, Some(default)) => match default {
+                        DefaultValue::Block(menu) => format!("({name}={menu})"),
+                        DefaultValue::Str(s) => format!("({name}={s:?})"),
+                        DefaultValue::Num(n, true) => format!("({name}={n})"),
+                        DefaultValue::Num(n, false) => format!("({name}=hidden {n})"),
+                        DefaultValue::Color((r, g, b)) => {
+                            format!("({name}=#{r:02x}{g:02x}{b:02x})")
+                        }
+                    },
+                    Fragment::BooleanInput(name) => format!("<{name}>"),
+                    Fragment::BlockInput(name) => format!("{{{name}}}"),
+                    Fragment::Dropdown(name, None) => format!("[{name} v]"),
+                    Fragment::Dropdown(name, Some(choices)) => {
+                        format!("[{name} v {}]", options(choices))
+                    }
+                    Fragment::Expander => "expander".into(),
+                    Fragment::AlignmentPoint(name) => format!("align {name}"),
+                    Fragment::Flag => "flag".into(),
+                    Fragment::Clockwise => "clockwise".into(),
+                    Fragment::Anticlockwise => "anticlockwise".into(),
+                    Fragment::WritableFieldText(name) => format!("[{name}]"),
+                    Fragment::FieldText(name) => format!("'{name}'"),
+                    Fragment::CustomColour(name) => format!("swatch {name}"),
+                    Fragment::Text(_) => unreachable!(),
+                });
+            }
+            if !text.is_empty() {
+                description.push_str(&format!(" {text:?}"));
+            }
+        }
+        if let Some(choices) = &spec.static_dropdown_options {
+            description.push_str(&format!(" | {}", options(choices)));
+        }
+        description
+    }
+
+    #[test]
+    fn every_built_in_file_parses() {
+        let mut files = HashMap::new();
+        for (name, text) in BUILT_IN {
+            let file = parse(name, text).unwrap_or_else(|err| panic!("{name}: {err}"));
+            for (opcode, _) in &file.blocks {
+                if let Some(other) = files.insert(opcode.clone(), *name) {
+                    panic!("{opcode} is defined in both {other} and {name}");
+                }
+            }
+        }
+        assert_eq!(files.len(), 180);
+        assert_eq!(BLOCKS.len(), 180);
+    }
+
+    #[test]
+    fn built_in_specs_are_unchanged() {
+        let mut descriptions: Vec<_> = BLOCKS
+            .iter()
+            .map(|(opcode, spec)| describe(opcode, spec))
+            .collect();
+        descriptions.sort();
+        let expected: Vec<_> = BUILT_IN_SPECS.lines().collect();
+        assert_eq!(descriptions.len(), expected.len());
+        for (description, expected) in descriptions.iter().zip(expected) {
+            assert_eq!(description, expected);
+        }
+    }
+}
@@ -0,0 +1,82 @@
+//! The extensions Scratch ships with, each adding a section of blocks to the toolbox.
+//! Their specs are always known so that projects using them render, but their blocks only
+//! show up in the toolbox once the extension is enabled. Each one comes from a block
+//! definition file with an `extension` table, so users can add their own.
+
+use std::sync::LazyLock;
+
+use crate::{definitions::block_files, opcodes::BLOCKS, project::Project};
+
+#[derive(Clone)]
+pub struct Extension {
+    /// also the prefix of every opcode it adds, like `pen` in `pen_clear`
+    pub id: String,
+    pub name: String,
+    /// the blocks in toolbox order followed by their menus
+    pub blocks: Vec<String>,
+}
+
+impl Extension {
+    /// the blocks that go into the toolbox, leaving out the menus
+    pub fn toolbox(&self) -> impl Iterator<Item = &str> {
+        self.blocks
+            .iter()
+            .map(String::as_str)
+            .filter(|opcode| !BLOCKS[*opcode].is_shadow)
+    }
+}
+
+/// the categories built into scratch-vm, which never appear in a project's `extensions`
+const CORE: &[&str] = &[
+    "argument",
+    "colour",
+    "control",
+    "data",
+    "event",
+    "looks",
+    "math",
+    "motion",
+    "operator",
+    "procedures",
+    "sensing",
+    "sound",
+];
+
+/// built-in extensions first, with user files replacing ones with the same id
+pub static EXTENSIONS: LazyLock<Vec<Extension>> = LazyLock::new(|| {
+    let mut extensions: Vec<Extension> = vec![];
+    for extension in block_files().filter_map(|file| file.extension) {
+        match extensions.iter_mut().find(|known| known.id == extension.id) {
+            Some(known) => *known = extension,
+            None => extensions.push(extension),
+        }
+    }
+    extensions
+});
+
+pub fn extension(id: &str) -> Option<&'static Extension> {
+    EXTENSIONS.iter().find(|extension| extension.id == id)
+}
+
+/// the extension an opcode comes from, even one we have no specs for
+pub fn extension_id_of(opcode: &str) -> Option<&str> {
+    let (prefix, _) = opcode.split_once('_')?;
+    (!CORE.contains(&prefix)).then_some(prefix)
+}
+
+/// Like scratch-vm, a project's extensions are whichever ones its blocks come from
+pub fn used_extensions(project: &Project) -> Vec<String> {
+    let mut ids: Vec<String> = vec![];
+    for target in &project.targets {
+        let blocks = target.decode_blocks();
+        let mut opcodes: Vec<_> = blocks.values().map(|b| b.opcode.as_str()).collect();
+        // a hash map has no order of its own
+        opcodes.sort();
+        for id in opcodes.into_iter().filter_map(extension_id_of) {
+            if !ids.iter().any(|used| used == id) {
+                ids.push(id.to_string());
+            }
+        }
+    }
+    ids
+}
@@ -0,0 +1,341 @@
+use crate::block::{Block, Field, Mutation};
+
+/// A single edit made through the runtime, holding enough to be applied in either direction
+#[derive(Clone)]
+pub enum Op {
+    /// the blocks are a snapshot of the whole stack, with the root first
+    Create {
+        id: String,
+        blocks: Vec<(String, Block)>,
+    },
+    Delete {
+        id: String,
+        blocks: Vec<(String, Block)>,
+    },
+    Attach {
+        id: String,
+        parent_id: String,
+        /// None for the next block in a stack
+        input_name: Option<String>,
+        is_shadow: bool,
+    },
+    Detach {
+        id: String,
+        parent_id: String,
+        input_name: Option<String>,
+        is_shadow: bool,
+    },
+    Slide {
+        id: String,
+        from: (i32, i32),
+        to: (i32, i32),
+    },
+    SetField {
+        id: String,
+        field_name: String,
+        old: Field,
+        new: Field,
+    },
+    SetMutation {
+        id: String,
+        old: Option<Mutation>,
+        new: Option<Mutation>,
+    },
+}
+
+impl Op {
+    pub fn inverse(&self) -> Op {
+        match self.clone() {
+            Op::Create { id, blocks } => Op::Delete { id, blocks },
+            Op::Delete { id, blocks } => Op::Create { id, blocks },
+            Op::Attach {
+                id,
+                parent_id,
+                input_name,
+                is_shadow,
+            } => Op::Detach {
+                id,
+                parent_id,
+                input_name,
+                is_shadow,
+            },
+            Op::Detach {
+                id,
+                parent_id,
+                input_name,
+                is_shadow,
+            } => Op::Attach {
+                id,
+                parent_id,
+                input_name,
+                is_shadow,
+            },
+            Op::Slide { id, from, to } => Op::Slide {
+                id,
+                from: to,
+                to: from,
+            },
+            Op::SetField {
+                id,
+                field_name,
+                old,
+                new,
+            } => Op::SetField {
+                id,
+                field_name,
+                old: new,
+                new: old,
+            },
+            Op::SetMutation { id, old, new } => Op::SetMutation {
+                id,
+                old: new,
+                new: old,
+            },
+        }
+    }
+
+    /// ids of the blocks that have to exist for the op to be applied
+    pub fn required_ids(&self) -> Vec<&str> {
+        match self {
+            Op::Create { .. } => vec![],
+            Op::Attach { id, parent_id, .. } | Op::Detach { id, parent_id, .. } => {
+                vec![id, parent_id]
+            }
+            Op::Delete { id, .. }
+            | Op::Slide { id, .. }
+            | Op::SetField { id, .. }
+            | Op::SetMutation { id, .. } => vec![id],
+        }
+    }
+
+    /// Folds a following op into this one, if the two amount to a single op anyway.
+    /// This is what keeps moving a held block around or typing into a field to one step.
+    fn absorb(&mut self, next: &Op) -> bool {
+        match (self, next) {
+            (
+                Op::Slide { id, to, .. },
+                Op::Slide {
+                    id: next_id,
+                    to: next_to,
+                    ..
+                },
+            ) if id == next_id => {
+                *to = *next_to;
+                true
+            }
+            (
+                Op::SetField {
+                    id,
+                    field_name,
+                    new,
+                    ..
+                },
+                Op::SetField {
+                    id: next_id,
+                    field_name: next_field_name,
+                    new: next_new,
+                    ..
+                },
+            ) if id == next_id && field_name == next_field_name => {
+                *new = next_new.clone();
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Undo and redo stacks of steps, where each step is every op made by a single user action
+#[derive(Default)]
+pub struct History {
+    undo: Vec<Vec<Op>>,
+    redo: Vec<Vec<Op>>,
+    /// the step being built up, until the next checkpoint
+    pending: Vec<Op>,
+}
+
+impl History {
+    pub fn record(&mut self, op: Op) {
+        self.redo.clear();
+        if let Some(last) = self.pending.last_mut() {
+            if last.absorb(&op) {
+                return;
+            }
+        }
+        self.pending.push(op);
+    }
+
+    /// ends the current step
+    pub fn checkpoint(&mut self) {
+        if !self.pending.is_empty() {
+            self.undo.push(std::mem::take(&mut self.pending));
+        }
+    }
+
+    /// returns the ops that reverse the last step, in the order they need to be applied
+    pub fn undo(&mut self) -> Option<Vec<Op>> {
+        self.checkpoint();
+        let step = self.undo.pop()?;
+        let inverse = step.iter().rev().map(Op::inverse).collect();
+        self.redo.push(step);
+        Some(inverse)
+    }
+
+    /// returns the ops of the last undone step
+    pub fn redo(&mut self) -> Option<Vec<Op>> {
+        self.checkpoint();
+        let step = self.redo.pop()?;
+        self.undo.push(step.clone());
+        Some(step)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn slide(id: &str, from: (i32, i32), to: (i32, i32)) -> Op {
+        Op::Slide {
+            id: id.into(),
+            from,
+            to,
+        }
+    }
+
+    fn set_field(field_name: &str, old: &str, new: &str) -> Op {
+        let field = |value: &str| Field {
+            value: value.into(),
+            id: None,
+        };
+        Op::SetField {
+            id: "a".into(),
+            field_name: field_name.into(),
+            old: field(old),
+            new: field(new),
+        }
+    }
+
+    #[test]
+    fn inverses_swap_directions() {
+        assert!(matches!(
+            slide("a", (1, 2), (3, 4)).inverse(),
+            Op::Slide {
+                from: (3, 4),
+                to: (1, 2),
+                ..
+            }
+        ));
+        let attach = Op::Attach {
+            id: "a".into(),
+            parent_id: "b".into(),
+            input_name: Some("SUBSTACK".into()),
+            is_shadow: false,
+        };
+        assert!(matches!(
+            attach.inverse(),
+            Op::Detach { ref id, ref parent_id, input_name: Some(ref input_name), is_shadow: false }
+                if id == "a" && parent_id == "b" && input_name == "SUBSTACK"
+        ));
+        let create = Op::Create {
+            id: "a".into(),
+            blocks: vec![("a".into(), Block::default())],
+        };
+        assert!(matches!(
+            create.inverse(),
+            Op::Delete { ref id, ref blocks } if id == "a" && blocks.len() == 1
+        ));
+        assert!(matches!(
+            set_field("NUM", "1", "2").inverse(),
+            Op::SetField { ref old, ref new, .. } if old.value == "2" && new.value == "1"
+        ));
+        assert!(matches!(
+            slide("a", (1, 2), (3, 4)).inverse().inverse(),
+            Op::Slide {
+                from: (1, 2),
+                to: (3, 4),
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn slides_of_the_same_block_are_absorbed() {
+        let mut op = slide("a", (0, 0), (1, 0));
+        assert!(op.absorb(&slide("a", (1, 0), (2, 5))));
+        assert!(matches!(
+            op,
+            Op::Slide {
+                from: (0, 0),
+                to: (2, 5),
+                ..
+            }
+        ));
+        assert!(!op.absorb(&slide("b", (2, 5), (3, 5))));
+        assert!(matches!(op, Op::Slide { to: (2, 5), .. }));
+    }
+
+    #[test]
+    fn typing_into_a_field_is_absorbed() {
+        let mut op = set_field("TEXT", "", "h");
+        assert!(op.absorb(&set_field("TEXT", "h", "hi")));
+        assert!(matches!(
+            op,
+            Op::SetField { ref old, ref new, .. } if old.value.is_empty() && new.value == "hi"
+        ));
+        assert!(!op.absorb(&set_field("NUM", "1", "2")));
+        assert!(!op.absorb(&slide("a", (0, 0), (1, 1))));
+    }
+
+    #[test]
+    fn checkpoints_end_a_step() {
+        let mut history = History::default();
+        history.record(slide("a", (0, 0), (1, 0)));
+        history.record(slide("a", (1, 0), (2, 0)));
+        history.checkpoint();
+        // nothing happened since, so this doesn't make an empty step
+        history.checkpoint();
+        history.record(slide("a", (2, 0), (3, 0)));
+
+        // undo ends the pending step first
+        let undone = history.undo().unwrap();
+        assert!(matches!(
+            undone[..],
+            [Op::Slide {
+                from: (3, 0),
+                to: (2, 0),
+                ..
+            }]
+        ));
+        let undone = history.undo().unwrap();
+        assert!(matches!(
+            undone[..],
+            [Op::Slide {
+                from: (2, 0),
+                to: (0, 0),
+                ..
+            }]
+        ));
+        assert!(history.undo().is_none());
+
+        let redone = history.redo().unwrap();
+        assert!(matches!(redone[..], [Op::Slide { to: (2, 0), .. }]));
+        // a new edit drops whatever could have been redone
+        history.record(slide("b", (0, 0), (0, 1)));
+        assert!(history.redo().is_none());
+    }
+
+    #[test]
+    fn undo_reverses_a_step_from_its_last_op() {
+        let mut history = History::default();
+        history.record(Op::Create {
+            id: "a".into(),
+            blocks: vec![],
+        });
+        history.record(slide("a", (0, 0), (4, 4)));
+        let undone = history.undo().unwrap();
+        assert!(matches!(
+            undone[..],
+            [Op::Slide { to: (0, 0), .. }, Op::Delete { .. }]
+        ));
+    }
+}
@@ -0,0 +1,2245 @@
+//! Runs projects without scratch-vm, straight off the editor's blocks.
+//!
+//! Every frame, each running thread goes until it yields: at the end of a loop iteration,
+//! while waiting, or when a custom block without screen refresh has run for too long.
+//! Time is counted in frames rather than read from the clock, the date starts from whatever
+//! the caller says it is, and randomness comes from a seeded generator, so the same project
+//! always runs the same way. Sprites are treated as
+//! points, since we don't know how big their costumes are.
+
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    fmt,
+    rc::Rc,
+};
+
+use indexmap::IndexMap;
+
+use crate::{block::Block, opcodes::BLOCKS, project::Project};
+
+pub const FRAMES_PER_SECOND: f64 = 30.0;
+pub const STAGE_WIDTH: f64 = 480.0;
+pub const STAGE_HEIGHT: f64 = 360.0;
+const CLONE_LIMIT: usize = 300;
+const LIST_LIMIT: usize = 200_000;
+/// how many blocks a thread runs in a single frame, in case it never yields by itself
+const BLOCK_LIMIT: usize = 100_000;
+
+/// Scratch is loosely typed, so values are converted whenever they're used
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Num(f64),
+    Str(String),
+    Bool(bool),
+}
+
+impl Value {
+    fn from_json(value: &serde_json::Value) -> Value {
+        match value {
+            serde_json::Value::Number(n) => Value::Num(n.as_f64().unwrap_or(0.0)),
+            serde_json::Value::String(s) => Value::Str(s.clone()),
+            serde_json::Value::Bool(b) => Value::Bool(*b),
+            serde_json::Value::Null => Value::Str(String::new()),
+            other => Value::Str(other.to_string()),
+        }
+    }
+
+    /// NaN for anything that isn't a number, including blank text
+    fn strict_number(&self) -> f64 {
+        match self {
+            Value::Num(n) => *n,
+            Value::Bool(b) => *b as u8 as f64,
+            Value::Str(s) => parse_number(s),
+        }
+    }
+
+    pub fn to_number(&self) -> f64 {
+        let n = self.strict_number();
+        if n.is_nan() {
+            0.0
+        } else {
+            n
+        }
+    }
+
+    pub fn to_bool(&self) -> bool {
+        match self {
+            Value::Num(n) => *n != 0.0 && !n.is_nan(),
+            Value::Bool(b) => *b,
+            Value::Str(s) => !(s.is_empty() || s == "0" || s.eq_ignore_ascii_case("false")),
+        }
+    }
+
+    /// numbers that are whole, or text without a decimal point
+    fn is_int(&self) -> bool {
+        match self {
+            Value::Num(n) => n.is_nan() || n.fract() == 0.0,
+            Value::Bool(_) => true,
+            Value::Str(s) => !s.contains('.'),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Num(n) => write!(f, "{}", format_number(*n)),
+            Value::Str(s) => write!(f, "{s}"),
+            Value::Bool(b) => write!(f, "{b}"),
+        }
+    }
+}
+
+/// the way JavaScript reads numbers out of text
+fn parse_number(s: &str) -> f64 {
+    let s = s.trim();
+    match s {
+        "" => f64::NAN,
+        "Infinity" | "+Infinity" => f64::INFINITY,
+        "-Infinity" => f64::NEG_INFINITY,
+        _ => {
+            if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+                return i64::from_str_radix(hex, 16).map_or(f64::NAN, |n| n as f64);
+            }
+            // rust also takes "inf" and "nan", which javascript doesn't
+            if s.contains(|c: char| c.is_ascii_alphabetic() && c != 'e' && c != 'E') {
+                return f64::NAN;
+            }
+            s.parse().unwrap_or(f64::NAN)
+        }
+    }
+}
+
+/// the way JavaScript prints numbers
+fn format_number(n: f64) -> String {
+    if n.is_nan() {
+        "NaN".into()
+    } else if n.is_infinite() {
+        if n > 0.0 { "Infinity" } else { "-Infinity" }.into()
+    } else if n == 0.0 {
+        "0".into()
+    } else {
+        n.to_string()
+    }
+}
+
+/// Numbers compare as numbers, and anything else compares as case-insensitive text
+fn compare(a: &Value, b: &Value) -> Ordering {
+    let (n1, n2) = (a.strict_number(), b.strict_number());
+    if n1.is_nan() || n2.is_nan() {
+        return a
+            .to_string()
+            .to_lowercase()
+            .cmp(&b.to_string().to_lowercase());
+    }
+    n1.partial_cmp(&n2).unwrap_or(Ordering::Equal)
+}
+
+pub struct Variable {
+    pub name: String,
+    pub value: Value,
+}
+
+pub struct List {
+    pub name: String,
+    pub items: Vec<Value>,
+}
+
+#[derive(Clone)]
+pub struct Bubble {
+    pub text: String,
+    pub is_think: bool,
+}
+
+/// The stage, a sprite, or a clone of one
+pub struct Sprite {
+    /// stays the same as sprites are reordered, cloned and deleted
+    pub id: usize,
+    pub name: String,
+    pub is_stage: bool,
+    pub is_clone: bool,
+    /// which of the project's targets the scripts come from, shared with clones
+    original: usize,
+    pub x: f64,
+    pub y: f64,
+    pub direction: f64,
+    pub size: f64,
+    pub visible: bool,
+    pub costume: usize,
+    /// backdrops, for the stage
+    pub costumes: Vec<String>,
+    pub rotation_style: String,
+    pub draggable: bool,
+    pub volume: f64,
+    pub effects: HashMap<String, f64>,
+    pub sound_effects: HashMap<String, f64>,
+    pub bubble: Option<Bubble>,
+    pub variables: IndexMap<String, Variable>,
+    pub lists: IndexMap<String, List>,
+}
+
+impl Sprite {
+    fn clone_of(&self, id: usize) -> Sprite {
+        Sprite {
+            id,
+            name: self.name.clone(),
+            is_stage: false,
+            is_clone: true,
+            original: self.original,
+            x: self.x,
+            y: self.y,
+            direction: self.direction,
+            size: self.size,
+            visible: self.visible,
+            costume: self.costume,
+            costumes: self.costumes.clone(),
+            rotation_style: self.rotation_style.clone(),
+            draggable: self.draggable,
+            volume: self.volume,
+            effects: self.effects.clone(),
+            sound_effects: self.sound_effects.clone(),
+            bubble: None,
+            variables: self
+                .variables
+                .iter()
+                .map(|(id, v)| {
+                    let value = v.value.clone();
+                    (
+                        id.clone(),
+                        Variable {
+                            name: v.name.clone(),
+                            value,
+                        },
+                    )
+                })
+                .collect(),
+            lists: self
+                .lists
+                .iter()
+                .map(|(id, l)| {
+                    let items = l.items.clone();
+                    (
+                        id.clone(),
+                        List {
+                            name: l.name.clone(),
+                            items,
+                        },
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    fn costume_name(&self) -> String {
+        self.costumes.get(self.costume).cloned().unwrap_or_default()
+    }
+
+    fn set_costume(&mut self, index: isize) {
+        let count = self.costumes.len().max(1) as isize;
+        self.costume = index.rem_euclid(count) as usize;
+    }
+
+    /// sprites are points, so they're kept on the stage by their centre
+    fn set_position(&mut self, x: f64, y: f64) {
+        if self.is_stage {
+            return;
+        }
+        self.x = x.clamp(-STAGE_WIDTH / 2.0, STAGE_WIDTH / 2.0);
+        self.y = y.clamp(-STAGE_HEIGHT / 2.0, STAGE_HEIGHT / 2.0);
+    }
+
+    fn set_direction(&mut self, direction: f64) {
+        if direction.is_finite() {
+            // wrap into (-180, 180]
+            self.direction = direction - ((direction + 179.0) / 360.0).floor() * 360.0;
+        }
+    }
+
+    fn is_on_edge(&self) -> bool {
+        self.x.abs() >= STAGE_WIDTH / 2.0 || self.y.abs() >= STAGE_HEIGHT / 2.0
+    }
+}
+
+/// Something a script did that can't be seen from the sprites' state alone
+pub enum Output {
+    Say { sprite: String, text: String },
+    Think { sprite: String, text: String },
+    Ask { sprite: String, question: String },
+}
+
+impl fmt::Display for Output {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Output::Say { sprite, text } => write!(f, "{sprite} says: {text}"),
+            Output::Think { sprite, text } => write!(f, "{sprite} thinks: {text}"),
+            Output::Ask { sprite, question } => write!(f, "{sprite} asks: {question}"),
+        }
+    }
+}
+
+/// Where the project stopped to be looked at
+pub struct Pause {
+    pub thread: usize,
+    /// index into the project's targets
+    pub target: usize,
+    pub block_id: String,
+}
+
+/// what makes the project pause next, besides breakpoints
+enum StepMode {
+    Continue,
+    /// before the next block of the thread, or of any thread once it's done.
+    /// blocks that take more than a frame, like waits, only count once
+    Step {
+        thread: Option<usize>,
+        depth: usize,
+        block_id: String,
+    },
+    /// once the thread moves past the block, without stopping inside of it
+    StepOver {
+        thread: usize,
+        depth: usize,
+        block_id: String,
+    },
+}
+
+/// A script that's running, as shown by the debugger
+pub struct ThreadInfo {
+    pub id: usize,
+    pub sprite: String,
+    pub is_clone: bool,
+    pub top_opcode: String,
+    /// None at the end of the script
+    pub opcode: Option<String>,
+    /// how many custom blocks and C-blocks deep it is
+    pub depth: usize,
+}
+
+pub struct Question {
+    thread: usize,
+    pub sprite: String,
+    pub text: String,
+}
+
+/// what a block is in the middle of, kept until it's done
+#[derive(Default)]
+enum BlockState {
+    #[default]
+    Fresh,
+    Count(f64),
+    Timer(f64),
+    Glide {
+        from: (f64, f64),
+        to: (f64, f64),
+        start: f64,
+        duration: f64,
+    },
+    Waiting(Vec<usize>),
+    Asking,
+}
+
+/// A stack of blocks being run, e.g. a script, the body of a loop or a custom block
+struct Frame {
+    /// None once the end of the stack is reached
+    block_id: Option<String>,
+    /// whether the block that started this one runs again once it's done
+    is_loop: bool,
+    warp: bool,
+    /// the body of a custom block, which "stop this script" returns from
+    is_procedure: bool,
+    arguments: Rc<HashMap<String, Value>>,
+    state: BlockState,
+}
+
+struct Thread {
+    id: usize,
+    sprite: usize,
+    top_id: String,
+    stack: Vec<Frame>,
+    is_done: bool,
+    /// set when the script is started over while it's running a block
+    restarted: bool,
+}
+
+/// What the thread does after running a block
+enum Flow {
+    Next,
+    Yield,
+    /// runs the stack in the input, and whether to come back to this block afterwards
+    Branch(&'static str, bool),
+    Call {
+        body: Option<String>,
+        arguments: HashMap<String, Value>,
+        warp: bool,
+    },
+    Stop,
+    /// leaves the custom block being run, or stops the thread outside of one
+    Return,
+}
+
+enum ListIndex {
+    All,
+    Invalid,
+    At(usize),
+}
+
+pub struct Interpreter {
+    /// the stage first, then sprites and clones from the back layer to the front
+    pub sprites: Vec<Sprite>,
+    /// the blocks of each of the project's targets
+    scripts: Vec<Rc<HashMap<String, Block>>>,
+    /// hat blocks of each target, top to bottom
+    hats: Vec<Vec<String>>,
+    /// prototype ids of each target, by proccode
+    procedures: Vec<HashMap<String, String>>,
+    threads: Vec<Thread>,
+    next_sprite_id: usize,
+    next_thread_id: usize,
+    pub frame: u64,
+    timer_start: f64,
+    rng: u64,
+    /// unix time the project started at, for the blocks that tell the date
+    pub start_time: f64,
+    pub mouse: (f64, f64),
+    pub mouse_down: bool,
+    pub pressed_keys: HashSet<String>,
+    pub answer: String,
+    pub question: Option<Question>,
+    /// variables and lists shown on the stage, by id
+    pub monitors: Vec<String>,
+    /// cleared by whoever shows it
+    pub output: Vec<Output>,
+    /// whether each "when timer >" hat was past its threshold on the last frame
+    edge_hats: HashMap<(usize, String), bool>,
+    /// block ids by index into the project's targets
+    pub breakpoints: HashSet<(usize, String)>,
+    pub paused: Option<Pause>,
+    step_mode: StepMode,
+    /// the thread that gets to run the block it's paused at, instead of pausing again
+    resuming: Option<usize>,
+    /// which thread runs next, since a frame can be paused halfway through
+    thread_cursor: usize,
+}
+
+impl Interpreter {
+    pub fn new(project: &Project, seed: u64, start_time: f64) -> Interpreter {
+        let mut interpreter = Interpreter {
+            sprites: vec![],
+            scripts: vec![],
+            hats: vec![],
+            procedures: vec![],
+            threads: vec![],
+            next_sprite_id: 0,
+            next_thread_id: 0,
+            frame: 0,
+            timer_start: 0.0,
+            rng: seed,
+            start_time,
+            mouse: (0.0, 0.0),
+            mouse_down: false,
+            pressed_keys: HashSet::new(),
+            answer: String::new(),
+            question: None,
+            monitors: vec![],
+            output: vec![],
+            edge_hats: HashMap::new(),
+            breakpoints: HashSet::new(),
+            paused: None,
+            step_mode: StepMode::Continue,
+            resuming: None,
+            thread_cursor: 0,
+        };
+        let mut order: Vec<_> = (0..project.targets.len()).collect();
+        order.sort_by_key(|&i| (!project.targets[i].is_stage, project.targets[i].layer_order));
+        for target in &project.targets {
+            let blocks = target.decode_blocks();
+            let mut hats: Vec<_> = blocks
+                .iter()
+                .filter(|(_, block)| {
+                    block.parent_id.is_none()
+                        && block.opcode != "procedures_definition"
+                        && BLOCKS.get(&block.opcode).is_some_and(|spec| spec.is_hat)
+                })
+                .map(|(id, block)| (block.y, block.x, id.clone()))
+                .collect();
+            hats.sort();
+            interpreter
+                .hats
+                .push(hats.into_iter().map(|(_, _, id)| id).collect());
+            interpreter.procedures.push(
+                blocks
+                    .iter()
+                    .filter(|(_, block)| block.opcode == "procedures_prototype")
+                    .filter_map(|(id, block)| {
+                        Some((block.mutation.as_ref()?.proccode.clone(), id.clone()))
+                    })
+                    .collect(),
+            );
+            interpreter.scripts.push(Rc::new(blocks));
+        }
+        for i in order {
+            let target = &project.targets[i];
+            let id = interpreter.next_sprite_id;
+            interpreter.next_sprite_id += 1;
+            interpreter.sprites.push(Sprite {
+                id,
+                name: target.name.clone(),
+                is_stage: target.is_stage,
+                is_clone: false,
+                original: i,
+                x: target.x.unwrap_or(0.0),
+                y: target.y.unwrap_or(0.0),
+                direction: target.direction.unwrap_or(90.0),
+                size: target.size.unwrap_or(100.0),
+                visible: target.visible.unwrap_or(true),
+                costume: target.current_costume,
+                costumes: target.costumes.iter().map(|c| c.name.clone()).collect(),
+                rotation_style: target
+                    .rotation_style
+                    .clone()
+                    .unwrap_or_else(|| "all around".into()),
+                draggable: target.draggable.unwrap_or(false),
+                volume: target.volume,
+                effects: HashMap::new(),
+                sound_effects: HashMap::new(),
+                bubble: None,
+                variables: target
+                    .variables
+                    .iter()
+                    .map(|(id, v)| {
+                        let value = Value::from_json(&v.value);
+                        (
+                            id.clone(),
+                            Variable {
+                                name: v.name.clone(),
+                                value,
+                            },
+                        )
+                    })
+                    .collect(),
+                lists: target
+                    .lists
+                    .iter()
+                    .map(|(id, l)| {
+                        let items = l.items.iter().map(Value::from_json).collect();
+                        (
+                            id.clone(),
+                            List {
+                                name: l.name.clone(),
+                                items,
+                            },
+                        )
+                    })
+                    .collect(),
+            });
+        }
+        interpreter.monitors = project
+            .monitors
+            .iter()
+            .filter(|monitor| monitor.get("visible").and_then(|v| v.as_bool()) == Some(true))
+            .filter_map(|monitor| Some(monitor.get("id")?.as_str()?.to_string()))
+            .collect();
+        interpreter
+    }
+
+    pub fn now(&self) -> f64 {
+        self.frame as f64 / FRAMES_PER_SECOND
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.threads.iter().any(|thread| !thread.is_done)
+    }
+
+    pub fn stage(&self) -> &Sprite {
+        &self.sprites[0]
+    }
+
+    pub fn green_flag(&mut self) {
+        self.stop_all();
+        self.start_hats("event_whenflagclicked", |_| true, None);
+    }
+
+    /// Stops every script and removes every clone
+    pub fn stop_all(&mut self) {
+        // they're removed at the end of the frame, since one of them might be running right now
+        for thread in &mut self.threads {
+            thread.is_done = true;
+        }
+        self.sprites.retain(|sprite| !sprite.is_clone);
+        for sprite in &mut self.sprites {
+            sprite.bubble = None;
+            sprite.effects.clear();
+        }
+        self.question = None;
+        self.paused = None;
+        self.step_mode = StepMode::Continue;
+        self.resuming = None;
+    }
+
+    /// returns the started threads
+    pub fn broadcast(&mut self, name: &str) -> Vec<usize> {
+        self.start_hats(
+            "event_whenbroadcastreceived",
+            |block| field(block, "BROADCAST_OPTION").eq_ignore_ascii_case(name),
+            None,
+        )
+    }
+
+    /// Keys are named the way the key menus have them, e.g. "space" or "a"
+    pub fn press_key(&mut self, key: &str) {
+        self.pressed_keys.insert(key.to_string());
+        self.start_hats(
+            "event_whenkeypressed",
+            |block| {
+                let option = field(block, "KEY_OPTION");
+                option == "any" || option.eq_ignore_ascii_case(key)
+            },
+            None,
+        );
+    }
+
+    pub fn release_key(&mut self, key: &str) {
+        self.pressed_keys.remove(key);
+    }
+
+    pub fn click(&mut self, sprite_id: usize) {
+        let Some(sprite) = self.sprites.iter().find(|s| s.id == sprite_id) else {
+            return;
+        };
+        let opcode = if sprite.is_stage {
+            "event_whenstageclicked"
+        } else {
+            "event_whenthisspriteclicked"
+        };
+        self.start_hats(opcode, |_| true, Some(sprite_id));
+    }
+
+    /// answers the question being asked, if there is one
+    pub fn answer_question(&mut self, text: &str) {
+        if let Some(question) = self.question.take() {
+            self.answer = text.to_string();
+            if let Some(sprite) = self.thread_sprite_mut(question.thread) {
+                sprite.bubble = None;
+            }
+        }
+    }
+
+    /// Runs a single frame
+    /// Runs a single frame, or the rest of one after a pause. Nothing happens while paused.
+    pub fn step(&mut self) {
+        if self.paused.is_some() {
+            return;
+        }
+        if self.thread_cursor == 0 {
+            self.check_edge_hats();
+        }
+        // threads started along the way get to run in the same frame
+        while self.thread_cursor < self.threads.len() {
+            let i = self.thread_cursor;
+            if !self.threads[i].is_done {
+                self.step_thread(i);
+            }
+            if self.paused.is_some() {
+                return;
+            }
+            let stepping = match &self.step_mode {
+                StepMode::Continue => None,
+                StepMode::Step { thread, .. } => *thread,
+                StepMode::StepOver { thread, .. } => Some(*thread),
+            };
+            if self.threads[i].is_done && stepping == Some(self.threads[i].id) {
+                // the script being stepped through is over, so stop at whatever runs next
+                self.step_mode = StepMode::Step {
+                    thread: None,
+                    depth: 0,
+                    block_id: String::new(),
+                };
+            }
+            self.thread_cursor += 1;
+        }
+        self.thread_cursor = 0;
+        self.threads.retain(|thread| !thread.is_done);
+        self.frame += 1;
+    }
+
+    /// keeps going until the next breakpoint
+    pub fn resume(&mut self) {
+        self.step_mode = StepMode::Continue;
+        self.unpause();
+    }
+
+    /// runs the block that's paused at, then pauses before the next one
+    pub fn step_block(&mut self) {
+        if let Some((thread, depth, block_id)) = self.pause_location() {
+            self.step_mode = StepMode::Step {
+                thread: Some(thread),
+                depth,
+                block_id,
+            };
+            self.unpause();
+        }
+    }
+
+    /// runs the block that's paused at along with anything inside of it, e.g. a C-block's body
+    /// or a custom block's definition, then pauses after it
+    pub fn step_over(&mut self) {
+        if let Some((thread, depth, block_id)) = self.pause_location() {
+            self.step_mode = StepMode::StepOver {
+                thread,
+                depth,
+                block_id,
+            };
+            self.unpause();
+        }
+    }
+
+    fn pause_location(&self) -> Option<(usize, usize, String)> {
+        let pause = self.paused.as_ref()?;
+        let thread = self.threads.iter().find(|t| t.id == pause.thread)?;
+        Some((thread.id, thread.stack.len(), pause.block_id.clone()))
+    }
+
+    fn unpause(&mut self) {
+        if let Some(pause) = self.paused.take() {
+            self.resuming = Some(pause.thread);
+        }
+    }
+
+    pub fn thread_list(&self) -> Vec<ThreadInfo> {
+        self.threads
+            .iter()
+            .filter(|thread| !thread.is_done)
+            .filter_map(|thread| {
+                let sprite = self.sprites.iter().find(|s| s.id == thread.sprite)?;
+                let blocks = &self.scripts[sprite.original];
+                let opcode_of = |id: &String| blocks.get(id).map(|block| block.opcode.clone());
+                Some(ThreadInfo {
+                    id: thread.id,
+                    sprite: sprite.name.clone(),
+                    is_clone: sprite.is_clone,
+                    top_opcode: opcode_of(&thread.top_id).unwrap_or_default(),
+                    opcode: thread
+                        .stack
+                        .last()
+                        .and_then(|frame| frame.block_id.as_ref())
+                        .and_then(opcode_of),
+                    depth: thread.stack.len().saturating_sub(1),
+                })
+            })
+            .collect()
+    }
+
+    /// whether the thread should stop before running the block
+    fn should_pause(&mut self, i: usize, block_id: &str) -> bool {
+        let thread = &self.threads[i];
+        if self.resuming == Some(thread.id) {
+            self.resuming = None;
+            return false;
+        }
+        let depth = thread.stack.len();
+        let is_stepping = match &self.step_mode {
+            StepMode::Continue => false,
+            StepMode::Step { thread: None, .. } => true,
+            StepMode::Step {
+                thread: Some(stepping),
+                depth: old_depth,
+                block_id: old_block_id,
+            } => *stepping == thread.id && (depth != *old_depth || block_id != old_block_id),
+            StepMode::StepOver {
+                thread: stepping,
+                depth: old_depth,
+                block_id: old_block_id,
+            } => {
+                *stepping == thread.id
+                    && (depth < *old_depth || (depth == *old_depth && block_id != old_block_id))
+            }
+        };
+        let original = self.me_ref(i).original;
+        is_stepping || self.breakpoints.contains(&(original, block_id.to_string()))
+    }
+
+    /// splitmix64, between 0 and 1
+    fn random(&mut self) -> f64 {
+        self.rng = self.rng.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.rng;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        (z >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn timer(&self) -> f64 {
+        self.now() - self.timer_start
+    }
+
+    fn check_edge_hats(&mut self) {
+        let mut triggered = vec![];
+        for sprite in self.sprites.iter().rev() {
+            let blocks = &self.scripts[sprite.original];
+            for top_id in &self.hats[sprite.original] {
+                let block = &blocks[top_id];
+                if block.opcode != "event_whengreaterthan" {
+                    continue;
+                }
+                let threshold = literal_input(blocks, block, "VALUE").to_number();
+                // there's no microphone, so loudness is always -1 like in scratch
+                let measured = match field(block, "WHENGREATERTHANMENU") {
+                    "TIMER" => self.timer(),
+                    _ => -1.0,
+                };
+                let is_over = measured > threshold;
+                let key = (sprite.id, top_id.clone());
+                let was_over = self.edge_hats.get(&key).copied().unwrap_or(false);
+                if is_over && !was_over {
+                    triggered.push((sprite.id, top_id.clone()));
+                }
+                self.edge_hats.insert(key, is_over);
+            }
+        }
+        for (sprite_id, top_id) in triggered {
+            self.start_thread(sprite_id, &top_id);
+        }
+    }
+
+    /// Starts every script under a matching hat, starting over ones that are already running
+    fn start_hats(
+        &mut self,
+        opcode: &str,
+        matches: impl Fn(&Block) -> bool,
+        only: Option<usize>,
+    ) -> Vec<usize> {
+        let mut to_start = vec![];
+        // front to back, like scratch
+        for sprite in self.sprites.iter().rev() {
+            if only.is_some_and(|id| id != sprite.id) {
+                continue;
+            }
+            let blocks = &self.scripts[sprite.original];
+            for top_id in &self.hats[sprite.original] {
+                let block = &blocks[top_id];
+                if block.opcode == opcode && matches(block) {
+                    to_start.push((sprite.id, top_id.clone()));
+                }
+            }
+        }
+        to_start
+            .into_iter()
+            .map(|(sprite_id, top_id)| self.start_thread(sprite_id, &top_id))
+            .collect()
+    }
+
+    fn start_thread(&mut self, sprite_id: usize, top_id: &str) -> usize {
+        let Some(sprite) = self.sprites.iter().find(|s| s.id == sprite_id) else {
+            return usize::MAX;
+        };
+        let frame = Frame {
+            block_id: self.scripts[sprite.original][top_id].next_id.clone(),
+            is_loop: false,
+            warp: false,
+            is_procedure: false,
+            arguments: Rc::default(),
+            state: BlockState::Fresh,
+        };
+        if let Some(thread) = self
+            .threads
+            .iter_mut()
+            .find(|t| !t.is_done && t.sprite == sprite_id && t.top_id == top_id)
+        {
+            thread.stack = vec![frame];
+            thread.restarted = true;
+            return thread.id;
+        }
+        let id = self.next_thread_id;
+        self.next_thread_id += 1;
+        self.threads.push(Thread {
+            id,
+            sprite: sprite_id,
+            top_id: top_id.to_string(),
+            stack: vec![frame],
+            is_done: false,
+            restarted: false,
+        });
+        id
+    }
+
+    fn is_thread_running(&self, id: usize) -> bool {
+        self.threads.iter().any(|t| t.id == id && !t.is_done)
+    }
+
+    fn thread_sprite_mut(&mut self, thread_id: usize) -> Option<&mut Sprite> {
+        let sprite_id = self.threads.iter().find(|t| t.id == thread_id)?.sprite;
+        self.sprites.iter_mut().find(|s| s.id == sprite_id)
+    }
+
+    fn step_thread(&mut self, i: usize) {
+        self.threads[i].restarted = false;
+        for _ in 0..BLOCK_LIMIT {
+            let thread = &mut self.threads[i];
+            if thread.is_done {
+                return;
+            }
+            let Some(frame) = thread.stack.last() else {
+                thread.is_done = true;
+                return;
+            };
+            let Some(block_id) = frame.block_id.clone() else {
+                let finished = thread.stack.pop().unwrap();
+                if thread.stack.is_empty() {
+                    thread.is_done = true;
+                    return;
+                }
+                if !finished.is_loop {
+                    self.advance(i);
+                } else if !finished.warp {
+                    // loops go around once a frame
+                    return;
+                }
+                continue;
+            };
+            let blocks = self.blocks_of(i);
+            let Some(block) = blocks.get(&block_id) else {
+                self.threads[i].is_done = true;
+                return;
+            };
+            if self.should_pause(i, &block_id) {
+                self.paused = Some(Pause {
+                    thread: self.threads[i].id,
+                    target: self.me_ref(i).original,
+                    block_id,
+                });
+                return;
+            }
+            let flow = self.execute(i, block);
+            if std::mem::take(&mut self.threads[i].restarted) {
+                return;
+            }
+            match flow {
+                Flow::Next => self.advance(i),
+                Flow::Yield => return,
+                Flow::Branch(input_name, is_loop) => {
+                    let thread = &mut self.threads[i];
+                    let frame = thread.stack.last().unwrap();
+                    let warp = frame.warp;
+                    match block
+                        .inputs
+                        .get(input_name)
+                        .and_then(|i| i.block_id.clone())
+                    {
+                        Some(substack_id) => {
+                            let arguments = frame.arguments.clone();
+                            thread.stack.push(Frame {
+                                block_id: Some(substack_id),
+                                is_loop,
+                                warp,
+                                is_procedure: false,
+                                arguments,
+                                state: BlockState::Fresh,
+                            });
+                        }
+                        // an empty loop still only goes around once a frame
+                        None if is_loop && !warp => return,
+                        None if is_loop => (),
+                        None => self.advance(i),
+                    }
+                }
+                Flow::Call {
+                    body,
+                    arguments,
+                    warp,
+                } => match body {
+                    Some(body) => {
+                        let thread = &mut self.threads[i];
+                        let warp = warp || thread.stack.last().unwrap().warp;
+                        thread.stack.push(Frame {
+                            block_id: Some(body),
+                            is_loop: false,
+                            warp,
+                            is_procedure: true,
+                            arguments: Rc::new(arguments),
+                            state: BlockState::Fresh,
+                        });
+                    }
+                    None => self.advance(i),
+                },
+                Flow::Stop => {
+                    self.threads[i].is_done = true;
+                    return;
+                }
+                Flow::Return => {
+                    let stack = &mut self.threads[i].stack;
+                    match stack.iter().rposition(|frame| frame.is_procedure) {
+                        Some(index) => {
+                            stack.truncate(index);
+                            self.advance(i);
+                        }
+                        None => {
+                            self.threads[i].is_done = true;
+                            return;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// moves on to the next block in the stack
+    fn advance(&mut self, i: usize) {
+        let blocks = self.blocks_of(i);
+        let frame = self.threads[i].stack.last_mut().unwrap();
+        frame.block_id = frame
+            .block_id
+            .as_ref()
+            .and_then(|id| blocks.get(id)?.next_id.clone());
+        frame.state = BlockState::Fresh;
+    }
+
+    fn blocks_of(&self, i: usize) -> Rc<HashMap<String, Block>> {
+        let original = self.me_ref(i).original;
+        Rc::clone(&self.scripts[original])
+    }
+
+    fn me_ref(&self, i: usize) -> &Sprite {
+        let sprite_id = self.threads[i].sprite;
+        self.sprites.iter().find(|s| s.id == sprite_id).unwrap()
+    }
+
+    fn me(&mut self, i: usize) -> &mut Sprite {
+        let sprite_id = self.threads[i].sprite;
+        self.sprites.iter_mut().find(|s| s.id == sprite_id).unwrap()
+    }
+
+    fn state(&mut self, i: usize) -> &mut BlockState {
+        &mut self.threads[i].stack.last_mut().unwrap().state
+    }
+
+    /// Original sprites by name, or the stage for `_stage_`
+    fn sprite_named(&self, name: &str) -> Option<&Sprite> {
+        if name == "_stage_" {
+            return Some(self.stage());
+        }
+        self.sprites
+            .iter()
+            .find(|s| !s.is_clone && !s.is_stage && s.name == name)
+    }
+
+    fn input(&mut self, i: usize, block: &Block, input_name: &str) -> Value {
+        let Some(input) = block.inputs.get(input_name) else {
+            return Value::Str(String::new());
+        };
+        match input.block_id.as_ref().or(input.shadow_id.as_ref()) {
+            Some(id) => {
+                let blocks = self.blocks_of(i);
+                match blocks.get(id) {
+                    Some(child) => self.evaluate(i, child),
+                    None => Value::Str(String::new()),
+                }
+            }
+            None => Value::Str(String::new()),
+        }
+    }
+
+    fn number(&mut self, i: usize, block: &Block, input_name: &str) -> f64 {
+        self.input(i, block, input_name).to_number()
+    }
+
+    fn string(&mut self, i: usize, block: &Block, input_name: &str) -> String {
+        self.input(i, block, input_name).to_string()
+    }
+
+    fn condition(&mut self, i: usize, block: &Block) -> bool {
+        self.input(i, block, "CONDITION").to_bool()
+    }
+
+    /// Finds a variable or list by id, then by name, looking in the sprite before the stage.
+    /// Anything missing is made on the sprite.
+    fn data_location(&mut self, i: usize, block: &Block, is_list: bool) -> (usize, String) {
+        let field_name = if is_list { "LIST" } else { "VARIABLE" };
+        let (id, name) = block
+            .fields
+            .get(field_name)
+            .map(|f| (f.id.clone(), f.value.clone()))
+            .unwrap_or_default();
+        let sprite_index = self
+            .sprites
+            .iter()
+            .position(|s| s.id == self.threads[i].sprite)
+            .unwrap();
+        let has = |sprite: &Sprite, matches: &dyn Fn(&str, &str) -> bool| -> Option<String> {
+            if is_list {
+                sprite
+                    .lists
+                    .iter()
+                    .find(|(k, l)| matches(k, &l.name))
+                    .map(|(k, _)| k.clone())
+            } else {
+                sprite
+                    .variables
+                    .iter()
+                    .find(|(k, v)| matches(k, &v.name))
+                    .map(|(k, _)| k.clone())
+            }
+        };
+        let by_id = |k: &str, _: &str| Some(k) == id.as_deref();
+        let by_name = |_: &str, n: &str| n == name;
+        for matches in [&by_id as &dyn Fn(&str, &str) -> bool, &by_name] {
+            for index in [sprite_index, 0] {
+                if let Some(key) = has(&self.sprites[index], matches) {
+                    return (index, key);
+                }
+            }
+        }
+        let key = id.unwrap_or_else(|| name.clone());
+        let sprite = &mut self.sprites[sprite_index];
+        if is_list {
+            sprite.lists.insert(
+                key.clone(),
+                List {
+                    name,
+                    items: vec![],
+                },
+            );
+        } else {
+            let value = Value::Num(0.0);
+            sprite
+                .variables
+                .insert(key.clone(), Variable { name, value });
+        }
+        (sprite_index, key)
+    }
+
+    fn variable(&mut self, i: usize, block: &Block) -> &mut Variable {
+        let (index, key) = self.data_location(i, block, false);
+        &mut self.sprites[index].variables[&key]
+    }
+
+    fn list(&mut self, i: usize, block: &Block) -> &mut List {
+        let (index, key) = self.data_location(i, block, true);
+        &mut self.sprites[index].lists[&key]
+    }
+
+    fn list_index(&mut self, index: &Value, length: usize, accept_all: bool) -> ListIndex {
+        if let Value::Str(s) = index {
+            match s.as_str() {
+                "all" if accept_all => return ListIndex::All,
+                "all" => return ListIndex::Invalid,
+                "last" if length > 0 => return ListIndex::At(length - 1),
+                "random" | "any" if length > 0 => {
+                    return ListIndex::At((self.random() * length as f64) as usize)
+                }
+                "last" | "random" | "any" => return ListIndex::Invalid,
+                _ => (),
+            }
+        }
+        let index = index.to_number().floor();
+        if index < 1.0 || index > length as f64 {
+            ListIndex::Invalid
+        } else {
+            ListIndex::At(index as usize - 1)
+        }
+    }
+
+    fn say(&mut self, i: usize, text: String, is_think: bool) {
+        let sprite = self.me(i);
+        let name = sprite.name.clone();
+        if text.is_empty() {
+            sprite.bubble = None;
+            return;
+        }
+        sprite.bubble = Some(Bubble {
+            text: text.clone(),
+            is_think,
+        });
+        self.output.push(if is_think {
+            Output::Think { sprite: name, text }
+        } else {
+            Output::Say { sprite: name, text }
+        });
+    }
+
+    /// takes a costume name, a number, or one of the special menu entries
+    fn switch_costume(&mut self, i: usize, requested: Value, is_backdrop: bool) -> Vec<usize> {
+        let sprite = if is_backdrop {
+            &mut self.sprites[0]
+        } else {
+            self.me(i)
+        };
+        let current = sprite.costume as isize;
+        let requested_name = requested.to_string();
+        if let Value::Num(n) = requested {
+            sprite.set_costume(n.round() as isize - 1);
+        } else if let Some(index) = sprite.costumes.iter().position(|c| *c == requested_name) {
+            sprite.costume = index;
+        } else {
+            match (requested_name.as_str(), is_backdrop) {
+                ("next costume", false) | ("next backdrop", true) => {
+                    sprite.set_costume(current + 1)
+                }
+                ("previous costume", false) | ("previous backdrop", true) => {
+                    sprite.set_costume(current - 1)
+                }
+                ("random backdrop", true) => {
+                    let count = sprite.costumes.len();
+                    if count > 1 {
+                        let offset = (self.random() * (count - 1) as f64) as isize + 1;
+                        self.sprites[0].set_costume(current + offset);
+                    }
+                }
+                _ => {
+                    let n = requested.strict_number();
+                    if n.is_finite() {
+                        sprite.set_costume(n.round() as isize - 1);
+                    }
+                }
+            }
+        }
+        if !is_backdrop {
+            return vec![];
+        }
+        let backdrop = self.stage().costume_name();
+        self.start_hats(
+            "event_whenbackdropswitchesto",
+            |block| field(block, "BACKDROP").eq_ignore_ascii_case(&backdrop),
+            None,
+        )
+    }
+
+    /// waits for the started threads to finish, over as many frames as it takes
+    fn wait_for_threads(&mut self, i: usize, start: impl FnOnce(&mut Self) -> Vec<usize>) -> Flow {
+        let waiting = match std::mem::take(self.state(i)) {
+            BlockState::Waiting(ids) => ids,
+            _ => start(self),
+        };
+        if waiting.iter().any(|&id| self.is_thread_running(id)) {
+            *self.state(i) = BlockState::Waiting(waiting);
+            Flow::Yield
+        } else {
+            Flow::Next
+        }
+    }
+
+    /// Runs for a while, yielding until the time is up
+    fn wait_for(&mut self, i: usize, seconds: impl FnOnce(&mut Self) -> f64) -> Option<Flow> {
+        let now = self.now();
+        match *self.state(i) {
+            BlockState::Fresh => {
+                let until = now + seconds(self).max(0.0);
+                *self.state(i) = BlockState::Timer(until);
+                Some(Flow::Yield)
+            }
+            BlockState::Timer(until) if now < until => Some(Flow::Yield),
+            _ => None,
+        }
+    }
+
+    /// Where a motion menu points to: the mouse, somewhere random or a sprite
+    fn menu_position(&mut self, i: usize, block: &Block, input_name: &str) -> Option<(f64, f64)> {
+        match self.string(i, block, input_name).as_str() {
+            "_mouse_" => Some(self.mouse),
+            "_random_" => {
+                let x = (STAGE_WIDTH * self.random() - STAGE_WIDTH / 2.0).round();
+                let y = (STAGE_HEIGHT * self.random() - STAGE_HEIGHT / 2.0).round();
+                Some((x, y))
+            }
+            name => self
+                .sprite_named(name)
+                .filter(|s| !s.is_stage)
+                .map(|s| (s.x, s.y)),
+        }
+    }
+
+    fn execute(&mut self, i: usize, block: &Block) -> Flow {
+        match block.opcode.as_str() {
+            // ==== motion ====
+            "motion_movesteps" => {
+                let steps = self.number(i, block, "STEPS");
+                let me = self.me(i);
+                let radians = (90.0 - me.direction).to_radians();
+                me.set_position(me.x + steps * radians.cos(), me.y + steps * radians.sin());
+            }
+            "motion_turnright" | "motion_turnleft" => {
+                let mut degrees = self.number(i, block, "DEGREES");
+                if block.opcode == "motion_turnleft" {
+                    degrees = -degrees;
+                }
+                let me = self.me(i);
+                me.set_direction(me.direction + degrees);
+            }
+            "motion_goto" => {
+                if let Some((x, y)) = self.menu_position(i, block, "TO") {
+                    self.me(i).set_position(x, y);
+                }
+            }
+            "motion_gotoxy" => {
+                let x = self.number(i, block, "X");
+                let y = self.number(i, block, "Y");
+                self.me(i).set_position(x, y);
+            }
+            "motion_glideto" | "motion_glidesecstoxy" => {
+                if let BlockState::Fresh = self.state(i) {
+                    let duration = self.number(i, block, "SECS");
+                    let to = if block.opcode == "motion_glideto" {
+                        self.menu_position(i, block, "TO")
+                    } else {
+                        Some((self.number(i, block, "X"), self.number(i, block, "Y")))
+                    };
+                    let Some(to) = to else {
+                        return Flow::Next;
+                    };
+                    let me = self.me(i);
+                    let from = (me.x, me.y);
+                    if duration <= 0.0 {
+                        me.set_position(to.0, to.1);
+                        return Flow::Next;
+                    }
+                    let start = self.now();
+                    *self.state(i) = BlockState::Glide {
+                        from,
+                        to,
+                        start,
+                        duration,
+                    };
+                    return Flow::Yield;
+                }
+                let now = self.now();
+                if let BlockState::Glide {
+                    from,
+                    to,
+                    start,
+                    duration,
+                } = *self.state(i)
+                {
+                    let t = (now - start) / duration;
+                    let me = self.me(i);
+                    if t >= 1.0 {
+                        me.set_position(to.0, to.1);
+                    } else {
+                        me.set_position(from.0 + (to.0 - from.0) * t, from.1 + (to.1 - from.1) * t);
+                        return Flow::Yield;
+                    }
+                }
+            }
+            "motion_pointindirection" => {
+                let direction = self.number(i, block, "DIRECTION");
+                self.me(i).set_direction(direction);
+            }
+            "motion_pointtowards" => {
+                if self.string(i, block, "TOWARDS") == "_random_" {
+                    let direction = (self.random() * 360.0).round() - 180.0;
+                    self.me(i).set_direction(direction);
+                } else if let Some((x, y)) = self.menu_position(i, block, "TOWARDS") {
+                    let me = self.me(i);
+                    let (dx, dy) = (x - me.x, y - me.y);
+                    me.set_direction(90.0 - dy.atan2(dx).to_degrees());
+                }
+            }
+            "motion_changexby" => {
+                let dx = self.number(i, block, "DX");
+                let me = self.me(i);
+                me.set_position(me.x + dx, me.y);
+            }
+            "motion_setx" => {
+                let x = self.number(i, block, "X");
+                let me = self.me(i);
+                me.set_position(x, me.y);
+            }
+            "motion_changeyby" => {
+                let dy = self.number(i, block, "DY");
+                let me = self.me(i);
+                me.set_position(me.x, me.y + dy);
+            }
+            "motion_sety" => {
+                let y = self.number(i, block, "Y");
+                let me = self.me(i);
+                me.set_position(me.x, y);
+            }
+            "motion_ifonedgebounce" => {
+                let me = self.me(i);
+                if me.x.abs() >= STAGE_WIDTH / 2.0 {
+                    me.set_direction(-me.direction);
+                }
+                if me.y.abs() >= STAGE_HEIGHT / 2.0 {
+                    me.set_direction(180.0 - me.direction);
+                }
+            }
+            "motion_setrotationstyle" => {
+                self.me(i).rotation_style = field(block, "STYLE").to_string();
+            }
+
+            // ==== looks ====
+            "looks_say" | "looks_think" => {
+                let text = self.string(i, block, "MESSAGE");
+                self.say(i, text, block.opcode == "looks_think");
+            }
+            "looks_sayforsecs" | "looks_thinkforsecs" => {
+                let is_think = block.opcode == "looks_thinkforsecs";
+                if let BlockState::Fresh = self.state(i) {
+                    let text = self.string(i, block, "MESSAGE");
+                    self.say(i, text, is_think);
+                }
+                if let Some(flow) = self.wait_for(i, |this| this.number(i, block, "SECS")) {
+                    return flow;
+                }
+                self.me(i).bubble = None;
+            }
+            "looks_switchcostumeto" => {
+                let costume = self.input(i, block, "COSTUME");
+                self.switch_costume(i, costume, false);
+            }
+            "looks_nextcostume" => {
+                let me = self.me(i);
+                me.set_costume(me.costume as isize + 1);
+            }
+            "looks_switchbackdropto" => {
+                let backdrop = self.input(i, block, "BACKDROP");
+                self.switch_costume(i, backdrop, true);
+            }
+            "looks_switchbackdroptoandwait" => {
+                return self.wait_for_threads(i, |this| {
+                    let backdrop = this.input(i, block, "BACKDROP");
+                    this.switch_costume(i, backdrop, true)
+                });
+            }
+            "looks_nextbackdrop" => {
+                self.switch_costume(i, Value::Str("next backdrop".into()), true);
+            }
+            "looks_changesizeby" => {
+                let change = self.number(i, block, "CHANGE");
+                let me = self.me(i);
+                me.size = (me.size + change).max(0.0);
+            }
+            "looks_setsizeto" => {
+                let size = self.number(i, block, "SIZE");
+                self.me(i).size = size.max(0.0);
+            }
+            "looks_changeeffectby" | "looks_seteffectto" => {
+                let effect = field(block, "EFFECT").to_lowercase();
+                let value = if block.opcode == "looks_changeeffectby" {
+                    self.number(i, block, "CHANGE")
+                } else {
+                    self.number(i, block, "VALUE")
+                };
+                let me = self.me(i);
+                let old = me.effects.get(&effect).copied().unwrap_or(0.0);
+                let mut new = if block.opcode == "looks_changeeffectby" {
+                    old + value
+                } else {
+                    value
+                };
+                match effect.as_str() {
+                    "ghost" => new = new.clamp(0.0, 100.0),
+                    "brightness" => new = new.clamp(-100.0, 100.0),
+                    _ => (),
+                }
+                me.effects.insert(effect, new);
+            }
+            "looks_cleargraphiceffects" => self.me(i).effects.clear(),
+            "looks_show" => self.me(i).visible = true,
+            "looks_hide" => self.me(i).visible = false,
+            "looks_gotofrontback" | "looks_goforwardbackwardlayers" => {
+                let Some(index) = self
+                    .sprites
+                    .iter()
+                    .position(|s| s.id == self.threads[i].sprite)
+                else {
+                    return Flow::Next;
+                };
+                if index == 0 {
+                    return Flow::Next;
+                }
+                let last = self.sprites.len() - 1;
+                let new_index = if block.opcode == "looks_gotofrontback" {
+                    if field(block, "FRONT_BACK") == "front" {
+                        last
+                    } else {
+                        1
+                    }
+                } else {
+                    let mut layers = self.number(i, block, "NUM");
+                    if field(block, "FORWARD_BACKWARD") == "backward" {
+                        layers = -layers;
+                    }
+                    (index as f64 + layers.round()).clamp(1.0, last as f64) as usize
+                };
+                let sprite = self.sprites.remove(index);
+                self.sprites.insert(new_index, sprite);
+            }
+
+            // ==== sound ====
+            "sound_play" | "sound_playuntildone" | "sound_stopallsounds" => (),
+            "sound_changeeffectby" | "sound_seteffectto" => {
+                let effect = field(block, "EFFECT").to_lowercase();
+                let value = self.number(i, block, "VALUE");
+                let me = self.me(i);
+                let old = me.sound_effects.get(&effect).copied().unwrap_or(0.0);
+                let new = if block.opcode == "sound_changeeffectby" {
+                    old + value
+                } else {
+                    value
+                };
+                let new = match effect.as_str() {
+                    "pitch" => new.clamp(-360.0, 360.0),
+                    _ => new.clamp(-100.0, 100.0),
+                };
+                me.sound_effects.insert(effect, new);
+            }
+            "sound_cleareffects" => self.me(i).sound_effects.clear(),
+            "sound_changevolumeby" | "sound_setvolumeto" => {
+                let value = self.number(i, block, "VOLUME");
+                let me = self.me(i);
+                let volume = if block.opcode == "sound_changevolumeby" {
+                    me.volume + value
+                } else {
+                    value
+                };
+                me.volume = volume.clamp(0.0, 100.0);
+            }
+
+            // ==== events ====
+            "event_broadcast" => {
+                let name = self.string(i, block, "BROADCAST_INPUT");
+                self.broadcast(&name);
+            }
+            "event_broadcastandwait" => {
+                return self.wait_for_threads(i, |this| {
+                    let name = this.string(i, block, "BROADCAST_INPUT");
+                    this.broadcast(&name)
+                });
+            }
+
+            // ==== control ====
+            "control_wait" => {
+                if let Some(flow) = self.wait_for(i, |this| this.number(i, block, "DURATION")) {
+                    return flow;
+                }
+            }
+            "control_repeat" => {
+                let remaining = match *self.state(i) {
+                    BlockState::Count(n) => n,
+                    _ => self.number(i, block, "TIMES").round(),
+                };
+                if remaining >= 1.0 {
+                    *self.state(i) = BlockState::Count(remaining - 1.0);
+                    return Flow::Branch("SUBSTACK", true);
+                }
+            }
+            "control_forever" => return Flow::Branch("SUBSTACK", true),
+            "control_if" => {
+                if self.condition(i, block) {
+                    return Flow::Branch("SUBSTACK", false);
+                }
+            }
+            "control_if_else" => {
+                return if self.condition(i, block) {
+                    Flow::Branch("SUBSTACK", false)
+                } else {
+                    Flow::Branch("SUBSTACK2", false)
+                };
+            }
+            "control_wait_until" => {
+                if !self.condition(i, block) {
+                    return Flow::Yield;
+                }
+            }
+            "control_repeat_until" => {
+                if !self.condition(i, block) {
+                    return Flow::Branch("SUBSTACK", true);
+                }
+            }
+            "control_stop" => match field(block, "STOP_OPTION") {
+                "all" => {
+                    self.stop_all();
+                    return Flow::Stop;
+                }
+                "this script" => return Flow::Return,
+                _ => {
+                    let (thread_id, sprite_id) = (self.threads[i].id, self.threads[i].sprite);
+                    for thread in &mut self.threads {
+                        if thread.sprite == sprite_id && thread.id != thread_id {
+                            thread.is_done = true;
+                        }
+                    }
+                }
+            },
+            "control_create_clone_of" => {
+                let option = self.string(i, block, "CLONE_OPTION");
+                let clones = self.sprites.iter().filter(|s| s.is_clone).count();
+                let source_id = if option == "_myself_" {
+                    Some(self.threads[i].sprite)
+                } else {
+                    self.sprite_named(&option).map(|s| s.id)
+                };
+                let index = source_id.and_then(|id| self.sprites.iter().position(|s| s.id == id));
+                if let Some(index) = index.filter(|&index| index > 0 && clones < CLONE_LIMIT) {
+                    let id = self.next_sprite_id;
+                    self.next_sprite_id += 1;
+                    let clone = self.sprites[index].clone_of(id);
+                    // clones go just behind whatever they were cloned from
+                    self.sprites.insert(index, clone);
+                    self.start_hats("control_start_as_clone", |_| true, Some(id));
+                }
+            }
+            "control_delete_this_clone" => {
+                let sprite_id = self.threads[i].sprite;
+                if self.me_ref(i).is_clone {
+                    for thread in &mut self.threads {
+                        if thread.sprite == sprite_id {
+                            thread.is_done = true;
+                        }
+                    }
+                    self.sprites.retain(|s| s.id != sprite_id);
+                    return Flow::Stop;
+                }
+            }
+
+            // ==== sensing ====
+            "sensing_askandwait" => {
+                let thread_id = self.threads[i].id;
+                let is_asking = self
+                    .question
+                    .as_ref()
+                    .is_some_and(|q| q.thread == thread_id);
+                match self.state(i) {
+                    BlockState::Fresh => {
+                        if self.question.is_some() {
+                            // someone else is asking, so wait for our turn
+                            return Flow::Yield;
+                        }
+                        let question = self.string(i, block, "QUESTION");
+                        let me = self.me(i);
+                        let sprite = me.name.clone();
+                        if me.visible && !me.is_stage {
+                            me.bubble = Some(Bubble {
+                                text: question.clone(),
+                                is_think: false,
+                            });
+                        }
+                        self.output.push(Output::Ask {
+                            sprite: sprite.clone(),
+                            question: question.clone(),
+                        });
+                        self.question = Some(Question {
+                            thread: thread_id,
+                            sprite,
+                            text: question,
+                        });
+                        *self.state(i) = BlockState::Asking;
+                        return Flow::Yield;
+                    }
+                    _ if is_asking => {
+                        return Flow::Yield;
+                    }
+                    _ => (),
+                }
+            }
+            "sensing_setdragmode" => {
+                self.me(i).draggable = field(block, "DRAG_MODE") == "draggable";
+            }
+            "sensing_resettimer" => self.timer_start = self.now(),
+
+            // ==== data ====
+            "data_setvariableto" => {
+                let value = self.input(i, block, "VALUE");
+                self.variable(i, block).value = value;
+            }
+            "data_changevariableby" => {
+                let change = self.number(i, block, "VALUE");
+                let variable = self.variable(i, block);
+                variable.value = Value::Num(variable.value.to_number() + change);
+            }
+            "data_showvariable" | "data_hidevariable" | "data_showlist" | "data_hidelist" => {
+                let is_list = block.opcode.ends_with("list");
+                let (_, id) = self.data_location(i, block, is_list);
+                self.monitors.retain(|monitor| *monitor != id);
+                if block.opcode.starts_with("data_show") {
+                    self.monitors.push(id);
+                }
+            }
+            "data_addtolist" => {
+                let item = self.input(i, block, "ITEM");
+                let list = self.list(i, block);
+                if list.items.len() < LIST_LIMIT {
+                    list.items.push(item);
+                }
+            }
+            "data_deleteoflist" => {
+                let index = self.input(i, block, "INDEX");
+                let length = self.list(i, block).items.len();
+                match self.list_index(&index, length, true) {
+                    ListIndex::All => self.list(i, block).items.clear(),
+                    ListIndex::At(index) => {
+                        self.list(i, block).items.remove(index);
+                    }
+                    ListIndex::Invalid => (),
+                }
+            }
+            "data_deletealloflist" => self.list(i, block).items.clear(),
+            "data_insertatlist" => {
+                let item = self.input(i, block, "ITEM");
+                let index = self.input(i, block, "INDEX");
+                let length = self.list(i, block).items.len();
+                if let ListIndex::At(index) = self.list_index(&index, length + 1, false) {
+                    let list = self.list(i, block);
+                    if list.items.len() < LIST_LIMIT {
+                        list.items.insert(index, item);
+                    }
+                }
+            }
+            "data_replaceitemoflist" => {
+                let item = self.input(i, block, "ITEM");
+                let index = self.input(i, block, "INDEX");
+                let length = self.list(i, block).items.len();
+                if let ListIndex::At(index) = self.list_index(&index, length, false) {
+                    self.list(i, block).items[index] = item;
+                }
+            }
+
+            // ==== custom blocks ====
+            "procedures_call" => {
+                let Some(proccode) = block.mutation.as_ref().map(|m| &m.proccode) else {
+                    return Flow::Next;
+                };
+                let original = self.me_ref(i).original;
+                let blocks = self.blocks_of(i);
+                let Some(prototype) = self.procedures[original]
+                    .get(proccode)
+                    .and_then(|id| blocks.get(id))
+                else {
+                    return Flow::Next;
+                };
+                let mutation = prototype.mutation.clone().unwrap_or_default();
+                let mut arguments = HashMap::new();
+                for ((id, name), is_boolean) in mutation
+                    .argument_ids
+                    .iter()
+                    .zip(&mutation.argument_names)
+                    .zip(mutation.argument_kinds())
+                {
+                    let has_input = block
+                        .inputs
+                        .get(id)
+                        .is_some_and(|input| input.block_id.is_some() || input.shadow_id.is_some());
+                    let value = match (has_input, is_boolean) {
+                        (false, true) => Value::Bool(false),
+                        _ => self.input(i, block, id),
+                    };
+                    arguments.insert(name.clone(), value);
+                }
+                let body = prototype
+                    .parent_id
+                    .as_ref()
+                    .and_then(|definition_id| blocks.get(definition_id)?.next_id.clone());
+                return Flow::Call {
+                    body,
+                    arguments,
+                    warp: mutation.warp,
+                };
+            }
+            // reporters can be used as blocks of their own
+            _ => {
+                self.evaluate(i, block);
+            }
+        }
+        Flow::Next
+    }
+
+    fn evaluate(&mut self, i: usize, block: &Block) -> Value {
+        use Value::{Bool, Num, Str};
+        match block.opcode.as_str() {
+            // ==== shadows ====
+            "math_number"
+            | "math_integer"
+            | "math_whole_number"
+            | "math_positive_number"
+            | "math_angle" => Str(field(block, "NUM").to_string()),
+            "text" => Str(field(block, "TEXT").to_string()),
+            "colour_picker" => Str(field(block, "COLOUR").to_string()),
+
+            // ==== motion ====
+            "motion_xposition" => Num(limit_precision(self.me_ref(i).x)),
+            "motion_yposition" => Num(limit_precision(self.me_ref(i).y)),
+            "motion_direction" => Num(self.me_ref(i).direction),
+
+            // ==== looks ====
+            "looks_costumenumbername" | "looks_backdropnumbername" => {
+                let sprite = if block.opcode == "looks_costumenumbername" {
+                    self.me_ref(i)
+                } else {
+                    self.stage()
+                };
+                if field(block, "NUMBER_NAME") == "name" {
+                    Str(sprite.costume_name())
+                } else {
+                    Num(sprite.costume as f64 + 1.0)
+                }
+            }
+            "looks_size" => Num(self.me_ref(i).size.round()),
+
+            // ==== sound ====
+            "sound_volume" => Num(self.me_ref(i).volume),
+
+            // ==== sensing ====
+            "sensing_touchingobject" => {
+                let object = self.string(i, block, "TOUCHINGOBJECTMENU");
+                let me = self.me_ref(i);
+                let touching = match object.as_str() {
+                    "_edge_" => me.is_on_edge(),
+                    "_mouse_" => distance(me, self.mouse) < 1.0,
+                    name => self.sprites.iter().any(|other| {
+                        other.id != me.id
+                            && !other.is_stage
+                            && other.visible
+                            && other.name == name
+                            && distance(me, (other.x, other.y)) < 1.0
+                    }),
+                };
+                Bool(touching && !me.is_stage)
+            }
+            "sensing_touchingcolor" | "sensing_coloristouchingcolor" => Bool(false),
+            "sensing_distanceto" => {
+                let target = match self.string(i, block, "DISTANCETOMENU").as_str() {
+                    "_mouse_" => Some(self.mouse),
+                    name => self
+                        .sprite_named(name)
+                        .filter(|s| !s.is_stage)
+                        .map(|s| (s.x, s.y)),
+                };
+                let me = self.me_ref(i);
+                match target {
+                    Some(position) if !me.is_stage => Num(distance(me, position)),
+                    _ => Num(10000.0),
+                }
+            }
+            "sensing_answer" => Str(self.answer.clone()),
+            "sensing_keypressed" => {
+                let key = self.string(i, block, "KEY_OPTION");
+                Bool(if key == "any" {
+                    !self.pressed_keys.is_empty()
+                } else {
+                    self.pressed_keys
+                        .iter()
+                        .any(|k| k.eq_ignore_ascii_case(&key))
+                })
+            }
+            "sensing_mousedown" => Bool(self.mouse_down),
+            "sensing_mousex" => Num(self.mouse.0),
+            "sensing_mousey" => Num(self.mouse.1),
+            // there's no microphone
+            "sensing_loudness" => Num(-1.0),
+            "sensing_timer" => Num(self.timer()),
+            "sensing_of" => {
+                let object = self.string(i, block, "OBJECT");
+                let property = field(block, "PROPERTY");
+                let Some(sprite) = self.sprite_named(&object) else {
+                    return Num(0.0);
+                };
+                match (property, sprite.is_stage) {
+                    ("backdrop #", true) | ("costume #", false) => Num(sprite.costume as f64 + 1.0),
+                    ("backdrop name", true) | ("costume name", false) => Str(sprite.costume_name()),
+                    ("volume", _) => Num(sprite.volume),
+                    ("x position", false) => Num(limit_precision(sprite.x)),
+                    ("y position", false) => Num(limit_precision(sprite.y)),
+                    ("direction", false) => Num(sprite.direction),
+                    ("size", false) => Num(sprite.size.round()),
+                    (name, _) => sprite
+                        .variables
+                        .values()
+                        .find(|v| v.name == name)
+                        .map_or(Num(0.0), |v| v.value.clone()),
+                }
+            }
+            "sensing_current" => {
+                let now = self.start_time + self.now();
+                let days = (now / 86400.0).floor();
+                let (year, month, day) = civil_from_days(days as i64);
+                let seconds = now - days * 86400.0;
+                Num(match field(block, "CURRENTMENU") {
+                    "YEAR" => year as f64,
+                    "MONTH" => month as f64,
+                    "DATE" => day as f64,
+                    // 1970-01-01 was a thursday, and sunday is 1
+                    "DAYOFWEEK" => ((days as i64 + 4).rem_euclid(7) + 1) as f64,
+                    "HOUR" => (seconds / 3600.0).floor(),
+                    "MINUTE" => (seconds % 3600.0 / 60.0).floor(),
+                    "SECOND" => (seconds % 60.0).floor(),
+                    _ => 0.0,
+                })
+            }
+            "sensing_dayssince2000" => {
+                Num((self.start_time + self.now() - 946_684_800.0) / 86400.0)
+            }
+            "sensing_username" => Str(String::new()),
+
+            // ==== operators ====
+            "operator_add" => Num(self.number(i, block, "NUM1") + self.number(i, block, "NUM2")),
+            "operator_subtract" => {
+                Num(self.number(i, block, "NUM1") - self.number(i, block, "NUM2"))
+            }
+            "operator_multiply" => {
+                Num(self.number(i, block, "NUM1") * self.number(i, block, "NUM2"))
+            }
+            "operator_divide" => Num(self.number(i, block, "NUM1") / self.number(i, block, "NUM2")),
+            "operator_random" => {
+                let from = self.input(i, block, "FROM");
+                let to = self.input(i, block, "TO");
+                let (a, b) = (from.to_number(), to.to_number());
+                let (low, high) = (a.min(b), a.max(b));
+                if low == high {
+                    Num(low)
+                } else if from.is_int() && to.is_int() {
+                    Num(low + (self.random() * (high - low + 1.0)).floor())
+                } else {
+                    Num(low + self.random() * (high - low))
+                }
+            }
+            "operator_gt" | "operator_lt" | "operator_equals" => {
+                let a = self.input(i, block, "OPERAND1");
+                let b = self.input(i, block, "OPERAND2");
+                let ordering = compare(&a, &b);
+                Bool(match block.opcode.as_str() {
+                    "operator_gt" => ordering == Ordering::Greater,
+                    "operator_lt" => ordering == Ordering::Less,
+                    _ => ordering == Ordering::Equal,
+                })
+            }
+            "operator_and" => Bool(
+                self.input(i, block, "OPERAND1").to_bool()
+                    && self.input(i, block, "OPERAND2").to_bool(),
+            ),
+            "operator_or" => Bool(
+                self.input(i, block, "OPERAND1").to_bool()
+                    || self.input(i, block, "OPERAND2").to_bool(),
+            ),
+            "operator_not" => Bool(!self.input(i, block, "OPERAND").to_bool()),
+            "operator_join" => {
+                Str(self.string(i, block, "STRING1") + &self.string(i, block, "STRING2"))
+            }
+            "operator_letter_of" => {
+                let index = self.number(i, block, "LETTER") - 1.0;
+                let string = self.string(i, block, "STRING");
+                let letter = (index >= 0.0)
+                    .then(|| string.chars().nth(index as usize))
+                    .flatten();
+                Str(letter.map(String::from).unwrap_or_default())
+            }
+            "operator_length" => Num(self.string(i, block, "STRING").chars().count() as f64),
+            "operator_contains" => {
+                let haystack = self.string(i, block, "STRING1").to_lowercase();
+                let needle = self.string(i, block, "STRING2").to_lowercase();
+                Bool(haystack.contains(&needle))
+            }
+            "operator_mod" => {
+                let n = self.number(i, block, "NUM1");
+                let modulus = self.number(i, block, "NUM2");
+                let mut result = n % modulus;
+                if result / modulus < 0.0 {
+                    result += modulus;
+                }
+                Num(result)
+            }
+            "operator_round" => Num((self.number(i, block, "NUM") + 0.5).floor()),
+            "operator_mathop" => {
+                let n = self.number(i, block, "NUM");
+                // trig works in degrees, and gets rounded so that e.g. sin 180 is exactly 0
+                let rounded = |x: f64| (x * 1e10).round() / 1e10;
+                Num(match field(block, "OPERATOR") {
+                    "abs" => n.abs(),
+                    "floor" => n.floor(),
+                    "ceiling" => n.ceil(),
+                    "sqrt" => n.sqrt(),
+                    "sin" => rounded(n.to_radians().sin()),
+                    "cos" => rounded(n.to_radians().cos()),
+                    "tan" => match n % 360.0 {
+                        90.0 | -270.0 => f64::INFINITY,
+                        270.0 | -90.0 => f64::NEG_INFINITY,
+                        n => rounded(n.to_radians().tan()),
+                    },
+                    "asin" => n.asin().to_degrees(),
+                    "acos" => n.acos().to_degrees(),
+                    "atan" => n.atan().to_degrees(),
+                    "ln" => n.ln(),
+                    "log" => n.log10(),
+                    "e ^" => n.exp(),
+                    "10 ^" => 10f64.powf(n),
+                    _ => 0.0,
+                })
+            }
+
+            // ==== data ====
+            "data_variable" => self.variable(i, block).value.clone(),
+            "data_listcontents" => {
+                let items = &self.list(i, block).items;
+                let texts: Vec<_> = items.iter().map(Value::to_string).collect();
+                // lists of single letters are joined without spaces, like scratch does
+                if texts.iter().all(|t| t.chars().count() == 1) {
+                    Str(texts.concat())
+                } else {
+                    Str(texts.join(" "))
+                }
+            }
+            "data_itemoflist" => {
+                let index = self.input(i, block, "INDEX");
+                let length = self.list(i, block).items.len();
+                match self.list_index(&index, length, false) {
+                    ListIndex::At(index) => self.list(i, block).items[index].clone(),
+                    _ => Str(String::new()),
+                }
+            }
+            "data_itemnumoflist" => {
+                let item = self.input(i, block, "ITEM");
+                let items = &self.list(i, block).items;
+                let position = items
+                    .iter()
+                    .position(|other| compare(other, &item) == Ordering::Equal);
+                Num(position.map_or(0.0, |p| p as f64 + 1.0))
+            }
+            "data_lengthoflist" => Num(self.list(i, block).items.len() as f64),
+            "data_listcontainsitem" => {
+                let item = self.input(i, block, "ITEM");
+                let items = &self.list(i, block).items;
+                Bool(
+                    items
+                        .iter()
+                        .any(|other| compare(other, &item) == Ordering::Equal),
+                )
+            }
+
+            // ==== custom blocks ====
+            "argument_reporter_string_number" | "argument_reporter_boolean" => {
+                let arguments = &self.threads[i].stack.last().unwrap().arguments;
+                match arguments.get(field(block, "VALUE")) {
+                    Some(value) => value.clone(),
+                    None if block.opcode == "argument_reporter_boolean" => Bool(false),
+                    None => Num(0.0),
+                }
+            }
+
+            // menus hold their choice in their only field
+            _ => match block.fields.values().next() {
+                Some(field) if block.fields.len() == 1 => Str(field.value.clone()),
+                _ => Str(String::new()),
+            },
+        }
+    }
+}
+
+fn field<'b>(block: &'b Block, field_name: &str) -> &'b str {
+    block
+        .fields
+        .get(field_name)
+        .map_or("", |f| f.value.as_str())
+}
+
+/// the value of a shadow in the input, without running anything
+fn literal_input(blocks: &HashMap<String, Block>, block: &Block, input_name: &str) -> Value {
+    let shadow = block
+        .inputs
+        .get(input_name)
+        .and_then(|input| blocks.get(input.shadow_id.as_ref()?));
+    match shadow.and_then(|shadow| shadow.fields.values().next()) {
+        Some(field) => Value::Str(field.value.clone()),
+        None => Value::Str(String::new()),
+    }
+}
+
+fn distance(sprite: &Sprite, (x, y): (f64, f64)) -> f64 {
+    (sprite.x - x).hypot(sprite.y - y)
+}
+
+/// hides floating point noise like 0.30000000000000004 in positions
+fn limit_precision(coordinate: f64) -> f64 {
+    let rounded = coordinate.round();
+    if (coordinate - rounded).abs() < 1e-9 {
+        rounded
+    } else {
+        coordinate
+    }
+}
+
+/// year, month and day of the days since 1970-01-01
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env::temp_dir,
+        sync::atomic::{AtomicUsize, Ordering::Relaxed},
+    };
+
+    use super::*;
+    use crate::{
+        backend::{MemoryBackend, VariableType},
+        runtime::Runtime,
+        scratchblocks::{create_scripts, parse_scripts},
+    };
+
+    /// 2000-01-01 00:00 UTC
+    const START_TIME: f64 = 946_684_800.0;
+
+    /// tests run in parallel, so each project goes through a file of its own
+    static PROJECTS: AtomicUsize = AtomicUsize::new(0);
+
+    /// A project whose sprite has the given scripts, with `n` as a variable for all sprites,
+    /// `items` as a list and a `go` broadcast
+    fn project_of(scripts: &str) -> Project {
+        let mut runtime = Runtime::new(Box::new(MemoryBackend::default()));
+        runtime.new_project().unwrap();
+        runtime
+            .create_data(VariableType::Scalar, "n", false, false)
+            .unwrap();
+        runtime
+            .create_data(VariableType::List, "items", false, false)
+            .unwrap();
+        runtime
+            .create_data(VariableType::Broadcast, "go", false, false)
+            .unwrap();
+        let scripts = parse_scripts(&runtime, scripts).unwrap();
+        create_scripts(&mut runtime, &scripts, 0, 0).unwrap();
+        let n = PROJECTS.fetch_add(1, Relaxed);
+        let path = temp_dir().join(format!("viiru-interpreter-{}-{n}.sb3", std::process::id()));
+        let path = path.to_str().unwrap();
+        assert!(runtime.save_project(path).unwrap());
+        let project = Project::load(path).unwrap();
+        let _ = std::fs::remove_file(path);
+        project
+    }
+
+    /// clicks the green flag and runs the given number of frames
+    fn run(scripts: &str, frames: usize) -> Interpreter {
+        let mut interpreter = Interpreter::new(&project_of(scripts), 0, START_TIME);
+        interpreter.green_flag();
+        for _ in 0..frames {
+            interpreter.step();
+        }
+        interpreter
+    }
+
+    fn variable(interpreter: &Interpreter, name: &str) -> Value {
+        let variable = interpreter
+            .stage()
+            .variables
+            .values()
+            .find(|variable| variable.name == name)
+            .unwrap();
+        variable.value.clone()
+    }
+
+    fn said(interpreter: &mut Interpreter) -> Vec<String> {
+        interpreter
+            .output
+            .drain(..)
+            .map(|output| match output {
+                Output::Say { text, .. } | Output::Think { text, .. } => text,
+                Output::Ask { question, .. } => question,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn numbers_are_parsed_like_javascript() {
+        assert_eq!(parse_number(" 12 "), 12.0);
+        assert_eq!(parse_number("1e3"), 1000.0);
+        assert_eq!(parse_number("0x1F"), 31.0);
+        assert_eq!(parse_number("-Infinity"), f64::NEG_INFINITY);
+        assert!(parse_number("").is_nan());
+        assert!(parse_number("inf").is_nan());
+        assert!(parse_number("nan").is_nan());
+        assert!(parse_number("12abc").is_nan());
+    }
+
+    #[test]
+    fn comparing_falls_back_to_text() {
+        use Value::{Bool, Num, Str};
+        assert_eq!(compare(&Str("10".into()), &Num(9.0)), Ordering::Greater);
+        assert_eq!(
+            compare(&Str("apple".into()), &Str("APPLE".into())),
+            Ordering::Equal
+        );
+        assert_eq!(compare(&Str("a".into()), &Str("b".into())), Ordering::Less);
+        assert_eq!(compare(&Bool(true), &Num(1.0)), Ordering::Equal);
+        // blank text isn't zero
+        assert_eq!(compare(&Str("".into()), &Num(0.0)), Ordering::Less);
+    }
+
+    #[test]
+    fn truthiness_follows_scratch() {
+        use Value::{Bool, Num, Str};
+        assert!(Str("hello".into()).to_bool());
+        assert!(!Str("".into()).to_bool());
+        assert!(!Str("0".into()).to_bool());
+        assert!(!Str("FALSE".into()).to_bool());
+        assert!(Str("0.0".into()).to_bool());
+        assert!(!Num(0.0).to_bool());
+        assert!(!Num(f64::NAN).to_bool());
+        assert!(Bool(true).to_bool());
+    }
+
+    #[test]
+    fn loops_yield_once_per_iteration() {
+        let mut interpreter = run(
+            "when flag clicked\nrepeat (10)\nchange [n v] by (1)\nend",
+            3,
+        );
+        assert_eq!(variable(&interpreter, "n").to_number(), 3.0);
+        for _ in 0..10 {
+            interpreter.step();
+        }
+        assert_eq!(variable(&interpreter, "n").to_number(), 10.0);
+        assert!(!interpreter.is_running());
+    }
+
+    #[test]
+    fn clones_run_their_own_hats() {
+        let interpreter = run(
+            "when flag clicked\ncreate a clone of (myself v)\ncreate a clone of (myself v)\n\n\
+             when I start as a clone\nchange [n v] by (1)",
+            5,
+        );
+        let clones = interpreter.sprites.iter().filter(|s| s.is_clone).count();
+        assert_eq!(clones, 2);
+        assert_eq!(variable(&interpreter, "n").to_number(), 2.0);
+    }
+
+    #[test]
+    fn broadcast_and_wait_waits_for_the_receivers() {
+        let mut interpreter = run(
+            "when flag clicked\nbroadcast (go v) and wait\nsay (n)\n\n\
+             when I receive [go v]\nrepeat (5)\nchange [n v] by (1)\nend",
+            20,
+        );
+        assert_eq!(said(&mut interpreter), ["5"]);
+    }
+
+    #[test]
+    fn stop_all_ends_every_script() {
+        let interpreter = run(
+            "when flag clicked\nforever\nchange [n v] by (1)\nend\n\n\
+             when flag clicked\nwait (0.1) seconds\nstop [all v]",
+            30,
+        );
+        assert!(!interpreter.is_running());
+        // the loop only got as far as the wait let it
+        assert_eq!(variable(&interpreter, "n").to_number(), 4.0);
+    }
+
+    #[test]
+    fn the_date_starts_where_it_is_told_to() {
+        let mut interpreter = run(
+            "when flag clicked\nsay (current (year v))\nsay (current (month v))\n\
+             say (days since 2000)",
+            1,
+        );
+        assert_eq!(said(&mut interpreter), ["2000", "1", "0"]);
+    }
+}
@@ -1,21 +1,45 @@
-mod block;
-mod bridge;
-mod opcodes;
-mod result;
-mod runtime;
-mod spec;
-mod ui;
+pub mod actions;
+pub mod backend;
+pub mod block;
+pub mod bridge;
+pub mod commands;
+pub mod config;
+pub mod definitions;
+pub mod extensions;
+pub mod history;
+pub mod interpreter;
+pub mod lint;
+pub mod opcodes;
+pub mod project;
+pub mod references;
+pub mod result;
+pub mod runtime;
+pub mod scratchblocks;
+pub mod spec;
+pub mod ui;
 mod util;
 
-use std::io::stdout;
+use std::{
+    io::stdout,
+    path::Path,
+    time::{Duration, Instant},
+};
 
+use actions::{open_project, pane_action, perform, write_project, Action, Outcome};
+use bridge::NeonBackend;
+use commands::{add_to_history, browse_history, complete, execute, load_history};
+use config::Config;
 use crossterm::{
-    event::{read, KeyCode, KeyEventKind},
+    event::{poll, read, Event, KeyCode, KeyEventKind},
     execute,
     terminal::{window_size, Clear, ClearType, WindowSize},
 };
+use definitions::load_user_definitions;
+use interpreter::FRAMES_PER_SECOND;
 use neon::prelude::*;
-use runtime::{Runtime, State};
+use references::Reference;
+use result::{undefined_or_throw, ViiruError, ViiruResult};
+use runtime::{DataPrompt, Runtime, State};
 use ui::{in_terminal_scope, Screen};
 
 #[neon::main]
@@ -26,407 +50,453 @@ fn main(mut cx: ModuleContext) -> NeonResult<()> {
 
 fn tui_main(mut cx: FunctionContext) -> JsResult<JsUndefined> {
     let api = cx.argument::<JsObject>(0)?;
-    let mut runtime = Runtime::new(&mut cx, api);
+    let mut runtime = Runtime::new(Box::new(NeonBackend { cx: &mut cx, api }));
 
-    let result = in_terminal_scope(|| {
-        // todo: replace with a proper implementation of "new project"
-        runtime.load_project("viiru_core/empty.sb3")?;
-        execute!(stdout(), Clear(ClearType::All))?;
+    // like the standalone editor, complain about broken files before taking over the screen
+    let result = load_user_definitions().and_then(|()| {
+        load_config(&mut runtime, None)?;
+        in_terminal_scope(|| {
+            runtime.new_project()?;
+            run_editor(&mut runtime)
+        })
+    });
 
-        runtime.viewport_offset_x = 3;
-        runtime.viewport_offset_y = 1;
-        runtime.toolbox_width = 45;
-        runtime.status_height = 5;
+    drop(runtime);
+    undefined_or_throw(&mut cx, result)
+}
 
-        let WindowSize { columns, rows, .. } = window_size()?;
+/// Reads the config file, reporting bindings that make no sense in the status bar
+pub fn load_config(runtime: &mut Runtime, path: Option<&Path>) -> ViiruResult {
+    let (config, warning) = Config::load(path)?;
+    runtime.config = config;
+    if let Some(warning) = warning {
+        runtime.status_message = warning;
+    }
+    Ok(())
+}
 
-        let mut screen = Screen::new(columns, rows);
+/// Runs the editor until the user quits. A project should already be loaded.
+pub fn run_editor(runtime: &mut Runtime) -> ViiruResult {
+    execute!(stdout(), Clear(ClearType::All))?;
 
-        runtime.set_viewport(columns, rows);
-        runtime.initialize_scroll();
+    runtime.viewport_offset_x = 3;
+    runtime.viewport_offset_y = 1;
+    runtime.toolbox_width = runtime.config.toolbox_width;
+    runtime.status_height = runtime.config.status_height;
+    runtime.command_history = load_history();
 
-        screen.clear();
-        screen.refresh_screen(&mut runtime)?;
-        screen.flush_contents()?;
-        screen.draw_cursor(&runtime)?;
-        let mut needs_refresh = false;
-        loop {
-            match read()? {
-                crossterm::event::Event::Key(event) => {
-                    if event.kind == KeyEventKind::Press {
-                        // suboptimal ordering, probably
-                        if let State::Command = runtime.state {
-                            match event.code {
-                                KeyCode::Enter => {
-                                    let buf = std::mem::take(&mut runtime.command_buffer);
-                                    match runtime.last_command {
-                                        'o' => {
-                                            if !runtime.load_project(&buf)? {
-                                                runtime.status_message =
-                                                    format!("Failed to open project file at {buf}");
-                                            } else {
-                                                runtime.status_message =
-                                                    format!("Opened project file {buf}");
-                                            }
-                                        }
-                                        'w' => {
-                                            if !runtime.save_project(&buf)? {
-                                                runtime.status_message = format!(
-                                                    "Failed to write project file to {buf}"
-                                                );
-                                            } else {
-                                                runtime.status_message =
-                                                    format!("Saved project into {buf}")
-                                            }
-                                        }
-                                        _ => (),
-                                    }
-                                    runtime.state = State::Move;
-                                }
-                                KeyCode::Esc => {
-                                    runtime.command_buffer.clear();
-                                    runtime.state = State::Move;
-                                }
-                                KeyCode::Backspace => {
-                                    runtime.command_buffer.pop();
+    let WindowSize { columns, rows, .. } = window_size()?;
+
+    let mut screen = Screen::new(columns, rows);
+
+    runtime.set_viewport(columns, rows);
+    runtime.initialize_scroll();
+
+    screen.clear();
+    screen.refresh_screen(runtime)?;
+    screen.flush_contents()?;
+    screen.draw_cursor(runtime)?;
+    let mut needs_refresh = false;
+    let frame_duration = Duration::from_secs_f64(1.0 / FRAMES_PER_SECOND);
+    let mut next_frame = Instant::now();
+    loop {
+        // the stage keeps running between key presses
+        let event = if let State::Run = runtime.state {
+            let now = Instant::now();
+            if now >= next_frame {
+                if runtime.step_running()? {
+                    // the paused block's position is only known once it has been drawn
+                    screen.clear();
+                    screen.refresh_screen(runtime)?;
+                    if let Some(pause) =
+                        runtime.interpreter.as_ref().and_then(|i| i.paused.as_ref())
+                    {
+                        runtime.jump_to_block(&pause.block_id.clone());
+                    }
+                    runtime.status_message = paused_message(&runtime.config);
+                }
+                next_frame = (next_frame + frame_duration).max(now);
+                needs_refresh = true;
+            }
+            let timeout = if needs_refresh {
+                Duration::ZERO
+            } else {
+                next_frame - now
+            };
+            if poll(timeout)? {
+                Some(read()?)
+            } else {
+                None
+            }
+        } else {
+            Some(read()?)
+        };
+        match event {
+            #[allow(clippy::collapsible_match)]
+            Some(Event::Key(event)) => {
+                if event.kind == KeyEventKind::Press {
+                    // suboptimal ordering, probably
+                    if let State::Run = runtime.state {
+                        let is_asking = runtime
+                            .interpreter
+                            .as_ref()
+                            .is_some_and(|interpreter| interpreter.question.is_some());
+                        let is_paused = runtime
+                            .interpreter
+                            .as_ref()
+                            .is_some_and(|interpreter| interpreter.paused.is_some());
+                        let run_action = runtime.config.run_action_for(&event);
+                        match event.code {
+                            _ if run_action.is_some() || is_paused => {
+                                let action = run_action
+                                    .or_else(|| runtime.config.debugger_action_for(&event));
+                                if let Some(action) = action {
+                                    perform(action, runtime, &mut screen)?;
                                 }
-                                KeyCode::Char(c) => runtime.command_buffer.push(c),
-                                _ => (),
                             }
-                            needs_refresh = true;
-                        } else if let State::Inline = runtime.state {
-                            // ugly implementation
-                            match event.code {
-                                KeyCode::Enter | KeyCode::Esc => {
-                                    runtime.state = State::Move;
-                                    runtime.status_message = "".into();
-                                    needs_refresh = true;
-                                }
-                                KeyCode::Backspace => {
-                                    let mut field =
-                                        runtime.get_strumber_field(&runtime.editing_shadow);
-                                    field.pop();
-                                    runtime.set_strumber_field(
-                                        &runtime.editing_shadow.clone(),
-                                        &field,
-                                    )?;
-                                    needs_refresh = true;
-                                }
-                                KeyCode::Char(c) => {
-                                    let mut field =
-                                        runtime.get_strumber_field(&runtime.editing_shadow);
-                                    field.push(c);
-                                    runtime.set_strumber_field(
-                                        &runtime.editing_shadow.clone(),
-                                        &field,
-                                    )?;
-                                    needs_refresh = true;
+                            // typing answers whatever is being asked
+                            KeyCode::Enter if is_asking => {
+                                let answer = std::mem::take(&mut runtime.command_buffer);
+                                if let Some(interpreter) = &mut runtime.interpreter {
+                                    interpreter.answer_question(&answer);
                                 }
-                                _ => (),
                             }
-                        } else {
-                            if let KeyCode::Char(c) = event.code {
-                                runtime.last_command = c;
+                            KeyCode::Backspace if is_asking => {
+                                runtime.command_buffer.pop();
                             }
-                            match event.code {
-                                KeyCode::Char('q') => {
-                                    if runtime.is_dirty() {
-                                        runtime.status_message =
-                                            "Unsaved changes. (Q to force)".into();
-                                        needs_refresh = true;
-                                    } else {
-                                        break;
-                                    }
+                            KeyCode::Char(c) if is_asking => runtime.command_buffer.push(c),
+                            code => {
+                                if let (Some(interpreter), Some(key)) =
+                                    (&mut runtime.interpreter, scratch_key_name(code))
+                                {
+                                    interpreter.press_key(&key);
                                 }
-                                KeyCode::Char('Q') => {
+                            }
+                        }
+                        needs_refresh = true;
+                    } else if let State::Command = runtime.state {
+                        match event.code {
+                            KeyCode::Enter if runtime.last_action == Some(Action::CommandLine) => {
+                                let line = std::mem::take(&mut runtime.command_buffer);
+                                runtime.completion = None;
+                                add_to_history(runtime, &line);
+                                runtime.state = State::Move;
+                                if let Outcome::Quit = execute(runtime, &mut screen, &line)? {
                                     break;
                                 }
-                                KeyCode::Char('o') => {
-                                    runtime.state = State::Command;
-                                    needs_refresh = true;
-                                }
-                                KeyCode::Char('w') => {
-                                    runtime.state = State::Command;
-                                    needs_refresh = true;
-                                }
-                                KeyCode::Char('h') => match runtime.state {
-                                    State::Move | State::Hold => {
-                                        runtime.move_x(-1)?;
-                                        if runtime.cursor_x - runtime.scroll_x
-                                            == runtime.viewport.y_min - 1
-                                        {
-                                            runtime.scroll_x -= 1;
-                                        }
-                                        needs_refresh = true;
-                                    }
-                                    _ => (),
-                                },
-                                KeyCode::Char('j') => match runtime.state {
-                                    State::Move | State::Hold => {
-                                        runtime.move_y(1)?;
-                                        if runtime.cursor_y - runtime.scroll_y
-                                            == runtime.viewport.y_max
-                                        {
-                                            runtime.scroll_y += 1;
-                                        }
-                                        needs_refresh = true;
-                                    }
-                                    State::Toolbox => {
-                                        runtime.toolbox_cursor = (runtime.toolbox_cursor + 1)
-                                            .min(runtime.toolbox.len() - 1);
-                                        while runtime.toolbox_cursor > runtime.toolbox_visible_max {
-                                            runtime.toolbox_scroll = (runtime.toolbox_scroll + 1)
-                                                .min(runtime.toolbox.len() - 1);
-                                            let vox = runtime.viewport_offset_x;
-                                            let voy = runtime.viewport_offset_y;
-                                            screen.draw_toolbox(&mut runtime, vox, voy, true)?;
-                                        }
-                                        needs_refresh = true;
-                                    }
-                                    _ => (),
-                                },
-                                KeyCode::Char('k') => match runtime.state {
-                                    State::Move | State::Hold => {
-                                        runtime.move_y(-1)?;
-                                        if runtime.cursor_y - runtime.scroll_y
-                                            < runtime.viewport.y_min
-                                        {
-                                            runtime.scroll_y -= 1;
-                                        }
-                                        needs_refresh = true;
-                                    }
-                                    State::Toolbox => {
-                                        runtime.toolbox_cursor =
-                                            runtime.toolbox_cursor.saturating_sub(1);
-                                        if runtime.toolbox_cursor == runtime.toolbox_scroll - 1 {
-                                            runtime.toolbox_scroll = runtime.toolbox_cursor;
-                                        }
-                                        needs_refresh = true;
-                                    }
-                                    _ => (),
-                                },
-                                KeyCode::Char('l') => match runtime.state {
-                                    State::Move | State::Hold => {
-                                        runtime.move_x(1)?;
-                                        if runtime.cursor_x - runtime.scroll_x
-                                            == runtime.viewport.x_max
-                                        {
-                                            runtime.scroll_x += 1;
-                                        }
-                                        needs_refresh = true;
-                                    }
-                                    _ => (),
-                                },
-                                KeyCode::Char('H') => match runtime.state {
-                                    State::Move | State::Hold => {
-                                        runtime.scroll_x -= 1;
-                                        runtime.move_x(-1)?;
-                                        needs_refresh = true;
-                                    }
-                                    _ => (),
-                                },
-                                KeyCode::Char('J') => match runtime.state {
-                                    State::Move | State::Hold => {
-                                        runtime.scroll_y += 1;
-                                        runtime.move_y(1)?;
-                                        needs_refresh = true;
-                                    }
-                                    State::Toolbox => {
-                                        runtime.toolbox_cursor = (runtime.toolbox_cursor + 1)
-                                            .min(runtime.toolbox.len() - 1);
-                                        runtime.toolbox_scroll = (runtime.toolbox_scroll + 1)
-                                            .min(runtime.toolbox.len() - 1);
-                                        needs_refresh = true;
-                                    }
-                                    _ => (),
-                                },
-                                KeyCode::Char('K') => match runtime.state {
-                                    State::Move | State::Hold => {
-                                        runtime.scroll_y -= 1;
-                                        runtime.move_y(-1)?;
-                                        needs_refresh = true;
-                                    }
-                                    State::Toolbox => {
-                                        runtime.toolbox_cursor =
-                                            runtime.toolbox_cursor.saturating_sub(1);
-                                        runtime.toolbox_scroll =
-                                            runtime.toolbox_scroll.saturating_sub(1);
-                                        needs_refresh = true;
-                                    }
-                                    _ => (),
-                                },
-                                KeyCode::Char('L') => match runtime.state {
-                                    State::Move | State::Hold => {
-                                        runtime.scroll_x += 1;
-                                        runtime.move_x(1)?;
-                                        needs_refresh = true;
-                                    }
-                                    _ => (),
-                                },
-                                KeyCode::Char('t') => match runtime.state {
-                                    State::Move => {
-                                        runtime.state = State::Toolbox;
-                                        needs_refresh = true;
-                                    }
-                                    State::Toolbox => {
-                                        runtime.state = State::Move;
-                                        needs_refresh = true;
+                            }
+                            KeyCode::Enter => {
+                                let buf = std::mem::take(&mut runtime.command_buffer);
+                                match runtime.last_action {
+                                    Some(Action::Open) => open_project(runtime, &buf)?,
+                                    Some(Action::Write) => {
+                                        write_project(runtime, &buf)?;
                                     }
-                                    _ => (),
-                                },
-                                KeyCode::Char('s') => match runtime.state {
-                                    State::Move => {
-                                        if let Some(a) = runtime
-                                            .block_positions
-                                            .get(&(runtime.cursor_x, runtime.cursor_y))
+                                    Some(Action::CustomBlock) => {
+                                        let definition_id = runtime.editing_definition.take();
+                                        if let Some(error) = runtime.signature_error(&buf) {
+                                            runtime.status_message = error.into();
+                                        } else if let Some(definition_id) = definition_id {
+                                            runtime.edit_custom_block(&definition_id, &buf)?;
+                                            runtime.status_message = "Edited custom block".into();
+                                        } else if let Some(definition_id) =
+                                            runtime.create_custom_block(&buf)?
                                         {
-                                            let selected = a.last().unwrap().clone();
-                                            let stamp_id = runtime.stamp_block(&selected, true)?;
-                                            runtime.put_to_cursor(&stamp_id)?;
-                                            needs_refresh = true;
+                                            runtime.put_to_cursor(&definition_id)?;
+                                            runtime.slide_block_to(
+                                                &definition_id,
+                                                runtime.cursor_x,
+                                                runtime.cursor_y,
+                                            )?;
+                                            runtime.status_message = "Created custom block".into();
                                             runtime.state = State::Hold;
                                         }
                                     }
-                                    State::Hold => {
-                                        if let Some(cursor_id) = runtime.cursor_block.clone() {
-                                            runtime.stamp_block(&cursor_id, true)?;
-                                            needs_refresh = true;
-                                        }
-                                    }
-                                    _ => (),
-                                },
-                                KeyCode::Char('D') => match runtime.state {
-                                    State::Move => {
-                                        if let Some(a) = runtime
-                                            .block_positions
-                                            .get(&(runtime.cursor_x, runtime.cursor_y))
-                                        {
-                                            let selected = a.last().unwrap().clone();
-                                            runtime.delete_block(&selected)?;
-                                            needs_refresh = true;
-                                        }
-                                    }
-                                    State::Hold => {
-                                        if let Some(cursor_id) = runtime.cursor_block.take() {
-                                            runtime.delete_block(&cursor_id)?;
-                                            needs_refresh = true;
-                                            runtime.state = State::Move;
-                                        }
-                                    }
-                                    _ => (),
-                                },
-                                KeyCode::Char(' ') => {
-                                    // interaction!
-                                    match runtime.state {
-                                        State::Move => {
-                                            if let Some(a) = runtime
-                                                .block_positions
-                                                .get(&(runtime.cursor_x, runtime.cursor_y))
-                                            {
-                                                let selected = a.last().unwrap().clone();
-                                                runtime.put_to_cursor(&selected)?;
-                                                needs_refresh = true;
-                                                runtime.state = State::Hold;
-                                            } else if let Some(shadow_id) = runtime
-                                                .writable_points
-                                                .get(&(runtime.cursor_x, runtime.cursor_y))
-                                            {
-                                                runtime.editing_shadow = shadow_id.clone();
-                                                runtime.state = State::Inline;
-                                                runtime.status_message = "Editing field".into();
-                                                needs_refresh = true;
-                                            }
-                                        }
-                                        State::Hold => {
-                                            if let Some((parent_id, input_name)) =
-                                                runtime.current_drop_point()
-                                            {
-                                                let cursor_id =
-                                                    runtime.cursor_block.take().unwrap();
-                                                if let Some(input_name) = input_name {
-                                                    // chuck existing inputs away to the right somewhere
-                                                    if let Some(existing_id) = runtime.blocks
-                                                        [&parent_id]
-                                                        .inputs[&input_name]
-                                                        .block_id
-                                                        .clone()
-                                                    {
-                                                        runtime.detach_block(&existing_id)?;
-                                                        // TODO: pick a more reasonable position
-                                                        runtime.slide_block_by(
-                                                            &existing_id,
-                                                            1,
-                                                            1,
-                                                        )?;
-                                                    }
-                                                    runtime.attach_input(
-                                                        &cursor_id,
-                                                        &parent_id,
-                                                        &input_name,
-                                                        false,
+                                    Some(Action::Data) => {
+                                        match runtime.data_prompt.take() {
+                                            Some(DataPrompt::Create {
+                                                variable_type,
+                                                is_local,
+                                                is_cloud,
+                                            }) => {
+                                                if let Some(error) = runtime.data_name_error(
+                                                    variable_type,
+                                                    &buf,
+                                                    None,
+                                                ) {
+                                                    runtime.status_message = error.into();
+                                                } else {
+                                                    runtime.create_data(
+                                                        variable_type,
+                                                        &buf,
+                                                        is_local,
+                                                        is_cloud,
                                                     )?;
+                                                    runtime.status_message =
+                                                        format!("Created `{buf}`");
+                                                }
+                                            }
+                                            Some(DataPrompt::Rename(entry)) => {
+                                                if let Some(error) = runtime.data_name_error(
+                                                    entry.variable_type,
+                                                    &buf,
+                                                    Some(&entry.id),
+                                                ) {
+                                                    runtime.status_message = error.into();
                                                 } else {
-                                                    // todo: support sandwiching
-                                                    if runtime.blocks[&parent_id].next_id.is_none()
-                                                    {
-                                                        if !runtime.blocks[&parent_id].is_boot() {
-                                                            runtime.attach_next(
-                                                                &cursor_id, &parent_id,
-                                                            )?;
-                                                        }
-                                                    } else {
-                                                        runtime.status_message =
-                                                            "Can't place between stacks yet".into()
-                                                    }
+                                                    runtime.rename_data(&entry.id, &buf)?;
+                                                    runtime.status_message = format!(
+                                                        "Renamed `{}` to `{buf}`",
+                                                        entry.name
+                                                    );
                                                 }
-                                            } else {
-                                                runtime.cursor_block.take().unwrap();
                                             }
-                                            needs_refresh = true;
-                                            runtime.state = State::Move;
+                                            None => (),
                                         }
-                                        State::Toolbox => {
-                                            let toolbox_id =
-                                                runtime.toolbox[runtime.toolbox_cursor].clone();
-                                            let spawned_id =
-                                                runtime.stamp_block(&toolbox_id, true)?;
-                                            runtime.put_to_cursor(&spawned_id)?;
-                                            runtime.slide_block_to(
-                                                &spawned_id,
-                                                runtime.cursor_x,
-                                                runtime.cursor_y,
-                                            )?;
-                                            needs_refresh = true;
-                                            runtime.state = State::Hold;
-                                        }
-                                        _ => (),
+                                        runtime.state = State::Data;
                                     }
+                                    _ => (),
+                                }
+                                // new custom blocks are held right away
+                                if let State::Command = runtime.state {
+                                    runtime.state = State::Move;
+                                }
+                            }
+                            KeyCode::Tab if runtime.last_action == Some(Action::CommandLine) => {
+                                complete(runtime);
+                            }
+                            KeyCode::Up | KeyCode::Down
+                                if runtime.last_action == Some(Action::CommandLine) =>
+                            {
+                                runtime.completion = None;
+                                browse_history(runtime, event.code == KeyCode::Up);
+                            }
+                            // like vim, backspacing past the start leaves the command line
+                            KeyCode::Backspace
+                                if runtime.last_action == Some(Action::CommandLine)
+                                    && runtime.command_buffer.is_empty() =>
+                            {
+                                runtime.state = State::Move;
+                            }
+                            KeyCode::Esc => {
+                                runtime.command_buffer.clear();
+                                runtime.completion = None;
+                                runtime.editing_definition = None;
+                                runtime.state = if runtime.data_prompt.take().is_some() {
+                                    State::Data
+                                } else {
+                                    State::Move
+                                };
+                            }
+                            KeyCode::Backspace => {
+                                runtime.completion = None;
+                                runtime.command_buffer.pop();
+                            }
+                            KeyCode::Char(c) => {
+                                runtime.completion = None;
+                                runtime.command_buffer.push(c);
+                            }
+                            _ => (),
+                        }
+                        needs_refresh = true;
+                    } else if runtime.state == State::Dropdown
+                        && runtime
+                            .dropdown_menu
+                            .as_ref()
+                            .is_some_and(|menu| menu.typing)
+                    {
+                        // a filter takes every letter, so it has to be started with search-toolbox
+                        let menu = runtime.dropdown_menu.as_mut().unwrap();
+                        match event.code {
+                            KeyCode::Enter => {
+                                runtime.commit_dropdown()?;
+                                runtime.state = State::Move;
+                            }
+                            KeyCode::Esc => {
+                                menu.filter.clear();
+                                menu.typing = false;
+                                menu.cursor = 0;
+                            }
+                            KeyCode::Backspace => {
+                                if menu.filter.pop().is_none() {
+                                    menu.typing = false;
                                 }
-                                _ => (),
+                                menu.cursor = 0;
+                            }
+                            KeyCode::Char(c) => {
+                                menu.filter.push(c);
+                                menu.cursor = 0;
+                            }
+                            KeyCode::Down => menu.move_cursor(1),
+                            KeyCode::Up => menu.move_cursor(-1),
+                            _ => (),
+                        }
+                        needs_refresh = true;
+                    } else if let State::Inline = runtime.state {
+                        // ugly implementation
+                        match event.code {
+                            KeyCode::Enter | KeyCode::Esc => {
+                                runtime.state = State::Move;
+                                runtime.status_message = "".into();
+                                needs_refresh = true;
+                            }
+                            KeyCode::Backspace => {
+                                let mut field = runtime.get_strumber_field(&runtime.editing_shadow);
+                                field.pop();
+                                runtime
+                                    .set_strumber_field(&runtime.editing_shadow.clone(), &field)?;
+                                needs_refresh = true;
                             }
+                            KeyCode::Char(c) => {
+                                let mut field = runtime.get_strumber_field(&runtime.editing_shadow);
+                                field.push(c);
+                                runtime
+                                    .set_strumber_field(&runtime.editing_shadow.clone(), &field)?;
+                                needs_refresh = true;
+                            }
+                            _ => (),
+                        }
+                    } else if runtime.state == State::Toolbox
+                        && runtime
+                            .toolbox_search
+                            .as_ref()
+                            .is_some_and(|search| search.typing || event.code == KeyCode::Esc)
+                    {
+                        let search = runtime.toolbox_search.as_mut().unwrap();
+                        match event.code {
+                            KeyCode::Esc => runtime.close_toolbox_search(),
+                            // an empty query isn't worth keeping around
+                            KeyCode::Enter if search.query.is_empty() => {
+                                runtime.close_toolbox_search()
+                            }
+                            KeyCode::Enter => search.typing = false,
+                            KeyCode::Backspace if search.query.is_empty() => {
+                                runtime.close_toolbox_search();
+                            }
+                            KeyCode::Backspace => {
+                                search.query.pop();
+                                search.cursor = 0;
+                                runtime.move_toolbox_search(0);
+                            }
+                            KeyCode::Char(c) => {
+                                search.query.push(c);
+                                search.cursor = 0;
+                                runtime.move_toolbox_search(0);
+                            }
+                            KeyCode::Down => runtime.move_toolbox_search(1),
+                            KeyCode::Up => runtime.move_toolbox_search(-1),
+                            _ => (),
+                        }
+                        needs_refresh = true;
+                    } else if let Some(action) = (runtime.state == State::Data)
+                        .then(|| runtime.config.data_action_for(&event))
+                        .flatten()
+                        .or_else(|| runtime.config.action_for(&event))
+                        .or_else(|| pane_action(runtime.state, event.code))
+                    {
+                        match perform(action, runtime, &mut screen)? {
+                            Outcome::Quit => break,
+                            Outcome::Redraw => needs_refresh = true,
+                            Outcome::Unchanged => (),
                         }
                     }
                 }
-                crossterm::event::Event::Resize(new_columns, new_rows) => {
-                    runtime.set_viewport(new_columns, new_rows);
-                    screen.resize(new_columns, new_rows);
-                    needs_refresh = true;
-                }
-                _ => (),
             }
-            // TODO: implement some form of culling & per-component refresh
-            if needs_refresh {
-                screen.clear();
-                screen.refresh_screen(&mut runtime)?;
-                screen.flush_contents()?;
-                // cursor is always drawn last
-                screen.draw_cursor(&runtime)?;
-                needs_refresh = false;
+            Some(Event::Paste(text)) if runtime.state == State::Move => {
+                paste_text(runtime, &text.replace("\r\n", "\n").replace('\r', "\n"))?;
+                needs_refresh = true;
             }
+            Some(Event::Resize(new_columns, new_rows)) => {
+                runtime.set_viewport(new_columns, new_rows);
+                screen.resize(new_columns, new_rows);
+                needs_refresh = true;
+            }
+            _ => (),
+        }
+        // held blocks, inline edits and open menus make up a single undo step until they're done
+        if let State::Move | State::Toolbox | State::Targets = runtime.state {
+            runtime.checkpoint();
         }
+        // TODO: implement some form of culling & per-component refresh
+        if needs_refresh {
+            screen.clear();
+            screen.refresh_screen(runtime)?;
+            screen.flush_contents()?;
+            // cursor is always drawn last
+            screen.draw_cursor(runtime)?;
+            needs_refresh = false;
+        }
+    }
 
-        Ok(())
-    });
+    Ok(())
+}
+
+/// switches to the reference's target if needed, which has to be drawn once to place its blocks
+fn jump_to_reference(
+    screen: &mut Screen,
+    runtime: &mut Runtime,
+    reference: &Reference,
+) -> ViiruResult<()> {
+    if reference.target != runtime.editing_target {
+        runtime.switch_target(reference.target)?;
+        runtime.status_message = format!("Editing {}", runtime.editing_target_name());
+        screen.clear();
+        screen.refresh_screen(runtime)?;
+    }
+    runtime.jump_to_block(&reference.block_id);
+    Ok(())
+}
+
+/// lists whichever of the debugger actions are still bound to a key
+fn paused_message(config: &Config) -> String {
+    let hints: Vec<_> = [
+        (Action::Step, "step"),
+        (Action::StepOver, "step over"),
+        (Action::Continue, "continue"),
+    ]
+    .into_iter()
+    .filter_map(|(action, what)| {
+        let key = config.keys_for(action).into_iter().next()?;
+        Some(format!("{key} to {what}"))
+    })
+    .collect();
+    if hints.is_empty() {
+        "Paused".into()
+    } else {
+        format!("Paused. {}", hints.join(", "))
+    }
+}
 
-    runtime.undefined_or_throw(result)
+/// the names the key menus use, e.g. "space" or "a"
+fn scratch_key_name(code: KeyCode) -> Option<String> {
+    Some(match code {
+        KeyCode::Char(' ') => "space".into(),
+        KeyCode::Char(c) => c.to_lowercase().to_string(),
+        KeyCode::Up => "up arrow".into(),
+        KeyCode::Down => "down arrow".into(),
+        KeyCode::Left => "left arrow".into(),
+        KeyCode::Right => "right arrow".into(),
+        KeyCode::Enter => "enter".into(),
+        _ => return None,
+    })
+}
+
+/// Creates scripts at the cursor out of scratchblocks text
+fn paste_text(runtime: &mut Runtime, text: &str) -> ViiruResult {
+    match scratchblocks::parse_scripts(runtime, text) {
+        Ok(scripts) => {
+            let (x, y) = (runtime.cursor_x, runtime.cursor_y);
+            runtime.status_message = match scratchblocks::create_scripts(runtime, &scripts, x, y) {
+                Ok(top_ids) => match top_ids.len() {
+                    0 => "Nothing to paste".into(),
+                    1 => "Pasted 1 script".into(),
+                    n => format!("Pasted {n} scripts"),
+                },
+                Err(ViiruError::ImportError(message)) => message,
+                Err(err) => return Err(err),
+            };
+        }
+        Err(error) => runtime.status_message = error,
+    }
+    Ok(())
 }
@@ -0,0 +1,485 @@
+//! Finds common mistakes in the editing target's scripts, such as scripts that never run
+//! or variables that are never read. Whether variables and broadcasts are used
+//! elsewhere depends on the other targets, which are scanned once up front as a `Usage`.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    block::Block,
+    opcodes::BLOCKS,
+    project::Project,
+    runtime::Runtime,
+    spec::{Fragment, Shape},
+};
+
+pub struct Diagnostic {
+    /// None for problems that aren't about any one block, like an unused variable
+    pub block_id: Option<String>,
+    pub message: String,
+}
+
+/// Which variables, lists and broadcasts some blocks use
+#[derive(Default)]
+pub struct Usage {
+    /// variable and list ids
+    reads: HashSet<String>,
+    writes: HashSet<String>,
+    /// broadcast names, in lowercase since that's how scratch matches them
+    sent: HashSet<String>,
+    received: HashSet<String>,
+    /// set when a broadcast's name is only known while running
+    sends_any: bool,
+}
+
+pub(crate) const VARIABLE_READS: [&str; 6] = [
+    "data_variable",
+    "data_listcontents",
+    "data_itemoflist",
+    "data_itemnumoflist",
+    "data_lengthoflist",
+    "data_listcontainsitem",
+];
+
+pub(crate) const VARIABLE_WRITES: [&str; 7] = [
+    "data_setvariableto",
+    "data_changevariableby",
+    "data_addtolist",
+    "data_deleteoflist",
+    "data_deletealloflist",
+    "data_insertatlist",
+    "data_replaceitemoflist",
+];
+
+impl Usage {
+    /// everything outside of the editing target, including monitors on the stage
+    pub fn of_project(project: &Project, editing_target: usize) -> Usage {
+        let mut usage = Usage::default();
+        for (i, target) in project.targets.iter().enumerate() {
+            if i == editing_target {
+                continue;
+            }
+            let blocks = target.decode_blocks();
+            usage.add_blocks(&blocks, blocks.keys());
+            // "backdrop # of Stage" style blocks read other sprites' variables by name
+            for block in blocks.values().filter(|b| b.opcode == "sensing_of") {
+                let object = block
+                    .inputs
+                    .get("OBJECT")
+                    .and_then(|input| blocks.get(input.shadow_id.as_ref()?))
+                    .map_or("", |menu| field(menu, "OBJECT"));
+                let property = field(block, "PROPERTY");
+                let owner = project.targets.iter().find(|t| match object {
+                    "_stage_" => t.is_stage,
+                    name => !t.is_stage && t.name == name,
+                });
+                if let Some(owner) = owner {
+                    let ids = owner.variables.iter().filter(|(_, v)| v.name == property);
+                    usage.reads.extend(ids.map(|(id, _)| id.clone()));
+                }
+            }
+        }
+        for monitor in &project.monitors {
+            let is_visible = monitor.get("visible").and_then(|v| v.as_bool()) == Some(true);
+            if let Some(id) = monitor.get("id").and_then(|id| id.as_str()) {
+                if is_visible {
+                    usage.reads.insert(id.to_string());
+                }
+            }
+        }
+        usage
+    }
+
+    fn add_blocks<'a>(
+        &mut self,
+        blocks: &HashMap<String, Block>,
+        ids: impl Iterator<Item = &'a String>,
+    ) {
+        for id in ids {
+            let Some(block) = blocks.get(id) else {
+                continue;
+            };
+            let opcode = block.opcode.as_str();
+            let data_id = block
+                .fields
+                .get("VARIABLE")
+                .or(block.fields.get("LIST"))
+                .and_then(|field| field.id.clone());
+            if let Some(data_id) = data_id {
+                if VARIABLE_READS.contains(&opcode) {
+                    self.reads.insert(data_id);
+                } else if VARIABLE_WRITES.contains(&opcode) {
+                    self.writes.insert(data_id);
+                }
+            }
+            match opcode {
+                "event_broadcast" | "event_broadcastandwait" => match broadcast_sent(blocks, block)
+                {
+                    Some(name) => {
+                        self.sent.insert(name.to_lowercase());
+                    }
+                    None => self.sends_any = true,
+                },
+                "event_whenbroadcastreceived" => {
+                    let name = field(block, "BROADCAST_OPTION").to_lowercase();
+                    self.received.insert(name);
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+pub(crate) fn field<'b>(block: &'b Block, field_name: &str) -> &'b str {
+    block
+        .fields
+        .get(field_name)
+        .map_or("", |f| f.value.as_str())
+}
+
+/// the name picked in the menu, or None if something's been dropped over it
+pub(crate) fn broadcast_sent(blocks: &HashMap<String, Block>, block: &Block) -> Option<String> {
+    let input = block.inputs.get("BROADCAST_INPUT")?;
+    if input.block_id.is_some() && input.block_id != input.shadow_id {
+        return None;
+    }
+    let menu = blocks.get(input.shadow_id.as_ref()?)?;
+    Some(field(menu, "BROADCAST_OPTION").to_string())
+}
+
+/// Looks over the editing target's scripts, top to bottom
+pub fn lint(runtime: &Runtime, others: &Usage) -> Vec<Diagnostic> {
+    let blocks = &runtime.blocks;
+    let mut diagnostics = vec![];
+    let mut problem = |id: &str, message: String| {
+        diagnostics.push(Diagnostic {
+            block_id: Some(id.to_string()),
+            message,
+        });
+    };
+
+    // the toolbox lives in the same map, so only look at what's reachable from the scripts
+    let mut script_ids = HashSet::new();
+    for top_id in &runtime.top_level {
+        script_ids.extend(runtime.script_of(top_id));
+    }
+    let mut ids: Vec<_> = script_ids.iter().collect();
+    ids.sort();
+
+    for top_id in &runtime.top_level {
+        let Some(spec) = BLOCKS.get(&blocks[top_id].opcode) else {
+            continue;
+        };
+        let is_definition = blocks[top_id].opcode == "procedures_definition";
+        if spec.shape == Shape::Stack && !spec.is_hat && !is_definition {
+            problem(top_id, "Script has no hat block, so it never runs".into());
+        }
+    }
+
+    for &id in &ids {
+        let block = &blocks[id];
+        let Some(spec) = BLOCKS.get(&block.opcode) else {
+            continue;
+        };
+        if block.opcode == "control_wait_until" {
+            if block
+                .inputs
+                .get("CONDITION")
+                .is_none_or(|i| i.block_id.is_none())
+            {
+                problem(id, "Wait until with nothing in it waits forever".into());
+            }
+        } else {
+            for fragment in spec.lines.iter().flatten() {
+                let input_name = match fragment {
+                    Fragment::BooleanInput(input_name) | Fragment::StrumberInput(input_name, _) => {
+                        input_name
+                    }
+                    _ => continue,
+                };
+                let is_empty = block
+                    .inputs
+                    .get(input_name)
+                    .is_none_or(|input| input.block_id.is_none() && input.shadow_id.is_none());
+                if is_empty {
+                    problem(id, format!("Empty {} input", input_name.to_lowercase()));
+                }
+            }
+        }
+        let never_finishes = block.opcode == "control_forever";
+        if let Some(next_id) = &block.next_id {
+            if never_finishes {
+                problem(
+                    next_id,
+                    "Unreachable, since the forever above never ends".into(),
+                );
+            } else if block.is_boot() {
+                problem(
+                    next_id,
+                    "Unreachable, since the block above stops the script".into(),
+                );
+            }
+        }
+    }
+
+    let mut usage = Usage::default();
+    usage.add_blocks(blocks, ids.iter().copied());
+    let first_use = |opcodes: &[&str], data_id: &str| {
+        ids.iter().copied().find(|&id| {
+            let block = &blocks[id];
+            opcodes.contains(&block.opcode.as_str())
+                && block
+                    .fields
+                    .values()
+                    .any(|field| field.id.as_deref() == Some(data_id))
+        })
+    };
+    let mut data: Vec<_> = runtime
+        .variables
+        .iter()
+        .map(|(id, name)| (id, name, "Variable"))
+        .chain(runtime.lists.iter().map(|(id, name)| (id, name, "List")))
+        .collect();
+    data.sort();
+    for (data_id, name, kind) in data {
+        let is_read = usage.reads.contains(data_id) || others.reads.contains(data_id);
+        let is_written = usage.writes.contains(data_id) || others.writes.contains(data_id);
+        let (message, block_id) = match (is_read, is_written) {
+            (true, true) => continue,
+            (false, false) => (format!("{kind} `{name}` is never used"), None),
+            (false, true) => (
+                format!("{kind} `{name}` is set but never read"),
+                first_use(&VARIABLE_WRITES, data_id),
+            ),
+            (true, false) => (
+                format!("{kind} `{name}` is read but never set"),
+                first_use(&VARIABLE_READS, data_id),
+            ),
+        };
+        diagnostics.push(Diagnostic {
+            block_id: block_id.cloned(),
+            message,
+        });
+    }
+
+    for &id in &ids {
+        let block = &blocks[id];
+        match block.opcode.as_str() {
+            "event_broadcast" | "event_broadcastandwait" => {
+                let Some(name) = broadcast_sent(blocks, block) else {
+                    continue;
+                };
+                let lowercase = name.to_lowercase();
+                if !usage.received.contains(&lowercase) && !others.received.contains(&lowercase) {
+                    diagnostics.push(Diagnostic {
+                        block_id: Some(id.clone()),
+                        message: format!("Broadcast `{name}` is sent but never received"),
+                    });
+                }
+            }
+            "event_whenbroadcastreceived" => {
+                let name = field(block, "BROADCAST_OPTION");
+                let lowercase = name.to_lowercase();
+                let is_sent = usage.sends_any
+                    || others.sends_any
+                    || usage.sent.contains(&lowercase)
+                    || others.sent.contains(&lowercase);
+                if !is_sent {
+                    diagnostics.push(Diagnostic {
+                        block_id: Some(id.clone()),
+                        message: format!("Broadcast `{name}` is received but never sent"),
+                    });
+                }
+            }
+            _ => (),
+        }
+    }
+
+    // top to bottom, then whatever isn't about a block
+    diagnostics.sort_by_key(|diagnostic| match &diagnostic.block_id {
+        Some(id) => {
+            let (x, y) = runtime.compute_own_xy(id);
+            (false, y, x)
+        }
+        None => (true, 0, 0),
+    });
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        backend::{
+            tests::{new_runtime, SPRITE, STAGE},
+            VariableType,
+        },
+        runtime::Runtime,
+        scratchblocks::{create_scripts, parse_scripts},
+    };
+
+    fn add_scripts(runtime: &mut Runtime, text: &str) -> Vec<String> {
+        let scripts = parse_scripts(runtime, text).unwrap();
+        create_scripts(runtime, &scripts, 0, 0).unwrap()
+    }
+
+    /// what the linter says about the sprite's scripts, with the messages about blocks first
+    fn messages(runtime: &mut Runtime) -> Vec<(Option<String>, String)> {
+        runtime.enable_lint().unwrap();
+        runtime
+            .diagnostics
+            .iter()
+            .map(|d| (d.block_id.clone(), d.message.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn scripts_without_hats_never_run() {
+        let mut runtime = new_runtime();
+        let top_ids = add_scripts(
+            &mut runtime,
+            "move (10) steps\n\nwhen @greenFlag clicked\nmove (10) steps",
+        );
+        assert_eq!(
+            messages(&mut runtime),
+            [(
+                Some(top_ids[0].clone()),
+                "Script has no hat block, so it never runs".into()
+            )]
+        );
+    }
+
+    #[test]
+    fn empty_inputs_are_found() {
+        let mut runtime = new_runtime();
+        let top_ids = add_scripts(
+            &mut runtime,
+            "when @greenFlag clicked\nwait until <>\nif <> then\nend",
+        );
+        let wait_id = runtime.blocks[&top_ids[0]].next_id.clone().unwrap();
+        let if_id = runtime.blocks[&wait_id].next_id.clone().unwrap();
+        assert_eq!(
+            messages(&mut runtime),
+            [
+                (
+                    Some(wait_id),
+                    "Wait until with nothing in it waits forever".into()
+                ),
+                (Some(if_id), "Empty condition input".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn blocks_after_the_end_are_unreachable() {
+        let mut runtime = new_runtime();
+        let top_ids = add_scripts(
+            &mut runtime,
+            "when @greenFlag clicked\nforever\nend\nmove (10) steps\n\n\
+             when this sprite clicked\nstop [all v]\nmove (10) steps",
+        );
+        let after_forever = runtime.blocks[&top_ids[0]].next_id.clone().unwrap();
+        let after_forever = runtime.blocks[&after_forever].next_id.clone();
+        let stop_id = runtime.blocks[&top_ids[1]].next_id.clone().unwrap();
+        let after_stop = runtime.blocks[&stop_id].next_id.clone();
+        assert_eq!(
+            messages(&mut runtime),
+            [
+                (
+                    after_forever,
+                    "Unreachable, since the forever above never ends".into()
+                ),
+                (
+                    after_stop,
+                    "Unreachable, since the block above stops the script".into()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn variables_have_to_be_both_set_and_read() {
+        let mut runtime = new_runtime();
+        for name in ["unused", "written", "read", "both"] {
+            runtime
+                .create_data(VariableType::Scalar, name, false, false)
+                .unwrap();
+        }
+        runtime
+            .create_data(VariableType::List, "items", false, false)
+            .unwrap();
+        let top_ids = add_scripts(
+            &mut runtime,
+            "when @greenFlag clicked\nset [written v] to (1)\nsay (read)\nchange [both v] by (1)\n\
+             add (both) to [items v]",
+        );
+        let set_id = runtime.blocks[&top_ids[0]].next_id.clone().unwrap();
+        let say_id = runtime.blocks[&set_id].next_id.clone().unwrap();
+        let read_id = runtime.blocks[&say_id].inputs["MESSAGE"].block_id.clone();
+        let messages = messages(&mut runtime);
+        assert!(messages.contains(&(None, "Variable `unused` is never used".into())));
+        assert!(messages.contains(&(
+            Some(set_id),
+            "Variable `written` is set but never read".into()
+        )));
+        assert!(messages.contains(&(read_id, "Variable `read` is read but never set".into())));
+        let add_id = messages
+            .iter()
+            .find(|(_, message)| message == "List `items` is set but never read")
+            .and_then(|(id, _)| id.clone())
+            .unwrap();
+        assert_eq!(runtime.blocks[&add_id].opcode, "data_addtolist");
+        assert!(!messages
+            .iter()
+            .any(|(_, message)| message.contains("`both`")));
+    }
+
+    #[test]
+    fn broadcasts_have_to_be_both_sent_and_received() {
+        let mut runtime = new_runtime();
+        for name in ["shout", "listen", "both"] {
+            runtime
+                .create_data(VariableType::Broadcast, name, false, false)
+                .unwrap();
+        }
+        let top_ids = add_scripts(
+            &mut runtime,
+            "when @greenFlag clicked\nbroadcast [shout v]\nbroadcast [both v]\n\n\
+             when I receive [listen v]\n\nwhen I receive [both v]",
+        );
+        let shout_id = runtime.blocks[&top_ids[0]].next_id.clone();
+        assert_eq!(
+            messages(&mut runtime),
+            [
+                (
+                    shout_id,
+                    "Broadcast `shout` is sent but never received".into()
+                ),
+                (
+                    Some(top_ids[1].clone()),
+                    "Broadcast `listen` is received but never sent".into()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn uses_in_other_targets_count() {
+        let mut runtime = new_runtime();
+        runtime
+            .create_data(VariableType::Scalar, "score", false, false)
+            .unwrap();
+        runtime
+            .create_data(VariableType::Broadcast, "go", false, false)
+            .unwrap();
+        add_scripts(
+            &mut runtime,
+            "when I receive [go v]\nchange [score v] by (1)",
+        );
+        runtime.switch_target(STAGE).unwrap();
+        add_scripts(
+            &mut runtime,
+            "when @greenFlag clicked\nbroadcast [go v]\nsay (score)",
+        );
+        runtime.switch_target(SPRITE).unwrap();
+        assert!(messages(&mut runtime).is_empty());
+    }
+}
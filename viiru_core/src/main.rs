@@ -0,0 +1,203 @@
+use std::{
+    env,
+    io::{stdin, BufRead},
+    path::Path,
+    process::ExitCode,
+};
+
+use viiru_core::{
+    backend::MemoryBackend, definitions::load_user_definitions, interpreter::Interpreter,
+    load_config, project::Project, run_editor, runtime::Runtime, ui::in_terminal_scope,
+};
+
+const USAGE: &str = "\
+usage: viiru [options] [path]
+       viiru run [--frames <n>] [--seed <n>] <path>
+
+  path                 project file to open
+  --new                start from an empty project, even if path already exists
+  --readonly           never write the project back to disk
+  -o, --output <path>  where to write the project (defaults to the opened path)
+  --config <path>      config file to use instead of ~/.config/viiru/config.toml
+  -h, --help           show this message
+
+run clicks the green flag without opening the editor, printing what sprites say
+and answering questions with lines from stdin. it stops once every script is done,
+or after the given number of frames (30 per second, 9000 by default). the clock
+starts at midnight on 1 January 2000 (UTC), so every run gives the same output.";
+
+/// unix time of 2000-01-01 00:00 UTC, where the date blocks start out during `viiru run`
+const RUN_START_TIME: f64 = 946_684_800.0;
+
+#[derive(Default)]
+struct RunArgs {
+    path: Option<String>,
+    frames: Option<u64>,
+    seed: u64,
+}
+
+#[derive(Default)]
+struct Args {
+    path: Option<String>,
+    new: bool,
+    readonly: bool,
+    output: Option<String>,
+    config: Option<String>,
+    help: bool,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut parsed = Args::default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--new" => parsed.new = true,
+            "--readonly" => parsed.readonly = true,
+            "-o" | "--output" => {
+                let path = args.next().ok_or(format!("{arg} expects a path"))?;
+                parsed.output = Some(path);
+            }
+            "--config" => {
+                let path = args.next().ok_or(format!("{arg} expects a path"))?;
+                parsed.config = Some(path);
+            }
+            "-h" | "--help" => parsed.help = true,
+            flag if flag.starts_with('-') => return Err(format!("unknown option {flag}")),
+            _ if parsed.path.is_some() => return Err("only one project can be opened".into()),
+            _ => parsed.path = Some(arg),
+        }
+    }
+    Ok(parsed)
+}
+
+fn parse_run_args(mut args: impl Iterator<Item = String>) -> Result<RunArgs, String> {
+    let mut parsed = RunArgs::default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--frames" | "--seed" => {
+                let value = args.next().ok_or(format!("{arg} expects a number"))?;
+                let n = value
+                    .parse()
+                    .map_err(|_| format!("{arg} expects a number, not {value}"))?;
+                if arg == "--frames" {
+                    parsed.frames = Some(n);
+                } else {
+                    parsed.seed = n;
+                }
+            }
+            flag if flag.starts_with('-') => return Err(format!("unknown option {flag}")),
+            _ if parsed.path.is_some() => return Err("only one project can be run".into()),
+            _ => parsed.path = Some(arg),
+        }
+    }
+    Ok(parsed)
+}
+
+fn run_headless(args: RunArgs) -> ExitCode {
+    let Some(path) = args.path else {
+        eprintln!("viiru: run expects a path\n{USAGE}");
+        return ExitCode::from(2);
+    };
+    let project = match Project::load(&path) {
+        Ok(project) => project,
+        Err(err) => {
+            eprintln!("viiru: could not open project file {path}: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let mut interpreter = Interpreter::new(&project, args.seed, RUN_START_TIME);
+    let mut answers = stdin().lock().lines();
+    interpreter.green_flag();
+    for _ in 0..args.frames.unwrap_or(9000) {
+        let time = interpreter.now();
+        interpreter.step();
+        for output in interpreter.output.drain(..) {
+            println!("[{time:.2}s] {output}");
+        }
+        if interpreter.question.is_some() {
+            // running out of answers is the same as answering with nothing
+            let answer = answers.next().and_then(Result::ok).unwrap_or_default();
+            interpreter.answer_question(&answer);
+        }
+        if !interpreter.is_running() {
+            break;
+        }
+    }
+    for sprite in &interpreter.sprites {
+        if sprite.is_clone {
+            continue;
+        }
+        for variable in sprite.variables.values() {
+            println!("{}: {} = {}", sprite.name, variable.name, variable.value);
+        }
+        for list in sprite.lists.values() {
+            let items: Vec<_> = list.items.iter().map(ToString::to_string).collect();
+            println!("{}: {} = [{}]", sprite.name, list.name, items.join(", "));
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+fn main() -> ExitCode {
+    if let Err(err) = load_user_definitions() {
+        eprintln!("viiru: {err}");
+        return ExitCode::FAILURE;
+    }
+    let mut args = env::args().skip(1).peekable();
+    if args.peek().is_some_and(|arg| arg == "run") {
+        return match parse_run_args(args.skip(1)) {
+            Ok(args) => run_headless(args),
+            Err(message) => {
+                eprintln!("viiru: {message}\n{USAGE}");
+                ExitCode::from(2)
+            }
+        };
+    }
+    let args = match parse_args(args) {
+        Ok(args) => args,
+        Err(message) => {
+            eprintln!("viiru: {message}\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+    if args.help {
+        println!("{USAGE}");
+        return ExitCode::SUCCESS;
+    }
+
+    let mut runtime = Runtime::new(Box::new(MemoryBackend::default()));
+    runtime.readonly = args.readonly;
+    if let Err(err) = load_config(&mut runtime, args.config.as_deref().map(Path::new)) {
+        eprintln!("viiru: {err}");
+        return ExitCode::FAILURE;
+    }
+    // load before entering the alternate screen, so that errors stay visible
+    let loaded = match &args.path {
+        Some(path) if !args.new => runtime.load_project(path),
+        _ => runtime.new_project().map(|()| true),
+    };
+    match loaded {
+        Ok(true) => (),
+        Ok(false) => {
+            eprintln!("viiru: could not open project file {}", args.path.unwrap());
+            return ExitCode::FAILURE;
+        }
+        Err(err) if err.is_file_error() => {
+            eprintln!(
+                "viiru: could not open project file {}: {err}",
+                args.path.unwrap()
+            );
+            return ExitCode::FAILURE;
+        }
+        Err(err) => {
+            eprintln!("viiru: {err}");
+            return ExitCode::FAILURE;
+        }
+    }
+    runtime.project_path = args.output.or(args.path);
+
+    if let Err(err) = in_terminal_scope(|| run_editor(&mut runtime)) {
+        eprintln!("viiru: {err}");
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
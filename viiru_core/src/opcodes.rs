@@ -1,163 +1,100 @@
 use std::{collections::HashMap, sync::LazyLock};
 
 use crate::{
+    backend::VariableType,
+    block::Block,
+    definitions::block_files,
     runtime::Runtime,
-    spec::{spec, DropdownOption, Spec},
+    spec::{DropdownOption, Fragment, Shape, Spec},
 };
 
-#[rustfmt::skip] // screw you you're not ruining my thick fat alignment
-pub static BLOCKS: LazyLock<HashMap<String, Spec>> = LazyLock::new(|| {
-    HashMap::from_iter([
-        // this DSL has become complex and arcane over time. I need to store these in a proper config file
-        ("motion_movesteps".into(),              spec("{5F95F8/FFFFFF/4472C6}move (STEPS=10.0) steps")),
-        ("motion_turnright".into(),              spec("{5F95F8/FFFFFF/4472C6}turn [$CLOCKWISE] (DEGREES=15.0) degrees")),
-        ("motion_turnleft".into(),               spec("{5F95F8/FFFFFF/4472C6}turn [$ANTICLOCKWISE] (DEGREES=15.0) degrees")),
-        ("motion_goto".into(),                   spec("{5F95F8/FFFFFF/4472C6}go to (TO=motion_goto_menu)")),
-        ("motion_gotoxy".into(),                 spec("{5F95F8/FFFFFF/4472C6}go to x: (X=0.0) y: (Y=0.0)")),
-        ("motion_glideto".into(),                spec("{5F95F8/FFFFFF/4472C6}glide (SECS=1.0) secs to (TO=motion_glideto_menu)")),
-        ("motion_glidesecstoxy".into(),          spec("{5F95F8/FFFFFF/4472C6}glide (SECS=1.0) secs to x: (X=0.0) y: (Y=0.0)")),
-        ("motion_pointindirection".into(),       spec("{5F95F8/FFFFFF/4472C6}point in direction (DIRECTION=90.0)")),
-        ("motion_pointtowards".into(),           spec("{5F95F8/FFFFFF/4472C6}point towards (TOWARDS=motion_pointtowards_menu)")),
-        ("motion_changexby".into(),              spec("{5F95F8/FFFFFF/4472C6}change x by (DX=10.0)")),
-        ("motion_setx".into(),                   spec("{5F95F8/FFFFFF/4472C6}set x to (X=0.0)")),
-        ("motion_changeyby".into(),              spec("{5F95F8/FFFFFF/4472C6}change y by (DY=10.0)")),
-        ("motion_sety".into(),                   spec("{5F95F8/FFFFFF/4472C6}set y to (Y=0.0)")),
-        ("motion_ifonedgebounce".into(),         spec("{5F95F8/FFFFFF/4472C6}if on edge, bounce")),
-        ("motion_setrotationstyle".into(),       spec("{5F95F8/FFFFFF/4472C6}set rotation style [STYLE&`left-right`&`don't rotate`&`all around`]")),
-        ("motion_xposition".into(),              spec("(5F95F8/FFFFFF/4472C6)x position")),
-        ("motion_yposition".into(),              spec("(5F95F8/FFFFFF/4472C6)y position")),
-        ("motion_direction".into(),              spec("(5F95F8/FFFFFF/4472C6)direction")),
-        ("looks_sayforsecs".into(),              spec("{9268F7/FFFFFF/714FC4}say (MESSAGE=`Hello!`) for (SECS=2.0) seconds")),
-        ("looks_say".into(),                     spec("{9268F7/FFFFFF/714FC4}say (MESSAGE=`Hello!`)")),
-        ("looks_thinkforsecs".into(),            spec("{9268F7/FFFFFF/714FC4}think (MESSAGE=`Hmm...`) for (SECS=2.0) seconds")),
-        ("looks_think".into(),                   spec("{9268F7/FFFFFF/714FC4}think (MESSAGE=`Hmm...`)")),
-        ("looks_switchcostumeto".into(),         spec("{9268F7/FFFFFF/714FC4}switch costume to (COSTUME=looks_costume)")),
-        ("looks_nextcostume".into(),             spec("{9268F7/FFFFFF/714FC4}next costume")),
-        ("looks_switchbackdropto".into(),        spec("{9268F7/FFFFFF/714FC4}switch backdrop to (BACKDROP=looks_backdrops)")),
-        ("looks_switchbackdroptoandwait".into(), spec("{9268F7/FFFFFF/714FC4}switch backdrop to (BACKDROP=looks_backdrops) and wait")),
-        ("looks_nextbackdrop".into(),            spec("{9268F7/FFFFFF/714FC4}next backdrop")),
-        ("looks_changesizeby".into(),            spec("{9268F7/FFFFFF/714FC4}change size by (CHANGE=10.0)")),
-        ("looks_setsizeto".into(),               spec("{9268F7/FFFFFF/714FC4}set size to (SIZE=100.0)%")),
-        ("looks_changeeffectby".into(),          spec("{9268F7/FFFFFF/714FC4}change [EFFECT/COLOR=`color`/FISHEYE=`fisheye`/WHIRL=`whirl`/PIXELATE=`pixelate`/MOSAIC=`mosaic`/BRIGHTNESS=`brightness`/GHOST=`ghost`] effect by (CHANGE=25.0)")),
-        ("looks_seteffectto".into(),             spec("{9268F7/FFFFFF/714FC4}set [EFFECT/COLOR=`color`/FISHEYE=`fisheye`/WHIRL=`whirl`/PIXELATE=`pixelate`/MOSAIC=`mosaic`/BRIGHTNESS=`brightness`/GHOST=`ghost`] effect to (VALUE=0.0)")),
-        ("looks_cleargraphiceffects".into(),     spec("{9268F7/FFFFFF/714FC4}clear graphic effects")),
-        ("looks_show".into(),                    spec("{9268F7/FFFFFF/714FC4}show")),
-        ("looks_hide".into(),                    spec("{9268F7/FFFFFF/714FC4}hide")),
-        ("looks_gotofrontback".into(),           spec("{9268F7/FFFFFF/714FC4}go to [FRONT_BACK&`front`&`back`] layer")),
-        ("looks_goforwardbackwardlayers".into(), spec("{9268F7/FFFFFF/714FC4}go [FORWARD_BACKWARD&`forward`&`backward`] (NUM=1.0) layers")),
-        ("looks_costumenumbername".into(),       spec("(9268F7/FFFFFF/714FC4)costume [NUMBER_NAME&`number`&`name`]")),
-        ("looks_backdropnumbername".into(),      spec("(9268F7/FFFFFF/714FC4)backdrop [NUMBER_NAME&`number`&`name`]")),
-        ("looks_size".into(),                    spec("(9268F7/FFFFFF/714FC4)size")),
-        ("sound_playuntildone".into(),           spec("{C169C9/FFFFFF/AF4BB7}play sound (SOUND_MENU=sound_sounds_menu) until done")),
-        ("sound_play".into(),                    spec("{C169C9/FFFFFF/AF4BB7}start sound (SOUND_MENU=sound_sounds_menu)")),
-        ("sound_stopallsounds".into(),           spec("{C169C9/FFFFFF/AF4BB7}stop all sounds")),
-        ("sound_changeeffectby".into(),          spec("{C169C9/FFFFFF/AF4BB7}change [EFFECT/PITCH=`pitch`/PAN=`pan left/right`] effect by (VALUE=10.0)")),
-        ("sound_seteffectto".into(),             spec("{C169C9/FFFFFF/AF4BB7}set [EFFECT/PITCH=`pitch`/PAN=`pan left/right`] effect to (VALUE=100.0)")),
-        ("sound_cleareffects".into(),            spec("{C169C9/FFFFFF/AF4BB7}clear sound effects")),
-        ("sound_changevolumeby".into(),          spec("{C169C9/FFFFFF/AF4BB7}change volume by (VOLUME=-10.0)")),
-        ("sound_setvolumeto".into(),             spec("{C169C9/FFFFFF/AF4BB7}set volume to (VOLUME=100.0)%")),
-        ("sound_volume".into(),                  spec("(C169C9/FFFFFF/AF4BB7)volume")),
-        ("event_whenflagclicked".into(),         spec("{F5C242/FFFFFF/C49B33^when [$FLAG] clicked")),
-        ("event_whenkeypressed".into(),          spec("{F5C242/FFFFFF/C49B33^when [KEY_OPTION&`space`&`up arrow`&`down arrow`&`right arrow`&`left arrow`&`any`&`a`&`b`&`c`&`d`&`e`&`f`&`g`&`h`&`i`&`j`&`k`&`l`&`m`&`n`&`o`&`p`&`q`&`r`&`s`&`t`&`u`&`v`&`w`&`x`&`y`&`z`&`0`&`1`&`2`&`3`&`4`&`5`&`6`&`7`&`8`&`9`] key pressed")), // case in point wtf is this
-        ("event_whenthisspriteclicked".into(),   spec("{F5C242/FFFFFF/C49B33^when this sprite clicked")),
-        ("event_whenstageclicked".into(),        spec("{F5C242/FFFFFF/C49B33^when stage clicked")),
-        ("event_whenbackdropswitchesto".into(),  spec("{F5C242/FFFFFF/C49B33^when backdrop switches to [BACKDROP]")),
-        ("event_whengreaterthan".into(),         spec("{F5C242/FFFFFF/C49B33^when [WHENGREATERTHANMENU/LOUDNESS=`loudness`] > (VALUE=10.0)")),
-        ("event_whenbroadcastreceived".into(),   spec("{F5C242/FFFFFF/C49B33^when I receive [BROADCAST_OPTION]")),
-        ("event_broadcast".into(),               spec("{F5C242/FFFFFF/C49B33}broadcast (BROADCAST_INPUT=event_broadcast_menu)")),
-        ("event_broadcastandwait".into(),        spec("{F5C242/FFFFFF/C49B33}broadcast (BROADCAST_INPUT=event_broadcast_menu) and wait")),
-        ("control_wait".into(),                  spec("{F3AF43/FFFFFF/C58E36}wait (DURATION=1.0) seconds")),
-        ("control_repeat".into(),                spec("{F3AF43/FFFFFF/C58E36}[:SUBSTACK]repeat (TIMES=10.0)\n{SUBSTACK}\n\t")),
-        ("control_forever".into(),               spec("{F3AF43/FFFFFF/C58E36}[:SUBSTACK]forever\n{SUBSTACK}\n\t")),
-        ("control_if".into(),                    spec("{F3AF43/FFFFFF/C58E36}[:SUBSTACK]if <CONDITION> then\n{SUBSTACK}\n\t")),
-        ("control_if_else".into(),               spec("{F3AF43/FFFFFF/C58E36}[:SUBSTACK]if <CONDITION> then\n{SUBSTACK}\n[:SUBSTACK2]else\n{SUBSTACK2}\n\t")),
-        ("control_wait_until".into(),            spec("{F3AF43/FFFFFF/C58E36}wait until <CONDITION>")),
-        ("control_repeat_until".into(),          spec("{F3AF43/FFFFFF/C58E36}[:SUBSTACK]repeat until <CONDITION>\n{SUBSTACK}\n\t")),
-        ("control_stop".into(),                  spec("{F3AF43/FFFFFF/C58E36}stop [STOP_OPTION]")),
-        ("control_start_as_clone".into(),        spec("{F3AF43/FFFFFF/C58E36^when I start as a clone")),
-        ("control_create_clone_of".into(),       spec("{F3AF43/FFFFFF/C58E36}create a clone of (CLONE_OPTION=control_create_clone_of_menu)")),
-        ("control_delete_this_clone".into(),     spec("{F3AF43/FFFFFF/C58E36}delete this clone")),
-        ("sensing_touchingobject".into(),        spec("<71AFD2/FFFFFF/4B8CB4>touching (TOUCHINGOBJECTMENU=sensing_touchingobjectmenu)?")),
-        ("sensing_touchingcolor".into(),         spec("<71AFD2/FFFFFF/4B8CB4>touching color (COLOR=#3D7088)?")),
-        ("sensing_coloristouchingcolor".into(),  spec("<71AFD2/FFFFFF/4B8CB4>color (COLOR=#461C5B) is touching (COLOR2=#5BB033)?")),
-        ("sensing_distanceto".into(),            spec("(71AFD2/FFFFFF/4B8CB4)distance to(DISTANCETOMENU=sensing_distancetomenu)")),
-        ("sensing_askandwait".into(),            spec("{71AFD2/FFFFFF/4B8CB4}ask (QUESTION=`What's your name?`) and wait")),
-        ("sensing_answer".into(),                spec("(71AFD2/FFFFFF/4B8CB4)answer")),
-        ("sensing_keypressed".into(),            spec("<71AFD2/FFFFFF/4B8CB4>key (KEY_OPTION=sensing_keyoptions) pressed?")),
-        ("sensing_mousedown".into(),             spec("<71AFD2/FFFFFF/4B8CB4>mouse down?")),
-        ("sensing_mousex".into(),                spec("(71AFD2/FFFFFF/4B8CB4)mouse x")),
-        ("sensing_mousey".into(),                spec("(71AFD2/FFFFFF/4B8CB4)mouse y")),
-        ("sensing_setdragmode".into(),           spec("{71AFD2/FFFFFF/4B8CB4}set drag mode [DRAG_MODE&`draggable`&`not draggable`]")),
-        ("sensing_loudness".into(),              spec("(71AFD2/FFFFFF/4B8CB4)loudness")),
-        ("sensing_timer".into(),                 spec("(71AFD2/FFFFFF/4B8CB4)timer")),
-        ("sensing_resettimer".into(),            spec("{71AFD2/FFFFFF/4B8CB4}reset timer")),
-        ("sensing_of".into(),                    spec("(71AFD2/FFFFFF/4B8CB4)[PROPERTY] of (OBJECT=sensing_of_object_menu)")),
-        ("sensing_current".into(),               spec("(71AFD2/FFFFFF/4B8CB4)current [CURRENTMENU/YEAR=`year`/MONTH=`month`/DATE=`date`/DAYOFWEEK=`day of week`/HOUR=`hour`/MINUTE=`minute`/SECOND=`second`]")),
-        ("sensing_dayssince2000".into(),         spec("(71AFD2/FFFFFF/4B8CB4)days since 2000")),
-        ("sensing_username".into(),              spec("(71AFD2/FFFFFF/4B8CB4)username")),
-        ("operator_add".into(),                  spec("(74BE65/FFFFFF/529244)(NUM1=@) + (NUM2=@)")),
-        ("operator_subtract".into(),             spec("(74BE65/FFFFFF/529244)(NUM1=@) - (NUM2=@)")),
-        ("operator_multiply".into(),             spec("(74BE65/FFFFFF/529244)(NUM1=@) * (NUM2=@)")),
-        ("operator_divide".into(),               spec("(74BE65/FFFFFF/529244)(NUM1=@) / (NUM2=@)")),
-        ("operator_random".into(),               spec("(74BE65/FFFFFF/529244)pick random (FROM=1.0) to (TO=10.0)")),
-        ("operator_gt".into(),                   spec("<74BE65/FFFFFF/529244>(OPERAND1=@) > (OPERAND2=50.0)")),
-        ("operator_lt".into(),                   spec("<74BE65/FFFFFF/529244>(OPERAND1=@) [<] (OPERAND2=50.0)")),
-        ("operator_equals".into(),               spec("<74BE65/FFFFFF/529244>(OPERAND1=@) = (OPERAND2=50.0)")),
-        ("operator_and".into(),                  spec("<74BE65/FFFFFF/529244><OPERAND1> and <OPERAND2>")),
-        ("operator_or".into(),                   spec("<74BE65/FFFFFF/529244><OPERAND1> or <OPERAND2>")),
-        ("operator_not".into(),                  spec("<74BE65/FFFFFF/529244>not <OPERAND>")),
-        ("operator_join".into(),                 spec("(74BE65/FFFFFF/529244)join (STRING1=`apple`) (STRING2=`banana`)")),
-        ("operator_letter_of".into(),            spec("(74BE65/FFFFFF/529244)letter (LETTER=1.0) of (STRING=`apple`)")),
-        ("operator_length".into(),               spec("(74BE65/FFFFFF/529244)length of (STRING=`apple`)")),
-        ("operator_contains".into(),             spec("{74BE65/FFFFFF/529244}(STRING1=`apple`) contains (STRING2=`a`)?")),
-        ("operator_mod".into(),                  spec("(74BE65/FFFFFF/529244)(NUM1=@) mod (NUM2=@)")),
-        ("operator_round".into(),                spec("(74BE65/FFFFFF/529244)round (NUM=@)")),
-        ("operator_mathop".into(),               spec("(74BE65/FFFFFF/529244)[OPERATOR&`abs`&`floor`&`ceiling`&`sqrt`&`sin`&`cos`&`tan`&`asin`&`acos`&`atan`&`ln`&`log`&`e ^`&`10 ^`] of (NUM=@)")),
-        ("data_variable".into(),                 spec("(F0923C/FFFFFF/FFFFFF)[&VARIABLE]")), // dynamic label (field contains ID)
-        ("data_setvariableto".into(),            spec("{F0923C/FFFFFF/CD742A}set [VARIABLE] to (VALUE=0.0)")),
-        ("data_changevariableby".into(),         spec("{F0923C/FFFFFF/CD742A}change [VARIABLE] by (VALUE=1.0)")),
-        ("data_showvariable".into(),             spec("{F0923C/FFFFFF/CD742A}show variable [VARIABLE]")),
-        ("data_hidevariable".into(),             spec("{F0923C/FFFFFF/CD742A}hide variable [VARIABLE]")),
-        ("data_listcontents".into(),             spec("(ED7035/FFFFFF/D55825)[&LIST]")), // dynamic label (field contains ID)
-        ("data_addtolist".into(),                spec("{ED7035/FFFFFF/D55825}add (ITEM=`thing`) to [LIST]")),
-        ("data_deleteoflist".into(),             spec("{ED7035/FFFFFF/D55825}delete (INDEX=1.0) of [LIST]")),
-        ("data_deletealloflist".into(),          spec("{ED7035/FFFFFF/D55825}delete all of [LIST]")),
-        ("data_insertatlist".into(),             spec("{ED7035/FFFFFF/D55825}insert (ITEM=`thing`) at (INDEX=1.0) of [LIST]")),
-        ("data_replaceitemoflist".into(),        spec("{ED7035/FFFFFF/D55825}replace item (INDEX=1.0) of [LIST] with (ITEM=`thing`)")),
-        ("data_itemoflist".into(),               spec("(ED7035/FFFFFF/D55825)item (INDEX=1.0) of [LIST]")),
-        ("data_itemnumoflist".into(),            spec("(ED7035/FFFFFF/D55825)item # of (ITEM=`thing`) in [LIST]")),
-        ("data_lengthoflist".into(),             spec("(ED7035/FFFFFF/D55825)length of [LIST]")),
-        ("data_listcontainsitem".into(),         spec("<ED7035/FFFFFF/D55825>[LIST] contains (ITEM=`thing`)?")),
-        ("data_showlist".into(),                 spec("{ED7035/FFFFFF/D55825}show list [LIST]")),
-        ("data_hidelist".into(),                 spec("{ED7035/FFFFFF/D55825}hide list [LIST]")),
-        // internal dropdowns
-        ("motion_goto_menu".into(),              spec("(517FD1/FFFFFF/4472C6![TO]")),
-        ("motion_glideto_menu".into(),           spec("(517FD1/FFFFFF/4472C6![TO]")),
-        ("motion_pointtowards_menu".into(),      spec("(517FD1/FFFFFF/4472C6![TOWARDS]")),
-        ("looks_costume".into(),                 spec("(7F5ECF/FFFFFF/714FC4![COSTUME]")),
-        ("looks_backdrops".into(),               spec("(7F5ECF/FFFFFF/714FC4![BACKDROP]")),
-        ("sound_sounds_menu".into(),             spec("(BB57C3/FFFFFF/AF4BB7![SOUND_MENU]")),
-        ("event_broadcast_menu".into(),          spec("(DDAE3B/FFFFFF/C49B33![BROADCAST_OPTION]")),
-        ("control_create_clone_of_menu".into(),  spec("(E09F3B/FFFFFF/C58E36![CLONE_OPTION]")),
-        ("sensing_touchingobjectmenu".into(),    spec("(62A6CD/FFFFFF/4B8CB4![TOUCHINGOBJECTMENU]")),
-        ("sensing_distancetomenu".into(),        spec("(62A6CD/FFFFFF/4B8CB4![DISTANCETOMENU]")),
-        ("sensing_keyoptions".into(),            spec("(62A6CD/FFFFFF/4B8CB4![KEY_OPTION&`space`&`up arrow`&`down arrow`&`right arrow`&`left arrow`&`any`&`a`&`b`&`c`&`d`&`e`&`f`&`g`&`h`&`i`&`j`&`k`&`l`&`m`&`n`&`o`&`p`&`q`&`r`&`s`&`t`&`u`&`v`&`w`&`x`&`y`&`z`&`0`&`1`&`2`&`3`&`4`&`5`&`6`&`7`&`8`&`9`]")),
-        ("sensing_of_object_menu".into(),        spec("(62A6CD/FFFFFF/4B8CB4![OBJECT]")),
-        // shadow blocks
-        ("math_number".into(),                   spec("(FFFFFF/595E73/FFFFFF![*NUM]")), // dynamic label (field contains text)
-        ("math_integer".into(),                  spec("(FFFFFF/595E73/FFFFFF![*NUM]")), // dynamic label (field contains text)
-        ("math_whole_number".into(),             spec("(FFFFFF/595E73/FFFFFF![*NUM]")), // dynamic label (field contains text)
-        ("math_positive_number".into(),          spec("(FFFFFF/595E73/FFFFFF![*NUM]")), // dynamic label (field contains text)
-        ("math_angle".into(),                    spec("(FFFFFF/595E73/FFFFFF![*NUM]")), // dynamic label (field contains text)
-        ("text".into(),                          spec("(FFFFFF/595E73/FFFFFF![*TEXT]")), // dynamic label (field contains text)
-        ("colour_picker".into(),                 spec("(FFFFFF/595E73/FFFFFF![#COLOUR]")), // dynamic label (field contains color)
-    ])
-});
+/// every block viiru knows how to draw, from the built-in definition files and the user's own
+pub static BLOCKS: LazyLock<HashMap<String, Spec>> =
+    LazyLock::new(|| block_files().flat_map(|file| file.blocks).collect());
+
+/// Blocks we have no spec for (hidden blocks, unknown extensions, other editors' additions)
+/// are drawn in grey with their opcode, followed by whatever inputs and fields they carry.
+/// Shadows only show their fields, since they sit inside another block's slot.
+pub fn fallback_spec(block: &Block, shape: Shape, is_shadow: bool) -> Spec {
+    let mut input_names: Vec<_> = block.inputs.keys().collect();
+    input_names.sort();
+    let mut field_names: Vec<_> = block.fields.keys().collect();
+    field_names.sort();
+    let (substacks, input_names): (Vec<_>, Vec<_>) = input_names
+        .into_iter()
+        .partition(|input_name| input_name.starts_with("SUBSTACK"));
+
+    let mut first_line = vec![];
+    if is_shadow {
+        for (i, field_name) in field_names.iter().enumerate() {
+            if i > 0 {
+                first_line.push(Fragment::Text(" ".into()));
+            }
+            first_line.push(Fragment::FieldText(field_name.to_string()));
+        }
+        if field_names.is_empty() {
+            first_line.push(Fragment::Text(block.opcode.clone()));
+        }
+    } else {
+        if shape == Shape::Stack {
+            if let Some(substack_name) = substacks.first() {
+                first_line.push(Fragment::AlignmentPoint(substack_name.to_string()));
+            }
+        }
+        first_line.push(Fragment::Text(block.opcode.clone()));
+        for input_name in input_names {
+            first_line.push(Fragment::Text(format!(" {input_name}: ")));
+            first_line.push(Fragment::StrumberInput(input_name.clone(), None));
+        }
+        for field_name in field_names {
+            first_line.push(Fragment::Text(format!(" {field_name}: ")));
+            first_line.push(Fragment::FieldText(field_name.clone()));
+        }
+    }
+
+    let mut lines = vec![first_line];
+    // only stacks have room for mouths; anywhere else they're just left out
+    if shape == Shape::Stack && !substacks.is_empty() {
+        for (i, substack_name) in substacks.iter().enumerate() {
+            if i > 0 {
+                lines.push(vec![
+                    Fragment::AlignmentPoint(substack_name.to_string()),
+                    Fragment::Text(substack_name.to_lowercase()),
+                ]);
+            }
+            lines.push(vec![Fragment::BlockInput(substack_name.to_string())]);
+        }
+        lines.push(vec![Fragment::Expander]);
+    }
+
+    // scratch names every hat "when ..."
+    let is_hat = shape == Shape::Stack
+        && block
+            .opcode
+            .split_once('_')
+            .is_some_and(|(_, name)| name.starts_with("when"));
+    let (block_color, alt_color) = if is_shadow {
+        ((0x70, 0x70, 0x70), (0x5C, 0x5C, 0x5C))
+    } else {
+        ((0x85, 0x85, 0x85), (0x70, 0x70, 0x70))
+    };
+    Spec {
+        shape,
+        is_shadow,
+        is_hat,
+        block_color,
+        text_color: (0xFF, 0xFF, 0xFF),
+        alt_color,
+        lines,
+        static_dropdown_options: None,
+    }
+}
 
 fn dup(s: &str) -> DropdownOption {
     DropdownOption {
         value: s.to_string(),
         display: s.to_string(),
+        id: None,
     }
 }
 
@@ -165,12 +102,84 @@ fn yup(value: &str, display: &str) -> DropdownOption {
     DropdownOption {
         value: value.to_string(),
         display: display.to_string(),
+        id: None,
+    }
+}
+
+/// Which kind of variable the block's dropdown picks from, if any
+pub fn variable_type_of(opcode: &str) -> Option<VariableType> {
+    match opcode {
+        "data_variable"
+        | "data_setvariableto"
+        | "data_changevariableby"
+        | "data_showvariable"
+        | "data_hidevariable" => Some(VariableType::Scalar),
+        "data_listcontents"
+        | "data_addtolist"
+        | "data_deleteoflist"
+        | "data_deletealloflist"
+        | "data_insertatlist"
+        | "data_replaceitemoflist"
+        | "data_itemoflist"
+        | "data_itemnumoflist"
+        | "data_lengthoflist"
+        | "data_listcontainsitem"
+        | "data_showlist"
+        | "data_hidelist" => Some(VariableType::List),
+        "event_whenbroadcastreceived" | "event_broadcast_menu" => Some(VariableType::Broadcast),
+        _ => None,
     }
 }
 
+/// The field names are displayed, but the ids are what the VM actually looks up
+pub fn variable_options(names: &HashMap<String, String>) -> Vec<DropdownOption> {
+    let mut options: Vec<_> = names
+        .iter()
+        .map(|(id, name)| DropdownOption {
+            value: name.clone(),
+            display: name.clone(),
+            id: Some(id.clone()),
+        })
+        .collect();
+    options.sort_by(|a, b| a.display.cmp(&b.display));
+    options
+}
+
+/// every sprite but the one being edited, by name
+fn other_sprites<'r>(runtime: &'r Runtime) -> impl Iterator<Item = DropdownOption> + 'r {
+    runtime
+        .targets
+        .iter()
+        .enumerate()
+        .filter(|&(i, target)| !target.is_stage && i != runtime.editing_target)
+        .map(|(_, target)| dup(&target.name))
+}
+
+/// the stage's costumes
+fn backdrop_names<'r>(runtime: &'r Runtime) -> impl Iterator<Item = &'r str> {
+    let stage = runtime.targets.iter().find(|target| target.is_stage);
+    stage
+        .into_iter()
+        .flat_map(|stage| stage.costumes.iter().map(String::as_str))
+}
+
+/// Menus naming costumes, sounds or backdrops can refer to ones that have since been removed
+pub fn is_asset_menu(opcode: &str) -> bool {
+    matches!(
+        opcode,
+        "looks_costume" | "looks_backdrops" | "sound_sounds_menu" | "event_whenbackdropswitchesto"
+    )
+}
+
 pub fn dropdown_options(runtime: &Runtime, block_id: &str, opcode: &str) -> Vec<DropdownOption> {
     if let Some(opts) = &BLOCKS[opcode].static_dropdown_options {
         opts.clone()
+    } else if let Some(variable_type) = variable_type_of(opcode) {
+        variable_options(match variable_type {
+            VariableType::Scalar => &runtime.variables,
+            VariableType::List => &runtime.lists,
+            VariableType::Broadcast => &runtime.broadcasts,
+        })
     } else {
         match opcode {
             "control_stop" => {
@@ -185,74 +194,87 @@ pub fn dropdown_options(runtime: &Runtime, block_id: &str, opcode: &str) -> Vec<
                 }
             }
             "control_create_clone_of_menu" => {
-                // todo: targets
-                todo!("targets")
-                // yup("_myself_", "myself")
-            }
-            "data_setvariableto"
-            | "data_changevariableby"
-            | "data_showvariable"
-            | "data_hidevariable" => {
-                todo!("variable dropdowns")
-            }
-            "data_addtolist"
-            | "data_deleteoflist"
-            | "data_deletealloflist"
-            | "data_insertatlist"
-            | "data_replaceitemoflist"
-            | "data_itemoflist"
-            | "data_itemnumoflist"
-            | "data_lengthoflist"
-            | "data_listcontainsitem"
-            | "data_showlist"
-            | "data_hidelist" => {
-                todo!("list dropdowns")
+                let is_stage = runtime
+                    .targets
+                    .get(runtime.editing_target)
+                    .is_some_and(|target| target.is_stage);
+                // the stage can't be cloned
+                let myself = (!is_stage).then(|| yup("_myself_", "myself"));
+                myself.into_iter().chain(other_sprites(runtime)).collect()
             }
-            "event_whenbackdropswitchesto" | "looks_backdrops" => {
-                todo!("backdrop dropdowns")
-            }
-            "looks_costume" => {
-                todo!("costume dropdowns")
+            "event_whenbackdropswitchesto" => backdrop_names(runtime).map(dup).collect(),
+            "looks_backdrops" => {
+                let mut options: Vec<_> = backdrop_names(runtime).map(dup).collect();
+                options.extend(["next backdrop", "previous backdrop", "random backdrop"].map(dup));
+                options
             }
+            "looks_costume" => runtime
+                .targets
+                .get(runtime.editing_target)
+                .map(|target| target.costumes.iter().map(|name| dup(name)).collect())
+                .unwrap_or_default(),
             "motion_pointtowards_menu" => {
-                todo!("targets")
-                // yup("_mouse_", "mouse-pointer")
-                // yup("_random_", "random direction")
+                let specials = [
+                    yup("_mouse_", "mouse-pointer"),
+                    yup("_random_", "random direction"),
+                ];
+                specials.into_iter().chain(other_sprites(runtime)).collect()
             }
             "motion_goto_menu" | "motion_glideto_menu" => {
-                todo!("targets")
-                // yup("_mouse_", "mouse-pointer")
-                // yup("_random_", "random position")
+                let specials = [
+                    yup("_mouse_", "mouse-pointer"),
+                    yup("_random_", "random position"),
+                ];
+                specials.into_iter().chain(other_sprites(runtime)).collect()
             }
             "sensing_touchingobjectmenu" => {
-                todo!("targets")
-                // yup("_mouse_", "mouse-pointer")
-                // yup("_edge_", "edge")
-            }
-            "sensing_distancetomenu" => {
-                todo!("targets")
-                // yup("_mouse_", "mouse-pointer")
-            }
-            "sensing_of_object_menu" => {
-                todo!("targets")
-                // yup("_stage_", "Stage")
+                let specials = [yup("_mouse_", "mouse-pointer"), yup("_edge_", "edge")];
+                specials.into_iter().chain(other_sprites(runtime)).collect()
             }
+            "sensing_distancetomenu" => std::iter::once(yup("_mouse_", "mouse-pointer"))
+                .chain(other_sprites(runtime))
+                .collect(),
+            "sensing_of_object_menu" => std::iter::once(yup("_stage_", "Stage"))
+                .chain(other_sprites(runtime))
+                .collect(),
             "sensing_of" => {
-                todo!("target-dependant properties")
-                // x position
-                // y position
-                // direction
-                // costume #
-                // costume name
-                // size
-                // volume
-                // backdrop #
-                // backdrop name
-            }
-            "sound_sounds_menu" => {
-                todo!("sounds")
+                let object = runtime.blocks[block_id]
+                    .inputs
+                    .get("OBJECT")
+                    .and_then(|input| runtime.blocks.get(input.shadow_id.as_ref()?))
+                    .and_then(|menu| menu.fields.get("OBJECT"))
+                    .map_or("", |field| field.value.as_str());
+                let target = runtime.targets.iter().find(|target| match object {
+                    "_stage_" => target.is_stage,
+                    name => !target.is_stage && target.name == name,
+                });
+                let properties: &[&str] = if target.is_some_and(|target| target.is_stage) {
+                    &["backdrop #", "backdrop name", "volume"]
+                } else {
+                    &[
+                        "x position",
+                        "y position",
+                        "direction",
+                        "costume #",
+                        "costume name",
+                        "size",
+                        "volume",
+                    ]
+                };
+                let variables = target.into_iter().flat_map(|target| &target.variables);
+                properties
+                    .iter()
+                    .copied()
+                    .chain(variables.map(String::as_str))
+                    .map(dup)
+                    .collect()
             }
-            _ => unimplemented!(),
+            "sound_sounds_menu" => runtime
+                .targets
+                .get(runtime.editing_target)
+                .map(|target| target.sounds.iter().map(|name| dup(name)).collect())
+                .unwrap_or_default(),
+            _ => vec![],
         }
     }
 }
@@ -370,12 +392,12 @@ pub const TOOLBOX: &[&str] = &[
     "operator_mod",
     "operator_round",
     "operator_mathop",
-    // "data_variable", // extra fields
+    "data_variable", // one per variable
     "data_setvariableto",
     "data_changevariableby",
     "data_showvariable",
     "data_hidevariable",
-    // "data_listcontents", // extra fields
+    "data_listcontents", // one per list
     "data_addtolist",
     "data_deleteoflist",
     "data_deletealloflist",
@@ -388,8 +410,51 @@ pub const TOOLBOX: &[&str] = &[
     "data_showlist",
     "data_hidelist",
     // "procedures_definition", // extra fields
-    // "procedures_call", // extra fields
+    // one per custom block
+    "procedures_call",
     // "procedures_prototype", // extra fields
     // "argument_reporter_string_number", // extra fields
     // "argument_reporter_boolean", // extra fields
 ];
+
+/// Scratch hides the sprite-only blocks while editing the stage, and vice versa
+pub fn in_palette(opcode: &str, is_stage: bool) -> bool {
+    const SPRITE_ONLY: &[&str] = &[
+        "looks_sayforsecs",
+        "looks_say",
+        "looks_thinkforsecs",
+        "looks_think",
+        "looks_switchcostumeto",
+        "looks_nextcostume",
+        "looks_changesizeby",
+        "looks_setsizeto",
+        "looks_show",
+        "looks_hide",
+        "looks_gotofrontback",
+        "looks_goforwardbackwardlayers",
+        "looks_costumenumbername",
+        "looks_size",
+        "event_whenthisspriteclicked",
+        "control_start_as_clone",
+        "control_delete_this_clone",
+        "sensing_touchingobject",
+        "sensing_touchingcolor",
+        "sensing_coloristouchingcolor",
+        "sensing_distanceto",
+        "sensing_setdragmode",
+        "pen_stamp",
+        "pen_penDown",
+        "pen_penUp",
+        "pen_setPenColorToColor",
+        "pen_changePenColorParamBy",
+        "pen_setPenColorParamTo",
+        "pen_changePenSizeBy",
+        "pen_setPenSizeTo",
+    ];
+    const STAGE_ONLY: &[&str] = &["event_whenstageclicked", "looks_switchbackdroptoandwait"];
+    if is_stage {
+        !opcode.starts_with("motion_") && !SPRITE_ONLY.contains(&opcode)
+    } else {
+        !STAGE_ONLY.contains(&opcode)
+    }
+}
@@ -0,0 +1,735 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{Read, Write},
+};
+
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::{Map, Value};
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::{
+    block::{Block, Field, Input, Mutation},
+    result::ViiruResult,
+};
+
+// 50px per cell
+const PIXELS_PER_CELL: f64 = 50.0;
+
+/// Scratch's own blank costume, used for both the backdrop and sprite of a new project
+const BLANK_SVG: &[u8] = br#"<svg version="1.1" width="2" height="2" viewBox="-1 -1 2 2" xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink">
+  <!-- Exported by Scratch - http://scratch.mit.edu/ -->
+</svg>"#;
+const BLANK_SVG_ASSET_ID: &str = "cd21514d0531fdffb22204e0ec5ed84a";
+
+/// The contents of an `.sb3` file: `project.json` plus every asset stored next to it.
+#[derive(Serialize, Deserialize, Default)]
+pub struct Project {
+    pub targets: Vec<Target>,
+    pub monitors: Vec<Value>,
+    pub extensions: Vec<String>,
+    pub meta: Value,
+    /// Costume and sound files, keyed by their file name in the archive
+    #[serde(skip)]
+    pub assets: Vec<(String, Vec<u8>)>,
+}
+
+/// Scratch accepts files that leave keys out, so missing ones get their defaults
+#[derive(Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Target {
+    pub is_stage: bool,
+    pub name: String,
+    pub variables: IndexMap<String, Variable>,
+    pub lists: IndexMap<String, List>,
+    pub broadcasts: IndexMap<String, String>,
+    pub blocks: IndexMap<String, SerializedBlock>,
+    pub comments: Map<String, Value>,
+    pub current_costume: usize,
+    pub costumes: Vec<Costume>,
+    pub sounds: Vec<Sound>,
+    #[serde(default = "full_volume", serialize_with = "serialize_number")]
+    pub volume: f64,
+    pub layer_order: usize,
+    // sprite-only properties
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub visible: Option<bool>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_optional_number"
+    )]
+    pub x: Option<f64>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_optional_number"
+    )]
+    pub y: Option<f64>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_optional_number"
+    )]
+    pub size: Option<f64>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_optional_number"
+    )]
+    pub direction: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub draggable: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rotation_style: Option<String>,
+    /// stage-only properties (tempo, video state, ...) and anything else we don't touch
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// Serialized as `[name, value]`, or `[name, value, true]` for cloud variables
+#[derive(Clone)]
+pub struct Variable {
+    pub name: String,
+    pub value: Value,
+    pub is_cloud: bool,
+}
+
+impl Serialize for Variable {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.is_cloud {
+            (&self.name, &self.value, true).serialize(serializer)
+        } else {
+            (&self.name, &self.value).serialize(serializer)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Variable {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let values = Vec::<Value>::deserialize(deserializer)?;
+        match values.as_slice() {
+            [Value::String(name), value, rest @ ..] => Ok(Variable {
+                name: name.clone(),
+                value: value.clone(),
+                is_cloud: rest.first().and_then(Value::as_bool).unwrap_or(false),
+            }),
+            _ => Err(serde::de::Error::custom("malformed variable")),
+        }
+    }
+}
+
+/// Serialized as `[name, [items...]]`
+#[derive(Clone)]
+pub struct List {
+    pub name: String,
+    pub items: Vec<Value>,
+}
+
+impl Serialize for List {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        (&self.name, &self.items).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for List {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let (name, items) = <(String, Vec<Value>)>::deserialize(deserializer)?;
+        Ok(List { name, items })
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Costume {
+    pub name: String,
+    pub asset_id: String,
+    pub md5ext: String,
+    pub data_format: String,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Sound {
+    pub name: String,
+    pub asset_id: String,
+    pub md5ext: String,
+    pub data_format: String,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// Blocks are either full objects, or top-level variable / list reporters
+/// compressed into primitive arrays like `[12, name, id, x, y]`.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum SerializedBlock {
+    Block(Box<RawBlock>),
+    Primitive(Vec<Value>),
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RawBlock {
+    pub opcode: String,
+    pub next: Option<String>,
+    pub parent: Option<String>,
+    pub inputs: IndexMap<String, Vec<Value>>,
+    pub fields: IndexMap<String, Vec<Value>>,
+    pub shadow: bool,
+    pub top_level: bool,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_optional_number"
+    )]
+    pub x: Option<f64>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_optional_number"
+    )]
+    pub y: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mutation: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+    /// whatever newer versions of scratch or other editors add to blocks
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+// input kinds
+const INPUT_SAME_BLOCK_SHADOW: u64 = 1;
+const INPUT_BLOCK_NO_SHADOW: u64 = 2;
+const INPUT_DIFF_BLOCK_SHADOW: u64 = 3;
+
+// primitive kinds, in the order scratch-vm assigns them
+const PRIMITIVES: [(u64, &str, &str); 10] = [
+    (4, "math_number", "NUM"),
+    (5, "math_positive_number", "NUM"),
+    (6, "math_whole_number", "NUM"),
+    (7, "math_integer", "NUM"),
+    (8, "math_angle", "NUM"),
+    (9, "colour_picker", "COLOUR"),
+    (10, "text", "TEXT"),
+    (11, "event_broadcast_menu", "BROADCAST_OPTION"),
+    (12, "data_variable", "VARIABLE"),
+    (13, "data_listcontents", "LIST"),
+];
+// primitives of this kind or above aren't shadows
+const FIRST_NON_SHADOW_PRIMITIVE: u64 = 12;
+
+fn full_volume() -> f64 {
+    100.0
+}
+
+/// Whole numbers are written without a fraction, like scratch writes them
+fn number(value: f64) -> Value {
+    if value.fract() == 0.0 && value.abs() < i64::MAX as f64 {
+        Value::from(value as i64)
+    } else {
+        Value::from(value)
+    }
+}
+
+fn serialize_number<S: Serializer>(value: &f64, serializer: S) -> Result<S::Ok, S::Error> {
+    number(*value).serialize(serializer)
+}
+
+fn serialize_optional_number<S: Serializer>(
+    value: &Option<f64>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    value.map(number).serialize(serializer)
+}
+
+fn value_to_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
+
+fn pixels_to_cells(pixels: Option<f64>) -> i32 {
+    // rounded, so that positions either side of zero are treated alike
+    (pixels.unwrap_or(0.0) / PIXELS_PER_CELL).round() as i32
+}
+
+fn cells_to_pixels(cells: i32) -> f64 {
+    cells as f64 * PIXELS_PER_CELL
+}
+
+/// Keeps the position a block was loaded at unless it has moved to another cell
+fn encode_position(cells: i32, previous: Option<f64>) -> f64 {
+    previous
+        .filter(|&pixels| pixels_to_cells(Some(pixels)) == cells)
+        .unwrap_or_else(|| cells_to_pixels(cells))
+}
+
+/// Keeps the order a block's inputs or fields were loaded in, with new ones sorted at the end
+fn sort_like_previous(names: &mut [&String], previous: Option<&IndexMap<String, Vec<Value>>>) {
+    names.sort_by_key(|name| {
+        let position = previous.and_then(|previous| previous.get_index_of(*name));
+        (position.unwrap_or(usize::MAX), *name)
+    });
+}
+
+/// Expands a primitive array into a block
+fn decode_primitive(primitive: &[Value]) -> Option<Block> {
+    let kind = primitive.first()?.as_u64()?;
+    let &(_, opcode, field_name) = PRIMITIVES.iter().find(|(k, _, _)| *k == kind)?;
+    let value = value_to_string(primitive.get(1)?);
+    let id = primitive.get(2).and_then(Value::as_str).map(str::to_string);
+    let block = Block {
+        opcode: opcode.to_string(),
+        fields: HashMap::from([(field_name.to_string(), Field { value, id })]),
+        is_shadow: kind < FIRST_NON_SHADOW_PRIMITIVE,
+        ..Default::default()
+    };
+    Some(block)
+}
+
+/// Compresses a block into a primitive array, if it is simple enough to be one
+fn encode_primitive(block: &Block) -> Option<Vec<Value>> {
+    let &(kind, _, field_name) = PRIMITIVES.iter().find(|(_, op, _)| *op == block.opcode)?;
+    if block.next_id.is_some() {
+        return None;
+    }
+    let field = block.fields.get(field_name)?;
+    let mut primitive = vec![kind.into(), field.value.clone().into()];
+    if kind >= 11 {
+        primitive.push(field.id.clone()?.into());
+    }
+    Some(primitive)
+}
+
+/// Custom block mutations store their argument lists as JSON inside strings
+pub fn decode_mutation(mutation: &Value) -> Option<Mutation> {
+    let string_list = |key: &str| -> Vec<String> {
+        mutation
+            .get(key)
+            .and_then(Value::as_str)
+            .and_then(|s| serde_json::from_str(s).ok())
+            .unwrap_or_default()
+    };
+    Some(Mutation {
+        proccode: mutation.get("proccode")?.as_str()?.to_string(),
+        argument_ids: string_list("argumentids"),
+        argument_names: string_list("argumentnames"),
+        argument_defaults: string_list("argumentdefaults"),
+        warp: match mutation.get("warp") {
+            Some(Value::Bool(warp)) => *warp,
+            Some(Value::String(warp)) => warp == "true",
+            _ => false,
+        },
+    })
+}
+
+/// Calls leave out the argument names and defaults, prototypes include them
+pub fn encode_mutation(mutation: &Mutation, is_prototype: bool) -> Value {
+    let string_list = |list: &Vec<String>| Value::from(serde_json::to_string(list).unwrap());
+    let mut encoded = Map::from_iter([
+        ("tagName".to_string(), "mutation".into()),
+        ("children".to_string(), Value::Array(vec![])),
+        ("proccode".to_string(), mutation.proccode.clone().into()),
+        (
+            "argumentids".to_string(),
+            string_list(&mutation.argument_ids),
+        ),
+    ]);
+    if is_prototype {
+        encoded.insert(
+            "argumentnames".to_string(),
+            string_list(&mutation.argument_names),
+        );
+        encoded.insert(
+            "argumentdefaults".to_string(),
+            string_list(&mutation.argument_defaults),
+        );
+    }
+    encoded.insert("warp".to_string(), mutation.warp.to_string().into());
+    Value::Object(encoded)
+}
+
+fn blank_costume(name: &str, rotation_center: (i32, i32)) -> Costume {
+    Costume {
+        name: name.to_string(),
+        asset_id: BLANK_SVG_ASSET_ID.to_string(),
+        md5ext: format!("{BLANK_SVG_ASSET_ID}.svg"),
+        data_format: "svg".to_string(),
+        extra: Map::from_iter([
+            ("rotationCenterX".to_string(), rotation_center.0.into()),
+            ("rotationCenterY".to_string(), rotation_center.1.into()),
+        ]),
+    }
+}
+
+impl Project {
+    /// A stage and a single sprite, both with blank costumes and no scripts
+    pub fn empty() -> Project {
+        let stage = Target {
+            is_stage: true,
+            name: "Stage".to_string(),
+            costumes: vec![blank_costume("backdrop1", (240, 180))],
+            volume: 100.0,
+            layer_order: 0,
+            extra: Map::from_iter([
+                ("tempo".to_string(), 60.into()),
+                ("videoTransparency".to_string(), 50.into()),
+                ("videoState".to_string(), "on".into()),
+                ("textToSpeechLanguage".to_string(), Value::Null),
+            ]),
+            ..Default::default()
+        };
+        let sprite = Target {
+            is_stage: false,
+            name: "Sprite1".to_string(),
+            costumes: vec![blank_costume("costume1", (0, 0))],
+            volume: 100.0,
+            layer_order: 1,
+            visible: Some(true),
+            x: Some(0.0),
+            y: Some(0.0),
+            size: Some(100.0),
+            direction: Some(90.0),
+            draggable: Some(false),
+            rotation_style: Some("all around".to_string()),
+            ..Default::default()
+        };
+        Project {
+            targets: vec![stage, sprite],
+            monitors: vec![],
+            extensions: vec![],
+            meta: serde_json::json!({
+                "semver": "3.0.0",
+                "vm": "0.2.0",
+                "agent": "viiru",
+            }),
+            assets: vec![(format!("{BLANK_SVG_ASSET_ID}.svg"), BLANK_SVG.to_vec())],
+        }
+    }
+
+    pub fn load(path: &str) -> ViiruResult<Project> {
+        let mut archive = ZipArchive::new(File::open(path)?)?;
+        let mut project_json = None;
+        let mut assets = vec![];
+        for i in 0..archive.len() {
+            let mut file = archive.by_index(i)?;
+            let mut contents = vec![];
+            file.read_to_end(&mut contents)?;
+            if file.name() == "project.json" {
+                project_json = Some(contents);
+            } else {
+                assets.push((file.name().to_string(), contents));
+            }
+        }
+        let Some(project_json) = project_json else {
+            return Err(zip::result::ZipError::FileNotFound.into());
+        };
+        let mut project: Project = serde_json::from_slice(&project_json)?;
+        project.assets = assets;
+        Ok(project)
+    }
+
+    pub fn save(&self, path: &str) -> ViiruResult<()> {
+        let mut archive = ZipWriter::new(File::create(path)?);
+        let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+        archive.start_file("project.json", options)?;
+        archive.write_all(&serde_json::to_vec(self)?)?;
+        for (name, contents) in &self.assets {
+            archive.start_file(name.as_str(), options)?;
+            archive.write_all(contents)?;
+        }
+        archive.finish()?;
+        Ok(())
+    }
+
+    /// scratch-vm starts out editing the first sprite, falling back to the stage
+    pub fn default_editing_target(&self) -> usize {
+        self.targets.iter().position(|t| !t.is_stage).unwrap_or(0)
+    }
+}
+
+impl Target {
+    /// Expands the serialized block map into editor blocks, inlining compressed primitives
+    pub fn decode_blocks(&self) -> HashMap<String, Block> {
+        let mut blocks = HashMap::new();
+        for (id, serialized) in &self.blocks {
+            match serialized {
+                SerializedBlock::Block(raw) => {
+                    let block = self.decode_block(id, raw, &mut blocks);
+                    blocks.insert(id.clone(), block);
+                }
+                SerializedBlock::Primitive(primitive) => {
+                    // top-level variable reporters carry their own position
+                    let Some(mut block) = decode_primitive(primitive) else {
+                        continue;
+                    };
+                    block.x = pixels_to_cells(primitive.get(3).and_then(Value::as_f64));
+                    block.y = pixels_to_cells(primitive.get(4).and_then(Value::as_f64));
+                    blocks.insert(id.clone(), block);
+                }
+            }
+        }
+        blocks
+    }
+
+    fn decode_block(&self, id: &str, raw: &RawBlock, blocks: &mut HashMap<String, Block>) -> Block {
+        let mut inputs = HashMap::new();
+        for (input_name, input) in &raw.inputs {
+            let mut decoded = Input {
+                shadow_id: None,
+                block_id: None,
+            };
+            for (slot, value) in input.iter().enumerate().skip(1) {
+                let (child_id, is_shadow) = match value {
+                    Value::String(child_id) => {
+                        let is_shadow = matches!(
+                            self.blocks.get(child_id),
+                            Some(SerializedBlock::Block(raw)) if raw.shadow
+                        );
+                        (child_id.clone(), is_shadow)
+                    }
+                    Value::Array(primitive) => {
+                        let Some(mut child) = decode_primitive(primitive) else {
+                            continue;
+                        };
+                        let child_id = format!("{id}-{input_name}-{slot}");
+                        let is_shadow = child.is_shadow;
+                        child.parent_id = Some(id.to_string());
+                        blocks.insert(child_id.clone(), child);
+                        (child_id, is_shadow)
+                    }
+                    _ => continue,
+                };
+                if is_shadow {
+                    decoded.shadow_id = Some(child_id);
+                } else {
+                    decoded.block_id = Some(child_id);
+                }
+            }
+            inputs.insert(input_name.clone(), decoded);
+        }
+        let fields = raw
+            .fields
+            .iter()
+            .map(|(field_name, field)| {
+                let value = field.first().map(value_to_string).unwrap_or_default();
+                let id = field.get(1).and_then(Value::as_str).map(str::to_string);
+                (field_name.clone(), Field { value, id })
+            })
+            .collect();
+        Block {
+            x: pixels_to_cells(raw.x),
+            y: pixels_to_cells(raw.y),
+            offset_x: 0,
+            offset_y: 0,
+            opcode: raw.opcode.clone(),
+            parent_id: raw.parent.clone(),
+            next_id: raw.next.clone(),
+            is_shadow: raw.shadow,
+            inputs,
+            fields,
+            mutation: raw.mutation.as_ref().and_then(decode_mutation),
+        }
+    }
+
+    /// Replaces the serialized block map with every block reachable from `top_level`,
+    /// compressing primitives the same way scratch-vm does.
+    pub fn encode_blocks(&mut self, blocks: &HashMap<String, Block>, top_level: &[String]) {
+        let mut encoded = IndexMap::new();
+        for id in top_level {
+            let block = &blocks[id];
+            if let Some(mut primitive) = encode_primitive(block)
+                .filter(|p| p[0].as_u64() >= Some(FIRST_NON_SHADOW_PRIMITIVE))
+            {
+                let previous = match self.blocks.get(id) {
+                    Some(SerializedBlock::Primitive(previous)) => previous.as_slice(),
+                    _ => &[],
+                };
+                let old_x = previous.get(3).and_then(Value::as_f64);
+                let old_y = previous.get(4).and_then(Value::as_f64);
+                primitive.push(number(encode_position(block.x, old_x)));
+                primitive.push(number(encode_position(block.y, old_y)));
+                encoded.insert(id.clone(), SerializedBlock::Primitive(primitive));
+            } else {
+                self.encode_block(id, blocks, &mut encoded);
+            }
+        }
+        self.blocks = encoded;
+    }
+
+    fn encode_block(
+        &self,
+        id: &str,
+        blocks: &HashMap<String, Block>,
+        encoded: &mut IndexMap<String, SerializedBlock>,
+    ) {
+        let block = &blocks[id];
+        let previous = match self.blocks.get(id) {
+            Some(SerializedBlock::Block(raw)) => Some(raw),
+            _ => None,
+        };
+        let is_top_level = block.parent_id.is_none();
+
+        let mut child_ids = vec![];
+        let mut encode_child = |child_id: &str| match encode_primitive(&blocks[child_id]) {
+            Some(primitive) => Value::Array(primitive),
+            None => {
+                child_ids.push(child_id.to_string());
+                Value::String(child_id.to_string())
+            }
+        };
+        let mut input_names: Vec<_> = block.inputs.keys().collect();
+        sort_like_previous(&mut input_names, previous.map(|raw| &raw.inputs));
+        let mut inputs = IndexMap::new();
+        for input_name in input_names {
+            let input = &block.inputs[input_name];
+            let serialized = match (&input.shadow_id, &input.block_id) {
+                (Some(shadow_id), None) => {
+                    vec![INPUT_SAME_BLOCK_SHADOW.into(), encode_child(shadow_id)]
+                }
+                (None, Some(block_id)) => {
+                    vec![INPUT_BLOCK_NO_SHADOW.into(), encode_child(block_id)]
+                }
+                (Some(shadow_id), Some(block_id)) => vec![
+                    INPUT_DIFF_BLOCK_SHADOW.into(),
+                    encode_child(block_id),
+                    encode_child(shadow_id),
+                ],
+                (None, None) => continue,
+            };
+            inputs.insert(input_name.clone(), serialized);
+        }
+
+        let mut field_names: Vec<_> = block.fields.keys().collect();
+        sort_like_previous(&mut field_names, previous.map(|raw| &raw.fields));
+        let fields = field_names
+            .into_iter()
+            .map(|field_name| {
+                let field = &block.fields[field_name];
+                let mut serialized = vec![Value::from(field.value.clone())];
+                if let Some(id) = &field.id {
+                    serialized.push(id.clone().into());
+                }
+                // keep an untouched field as it was, e.g. with an explicit null id
+                let unchanged = previous
+                    .and_then(|raw| raw.fields.get(field_name))
+                    .filter(|old| old.len() == 2 && old[1].is_null() && old[..1] == serialized);
+                if let Some(old) = unchanged {
+                    serialized = old.clone();
+                }
+                (field_name.clone(), serialized)
+            })
+            .collect();
+
+        encoded.insert(
+            id.to_string(),
+            SerializedBlock::Block(Box::new(RawBlock {
+                opcode: block.opcode.clone(),
+                next: block.next_id.clone(),
+                parent: block.parent_id.clone(),
+                inputs,
+                fields,
+                shadow: block.is_shadow,
+                top_level: is_top_level,
+                x: is_top_level.then(|| encode_position(block.x, previous.and_then(|raw| raw.x))),
+                y: is_top_level.then(|| encode_position(block.y, previous.and_then(|raw| raw.y))),
+                // other mutations (e.g. control_stop's) are passed through untouched
+                mutation: block
+                    .mutation
+                    .as_ref()
+                    .map(|m| encode_mutation(m, block.opcode == "procedures_prototype"))
+                    .or_else(|| previous.and_then(|raw| raw.mutation.clone())),
+                comment: previous.and_then(|raw| raw.comment.clone()),
+                extra: previous.map(|raw| raw.extra.clone()).unwrap_or_default(),
+            })),
+        );
+        for child_id in child_ids {
+            self.encode_block(&child_id, blocks, encoded);
+        }
+        if let Some(next_id) = &block.next_id {
+            self.encode_block(next_id, blocks, encoded);
+        }
+    }
+
+    /// Renames the fields referring to a variable, list or broadcast without decoding anything
+    pub fn rename_references(&mut self, id: &str, name: &str) {
+        // only references keep an id after the value, in both fields and primitives
+        let rename = |values: &mut Vec<Value>, id_index: usize| {
+            if values.get(id_index).and_then(Value::as_str) == Some(id) {
+                values[id_index - 1] = name.into();
+            }
+        };
+        for serialized in self.blocks.values_mut() {
+            match serialized {
+                SerializedBlock::Primitive(primitive) => rename(primitive, 2),
+                SerializedBlock::Block(raw) => {
+                    for field in raw.fields.values_mut() {
+                        rename(field, 1);
+                    }
+                    // compressed reporters and menus sit right inside the inputs
+                    for value in raw.inputs.values_mut().flat_map(|input| input.iter_mut()) {
+                        if let Value::Array(primitive) = value {
+                            rename(primitive, 2);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// id -> name
+    pub fn variable_names(&self) -> HashMap<String, String> {
+        self.variables
+            .iter()
+            .map(|(id, var)| (id.clone(), var.name.clone()))
+            .collect()
+    }
+
+    /// id -> name
+    pub fn list_names(&self) -> HashMap<String, String> {
+        self.lists
+            .iter()
+            .map(|(id, list)| (id.clone(), list.name.clone()))
+            .collect()
+    }
+
+    /// id -> name
+    pub fn broadcast_names(&self) -> HashMap<String, String> {
+        self.broadcasts.clone().into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a stage and a sprite using every kind of input, with things viiru never looks at
+    const ROUND_TRIP: &str = include_str!("../tests/round_trip.json");
+
+    #[test]
+    fn projects_are_written_back_as_they_were_read() {
+        let mut project: Project = serde_json::from_str(ROUND_TRIP).unwrap();
+        for target in &mut project.targets {
+            let blocks = target.decode_blocks();
+            let top_level: Vec<_> = blocks
+                .iter()
+                .filter(|(_, block)| block.parent_id.is_none())
+                .map(|(id, _)| id.clone())
+                .collect();
+            target.encode_blocks(&blocks, &top_level);
+        }
+        let written = serde_json::to_value(&project).unwrap();
+        let original: Value = serde_json::from_str(ROUND_TRIP).unwrap();
+        assert_eq!(written, original);
+    }
+
+    #[test]
+    fn positions_round_to_the_nearest_cell() {
+        assert_eq!(pixels_to_cells(Some(7.0)), 0);
+        assert_eq!(pixels_to_cells(Some(-7.0)), 0);
+        assert_eq!(pixels_to_cells(Some(40.0)), 1);
+        assert_eq!(pixels_to_cells(Some(-40.0)), -1);
+        assert_eq!(pixels_to_cells(None), 0);
+    }
+}
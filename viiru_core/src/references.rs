@@ -0,0 +1,246 @@
+//! Finds every block in the project that uses the same variable, list or broadcast.
+//! Variables and lists are matched by id, broadcasts by name like scratch does.
+
+use std::collections::HashMap;
+
+use crate::{
+    block::Block,
+    lint::{broadcast_sent, field, VARIABLE_READS, VARIABLE_WRITES},
+};
+
+#[derive(Clone)]
+pub struct Reference {
+    /// index into the project's targets
+    pub target: usize,
+    pub block_id: String,
+    pub opcode: String,
+    /// e.g. "reads" or "sends"
+    pub role: &'static str,
+}
+
+#[derive(Clone, PartialEq, Eq)]
+pub enum Referent {
+    /// variable or list id
+    Data(String),
+    /// lowercase broadcast name
+    Broadcast(String),
+}
+
+/// What the block refers to, either through its own fields or through a menu it holds,
+/// along with the name to show for it
+pub fn referent_of(blocks: &HashMap<String, Block>, block_id: &str) -> Option<(Referent, String)> {
+    let block = blocks.get(block_id)?;
+    let menus = block
+        .inputs
+        .values()
+        .filter_map(|input| blocks.get(input.shadow_id.as_ref()?));
+    for candidate in std::iter::once(block).chain(menus) {
+        for (field_name, field) in &candidate.fields {
+            let Some(id) = &field.id else {
+                continue;
+            };
+            match field_name.as_str() {
+                "VARIABLE" | "LIST" => {
+                    return Some((Referent::Data(id.clone()), field.value.clone()));
+                }
+                "BROADCAST_OPTION" => {
+                    let referent = Referent::Broadcast(field.value.to_lowercase());
+                    return Some((referent, field.value.clone()));
+                }
+                _ => (),
+            }
+        }
+    }
+    None
+}
+
+/// how the block uses the referent, if at all
+fn role_of(
+    blocks: &HashMap<String, Block>,
+    block: &Block,
+    referent: &Referent,
+) -> Option<&'static str> {
+    let opcode = block.opcode.as_str();
+    match referent {
+        Referent::Data(id) => {
+            let field = block.fields.get("VARIABLE").or(block.fields.get("LIST"))?;
+            if field.id.as_ref() != Some(id) {
+                None
+            } else if VARIABLE_READS.contains(&opcode) {
+                Some("reads")
+            } else if VARIABLE_WRITES.contains(&opcode) {
+                Some("writes")
+            } else {
+                Some("uses")
+            }
+        }
+        Referent::Broadcast(name) => match opcode {
+            "event_whenbroadcastreceived" => {
+                (field(block, "BROADCAST_OPTION").to_lowercase() == *name).then_some("receives")
+            }
+            "event_broadcast" | "event_broadcastandwait" => broadcast_sent(blocks, block)
+                .filter(|sent| sent.to_lowercase() == *name)
+                .map(|_| "sends"),
+            _ => None,
+        },
+    }
+}
+
+/// Every use within one target's scripts, in reading order: scripts top to bottom, and
+/// each block before whatever is inside it, before the blocks below it
+pub fn find_in_target(
+    target: usize,
+    blocks: &HashMap<String, Block>,
+    top_level: &[String],
+    referent: &Referent,
+) -> Vec<Reference> {
+    let mut tops: Vec<_> = top_level
+        .iter()
+        .filter(|id| blocks.contains_key(*id))
+        .collect();
+    tops.sort_by_key(|id| (blocks[*id].y, blocks[*id].x));
+    let mut references = vec![];
+    for top_id in tops {
+        let mut pending = vec![top_id.clone()];
+        while let Some(id) = pending.pop() {
+            let Some(block) = blocks.get(&id) else {
+                continue;
+            };
+            if let Some(role) = role_of(blocks, block, referent) {
+                references.push(Reference {
+                    target,
+                    block_id: id.clone(),
+                    opcode: block.opcode.clone(),
+                    role,
+                });
+            }
+            // pushed in reverse, since the last one is visited first
+            pending.extend(block.next_id.iter().cloned());
+            let mut input_names: Vec<_> = block.inputs.keys().collect();
+            input_names.sort();
+            for input_name in input_names.into_iter().rev() {
+                let input = &block.inputs[input_name];
+                pending.extend(input.shadow_id.iter().cloned());
+                if input.block_id != input.shadow_id {
+                    pending.extend(input.block_id.iter().cloned());
+                }
+            }
+        }
+    }
+    references
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        backend::{
+            tests::{new_runtime, SPRITE, STAGE},
+            VariableType,
+        },
+        runtime::Runtime,
+        scratchblocks::{create_scripts, parse_scripts},
+    };
+
+    fn add_scripts(runtime: &mut Runtime, text: &str) -> Vec<String> {
+        let scripts = parse_scripts(runtime, text).unwrap();
+        create_scripts(runtime, &scripts, 0, 0).unwrap()
+    }
+
+    fn next(runtime: &Runtime, id: &str) -> String {
+        runtime.blocks[id].next_id.clone().unwrap()
+    }
+
+    /// a variable and a broadcast used by both the sprite and the stage
+    fn new_project() -> Runtime<'static> {
+        let mut runtime = new_runtime();
+        runtime
+            .create_data(VariableType::Scalar, "score", false, false)
+            .unwrap();
+        runtime
+            .create_data(VariableType::Broadcast, "go", false, false)
+            .unwrap();
+        runtime.switch_target(STAGE).unwrap();
+        add_scripts(
+            &mut runtime,
+            "when I receive [go v]\nchange [score v] by (1)",
+        );
+        runtime.switch_target(SPRITE).unwrap();
+        runtime
+    }
+
+    #[test]
+    fn variables_are_found_in_every_target() {
+        let mut runtime = new_project();
+        let top_ids = add_scripts(
+            &mut runtime,
+            "when @greenFlag clicked\nset [score v] to (0)\nsay (score)",
+        );
+        let set_id = next(&runtime, &top_ids[0]);
+        let say_id = next(&runtime, &set_id);
+        let reporter_id = runtime.blocks[&say_id].inputs["MESSAGE"]
+            .block_id
+            .clone()
+            .unwrap();
+
+        let list = runtime.find_references(&set_id, false).unwrap().unwrap();
+        assert_eq!(list.title, "references to `score`");
+        let found: Vec<_> = list
+            .references
+            .iter()
+            .map(|r| (r.target, r.opcode.as_str(), r.role))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (SPRITE, "data_setvariableto", "writes"),
+                (SPRITE, "data_variable", "reads"),
+                (STAGE, "data_changevariableby", "writes"),
+            ]
+        );
+        assert_eq!(list.references[1].block_id, reporter_id);
+        // the reporter finds the same blocks
+        let from_reporter = runtime
+            .find_references(&reporter_id, false)
+            .unwrap()
+            .unwrap();
+        assert_eq!(from_reporter.references.len(), 3);
+        // and variables have nothing to jump to
+        assert!(runtime.find_references(&set_id, true).unwrap().is_none());
+    }
+
+    #[test]
+    fn broadcasts_go_to_their_receivers() {
+        let mut runtime = new_project();
+        let top_ids = add_scripts(
+            &mut runtime,
+            "when @greenFlag clicked\nbroadcast [go v]\n\nwhen I receive [go v]\nmove (10) steps",
+        );
+        let broadcast_id = next(&runtime, &top_ids[0]);
+
+        let list = runtime
+            .find_references(&broadcast_id, false)
+            .unwrap()
+            .unwrap();
+        let found: Vec<_> = list.references.iter().map(|r| (r.target, r.role)).collect();
+        assert_eq!(
+            found,
+            [(SPRITE, "sends"), (SPRITE, "receives"), (STAGE, "receives")]
+        );
+
+        let list = runtime
+            .find_references(&broadcast_id, true)
+            .unwrap()
+            .unwrap();
+        assert_eq!(list.title, "receivers of `go`");
+        let found: Vec<_> = list.references.iter().map(|r| r.target).collect();
+        assert_eq!(found, [SPRITE, STAGE]);
+    }
+
+    #[test]
+    fn blocks_without_a_referent_have_no_references() {
+        let mut runtime = new_project();
+        let top_ids = add_scripts(&mut runtime, "when @greenFlag clicked\nmove (10) steps");
+        let move_id = next(&runtime, &top_ids[0]);
+        assert!(runtime.find_references(&move_id, false).unwrap().is_none());
+    }
+}
@@ -1,6 +1,7 @@
-use std::io;
+use std::{fmt, io};
 
 use neon::{prelude::*, result::Throw};
+use zip::result::ZipError;
 
 pub type ViiruResult<T = ()> = Result<T, ViiruError>;
 
@@ -8,6 +9,41 @@ pub type ViiruResult<T = ()> = Result<T, ViiruError>;
 pub enum ViiruError {
     JsThrow(Throw),
     IoError(io::Error),
+    JsonError(serde_json::Error),
+    ZipError(ZipError),
+    /// a block definition file that doesn't make sense, along with where
+    DefinitionError(String),
+    /// a config file that can't be read, along with where
+    ConfigError(String),
+    /// the id of a block an edit expected to find
+    MissingBlock(String),
+    /// pasted text that parsed, but doesn't fit the blocks it's turned into
+    ImportError(String),
+}
+
+impl fmt::Display for ViiruError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ViiruError::JsThrow(throw) => write!(f, "{throw}"),
+            ViiruError::IoError(err) => write!(f, "{err}"),
+            ViiruError::JsonError(err) => write!(f, "{err}"),
+            ViiruError::ZipError(err) => write!(f, "{err}"),
+            ViiruError::DefinitionError(message) => write!(f, "{message}"),
+            ViiruError::ConfigError(message) => write!(f, "{message}"),
+            ViiruError::MissingBlock(id) => write!(f, "no block with the id {id}"),
+            ViiruError::ImportError(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+impl ViiruError {
+    /// whether it came from reading or writing a project file, rather than from the editor
+    pub fn is_file_error(&self) -> bool {
+        matches!(
+            self,
+            ViiruError::IoError(_) | ViiruError::JsonError(_) | ViiruError::ZipError(_)
+        )
+    }
 }
 
 impl From<Throw> for ViiruError {
@@ -22,6 +58,18 @@ impl From<io::Error> for ViiruError {
     }
 }
 
+impl From<serde_json::Error> for ViiruError {
+    fn from(err: serde_json::Error) -> Self {
+        ViiruError::JsonError(err)
+    }
+}
+
+impl From<ZipError> for ViiruError {
+    fn from(err: ZipError) -> Self {
+        ViiruError::ZipError(err)
+    }
+}
+
 pub fn undefined_or_throw<'a, T>(
     cx: &mut FunctionContext<'a>,
     result: ViiruResult<T>,
@@ -30,5 +78,11 @@ pub fn undefined_or_throw<'a, T>(
         Ok(_t) => Ok(cx.undefined()),
         Err(ViiruError::JsThrow(throw)) => Err(throw),
         Err(ViiruError::IoError(err)) => cx.throw_error(err.to_string()),
+        Err(ViiruError::JsonError(err)) => cx.throw_error(err.to_string()),
+        Err(ViiruError::ZipError(err)) => cx.throw_error(err.to_string()),
+        Err(ViiruError::DefinitionError(message)) => cx.throw_error(message),
+        Err(ViiruError::ConfigError(message)) => cx.throw_error(message),
+        Err(ViiruError::MissingBlock(id)) => cx.throw_error(format!("no block with the id {id}")),
+        Err(ViiruError::ImportError(message)) => cx.throw_error(message),
     }
 }
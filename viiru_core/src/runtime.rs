@@ -1,14 +1,29 @@
-use std::collections::HashMap;
-
-use neon::prelude::*;
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 use crate::{
-    block::{Block, Field, Input},
-    bridge::{self, map_each_value, string_of, to_block, VariableType},
-    opcodes::{BLOCKS, NUMBERS_ISH, TOOLBOX},
-    result::{undefined_or_throw, ViiruResult},
-    spec::Fragment,
+    actions::Action,
+    backend::{ProjectBackend, TargetInfo, VariableType},
+    block::{parse_signature, Block, Field, Mutation, Signature},
+    commands::Completion,
+    config::Config,
+    extensions::{extension, EXTENSIONS},
+    history::{History, Op},
+    interpreter::Interpreter,
+    lint::{lint, Diagnostic, Usage},
+    opcodes::{
+        dropdown_options, fallback_spec, in_palette, variable_options, variable_type_of, BLOCKS,
+        NUMBERS_ISH, TOOLBOX,
+    },
+    references::{find_in_target, referent_of, Reference, Referent},
+    result::{ViiruError, ViiruResult},
+    scratchblocks::block_text,
+    spec::{DropdownOption, Fragment, Shape, Spec},
     ui::{Accumulators, DropPoint},
+    util::fuzzy_match,
 };
 
 #[derive(Clone, Copy)]
@@ -30,14 +45,115 @@ impl Viewport {
     }
 }
 
-pub struct Runtime<'js, 'a> {
+/// Where we left off in a target, restored when switching back to it
+#[derive(Clone, Copy)]
+pub struct TargetView {
+    pub scroll_x: i32,
+    pub scroll_y: i32,
+    pub cursor_x: i32,
+    pub cursor_y: i32,
+}
+
+/// The popup listing a dropdown's options
+pub struct DropdownMenu {
+    pub block_id: String,
+    pub options: Vec<DropdownOption>,
+    /// index into `visible_options`
+    pub cursor: usize,
+    pub filter: String,
+    /// letters go into the filter after a `/`, until Esc or backspacing past the start
+    pub typing: bool,
+}
+
+impl DropdownMenu {
+    /// options containing the filter text, ignoring case
+    pub fn visible_options(&self) -> Vec<&DropdownOption> {
+        let filter = self.filter.to_lowercase();
+        self.options
+            .iter()
+            .filter(|option| option.display.to_lowercase().contains(&filter))
+            .collect()
+    }
+
+    pub fn move_cursor(&mut self, delta: isize) {
+        let count = self.visible_options().len();
+        self.cursor = self
+            .cursor
+            .saturating_add_signed(delta)
+            .min(count.saturating_sub(1));
+    }
+}
+
+/// The `/` filter over the toolbox, which lists the matching blocks by their text
+pub struct ToolboxSearch {
+    pub query: String,
+    /// letters go into the query until Enter, after which the toolbox keys work again
+    pub typing: bool,
+    /// index into `toolbox_matches`
+    pub cursor: usize,
+}
+
+/// A toolbox block matching the search
+pub struct ToolboxMatch {
+    /// index into the toolbox
+    pub index: usize,
+    pub text: String,
+    /// chars of the text that matched the query
+    pub positions: Vec<usize>,
+}
+
+/// Every use of a variable, list or broadcast, listed where the toolbox usually goes
+pub struct ReferenceList {
+    pub title: String,
+    /// what's being referred to
+    pub name: String,
+    pub references: Vec<Reference>,
+    pub cursor: usize,
+    /// set when warning that the variable, list or broadcast with this id is still in use
+    pub deleting: Option<String>,
+}
+
+/// A variable, list or broadcast the editing target can use
+#[derive(Clone)]
+pub struct DataEntry {
+    pub id: String,
+    pub name: String,
+    pub variable_type: VariableType,
+    /// owned by a sprite rather than the stage
+    pub is_local: bool,
+    pub is_cloud: bool,
+}
+
+/// The variables, lists and broadcasts, listed where the toolbox usually goes
+pub struct DataList {
+    pub entries: Vec<DataEntry>,
+    pub cursor: usize,
+}
+
+/// What the command line is asking a name for
+pub enum DataPrompt {
+    Create {
+        variable_type: VariableType,
+        is_local: bool,
+        is_cloud: bool,
+    },
+    Rename(DataEntry),
+}
+
+pub struct Runtime<'a> {
     // internals
     next_usable_id: usize,
-    cx: &'a mut FunctionContext<'js>,
-    api: Handle<'js, JsObject>,
+    backend: Box<dyn ProjectBackend + 'a>,
     do_sync: bool,
+    /// set while undoing or redoing, so that the replayed ops aren't recorded again
+    replaying: bool,
+    history: History,
     pub state: State,
     is_dirty: bool,
+    /// refuse to write the project back out
+    pub readonly: bool,
+    /// where the project was last opened from or written to
+    pub project_path: Option<String>,
     // ui
     pub viewport: Viewport,
     pub window_cols: u16,
@@ -49,11 +165,46 @@ pub struct Runtime<'js, 'a> {
     pub toolbox_cursor: usize,
     pub toolbox_scroll: usize,
     pub toolbox_visible_max: usize,
-    pub last_command: char,
+    pub toolbox_search: Option<ToolboxSearch>,
+    pub target_cursor: usize,
+    target_views: HashMap<usize, TargetView>,
+    /// every target keeps its own undo history
+    histories: HashMap<usize, History>,
+    /// what the command line is being used for
+    pub last_action: Option<Action>,
     pub command_buffer: String,
+    /// the Tab completion being cycled through on the `:` line
+    pub completion: Option<Completion>,
+    /// `:` commands, oldest first, including those of earlier sessions
+    pub command_history: Vec<String>,
+    pub history_cursor: Option<usize>,
+    /// what was typed before stepping into the history
+    pub history_draft: String,
     pub status_message: String,
     pub editing_shadow: String,
+    pub dropdown_menu: Option<DropdownMenu>,
+    /// the custom block definition being edited through the command line
+    pub editing_definition: Option<String>,
+    /// the last script copied as text
+    pub clipboard: String,
+    /// the project running on the stage pane
+    pub interpreter: Option<Interpreter>,
+    /// block ids by editing target
+    pub breakpoints: HashSet<(usize, String)>,
+    /// what the other targets use, or None while linting is off
+    pub lint_usage: Option<Usage>,
+    pub diagnostics: Vec<Diagnostic>,
+    pub diagnostic_cursor: usize,
+    pub reference_list: Option<ReferenceList>,
+    pub data_list: Option<DataList>,
+    pub data_prompt: Option<DataPrompt>,
+    pub extension_cursor: usize,
+    pub help_scroll: usize,
+    /// shape and shadowness of each opcode without a spec, going by where it's been seen
+    fallback_shapes: HashMap<String, (Shape, bool)>,
     // constant data
+    /// key bindings, layout sizes and colours from the config file
+    pub config: Config,
     pub viewport_offset_x: i32,
     pub viewport_offset_y: i32,
     pub toolbox_width: i32,
@@ -64,12 +215,17 @@ pub struct Runtime<'js, 'a> {
     pub cursor_block: Option<String>,
     pub drop_points: HashMap<(i32, i32), DropPoint>,
     pub writable_points: HashMap<(i32, i32), String>,
+    pub dropdown_points: HashMap<(i32, i32), String>,
     // synchronized data
+    pub targets: Vec<TargetInfo>,
+    pub editing_target: usize,
     pub blocks: HashMap<String, Block>,
     pub top_level: Vec<String>,
     pub variables: HashMap<String, String>,
     pub lists: HashMap<String, String>,
     pub broadcasts: HashMap<String, String>,
+    /// ids of the enabled extensions, which get their own toolbox sections
+    pub extensions: Vec<String>,
 }
 
 #[derive(Clone, Copy, PartialEq, Eq)]
@@ -77,20 +233,31 @@ pub enum State {
     Move,
     Hold,
     Toolbox,
+    Targets,
+    Dropdown,
     Command,
     Inline,
+    Run,
+    Diagnostics,
+    References,
+    Data,
+    Extensions,
+    Help,
 }
 
-impl<'js, 'rt> Runtime<'js, 'rt> {
-    pub fn new(cx: &'rt mut FunctionContext<'js>, api: Handle<'js, JsObject>) -> Self {
+impl<'a> Runtime<'a> {
+    pub fn new(backend: Box<dyn ProjectBackend + 'a>) -> Self {
         Runtime {
             // internals
-            cx,
-            api,
+            backend,
             next_usable_id: 0,
             state: State::Move,
             do_sync: true,
+            replaying: false,
+            history: History::default(),
             is_dirty: false,
+            readonly: false,
+            project_path: None,
             // ui
             viewport: Viewport {
                 x_min: 0,
@@ -107,11 +274,34 @@ impl<'js, 'rt> Runtime<'js, 'rt> {
             toolbox_cursor: 0,
             toolbox_scroll: 0,
             toolbox_visible_max: 0,
-            last_command: ' ',
+            toolbox_search: None,
+            target_cursor: 0,
+            target_views: HashMap::new(),
+            histories: HashMap::new(),
+            last_action: None,
             command_buffer: String::new(),
+            completion: None,
+            command_history: vec![],
+            history_cursor: None,
+            history_draft: String::new(),
             status_message: String::new(),
             editing_shadow: String::new(),
+            dropdown_menu: None,
+            editing_definition: None,
+            clipboard: String::new(),
+            interpreter: None,
+            breakpoints: HashSet::new(),
+            lint_usage: None,
+            diagnostics: vec![],
+            diagnostic_cursor: 0,
+            reference_list: None,
+            data_list: None,
+            data_prompt: None,
+            extension_cursor: 0,
+            help_scroll: 0,
+            fallback_shapes: HashMap::new(),
             // constant data
+            config: Config::default(),
             viewport_offset_x: 0,
             viewport_offset_y: 0,
             toolbox_width: 0,
@@ -122,12 +312,16 @@ impl<'js, 'rt> Runtime<'js, 'rt> {
             cursor_block: None,
             drop_points: HashMap::new(),
             writable_points: HashMap::new(),
+            dropdown_points: HashMap::new(),
             // synchronized data
+            targets: vec![],
+            editing_target: 0,
             blocks: HashMap::new(),
             top_level: vec![],
             variables: HashMap::new(),
             lists: HashMap::new(),
             broadcasts: HashMap::new(),
+            extensions: vec![],
         }
     }
 
@@ -135,7 +329,7 @@ impl<'js, 'rt> Runtime<'js, 'rt> {
         self.is_dirty
     }
 
-    pub fn move_x(&mut self, dx: i32) -> NeonResult<()> {
+    pub fn move_x(&mut self, dx: i32) -> ViiruResult<()> {
         self.cursor_x += dx;
         if let Some(id) = self.cursor_block.clone() {
             self.slide_block_by(&id, dx, 0)?;
@@ -143,7 +337,7 @@ impl<'js, 'rt> Runtime<'js, 'rt> {
         Ok(())
     }
 
-    pub fn move_y(&mut self, dy: i32) -> NeonResult<()> {
+    pub fn move_y(&mut self, dy: i32) -> ViiruResult<()> {
         self.cursor_y += dy;
         if let Some(id) = self.cursor_block.clone() {
             self.slide_block_by(&id, 0, dy)?;
@@ -151,7 +345,7 @@ impl<'js, 'rt> Runtime<'js, 'rt> {
         Ok(())
     }
 
-    pub fn put_to_cursor(&mut self, id: &str) -> NeonResult<()> {
+    pub fn put_to_cursor(&mut self, id: &str) -> ViiruResult<()> {
         self.detach_block(id)?;
         self.cursor_block = Some(id.to_string());
         Ok(())
@@ -188,6 +382,7 @@ impl<'js, 'rt> Runtime<'js, 'rt> {
         self.block_positions = accumulators.block_positions;
         self.drop_points = accumulators.drop_points;
         self.writable_points = accumulators.writable_points;
+        self.dropdown_points = accumulators.dropdown_points;
         for (id, (dx, dy)) in accumulators.block_offsets {
             self.blocks.get_mut(&id).unwrap().offset_x = dx;
             self.blocks.get_mut(&id).unwrap().offset_y = dy;
@@ -206,7 +401,7 @@ impl<'js, 'rt> Runtime<'js, 'rt> {
 
     pub fn set_viewport(&mut self, columns: u16, rows: u16) {
         self.window_cols = columns;
-        self.window_rows = columns;
+        self.window_rows = rows;
         self.viewport.x_min = self.viewport_offset_x;
         self.viewport.x_max = columns as i32 - self.toolbox_width;
         self.viewport.y_min = self.viewport_offset_y;
@@ -219,106 +414,740 @@ impl<'js, 'rt> Runtime<'js, 'rt> {
     }
 
     /// be sure to clear the screen afterwards, as this creates some spam from the JS side
-    pub fn load_project(&mut self, path: &str) -> NeonResult<bool> {
+    pub fn load_project(&mut self, path: &str) -> ViiruResult<bool> {
         // todo: to ensure a proper reset, move self and return a new Runtime
-        let success = bridge::load_project(self.cx, self.api, path)?.value(self.cx);
-        if !success {
+        if !self.backend.load_project(path)? {
             return Ok(false);
         }
+        self.sync_from_backend()?;
+        Ok(true)
+    }
+
+    pub fn new_project(&mut self) -> ViiruResult<()> {
+        self.backend.new_project()?;
+        self.sync_from_backend()
+    }
+
+    /// (re)initializes all state after the backend switches projects
+    fn sync_from_backend(&mut self) -> ViiruResult<()> {
+        self.is_dirty = false;
+        self.targets = self.backend.get_targets()?;
+        self.editing_target = self.backend.get_editing_target()?;
+        self.target_cursor = self.editing_target;
+        self.target_views.clear();
+        self.histories.clear();
+        self.history = History::default();
+        self.lint_usage = None;
+        self.diagnostics.clear();
+        self.reference_list = None;
+        self.extensions = self.backend.get_extensions()?;
+        self.sync_target_from_backend()?;
+        // ui
+        self.initialize_scroll();
+        self.cursor_x = 0;
+        self.cursor_y = 0;
+        Ok(())
+    }
+
+    /// reloads everything specific to the editing target
+    fn sync_target_from_backend(&mut self) -> ViiruResult<()> {
         // synchronized + constant
-        self.blocks = self.get_all_blocks()?;
+        self.blocks = self.backend.get_all_blocks()?;
+        self.fill_missing_slots();
+        self.learn_fallback_shapes();
         self.top_level = self
             .blocks
             .iter()
             .filter(|&(_, block)| block.parent_id.is_none())
             .map(|(id, _)| id.clone())
             .collect();
+        self.variables = self.backend.get_variables_of_type(VariableType::Scalar)?;
+        self.lists = self.backend.get_variables_of_type(VariableType::List)?;
+        self.broadcasts = self
+            .backend
+            .get_variables_of_type(VariableType::Broadcast)?;
+        // the toolbox depends on which variables exist
         self.initialize_toolbox_blocks()?;
-        self.variables = self.get_variables_of_type(VariableType::Scalar)?;
-        self.lists = self.get_variables_of_type(VariableType::List)?;
-        self.broadcasts = self.get_variables_of_type(VariableType::Broadcast)?;
+        if self.lint_usage.is_some() {
+            self.enable_lint()?;
+        }
         // ephemeral
         self.block_positions.clear();
         self.cursor_block = None;
         self.drop_points.clear();
         self.writable_points.clear();
-        // ui
-        self.initialize_scroll();
-        self.cursor_x = 0;
-        self.cursor_y = 0;
-        Ok(true)
+        self.dropdown_points.clear();
+        Ok(())
+    }
+
+    pub fn editing_target_name(&self) -> &str {
+        self.targets
+            .get(self.editing_target)
+            .map_or("", |target| target.name.as_str())
+    }
+
+    /// Stashes the scroll and cursor of the current target, then restores the ones of the
+    /// new target. Blocks are refetched, since the backend owns every target's scripts.
+    pub fn switch_target(&mut self, index: usize) -> ViiruResult<()> {
+        if index == self.editing_target || index >= self.targets.len() {
+            return Ok(());
+        }
+        self.target_views.insert(
+            self.editing_target,
+            TargetView {
+                scroll_x: self.scroll_x,
+                scroll_y: self.scroll_y,
+                cursor_x: self.cursor_x,
+                cursor_y: self.cursor_y,
+            },
+        );
+        self.history.checkpoint();
+        let history = std::mem::take(&mut self.history);
+        self.histories.insert(self.editing_target, history);
+        self.history = self.histories.remove(&index).unwrap_or_default();
+        self.backend.set_editing_target(index)?;
+        self.editing_target = index;
+        self.sync_target_from_backend()?;
+        if let Some(view) = self.target_views.get(&index) {
+            self.scroll_x = view.scroll_x;
+            self.scroll_y = view.scroll_y;
+            self.cursor_x = view.cursor_x;
+            self.cursor_y = view.cursor_y;
+        } else {
+            self.initialize_scroll();
+            self.cursor_x = 0;
+            self.cursor_y = 0;
+        }
+        Ok(())
+    }
+
+    /// Project files leave out inputs that were never filled in,
+    /// but the renderer expects every slot in the spec to be present
+    fn fill_missing_slots(&mut self) {
+        for block in self.blocks.values_mut() {
+            if let Some(spec) = BLOCKS.get(&block.opcode) {
+                for input_name in spec.input_names() {
+                    block.inputs.entry(input_name.clone()).or_default();
+                }
+                for field_name in spec.field_names() {
+                    block.fields.entry(field_name.clone()).or_default();
+                }
+            }
+            if let Some(mutation) = &block.mutation {
+                for argument_id in &mutation.argument_ids {
+                    block.inputs.entry(argument_id.clone()).or_default();
+                }
+            }
+        }
+    }
+
+    pub fn is_extension_enabled(&self, id: &str) -> bool {
+        self.extensions.iter().any(|enabled| enabled == id)
+    }
+
+    /// adds the extension's blocks to the toolbox, scrolling it to the new section
+    pub fn enable_extension(&mut self, id: &str) -> ViiruResult<()> {
+        if self.is_extension_enabled(id) {
+            return Ok(());
+        }
+        self.backend.enable_extension(id)?;
+        self.extensions.push(id.to_string());
+        self.refresh_toolbox()?;
+        let first = extension(id).and_then(|extension| extension.toolbox().next());
+        if let Some(index) = first.and_then(|opcode| {
+            self.toolbox
+                .iter()
+                .position(|toolbox_id| self.blocks[toolbox_id].opcode == opcode)
+        }) {
+            self.toolbox_cursor = index;
+            self.toolbox_scroll = index;
+        }
+        Ok(())
+    }
+
+    /// Opcodes without a spec can only be told apart by how the project uses them: reporters
+    /// sit in inputs, and anything else is a stack block. Booleans give themselves away by
+    /// sitting in a known block's boolean slot.
+    fn learn_fallback_shapes(&mut self) {
+        for (block_id, block) in &self.blocks {
+            if BLOCKS.contains_key(&block.opcode) {
+                continue;
+            }
+            let id = |id: &Option<String>| id.as_ref() == Some(block_id);
+            let slot = block.parent_id.as_ref().and_then(|parent_id| {
+                let parent = &self.blocks[parent_id];
+                let (input_name, input) = parent
+                    .inputs
+                    .iter()
+                    .find(|(_, input)| id(&input.block_id) || id(&input.shadow_id))?;
+                Some((parent, input_name, id(&input.shadow_id)))
+            });
+            let learned = match slot {
+                Some((parent, input_name, is_shadow)) => {
+                    let is_boolean = BLOCKS.get(&parent.opcode).is_some_and(|spec| {
+                        spec.lines.iter().flatten().any(|fragment| {
+                            matches!(fragment, Fragment::BooleanInput(name) if name == input_name)
+                        })
+                    });
+                    if is_boolean {
+                        (Shape::Hexagon, false)
+                    } else {
+                        (Shape::Circle, is_shadow)
+                    }
+                }
+                None => (Shape::Stack, false),
+            };
+            // a top-level block on its own could be anything, so don't let it override
+            let is_loose = slot.is_none() && block.parent_id.is_none() && block.next_id.is_none();
+            if !is_loose || !self.fallback_shapes.contains_key(&block.opcode) {
+                self.fallback_shapes.insert(block.opcode.clone(), learned);
+            }
+        }
+    }
+
+    /// the spec a block is drawn with, made up on the spot for opcodes we don't know
+    pub fn spec_of(&self, block_id: &str) -> Cow<'static, Spec> {
+        let block = &self.blocks[block_id];
+        match BLOCKS.get(&block.opcode) {
+            Some(spec) => Cow::Borrowed(spec),
+            None => {
+                let (shape, is_shadow) = self
+                    .fallback_shapes
+                    .get(&block.opcode)
+                    .copied()
+                    .unwrap_or((Shape::Stack, false));
+                Cow::Owned(fallback_spec(block, shape, is_shadow))
+            }
+        }
+    }
+
+    /// The toolbox blocks whose text fuzzily matches the search, best first
+    pub fn toolbox_matches(&self) -> Vec<ToolboxMatch> {
+        let Some(search) = &self.toolbox_search else {
+            return vec![];
+        };
+        let mut matches: Vec<_> = self
+            .toolbox
+            .iter()
+            .enumerate()
+            .filter_map(|(index, id)| {
+                let text = block_text(self, id);
+                let (score, positions) = if search.query.is_empty() {
+                    (0, vec![])
+                } else {
+                    fuzzy_match(&search.query, &text)?
+                };
+                Some((
+                    score,
+                    ToolboxMatch {
+                        index,
+                        text,
+                        positions,
+                    },
+                ))
+            })
+            .collect();
+        // stable, so equally good matches keep the toolbox order
+        matches.sort_by_key(|(score, _)| *score);
+        matches.into_iter().map(|(_, m)| m).collect()
+    }
+
+    /// Moves through the search results, keeping the toolbox cursor on the picked block
+    pub fn move_toolbox_search(&mut self, delta: isize) {
+        let matches = self.toolbox_matches();
+        let Some(search) = &mut self.toolbox_search else {
+            return;
+        };
+        search.cursor = search
+            .cursor
+            .saturating_add_signed(delta)
+            .min(matches.len().saturating_sub(1));
+        if let Some(picked) = matches.get(search.cursor) {
+            self.toolbox_cursor = picked.index;
+        }
+    }
+
+    /// Goes back to the whole toolbox, scrolled to whichever block the search was on
+    pub fn close_toolbox_search(&mut self) {
+        if self.toolbox_search.take().is_some() {
+            self.toolbox_scroll = self.toolbox_cursor;
+        }
+    }
+
+    /// the toolbox block space would spawn, which a search may have filtered out entirely
+    pub fn toolbox_selection(&self) -> Option<String> {
+        if self.toolbox_search.is_some() && self.toolbox_matches().is_empty() {
+            return None;
+        }
+        self.toolbox.get(self.toolbox_cursor).cloned()
+    }
+
+    /// rebuilds the toolbox, e.g. after custom blocks were added or removed
+    pub fn refresh_toolbox(&mut self) -> ViiruResult<()> {
+        for id in std::mem::take(&mut self.toolbox) {
+            self.delete_blocks_recursively(&id);
+        }
+        self.initialize_toolbox_blocks()
     }
 
-    fn initialize_toolbox_blocks(&mut self) -> NeonResult<()> {
+    fn initialize_toolbox_blocks(&mut self) -> ViiruResult<()> {
+        self.toolbox.clear();
+        let is_stage = self
+            .targets
+            .get(self.editing_target)
+            .is_some_and(|target| target.is_stage);
+        // each enabled extension gets a section after the built-in blocks
+        let extension_blocks = EXTENSIONS
+            .iter()
+            .filter(|extension| self.is_extension_enabled(&extension.id))
+            .flat_map(|extension| extension.toolbox());
+        let opcodes: Vec<_> = TOOLBOX
+            .iter()
+            .copied()
+            .chain(extension_blocks)
+            .filter(|op| in_palette(op, is_stage))
+            .collect();
         self.do_sync = false;
-        for opcode in TOOLBOX.iter() {
-            let (id, _) = self.create_block_template(opcode)?;
+        for opcode in opcodes {
+            if let ("data_variable" | "data_listcontents", Some(variable_type)) =
+                (opcode, variable_type_of(opcode))
+            {
+                // reporters get an entry for every variable
+                for option in variable_options(self.variables_of_type(variable_type)) {
+                    let (id, _) = self.create_block_template(opcode)?;
+                    self.select_dropdown_option(&id, &option)?;
+                    self.remove_top_level(&id);
+                    self.toolbox.push(id);
+                }
+                continue;
+            }
+            if opcode == "procedures_call" {
+                // and calls get an entry for every custom block
+                let mut prototypes: Vec<_> = self
+                    .blocks
+                    .values()
+                    .filter(|block| block.opcode == "procedures_prototype")
+                    .filter_map(|block| block.mutation.clone())
+                    .collect();
+                prototypes.sort_by(|a, b| a.proccode.cmp(&b.proccode));
+                prototypes.dedup_by(|a, b| a.proccode == b.proccode);
+                for prototype in prototypes {
+                    let (id, _) = self.create_block_template(opcode)?;
+                    self.set_call_mutation(&id, &prototype)?;
+                    self.remove_top_level(&id);
+                    self.toolbox.push(id);
+                }
+                continue;
+            }
+            let (id, child_ids) = self.create_block_template(opcode)?;
+            // like scratch, default to the first entry of every dropdown
+            for block_id in std::iter::once(&id).chain(&child_ids) {
+                let block = &self.blocks[block_id];
+                let has_dropdown = BLOCKS[&block.opcode].dropdown_names().next().is_some();
+                if let Some(option) = has_dropdown
+                    .then(|| dropdown_options(self, block_id, &block.opcode))
+                    .and_then(|options| options.into_iter().next())
+                {
+                    self.select_dropdown_option(block_id, &option)?;
+                }
+            }
             self.remove_top_level(&id);
             self.toolbox.push(id);
         }
         self.do_sync = true;
+        // the toolbox changes size between targets and as custom blocks come and go
+        let last = self.toolbox.len().saturating_sub(1);
+        self.toolbox_cursor = self.toolbox_cursor.min(last);
+        self.toolbox_scroll = self.toolbox_scroll.min(self.toolbox_cursor);
         Ok(())
     }
 
-    pub fn save_project(&mut self, path: &str) -> NeonResult<bool> {
-        let success = bridge::save_project(self.cx, self.api, path)?.value(self.cx);
-        self.is_dirty = false;
+    /// id -> name
+    pub fn variables_of_type(&self, variable_type: VariableType) -> &HashMap<String, String> {
+        match variable_type {
+            VariableType::Scalar => &self.variables,
+            VariableType::List => &self.lists,
+            VariableType::Broadcast => &self.broadcasts,
+        }
+    }
+
+    /// Clicks the green flag on the project as it is right now, showing the stage pane
+    pub fn start_running(&mut self) -> ViiruResult<()> {
+        let project = self.backend.get_project()?;
+        // unlike `viiru run`, nobody expects the same random numbers or date every time here
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let mut interpreter = Interpreter::new(&project, now.as_nanos() as u64, now.as_secs_f64());
+        interpreter.breakpoints = self.breakpoints.clone();
+        interpreter.green_flag();
+        self.interpreter = Some(interpreter);
+        self.command_buffer.clear();
+        self.state = State::Run;
+        Ok(())
+    }
+
+    pub fn stop_running(&mut self) {
+        self.interpreter = None;
+        self.command_buffer.clear();
+        self.state = State::Move;
+    }
+
+    /// Runs a single frame of the project. Returns true if it has just paused, after switching to
+    /// the paused target. Jump to the block once it's been drawn, since that's when its position is known.
+    pub fn step_running(&mut self) -> ViiruResult<bool> {
+        let Some(interpreter) = &mut self.interpreter else {
+            return Ok(false);
+        };
+        let was_paused = interpreter.paused.is_some();
+        interpreter.step();
+        // bubbles are shown instead
+        interpreter.output.clear();
+        // terminals only say when keys are pressed, so they're let go of right away
+        interpreter.pressed_keys.clear();
+        let Some(pause) = interpreter.paused.as_ref().filter(|_| !was_paused) else {
+            return Ok(false);
+        };
+        let target = pause.target;
+        self.switch_target(target)?;
+        Ok(true)
+    }
+
+    /// Returns whether the block has a breakpoint now. Only stack blocks are paused at,
+    /// so reporters and shadows stand for the block they're in and hats for the block under
+    /// them. A hat with nothing under it can't have one, which gives `None`.
+    pub fn toggle_breakpoint(&mut self, block_id: &str) -> Option<bool> {
+        let mut block_id = block_id;
+        while let Some(block) = self.blocks.get(block_id) {
+            let spec = BLOCKS.get(&block.opcode);
+            let is_stack = spec.is_none_or(|spec| spec.shape == Shape::Stack);
+            match &block.parent_id {
+                Some(parent_id) if !is_stack => block_id = parent_id,
+                _ if spec.is_some_and(|spec| spec.is_hat) => block_id = block.next_id.as_ref()?,
+                _ => break,
+            }
+        }
+        let key = (self.editing_target, block_id.to_string());
+        if self.breakpoints.remove(&key) {
+            Some(false)
+        } else {
+            self.breakpoints.insert(key);
+            Some(true)
+        }
+    }
+
+    /// Moves the cursor onto the block, scrolling only if it's out of view
+    pub fn jump_to_block(&mut self, block_id: &str) {
+        if !self.blocks.contains_key(block_id) {
+            return;
+        }
+        let (x, y) = self.compute_own_xy(block_id);
+        self.jump_to(x, y);
+    }
+
+    /// Moves the cursor, scrolling only if it would end up out of view
+    pub fn jump_to(&mut self, x: i32, y: i32) {
+        self.cursor_x = x;
+        self.cursor_y = y;
+        let (screen_x, screen_y) = (x - self.scroll_x, y - self.scroll_y);
+        if screen_x < self.viewport.x_min || screen_x >= self.viewport.x_max {
+            self.scroll_x = x - self.viewport_offset_x - self.viewport.width() / 2;
+        }
+        if screen_y < self.viewport.y_min || screen_y >= self.viewport.y_max {
+            self.scroll_y = y - self.viewport_offset_y - self.viewport.height() / 2;
+        }
+    }
+
+    /// every block in the same script as this one
+    pub fn script_of(&self, block_id: &str) -> HashSet<String> {
+        let mut top_id = block_id;
+        while let Some(parent_id) = self.blocks.get(top_id).and_then(|b| b.parent_id.as_ref()) {
+            top_id = parent_id;
+        }
+        let mut script = HashSet::new();
+        let mut pending = vec![top_id.to_string()];
+        while let Some(id) = pending.pop() {
+            let Some(block) = self.blocks.get(&id) else {
+                continue;
+            };
+            pending.extend(block.next_id.iter().cloned());
+            for input in block.inputs.values() {
+                pending.extend(input.block_id.iter().chain(&input.shadow_id).cloned());
+            }
+            script.insert(id);
+        }
+        script
+    }
+
+    /// Looks at the other targets once, after which `relint` keeps the diagnostics current
+    pub fn enable_lint(&mut self) -> ViiruResult<()> {
+        let project = self.backend.get_project()?;
+        self.lint_usage = Some(Usage::of_project(&project, self.editing_target));
+        self.relint();
+        Ok(())
+    }
+
+    pub fn relint(&mut self) {
+        if let Some(usage) = &self.lint_usage {
+            self.diagnostics = lint(self, usage);
+            self.diagnostic_cursor = self
+                .diagnostic_cursor
+                .min(self.diagnostics.len().saturating_sub(1));
+        }
+    }
+
+    /// Lists every block in the project using what this block refers to, starting with the
+    /// editing target. With `receivers_only`, that's just the hats receiving its broadcast.
+    /// None if the block doesn't refer to a variable, list or broadcast.
+    pub fn find_references(
+        &mut self,
+        block_id: &str,
+        receivers_only: bool,
+    ) -> ViiruResult<Option<ReferenceList>> {
+        let Some((referent, name)) = referent_of(&self.blocks, block_id) else {
+            return Ok(None);
+        };
+        if receivers_only && !matches!(referent, Referent::Broadcast(_)) {
+            return Ok(None);
+        }
+        let mut references = self.find_uses(&referent)?;
+        if receivers_only {
+            references.retain(|reference| reference.role == "receives");
+        }
+        let title = if receivers_only {
+            format!("receivers of `{name}`")
+        } else {
+            format!("references to `{name}`")
+        };
+        Ok(Some(ReferenceList {
+            title,
+            name,
+            references,
+            cursor: 0,
+            deleting: None,
+        }))
+    }
+
+    /// every block in the project using the referent, starting with the editing target
+    fn find_uses(&mut self, referent: &Referent) -> ViiruResult<Vec<Reference>> {
+        // the toolbox is in the same map, so only the editing target's own scripts are searched
+        let mut references =
+            find_in_target(self.editing_target, &self.blocks, &self.top_level, referent);
+        let project = self.backend.get_project()?;
+        for (i, target) in project.targets.iter().enumerate() {
+            if i == self.editing_target {
+                continue;
+            }
+            let blocks = target.decode_blocks();
+            let top_level: Vec<_> = blocks
+                .iter()
+                .filter(|(_, block)| block.parent_id.is_none())
+                .map(|(id, _)| id.clone())
+                .collect();
+            references.extend(find_in_target(i, &blocks, &top_level, referent));
+        }
+        Ok(references)
+    }
+
+    /// Lists the variables, lists and broadcasts the editing target can use
+    pub fn load_data_list(&mut self) -> ViiruResult<()> {
+        let project = self.backend.get_project()?;
+        let mut entries = vec![];
+        for (i, target) in project.targets.iter().enumerate() {
+            let is_local = !target.is_stage;
+            if is_local && i != self.editing_target {
+                continue;
+            }
+            let entry = |id: &String, name: &String, variable_type, is_cloud| DataEntry {
+                id: id.clone(),
+                name: name.clone(),
+                variable_type,
+                is_local,
+                is_cloud,
+            };
+            for (id, variable) in &target.variables {
+                entries.push(entry(
+                    id,
+                    &variable.name,
+                    VariableType::Scalar,
+                    variable.is_cloud,
+                ));
+            }
+            for (id, list) in &target.lists {
+                entries.push(entry(id, &list.name, VariableType::List, false));
+            }
+            for (id, name) in &target.broadcasts {
+                entries.push(entry(id, name, VariableType::Broadcast, false));
+            }
+        }
+        entries.sort_by_key(|entry| {
+            let order = match entry.variable_type {
+                VariableType::Scalar => 0,
+                VariableType::List => 1,
+                VariableType::Broadcast => 2,
+            };
+            (order, entry.name.to_lowercase())
+        });
+        let cursor = self.data_list.as_ref().map_or(0, |list| list.cursor);
+        self.data_list = Some(DataList {
+            cursor: cursor.min(entries.len().saturating_sub(1)),
+            entries,
+        });
+        Ok(())
+    }
+
+    /// explains what's wrong with a new name, if anything
+    pub fn data_name_error(
+        &self,
+        variable_type: VariableType,
+        name: &str,
+        renaming: Option<&str>,
+    ) -> Option<&'static str> {
+        if name.trim().is_empty() {
+            return Some("Names can't be empty");
+        }
+        let taken = self
+            .variables_of_type(variable_type)
+            .iter()
+            .any(|(id, other)| Some(id.as_str()) != renaming && other == name);
+        taken.then_some("That name is already taken")
+    }
+
+    /// Only global variables can be cloud variables, and the stage has nothing but globals
+    pub fn create_data(
+        &mut self,
+        variable_type: VariableType,
+        name: &str,
+        is_local: bool,
+        is_cloud: bool,
+    ) -> ViiruResult<()> {
+        let is_stage = self
+            .targets
+            .get(self.editing_target)
+            .is_some_and(|target| target.is_stage);
+        let is_local = is_local && !is_stage && variable_type != VariableType::Broadcast;
+        let is_cloud = is_cloud && !is_local && variable_type == VariableType::Scalar;
+        // scratch marks cloud variables by name
+        let name = if is_cloud && !name.starts_with("☁ ") {
+            format!("☁ {name}")
+        } else {
+            name.to_string()
+        };
+        let mut n = self.next_usable_id;
+        let mut id = format!("viiru-data-{n}");
+        while self.variables.contains_key(&id)
+            || self.lists.contains_key(&id)
+            || self.broadcasts.contains_key(&id)
+        {
+            n += 1;
+            id = format!("viiru-data-{n}");
+        }
+        self.next_usable_id = n + 1;
+        self.backend
+            .create_variable(variable_type, &id, &name, is_local, is_cloud)?;
+        self.sync_data_from_backend()?;
+        self.select_data(&id);
+        Ok(())
+    }
+
+    pub fn rename_data(&mut self, id: &str, name: &str) -> ViiruResult<()> {
+        self.backend.rename_variable(id, name)?;
+        self.sync_data_from_backend()?;
+        // renaming can change where it's sorted
+        self.select_data(id);
+        Ok(())
+    }
+
+    fn select_data(&mut self, id: &str) {
+        if let Some(list) = &mut self.data_list {
+            if let Some(i) = list.entries.iter().position(|entry| entry.id == id) {
+                list.cursor = i;
+            }
+        }
+    }
+
+    /// the blocks using it, which deleting it would leave referring to nothing
+    pub fn uses_of_data(&mut self, entry: &DataEntry) -> ViiruResult<Vec<Reference>> {
+        let referent = match entry.variable_type {
+            VariableType::Broadcast => Referent::Broadcast(entry.name.to_lowercase()),
+            _ => Referent::Data(entry.id.clone()),
+        };
+        self.find_uses(&referent)
+    }
+
+    pub fn delete_data(&mut self, id: &str) -> ViiruResult<()> {
+        self.backend.delete_variable(id)?;
+        self.sync_data_from_backend()
+    }
+
+    /// renamed fields and the toolbox's variable reporters both need refetching
+    fn sync_data_from_backend(&mut self) -> ViiruResult<()> {
+        self.is_dirty = true;
+        // `of` blocks list each sprite's variables
+        self.targets = self.backend.get_targets()?;
+        self.sync_target_from_backend()?;
+        self.load_data_list()
+    }
+
+    pub fn save_project(&mut self, path: &str) -> ViiruResult<bool> {
+        let success = self.backend.save_project(path)?;
+        if success {
+            self.is_dirty = false;
+        }
         Ok(success)
     }
 
-    pub fn create_single_block(&mut self, opcode: &str) -> NeonResult<String> {
-        let spec = &BLOCKS[opcode];
-        let is_shadow = spec.is_shadow;
-        let id = if self.do_sync {
+    pub fn create_single_block(&mut self, opcode: &str) -> ViiruResult<String> {
+        let id = self.next_block_id();
+        self.create_block_with_id(opcode, &id, None)?;
+        Ok(id)
+    }
+
+    /// an empty block with the same opcode and slots as the template
+    fn create_block_like(&mut self, template: &Block) -> ViiruResult<String> {
+        let id = self.next_block_id();
+        self.create_block_with_id(&template.opcode, &id, Some(template))?;
+        Ok(id)
+    }
+
+    fn next_block_id(&mut self) -> String {
+        if self.do_sync {
             self.generate_id()
         } else {
             self.generate_fake_id()
+        }
+    }
+
+    fn create_block_with_id(
+        &mut self,
+        opcode: &str,
+        id: &str,
+        template: Option<&Block>,
+    ) -> ViiruResult<()> {
+        // opcodes without a spec get their slots from whatever they're copied from
+        let spec = BLOCKS.get(opcode);
+        let is_shadow = spec.map_or(template.is_some_and(|t| t.is_shadow), |spec| spec.is_shadow);
+        let (input_names, field_names): (Vec<_>, Vec<_>) = match (spec, template) {
+            (Some(spec), _) => (
+                spec.input_names().cloned().collect(),
+                spec.field_names().cloned().collect(),
+            ),
+            (None, Some(template)) => (
+                template.inputs.keys().cloned().collect(),
+                template.fields.keys().cloned().collect(),
+            ),
+            (None, None) => (vec![], vec![]),
         };
-        let inputs: HashMap<_, _> = spec
-            .lines
-            .iter()
-            .flatten()
-            .filter_map(|frag| {
-                if let Fragment::StrumberInput(input, _)
-                | Fragment::BooleanInput(input)
-                | Fragment::BlockInput(input) = frag
-                {
-                    Some((
-                        input.clone(),
-                        Input {
-                            shadow_id: None,
-                            block_id: None,
-                        },
-                    ))
-                } else {
-                    None
-                }
-            })
+        let inputs = input_names
+            .into_iter()
+            .map(|input_name| (input_name, Default::default()))
             .collect();
-        let fields: HashMap<_, _> = spec
-            .lines
-            .iter()
-            .flatten()
-            .filter_map(|frag| {
-                if let Fragment::FieldText(field)
-                | Fragment::WritableFieldText(field)
-                | Fragment::CustomColour(field)
-                | Fragment::Dropdown(field, _) = frag
-                {
-                    Some((
-                        field.clone(),
-                        Field {
-                            value: "".into(),
-                            id: None,
-                        },
-                    ))
-                } else {
-                    None
-                }
-            })
+        let fields = field_names
+            .into_iter()
+            .map(|field_name| (field_name, Default::default()))
             .collect();
         self.blocks.insert(
-            id.clone(),
+            id.to_string(),
             Block {
                 x: 0,
                 y: 0,
@@ -327,21 +1156,27 @@ impl<'js, 'rt> Runtime<'js, 'rt> {
                 opcode: opcode.to_string(),
                 parent_id: None,
                 next_id: None,
+                is_shadow,
                 inputs,
                 fields,
+                mutation: None,
             },
         );
-        self.top_level.push(id.clone());
-        // todo: perhaps we can let the vm generate the ID
+        self.top_level.push(id.to_string());
+        // todo: perhaps we can let the backend generate the ID
         if self.do_sync {
             self.is_dirty = true;
-            bridge::create_block(self.cx, self.api, opcode, is_shadow, Some(&id))?;
+            self.backend.create_block(opcode, is_shadow, id)?;
         }
-        Ok(id)
+        self.record(|runtime| Op::Create {
+            id: id.to_string(),
+            blocks: runtime.snapshot(id),
+        });
+        Ok(())
     }
 
     // special!
-    pub fn create_block_template(&mut self, opcode: &str) -> NeonResult<(String, Vec<String>)> {
+    pub fn create_block_template(&mut self, opcode: &str) -> ViiruResult<(String, Vec<String>)> {
         let id = self.create_single_block(opcode)?;
         let spec = &BLOCKS[opcode];
         let mut child_ids = vec![];
@@ -381,7 +1216,11 @@ impl<'js, 'rt> Runtime<'js, 'rt> {
     pub fn stamp_block(&mut self, block_id: &str, is_root: bool) -> ViiruResult<String> {
         let original = self.blocks[block_id].clone();
         let (new_x, new_y) = self.compute_own_xy(block_id);
-        let stamp_id = self.create_single_block(&original.opcode)?;
+        let stamp_id = self.create_block_like(&original)?;
+        // custom blocks need their argument inputs before anything can be attached
+        if let Some(mutation) = &original.mutation {
+            self.set_mutation(&stamp_id, mutation.clone())?;
+        }
 
         // block sliding is recursive and so only needs to be performed on the root block
         if is_root {
@@ -404,12 +1243,19 @@ impl<'js, 'rt> Runtime<'js, 'rt> {
                 self.attach_input(&stamp_next_id, &stamp_id, &input_name, false)?;
             }
         }
+        if is_root && original.opcode == "procedures_definition" {
+            self.refresh_toolbox()?;
+        }
 
         Ok(stamp_id)
     }
 
     fn delete_blocks_recursively(&mut self, id: &str) {
         let block = self.blocks.remove(id).unwrap();
+        // the backends take the rest of the stack along too
+        if let Some(next_id) = &block.next_id {
+            self.delete_blocks_recursively(next_id);
+        }
         for input in block.inputs.values() {
             if let Some(id) = &input.block_id {
                 self.delete_blocks_recursively(id);
@@ -420,39 +1266,60 @@ impl<'js, 'rt> Runtime<'js, 'rt> {
         }
     }
 
-    pub fn delete_block(&mut self, id: &str) -> NeonResult<()> {
+    pub fn delete_block(&mut self, id: &str) -> ViiruResult<()> {
+        let is_definition = self.blocks[id].opcode == "procedures_definition";
         self.detach_block(id)?;
+        self.record(|runtime| Op::Delete {
+            id: id.to_string(),
+            blocks: runtime.snapshot(id),
+        });
         self.remove_top_level(id);
         self.delete_blocks_recursively(id);
         // The VM handles recursion.
         if self.do_sync {
             self.is_dirty = true;
-            bridge::delete_block(self.cx, self.api, id)?;
+            self.backend.delete_block(id)?;
+        }
+        if is_definition {
+            self.refresh_toolbox()?;
         }
         Ok(())
     }
 
-    pub fn slide_block_by(&mut self, id: &str, dx: i32, dy: i32) -> NeonResult<()> {
+    pub fn slide_block_by(&mut self, id: &str, dx: i32, dy: i32) -> ViiruResult<()> {
+        self.record(|runtime| {
+            let block = &runtime.blocks[id];
+            Op::Slide {
+                id: id.to_string(),
+                from: (block.x, block.y),
+                to: (block.x + dx, block.y + dy),
+            }
+        });
+        self.slide_recursively(id, dx, dy)
+    }
+
+    /// only the root of a slide is recorded, since undoing it moves the children along
+    fn slide_recursively(&mut self, id: &str, dx: i32, dy: i32) -> ViiruResult<()> {
         let block = self.blocks.get_mut(id).unwrap();
         block.x += dx;
         block.y += dy;
         if self.do_sync {
             self.is_dirty = true;
-            bridge::slide_block(self.cx, self.api, id, block.x, block.y)?;
+            self.backend.slide_block(id, block.x, block.y)?;
         }
         for child in block.inputs.clone().values() {
             if let Some(id) = &child.block_id {
-                self.slide_block_by(id, dx, dy)?;
+                self.slide_recursively(id, dx, dy)?;
             }
             if let Some(id) = &child.shadow_id {
-                self.slide_block_by(id, dx, dy)?;
+                self.slide_recursively(id, dx, dy)?;
             }
         }
 
         Ok(())
     }
 
-    pub fn slide_block_to(&mut self, id: &str, x: i32, y: i32) -> NeonResult<()> {
+    pub fn slide_block_to(&mut self, id: &str, x: i32, y: i32) -> ViiruResult<()> {
         let block = self.blocks.get_mut(id).unwrap();
         let dx = x - block.x;
         let dy = y - block.y;
@@ -460,74 +1327,170 @@ impl<'js, 'rt> Runtime<'js, 'rt> {
         Ok(())
     }
 
-    pub fn current_drop_point(&self) -> Option<(String, Option<String>)> {
+    pub fn current_drop_point(&self) -> Option<DropPoint> {
         if let Some(cursor_id) = &self.cursor_block {
-            let shape = BLOCKS[&self.blocks[cursor_id].opcode].shape;
+            let spec = self.spec_of(cursor_id);
             // cursor blocks are top-level, so these are up to date
             let x = self.blocks[cursor_id].x;
             let y = self.blocks[cursor_id].y;
             if let Some(drop_point) = self.drop_points.get(&(x, y)) {
-                if drop_point.shape == shape && !BLOCKS[&self.blocks[cursor_id].opcode].is_hat {
-                    return Some((drop_point.id.clone(), drop_point.input.clone()));
+                // the prototype and its arguments are fixed in place
+                let is_fixed = matches!(
+                    self.blocks[&drop_point.id].opcode.as_str(),
+                    "procedures_definition" | "procedures_prototype"
+                ) && drop_point.input.is_some();
+                // hats can only go on top
+                if drop_point.shape == spec.shape
+                    && (!spec.is_hat || drop_point.is_above)
+                    && drop_point.id != *cursor_id
+                    && !is_fixed
+                {
+                    return Some(drop_point.clone());
                 }
             }
         }
         None
     }
 
+    /// the last block of the stack
+    pub fn stack_bottom(&self, id: &str) -> String {
+        let mut bottom = id;
+        while let Some(next_id) = &self.blocks[bottom].next_id {
+            bottom = next_id;
+        }
+        bottom.to_string()
+    }
+
+    /// Where the rest of a stack goes when this stack is slotted in above it: into the mouth of
+    /// a lone, empty C-block, or else after the last block. None if the stack ends in a cap.
+    fn stack_continuation(&self, id: &str) -> Option<(String, Option<String>)> {
+        let block = &self.blocks[id];
+        if block.next_id.is_none() {
+            let spec = self.spec_of(id);
+            let mouth = spec
+                .substack_names()
+                .next()
+                .filter(|input_name| block.inputs[*input_name].block_id.is_none());
+            if let Some(input_name) = mouth {
+                return Some((id.to_string(), Some(input_name.clone())));
+            }
+        }
+        let bottom = self.stack_bottom(id);
+        (!self.blocks[&bottom].is_boot()).then_some((bottom, None))
+    }
+
+    fn attach_continuation(
+        &mut self,
+        rest_id: &str,
+        (parent_id, input_name): (String, Option<String>),
+    ) -> ViiruResult<()> {
+        match input_name {
+            Some(input_name) => self.attach_input(rest_id, &parent_id, &input_name, false),
+            None => self.attach_next(rest_id, &parent_id),
+        }
+    }
+
+    /// Slots a stack in after a block, or into the mouth of a C-block. Whatever was there
+    /// before is moved below the stack, or wrapped by it. Returns false if the two don't fit.
+    pub fn insert_stack(
+        &mut self,
+        id: &str,
+        parent_id: &str,
+        input_name: Option<&str>,
+    ) -> ViiruResult<bool> {
+        let parent = &self.blocks[parent_id];
+        let rest_id = match input_name {
+            Some(input_name) => parent.inputs[input_name].block_id.clone(),
+            None if parent.is_boot() => return Ok(false),
+            None => parent.next_id.clone(),
+        };
+        let continuation = self.stack_continuation(id);
+        if rest_id.is_some() && continuation.is_none() {
+            return Ok(false);
+        }
+        if let Some(rest_id) = &rest_id {
+            self.detach_block(rest_id)?;
+        }
+        match input_name {
+            Some(input_name) => self.attach_input(id, parent_id, input_name, false)?,
+            None => self.attach_next(id, parent_id)?,
+        }
+        if let (Some(rest_id), Some(continuation)) = (rest_id, continuation) {
+            self.attach_continuation(&rest_id, continuation)?;
+        }
+        Ok(true)
+    }
+
+    /// Puts a stack on top of a script, which goes below it or inside its mouth.
+    /// Returns false if the stack ends in a cap.
+    pub fn insert_above(&mut self, id: &str, top_id: &str) -> ViiruResult<bool> {
+        let Some(continuation) = self.stack_continuation(id) else {
+            return Ok(false);
+        };
+        self.attach_continuation(top_id, continuation)?;
+        Ok(true)
+    }
+
     pub fn attach_input(
         &mut self,
         id: &str,
         parent_id: &str,
         input_name: &str,
         is_shadow: bool,
-    ) -> NeonResult<()> {
-        let parent = self.blocks.get_mut(parent_id).unwrap();
-        parent.set_input(input_name, id, is_shadow);
-        self.blocks.get_mut(id).unwrap().parent_id = Some(parent_id.to_string());
+    ) -> ViiruResult<()> {
+        self.require_blocks(&[id, parent_id])?;
+        self.block_mut(parent_id)?
+            .set_input(input_name, id, is_shadow);
+        self.block_mut(id)?.parent_id = Some(parent_id.to_string());
         self.remove_top_level(id);
         if self.do_sync {
             self.is_dirty = true;
-            bridge::attach_block(
-                self.cx,
-                self.api,
-                id,
-                parent_id,
-                Some(input_name),
-                is_shadow,
-            )?;
+            self.backend
+                .attach_block(id, parent_id, Some(input_name), is_shadow)?;
         }
+        self.record(|_| Op::Attach {
+            id: id.to_string(),
+            parent_id: parent_id.to_string(),
+            input_name: Some(input_name.to_string()),
+            is_shadow,
+        });
         Ok(())
     }
 
-    pub fn attach_next(&mut self, id: &str, parent_id: &str) -> NeonResult<()> {
-        let parent = self.blocks.get_mut(parent_id).unwrap();
+    pub fn attach_next(&mut self, id: &str, parent_id: &str) -> ViiruResult<()> {
+        self.require_blocks(&[id, parent_id])?;
 
-        parent.next_id.replace(id.to_string());
-        self.blocks.get_mut(id).unwrap().parent_id = Some(parent_id.to_string());
+        self.block_mut(parent_id)?.next_id.replace(id.to_string());
+        self.block_mut(id)?.parent_id = Some(parent_id.to_string());
         self.remove_top_level(id);
 
         if self.do_sync {
             self.is_dirty = true;
-            bridge::attach_block(self.cx, self.api, id, parent_id, None, false)?;
+            self.backend.attach_block(id, parent_id, None, false)?;
         }
+        self.record(|_| Op::Attach {
+            id: id.to_string(),
+            parent_id: parent_id.to_string(),
+            input_name: None,
+            is_shadow: false,
+        });
 
         Ok(())
     }
 
-    pub fn detach_block(&mut self, id: &str) -> NeonResult<()> {
+    pub fn detach_block(&mut self, id: &str) -> ViiruResult<()> {
         let (new_x, new_y) = self.compute_own_xy(id);
         let parent_id = self.blocks[id].parent_id.clone();
         if let Some(parent_id) = parent_id {
             let parent = self.blocks.get_mut(&parent_id).unwrap();
-            let mut removed = false;
+            let mut removed_from = None;
             if let Some(next_id) = &parent.next_id {
                 if next_id == id {
                     parent.next_id = None;
-                    removed = true;
+                    removed_from = Some((None, false));
                 }
             }
-            if !removed {
+            if removed_from.is_none() {
                 let (input_name, is_shadow) = parent
                     .inputs
                     .iter()
@@ -543,14 +1506,23 @@ impl<'js, 'rt> Runtime<'js, 'rt> {
                     .next()
                     .unwrap();
                 parent.remove_input(&input_name, is_shadow);
+                removed_from = Some((Some(input_name), is_shadow));
             }
             self.top_level.push(id.to_string());
+            if let Some((input_name, is_shadow)) = removed_from {
+                self.record(|_| Op::Detach {
+                    id: id.to_string(),
+                    parent_id,
+                    input_name,
+                    is_shadow,
+                });
+            }
         }
         self.blocks.get_mut(id).unwrap().parent_id = None;
         self.slide_block_to(id, new_x, new_y)?;
         if self.do_sync {
             self.is_dirty = true;
-            bridge::detach_block(self.cx, self.api, id)?;
+            self.backend.detach_block(id)?;
         }
         Ok(())
     }
@@ -562,8 +1534,9 @@ impl<'js, 'rt> Runtime<'js, 'rt> {
         field_name: &str,
         text: &str,
         data_id: Option<&str>,
-    ) -> NeonResult<()> {
-        let block = self.blocks.get_mut(block_id).unwrap();
+    ) -> ViiruResult<()> {
+        let block = self.block_mut(block_id)?;
+        let old = block.fields.get(field_name).cloned().unwrap_or_default();
         block.set_field_text(field_name, text);
         if let Some(id) = data_id {
             block.set_field_id(field_name, id);
@@ -572,7 +1545,86 @@ impl<'js, 'rt> Runtime<'js, 'rt> {
         }
         if self.do_sync {
             self.is_dirty = true;
-            bridge::change_field(self.cx, self.api, block_id, field_name, text, data_id)?;
+            self.backend
+                .change_field(block_id, field_name, text, data_id)?;
+        }
+        self.record(|_| Op::SetField {
+            id: block_id.to_string(),
+            field_name: field_name.to_string(),
+            old,
+            new: Field {
+                value: text.to_string(),
+                id: data_id.map(str::to_string),
+            },
+        });
+        Ok(())
+    }
+
+    /// Blocks only ever have a single dropdown. Variable reporters have none,
+    /// but their name field is set the same way.
+    pub fn dropdown_field(&self, block_id: &str) -> Option<String> {
+        let spec = BLOCKS.get(&self.blocks[block_id].opcode)?;
+        spec.dropdown_names().chain(spec.field_names()).next().cloned()
+    }
+
+    pub fn select_dropdown_option(
+        &mut self,
+        block_id: &str,
+        option: &DropdownOption,
+    ) -> ViiruResult<()> {
+        let Some(field_name) = self.dropdown_field(block_id) else {
+            return Ok(());
+        };
+        self.set_field(block_id, &field_name, &option.value, option.id.as_deref())?;
+        // `of` blocks offer different properties for the stage and each sprite
+        let parent_id = self.blocks[block_id].parent_id.clone();
+        if let Some(parent_id) = parent_id.filter(|id| self.blocks[id].opcode == "sensing_of") {
+            let property = self.blocks[&parent_id].fields.get("PROPERTY").cloned();
+            let options = dropdown_options(self, &parent_id, "sensing_of");
+            let is_available =
+                property.is_some_and(|property| options.iter().any(|o| o.value == property.value));
+            if let (false, Some(first)) = (is_available, options.first()) {
+                self.set_field(&parent_id, "PROPERTY", &first.value, None)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// returns false if there is nothing to pick from
+    pub fn open_dropdown(&mut self, block_id: &str) -> bool {
+        let block = &self.blocks[block_id];
+        let options = dropdown_options(self, block_id, &block.opcode);
+        if options.is_empty() {
+            return false;
+        }
+        // start out on whatever is currently selected
+        let field = self
+            .dropdown_field(block_id)
+            .and_then(|field_name| block.fields.get(&field_name));
+        let cursor = field
+            .and_then(|field| {
+                options
+                    .iter()
+                    .position(|option| option.value == field.value)
+            })
+            .unwrap_or(0);
+        self.dropdown_menu = Some(DropdownMenu {
+            block_id: block_id.to_string(),
+            options,
+            cursor,
+            filter: String::new(),
+            typing: false,
+        });
+        true
+    }
+
+    /// applies the option under the menu cursor, closing the menu either way
+    pub fn commit_dropdown(&mut self) -> ViiruResult<()> {
+        let Some(menu) = self.dropdown_menu.take() else {
+            return Ok(());
+        };
+        if let Some(&option) = menu.visible_options().get(menu.cursor) {
+            self.select_dropdown_option(&menu.block_id, option)?;
         }
         Ok(())
     }
@@ -589,7 +1641,7 @@ impl<'js, 'rt> Runtime<'js, 'rt> {
             "".into()
         }
     }
-    pub fn set_strumber_field(&mut self, id: &str, text: &str) -> NeonResult<()> {
+    pub fn set_strumber_field(&mut self, id: &str, text: &str) -> ViiruResult<()> {
         let block = self.blocks.get_mut(id).unwrap();
         if block.opcode == "text" {
             self.set_field(id, "TEXT", text, None)?;
@@ -599,25 +1651,469 @@ impl<'js, 'rt> Runtime<'js, 'rt> {
         Ok(())
     }
 
-    // todo: ChangeMutation(String, ()),
+    /// Also makes sure there's an input for every argument, and drops the emptied out inputs
+    /// of arguments that are gone
+    pub fn set_mutation(&mut self, block_id: &str, mutation: Mutation) -> ViiruResult<()> {
+        let block = self.blocks.get_mut(block_id).unwrap();
+        let old = block.mutation.take();
+        for old_id in old.iter().flat_map(|old| &old.argument_ids) {
+            let is_empty = block
+                .inputs
+                .get(old_id)
+                .is_some_and(|input| input.block_id.is_none() && input.shadow_id.is_none());
+            if is_empty && !mutation.argument_ids.contains(old_id) {
+                block.inputs.remove(old_id);
+            }
+        }
+        for argument_id in &mutation.argument_ids {
+            block.inputs.entry(argument_id.clone()).or_default();
+        }
+        if self.do_sync {
+            self.is_dirty = true;
+            self.backend.change_mutation(block_id, &mutation)?;
+        }
+        block.mutation = Some(mutation.clone());
+        self.record(|_| Op::SetMutation {
+            id: block_id.to_string(),
+            old,
+            new: Some(mutation),
+        });
+        Ok(())
+    }
+
+    /// deletes whatever is in the input, which is then dropped by `set_mutation`
+    fn remove_argument(&mut self, block_id: &str, argument_id: &str) -> ViiruResult<()> {
+        let Some(input) = self.blocks[block_id].inputs.get(argument_id).cloned() else {
+            return Ok(());
+        };
+        for child_id in input.block_id.iter().chain(&input.shadow_id) {
+            self.delete_block(child_id)?;
+        }
+        Ok(())
+    }
 
-    // internal use, only needed for synchronization
-    fn get_all_blocks(&mut self) -> NeonResult<HashMap<String, Block>> {
-        let blocks: Handle<JsObject> = bridge::get_all_blocks(self.cx, self.api)?;
-        map_each_value(self.cx, blocks, |cx, obj| to_block(cx, obj))
+    /// Makes a call match its prototype. String and number arguments get an empty text shadow,
+    /// and anything plugged into an argument that no longer exists is thrown away.
+    pub fn set_call_mutation(&mut self, call_id: &str, prototype: &Mutation) -> ViiruResult<()> {
+        let old_ids: Vec<_> = self.blocks[call_id].inputs.keys().cloned().collect();
+        for old_id in old_ids {
+            if !prototype.argument_ids.contains(&old_id) {
+                self.remove_argument(call_id, &old_id)?;
+            }
+        }
+        self.set_mutation(
+            call_id,
+            Mutation {
+                proccode: prototype.proccode.clone(),
+                argument_ids: prototype.argument_ids.clone(),
+                argument_names: vec![],
+                argument_defaults: vec![],
+                warp: prototype.warp,
+            },
+        )?;
+        for (argument_id, is_boolean) in prototype
+            .argument_ids
+            .iter()
+            .zip(prototype.argument_kinds())
+        {
+            if !is_boolean && self.blocks[call_id].inputs[argument_id].shadow_id.is_none() {
+                let text_id = self.create_single_block("text")?;
+                self.set_field(&text_id, "TEXT", "", None)?;
+                self.attach_input(&text_id, call_id, argument_id, true)?;
+            }
+        }
+        Ok(())
     }
 
-    fn get_variables_of_type(
-        &mut self,
-        variable_type: VariableType,
-    ) -> NeonResult<HashMap<String, String>> {
-        let vars: Handle<JsObject> =
-            bridge::get_variables_of_type(self.cx, self.api, variable_type)?;
-        map_each_value(self.cx, vars, |cx, obj| Ok(string_of(cx, obj)))
+    pub fn prototype_of(&self, definition_id: &str) -> Option<String> {
+        self.blocks[definition_id]
+            .inputs
+            .get("custom_block")?
+            .shadow_id
+            .clone()
+    }
+
+    /// Definitions can be found from any of their parts, or from a call
+    pub fn definition_of(&self, block_id: &str) -> Option<String> {
+        let block = &self.blocks[block_id];
+        match block.opcode.as_str() {
+            "procedures_definition" => Some(block_id.to_string()),
+            "procedures_prototype"
+            | "argument_reporter_string_number"
+            | "argument_reporter_boolean" => self.definition_of(block.parent_id.as_ref()?),
+            "procedures_call" => {
+                let proccode = &block.mutation.as_ref()?.proccode;
+                self.blocks
+                    .iter()
+                    .find(|(_, other)| {
+                        other.opcode == "procedures_prototype"
+                            && other.mutation.as_ref().map(|m| &m.proccode) == Some(proccode)
+                    })
+                    .and_then(|(_, prototype)| prototype.parent_id.clone())
+            }
+            _ => None,
+        }
+    }
+
+    /// The prototype and its arguments are welded to their definition,
+    /// so grabbing either of them grabs the whole definition
+    pub fn movable_block(&self, block_id: &str) -> String {
+        let block = &self.blocks[block_id];
+        match (block.opcode.as_str(), &block.parent_id) {
+            ("procedures_prototype", Some(parent_id)) => parent_id.clone(),
+            (_, Some(parent_id)) if self.is_prototype_argument(block_id) => {
+                self.movable_block(parent_id)
+            }
+            _ => block_id.to_string(),
+        }
+    }
+
+    /// these get copied out of the prototype instead of being moved
+    pub fn is_prototype_argument(&self, block_id: &str) -> bool {
+        self.blocks[block_id]
+            .parent_id
+            .as_ref()
+            .is_some_and(|parent_id| self.blocks[parent_id].opcode == "procedures_prototype")
+    }
+
+    /// explains what's wrong with a custom block signature, if anything
+    pub fn signature_error(&self, signature: &str) -> Option<&'static str> {
+        let Some(Signature { proccode, .. }) = parse_signature(signature) else {
+            return Some("Invalid custom block, try e.g. `jump (height) <fast>`");
+        };
+        let editing_prototype = self
+            .editing_definition
+            .as_ref()
+            .and_then(|id| self.prototype_of(id));
+        let taken = self.blocks.iter().any(|(id, block)| {
+            block.opcode == "procedures_prototype"
+                && Some(id) != editing_prototype.as_ref()
+                && block.mutation.as_ref().map(|m| &m.proccode) == Some(&proccode)
+        });
+        taken.then_some("A custom block with that text already exists")
+    }
+
+    /// returns the new definition, which still needs to be placed somewhere
+    pub fn create_custom_block(&mut self, signature: &str) -> ViiruResult<Option<String>> {
+        if parse_signature(signature).is_none() {
+            return Ok(None);
+        }
+        let definition_id = self.create_single_block("procedures_definition")?;
+        let prototype_id = self.create_single_block("procedures_prototype")?;
+        self.attach_input(&prototype_id, &definition_id, "custom_block", true)?;
+        self.edit_custom_block(&definition_id, signature)?;
+        Ok(Some(definition_id))
+    }
+
+    /// Changes the text, arguments and warp of a custom block, along with every call to it.
+    /// Arguments keep their ids as long as their name and kind stay the same.
+    pub fn edit_custom_block(&mut self, definition_id: &str, signature: &str) -> ViiruResult<()> {
+        let Some(Signature {
+            proccode,
+            arguments,
+            warp,
+        }) = parse_signature(signature)
+        else {
+            return Ok(());
+        };
+        let Some(prototype_id) = self.prototype_of(definition_id) else {
+            return Ok(());
+        };
+        let old = self.blocks[&prototype_id]
+            .mutation
+            .clone()
+            .unwrap_or_default();
+        let old_arguments: Vec<_> = old
+            .argument_ids
+            .iter()
+            .zip(&old.argument_names)
+            .zip(old.argument_kinds())
+            .collect();
+
+        let mut argument_ids = vec![];
+        for (name, is_boolean) in &arguments {
+            let kept = old_arguments
+                .iter()
+                .find(|((id, old_name), old_is_boolean)| {
+                    *old_name == name && old_is_boolean == is_boolean && !argument_ids.contains(*id)
+                });
+            let id = match kept {
+                Some(((id, _), _)) => (*id).clone(),
+                None => self.generate_id(),
+            };
+            argument_ids.push(id);
+        }
+        for old_id in &old.argument_ids {
+            if !argument_ids.contains(old_id) {
+                self.remove_argument(&prototype_id, old_id)?;
+            }
+        }
+        let mutation = Mutation {
+            proccode,
+            argument_ids: argument_ids.clone(),
+            argument_names: arguments.iter().map(|(name, _)| name.clone()).collect(),
+            argument_defaults: arguments
+                .iter()
+                .map(|(_, is_boolean)| if *is_boolean { "false" } else { "" }.to_string())
+                .collect(),
+            warp,
+        };
+        self.set_mutation(&prototype_id, mutation.clone())?;
+        for (argument_id, (name, is_boolean)) in argument_ids.iter().zip(&arguments) {
+            if self.blocks[&prototype_id].inputs[argument_id]
+                .shadow_id
+                .is_some()
+            {
+                continue;
+            }
+            let opcode = if *is_boolean {
+                "argument_reporter_boolean"
+            } else {
+                "argument_reporter_string_number"
+            };
+            let reporter_id = self.create_single_block(opcode)?;
+            self.set_field(&reporter_id, "VALUE", name, None)?;
+            self.attach_input(&reporter_id, &prototype_id, argument_id, true)?;
+        }
+
+        let call_ids: Vec<_> = self
+            .blocks
+            .iter()
+            .filter(|(id, block)| {
+                block.opcode == "procedures_call"
+                    && !self.toolbox.contains(id)
+                    && block.mutation.as_ref().map(|m| &m.proccode) == Some(&old.proccode)
+            })
+            .map(|(id, _)| id.clone())
+            .collect();
+        if !old.proccode.is_empty() {
+            for call_id in call_ids {
+                self.set_call_mutation(&call_id, &mutation)?;
+            }
+        }
+        self.refresh_toolbox()
     }
 
-    /// Finalize results
-    pub fn undefined_or_throw(&mut self, result: ViiruResult) -> JsResult<'js, JsUndefined> {
-        undefined_or_throw(self.cx, result)
+    fn record(&mut self, op: impl FnOnce(&Self) -> Op) {
+        // the toolbox isn't part of the project, so it's never recorded
+        if self.do_sync && !self.replaying {
+            let op = op(self);
+            self.history.record(op);
+        }
+    }
+
+    /// ends the current undo step
+    pub fn checkpoint(&mut self) {
+        self.history.checkpoint();
+    }
+
+    /// the block, its inputs and the rest of its stack, parents first
+    fn snapshot(&self, id: &str) -> Vec<(String, Block)> {
+        let mut blocks = vec![];
+        let mut stack = vec![id.to_string()];
+        while let Some(id) = stack.pop() {
+            let block = self.blocks[&id].clone();
+            stack.extend(block.next_id.iter().cloned());
+            for input in block.inputs.values() {
+                stack.extend(input.block_id.iter().chain(&input.shadow_id).cloned());
+            }
+            blocks.push((id, block));
+        }
+        blocks
+    }
+
+    /// recreates a snapshot with its original ids, the root ending up unattached
+    fn restore_snapshot(&mut self, blocks: &[(String, Block)]) -> ViiruResult<()> {
+        for (id, block) in blocks {
+            self.create_block_with_id(&block.opcode, id, Some(block))?;
+            if let Some(mutation) = &block.mutation {
+                self.set_mutation(id, mutation.clone())?;
+            }
+            for (field_name, field) in &block.fields {
+                self.set_field(id, field_name, &field.value, field.id.as_deref())?;
+            }
+        }
+        let (root_id, root) = &blocks[0];
+        self.slide_block_to(root_id, root.x, root.y)?;
+        for (id, block) in blocks {
+            if let Some(next_id) = &block.next_id {
+                self.attach_next(next_id, id)?;
+            }
+            for (input_name, input) in &block.inputs {
+                if let Some(shadow_id) = &input.shadow_id {
+                    self.attach_input(shadow_id, id, input_name, true)?;
+                }
+                if let Some(child_id) = &input.block_id {
+                    self.attach_input(child_id, id, input_name, false)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn block_mut(&mut self, id: &str) -> ViiruResult<&mut Block> {
+        self.blocks
+            .get_mut(id)
+            .ok_or_else(|| ViiruError::MissingBlock(id.to_string()))
+    }
+
+    fn require_blocks(&self, ids: &[&str]) -> ViiruResult<()> {
+        match ids.iter().find(|id| !self.blocks.contains_key(**id)) {
+            Some(id) => Err(ViiruError::MissingBlock(id.to_string())),
+            None => Ok(()),
+        }
+    }
+
+    fn apply(&mut self, op: &Op) -> ViiruResult<()> {
+        self.require_blocks(&op.required_ids())?;
+        match op {
+            Op::Create { blocks, .. } => self.restore_snapshot(blocks),
+            Op::Delete { id, .. } => self.delete_block(id),
+            Op::Attach {
+                id,
+                parent_id,
+                input_name: Some(input_name),
+                is_shadow,
+            } => self.attach_input(id, parent_id, input_name, *is_shadow),
+            Op::Attach {
+                id,
+                parent_id,
+                input_name: None,
+                ..
+            } => self.attach_next(id, parent_id),
+            Op::Detach { id, .. } => self.detach_block(id),
+            Op::Slide { id, to, .. } => self.slide_block_to(id, to.0, to.1),
+            Op::SetField {
+                id,
+                field_name,
+                new,
+                ..
+            } => self.set_field(id, field_name, &new.value, new.id.as_deref()),
+            Op::SetMutation { id, new, .. } => match new {
+                Some(mutation) => self.set_mutation(id, mutation.clone()),
+                // only freshly created blocks lack one, and those get deleted right after
+                None => {
+                    self.block_mut(id)?.mutation = None;
+                    Ok(())
+                }
+            },
+        }
+    }
+
+    fn replay(&mut self, ops: &[Op]) -> ViiruResult<()> {
+        self.replaying = true;
+        let result = ops.iter().try_for_each(|op| self.apply(op));
+        self.replaying = false;
+        // a step that can't be applied leaves the rest of the history meaningless too
+        if let Err(ViiruError::MissingBlock(_)) = result {
+            self.history = History::default();
+        }
+        result?;
+        // custom blocks may have come or gone
+        self.refresh_toolbox()
+    }
+
+    /// returns false if there was nothing to undo
+    pub fn undo(&mut self) -> ViiruResult<bool> {
+        let Some(ops) = self.history.undo() else {
+            return Ok(false);
+        };
+        self.replay(&ops)?;
+        Ok(true)
+    }
+
+    /// returns false if there was nothing to redo
+    pub fn redo(&mut self) -> ViiruResult<bool> {
+        let Some(ops) = self.history.redo() else {
+            return Ok(false);
+        };
+        self.replay(&ops)?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::tests::{create_script, new_runtime, round_trip, SPRITE, STAGE};
+
+    #[test]
+    fn undo_restores_deleted_blocks_in_the_backend() {
+        let mut runtime = new_runtime();
+        let (hat_id, move_id) = create_script(&mut runtime);
+        runtime.delete_block(&hat_id).unwrap();
+        runtime.checkpoint();
+
+        assert!(runtime.undo().unwrap());
+        let loaded = round_trip(&mut runtime, "undo");
+        assert_eq!(
+            loaded.blocks[&hat_id].next_id.as_deref(),
+            Some(move_id.as_str())
+        );
+
+        assert!(runtime.redo().unwrap());
+        assert!(!runtime.blocks.contains_key(&hat_id));
+        assert!(!runtime.blocks.contains_key(&move_id));
+    }
+
+    #[test]
+    fn detaching_and_undoing_it() {
+        let mut runtime = new_runtime();
+        let (hat_id, move_id) = create_script(&mut runtime);
+        runtime.detach_block(&move_id).unwrap();
+        runtime.checkpoint();
+        assert_eq!(runtime.blocks[&hat_id].next_id, None);
+        assert_eq!(runtime.blocks[&move_id].parent_id, None);
+
+        assert!(runtime.undo().unwrap());
+        let loaded = round_trip(&mut runtime, "detach");
+        assert_eq!(
+            loaded.blocks[&hat_id].next_id.as_deref(),
+            Some(move_id.as_str())
+        );
+        assert_eq!(
+            loaded.blocks[&move_id].parent_id.as_deref(),
+            Some(hat_id.as_str())
+        );
+    }
+
+    #[test]
+    fn switching_targets_keeps_each_ones_blocks() {
+        let mut runtime = new_runtime();
+        assert_eq!(runtime.editing_target, SPRITE);
+        let (hat_id, move_id) = create_script(&mut runtime);
+
+        runtime.switch_target(STAGE).unwrap();
+        assert!(!runtime.blocks.contains_key(&hat_id));
+        let stage_id = runtime
+            .create_single_block("event_whenflagclicked")
+            .unwrap();
+        runtime.checkpoint();
+
+        runtime.switch_target(SPRITE).unwrap();
+        assert!(runtime.blocks.contains_key(&hat_id));
+        assert!(runtime.blocks.contains_key(&move_id));
+        assert!(!runtime.blocks.contains_key(&stage_id));
+
+        let mut loaded = round_trip(&mut runtime, "targets");
+        assert!(loaded.blocks.contains_key(&hat_id));
+        assert!(!loaded.blocks.contains_key(&stage_id));
+        loaded.switch_target(STAGE).unwrap();
+        assert!(loaded.blocks.contains_key(&stage_id));
+        assert!(!loaded.blocks.contains_key(&hat_id));
+    }
+
+    #[test]
+    fn undo_works_after_switching_targets() {
+        let mut runtime = new_runtime();
+        let (hat_id, _) = create_script(&mut runtime);
+        runtime.switch_target(STAGE).unwrap();
+        runtime.switch_target(SPRITE).unwrap();
+
+        assert!(runtime.undo().unwrap());
+        assert!(!runtime.blocks.contains_key(&hat_id));
+        // the backend has forgotten it as well
+        runtime.switch_target(STAGE).unwrap();
+        runtime.switch_target(SPRITE).unwrap();
+        assert!(!runtime.blocks.contains_key(&hat_id));
     }
 }
@@ -0,0 +1,1013 @@
+//! Conversion between scripts and the scratchblocks text syntax, e.g.
+//!
+//! ```text
+//! when @greenFlag clicked
+//! forever
+//!   move (10) steps
+//!   if <touching (edge v)?> then
+//!     turn @turnRight (15) degrees
+//!   end
+//! end
+//! ```
+//!
+//! Blocks are looked up by the text of their spec, so this only knows about the blocks in `BLOCKS`
+//! plus the custom blocks of the editing target.
+
+use crate::{
+    backend::VariableType,
+    block::{parse_signature, Mutation},
+    opcodes::{dropdown_options, variable_type_of, BLOCKS},
+    result::{ViiruError, ViiruResult},
+    runtime::Runtime,
+    spec::{proccode_line, DefaultValue, Fragment, Shape},
+};
+
+// ==== export ====
+
+/// Renders the script containing the block, from its topmost block down
+pub fn export_script(runtime: &Runtime, block_id: &str) -> String {
+    let mut top_id = block_id;
+    while let Some(parent_id) = &runtime.blocks[top_id].parent_id {
+        top_id = parent_id;
+    }
+    let mut lines = vec![];
+    export_stack(runtime, top_id, 0, &mut lines);
+    lines.join("\n")
+}
+
+fn export_stack(runtime: &Runtime, block_id: &str, depth: usize, lines: &mut Vec<String>) {
+    let indent = "  ".repeat(depth);
+    let mut current = Some(block_id);
+    while let Some(block_id) = current {
+        let block = &runtime.blocks[block_id];
+        lines.push(format!("{indent}{}", block_text(runtime, block_id)));
+        if let Some(spec) = BLOCKS.get(&block.opcode) {
+            // the remaining lines of C-blocks are either mouths or labels like "else"
+            let mut has_mouth = false;
+            for line in spec.lines.iter().skip(1) {
+                if let Some(Fragment::BlockInput(input_name)) = line
+                    .iter()
+                    .find(|frag| matches!(frag, Fragment::BlockInput(_)))
+                {
+                    has_mouth = true;
+                    if let Some(child_id) = &block.inputs[input_name].block_id {
+                        export_stack(runtime, child_id, depth + 1, lines);
+                    }
+                } else {
+                    let label = fragments_text(runtime, block_id, line);
+                    if !label.is_empty() {
+                        lines.push(format!("{indent}{label}"));
+                    }
+                }
+            }
+            if has_mouth {
+                lines.push(format!("{indent}end"));
+            }
+        }
+        current = block.next_id.as_deref();
+    }
+}
+
+/// A single block, wrapped according to its shape
+pub fn block_text(runtime: &Runtime, block_id: &str) -> String {
+    let block = &runtime.blocks[block_id];
+    let Some(spec) = BLOCKS.get(&block.opcode) else {
+        return format!("{} :: grey", block.opcode);
+    };
+    let text = match block.opcode.as_str() {
+        "procedures_definition" => {
+            let mutation = runtime
+                .prototype_of(block_id)
+                .and_then(|id| runtime.blocks[&id].mutation.as_ref());
+            format!(
+                "define {}",
+                mutation.map(Mutation::signature).unwrap_or_default()
+            )
+        }
+        "data_listcontents" => format!("{} :: list", block.fields["LIST"].value),
+        _ => match &block.mutation {
+            Some(mutation) => fragments_text(runtime, block_id, &proccode_line(mutation, false)),
+            None => fragments_text(runtime, block_id, &spec.lines[0]),
+        },
+    };
+    match spec.shape {
+        Shape::Circle => format!("({text})"),
+        Shape::Hexagon => format!("<{text}>"),
+        Shape::Stack => text,
+    }
+}
+
+fn fragments_text(runtime: &Runtime, block_id: &str, fragments: &[Fragment]) -> String {
+    let block = &runtime.blocks[block_id];
+    let mut text = String::new();
+    for fragment in fragments {
+        match fragment {
+            Fragment::Text(s) => text.push_str(s),
+            Fragment::StrumberInput(input_name, _) | Fragment::BooleanInput(input_name) => {
+                let input = &block.inputs[input_name];
+                let is_boolean = matches!(fragment, Fragment::BooleanInput(_));
+                text.push_str(&match (&input.block_id, &input.shadow_id) {
+                    (Some(child_id), _) => block_text(runtime, child_id),
+                    (None, Some(shadow_id)) => shadow_text(runtime, shadow_id),
+                    (None, None) if is_boolean => "<>".into(),
+                    (None, None) => "()".into(),
+                });
+            }
+            Fragment::Dropdown(field_name, _) => {
+                text.push_str(&format!(
+                    "[{} v]",
+                    escape(&display_of(runtime, block_id, field_name))
+                ));
+            }
+            Fragment::FieldText(field_name) | Fragment::WritableFieldText(field_name) => {
+                text.push_str(&block.fields[field_name].value);
+            }
+            Fragment::Flag => text.push_str("@greenFlag"),
+            Fragment::Clockwise => text.push_str("@turnRight"),
+            Fragment::Anticlockwise => text.push_str("@turnLeft"),
+            Fragment::BlockInput(_)
+            | Fragment::Expander
+            | Fragment::AlignmentPoint(_)
+            | Fragment::CustomColour(_) => (),
+        }
+    }
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// numbers go in round brackets, text and colours in square ones, and menus get a "v"
+fn shadow_text(runtime: &Runtime, shadow_id: &str) -> String {
+    let shadow = &runtime.blocks[shadow_id];
+    let Some(field_name) = runtime.dropdown_field(shadow_id) else {
+        return block_text(runtime, shadow_id);
+    };
+    let value = display_of(runtime, shadow_id, &field_name);
+    match shadow.opcode.as_str() {
+        "text" | "colour_picker" => format!("[{}]", escape(&value)),
+        opcode if opcode.starts_with("math_") => format!("({value})"),
+        _ => format!("({value} v)"),
+    }
+}
+
+/// what the dropdown shows for the field's value, which may differ from the value itself
+fn display_of(runtime: &Runtime, block_id: &str, field_name: &str) -> String {
+    let block = &runtime.blocks[block_id];
+    let value = block
+        .fields
+        .get(field_name)
+        .map(|field| field.value.clone())
+        .unwrap_or_default();
+    dropdown_options(runtime, block_id, &block.opcode)
+        .into_iter()
+        .find(|option| option.value == value)
+        .map_or(value, |option| option.display)
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace(']', "\\]")
+}
+
+// ==== tokenizing ====
+
+#[derive(Debug, Clone)]
+enum Token {
+    Word(String),
+    /// `(...)`, a number, variable or reporter
+    Round(Vec<Token>),
+    /// `<...>`
+    Boolean(Vec<Token>),
+    /// `[...]`, literal text
+    Square(String),
+    /// `[... v]` or `(... v)`
+    Menu(String),
+}
+
+struct Tokenizer {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Tokenizer {
+    fn peek_at(&self, pos: usize) -> Option<char> {
+        self.chars.get(pos).copied()
+    }
+
+    /// `<` and `>` surrounded by spaces are comparisons, not brackets
+    fn is_bracket(&self, pos: usize) -> bool {
+        let spaced = |c: Option<char>| c.is_none_or(char::is_whitespace);
+        let before = pos.checked_sub(1).and_then(|p| self.peek_at(p));
+        match self.peek_at(pos) {
+            Some('<') => !spaced(self.peek_at(pos + 1)),
+            Some('>') => !spaced(before) || !spaced(self.peek_at(pos + 1)),
+            _ => false,
+        }
+    }
+
+    fn tokens(&mut self, closer: Option<char>) -> Result<Vec<Token>, String> {
+        let mut tokens = vec![];
+        loop {
+            while self.peek_at(self.pos).is_some_and(char::is_whitespace) {
+                self.pos += 1;
+            }
+            let Some(c) = self.peek_at(self.pos) else {
+                return match closer {
+                    Some(closer) => Err(format!("Missing `{closer}`")),
+                    None => Ok(tokens),
+                };
+            };
+            if Some(c) == closer && (c == ')' || self.is_bracket(self.pos)) {
+                self.pos += 1;
+                return Ok(tokens);
+            }
+            match c {
+                '(' => {
+                    self.pos += 1;
+                    let inner = self.tokens(Some(')'))?;
+                    tokens.push(round_or_menu(inner));
+                }
+                '[' => {
+                    self.pos += 1;
+                    tokens.push(square_or_menu(self.square()?));
+                }
+                '<' if self.is_bracket(self.pos) => {
+                    self.pos += 1;
+                    tokens.push(Token::Boolean(self.tokens(Some('>'))?));
+                }
+                _ => tokens.push(Token::Word(self.word(closer))),
+            }
+        }
+    }
+
+    fn square(&mut self) -> Result<String, String> {
+        let mut raw = String::new();
+        while let Some(c) = self.peek_at(self.pos) {
+            self.pos += 1;
+            match c {
+                ']' => return Ok(raw),
+                '\\' => raw.extend(self.peek_at(self.pos).inspect(|_| self.pos += 1)),
+                _ => raw.push(c),
+            }
+        }
+        Err("Missing `]`".into())
+    }
+
+    fn word(&mut self, closer: Option<char>) -> String {
+        let start = self.pos;
+        while let Some(c) = self.peek_at(self.pos) {
+            let ends = c.is_whitespace()
+                || c == '('
+                || c == '['
+                || (Some(c) == closer && (c == ')' || self.is_bracket(self.pos)));
+            // a word always has at least one character, even if it's a stray bracket
+            if ends && self.pos > start {
+                break;
+            }
+            self.pos += 1;
+        }
+        self.chars[start..self.pos].iter().collect()
+    }
+}
+
+fn round_or_menu(inner: Vec<Token>) -> Token {
+    let is_menu = inner.len() >= 2
+        && matches!(inner.last(), Some(Token::Word(v)) if v == "v")
+        && inner.iter().all(|token| matches!(token, Token::Word(_)));
+    if is_menu {
+        Token::Menu(words(&inner[..inner.len() - 1]))
+    } else {
+        Token::Round(inner)
+    }
+}
+
+fn square_or_menu(raw: String) -> Token {
+    match raw.strip_suffix(" v") {
+        Some(value) => Token::Menu(value.trim().to_string()),
+        None => Token::Square(raw),
+    }
+}
+
+fn tokenize(line: &str) -> Result<Vec<Token>, String> {
+    Tokenizer {
+        chars: line.chars().collect(),
+        pos: 0,
+    }
+    .tokens(None)
+}
+
+/// The signature of a `define` line. Square brackets are taken as arguments too,
+/// since that's how scratchblocks writes string arguments.
+fn define_signature(tokens: &[Token]) -> Option<String> {
+    let [Token::Word(define), rest @ ..] = tokens else {
+        return None;
+    };
+    let signature = rest
+        .iter()
+        .map(|token| match token {
+            Token::Round(inner) => format!("({})", words(inner)),
+            Token::Boolean(inner) => format!("<{}>", words(inner)),
+            Token::Square(text) | Token::Menu(text) => format!("({text})"),
+            Token::Word(word) => word.clone(),
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+    (define == "define").then_some(signature)
+}
+
+/// the text of the words among the tokens
+fn words(tokens: &[Token]) -> String {
+    tokens
+        .iter()
+        .filter_map(|token| match token {
+            Token::Word(word) => Some(word.as_str()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// ==== matching ====
+
+enum Item {
+    Word(String),
+    /// any of these words, lowercase
+    Icon(&'static [&'static str]),
+    Input(String, Option<DefaultValue>),
+    Boolean(String),
+    Field(String),
+}
+
+const FLAG: &[&str] = &["@greenflag", "flag"];
+const CLOCKWISE: &[&str] = &["@turnright", "cw", "right"];
+const ANTICLOCKWISE: &[&str] = &["@turnleft", "ccw", "left"];
+
+fn template(fragments: &[Fragment]) -> Vec<Item> {
+    let mut items = vec![];
+    for fragment in fragments {
+        match fragment {
+            Fragment::Text(text) => items.extend(
+                text.split_whitespace()
+                    .map(|w| Item::Word(w.to_lowercase())),
+            ),
+            Fragment::StrumberInput(input_name, default) => {
+                items.push(Item::Input(input_name.clone(), default.clone()))
+            }
+            Fragment::BooleanInput(input_name) => items.push(Item::Boolean(input_name.clone())),
+            Fragment::Dropdown(field_name, _) => items.push(Item::Field(field_name.clone())),
+            Fragment::Flag => items.push(Item::Icon(FLAG)),
+            Fragment::Clockwise => items.push(Item::Icon(CLOCKWISE)),
+            Fragment::Anticlockwise => items.push(Item::Icon(ANTICLOCKWISE)),
+            _ => (),
+        }
+    }
+    items
+}
+
+/// Returns how loosely the tokens fit, e.g. a plain `[text]` standing in for a `[dropdown v]`
+fn fit(items: &[Item], tokens: &[Token]) -> Option<usize> {
+    if items.len() != tokens.len() {
+        return None;
+    }
+    let mut looseness = 0;
+    for (item, token) in items.iter().zip(tokens) {
+        match (item, token) {
+            (Item::Word(word), Token::Word(other)) if *word == other.to_lowercase() => (),
+            (Item::Icon(words), Token::Word(other))
+                if words.contains(&other.to_lowercase().as_str()) => {}
+            (Item::Input(..), Token::Round(_) | Token::Square(_) | Token::Boolean(_)) => (),
+            (Item::Input(_, Some(DefaultValue::Block(_))), Token::Menu(_)) => (),
+            (Item::Input(..), Token::Menu(_)) => looseness += 1,
+            (Item::Boolean(_), Token::Boolean(_)) => (),
+            (Item::Field(_), Token::Menu(_)) => (),
+            (Item::Field(_), Token::Square(_)) => looseness += 1,
+            _ => return None,
+        }
+    }
+    Some(looseness)
+}
+
+/// a block parsed out of text, not created yet
+struct Node {
+    opcode: String,
+    /// the proccode of calls, or the signature of definitions
+    proccode: Option<String>,
+    /// field name, then the text shown in its dropdown
+    fields: Vec<(String, String)>,
+    /// calls have their inputs in argument order, since the ids only exist once created
+    inputs: Vec<(String, Arg)>,
+    mouths: Vec<(String, Vec<Node>)>,
+}
+
+impl Node {
+    fn new(opcode: &str) -> Node {
+        Node {
+            opcode: opcode.to_string(),
+            proccode: None,
+            fields: vec![],
+            inputs: vec![],
+            mouths: vec![],
+        }
+    }
+}
+
+enum Arg {
+    /// goes into the shadow, whether it's a number, text or a menu
+    Value(String),
+    Block(Node),
+    Empty,
+}
+
+/// An open C-block, with the labels (e.g. "else") that lead to each of its remaining mouths
+struct Open {
+    node: Node,
+    mouths: Vec<(String, String)>,
+    current: String,
+    stack: Vec<Node>,
+}
+
+struct Parser<'r, 'a> {
+    runtime: &'r Runtime<'a>,
+    /// custom blocks, including the ones defined in the text itself
+    prototypes: Vec<Mutation>,
+    /// names of the arguments of the definition being parsed, and whether each is a boolean
+    arguments: Vec<(String, bool)>,
+}
+
+impl Parser<'_, '_> {
+    fn best_match(&self, tokens: &[Token], shape: Shape) -> Result<Option<Node>, String> {
+        let mut opcodes: Vec<_> = BLOCKS
+            .iter()
+            .filter(|(opcode, spec)| {
+                spec.shape == shape
+                    && !spec.is_shadow
+                    && !matches!(
+                        opcode.as_str(),
+                        "data_variable"
+                            | "data_listcontents"
+                            | "argument_reporter_string_number"
+                            | "argument_reporter_boolean"
+                            | "procedures_definition"
+                            | "procedures_call"
+                    )
+            })
+            .collect();
+        opcodes.sort_by_key(|(opcode, _)| opcode.as_str());
+        let mut candidates = vec![];
+        for (opcode, spec) in opcodes {
+            candidates.push((opcode.as_str(), None, template(&spec.lines[0])));
+        }
+        if shape == Shape::Stack {
+            for prototype in &self.prototypes {
+                let items = template(&proccode_line(prototype, false));
+                candidates.push(("procedures_call", Some(prototype.proccode.as_str()), items));
+            }
+        }
+        let mut fitting: Vec<_> = candidates
+            .iter()
+            .filter_map(|(opcode, proccode, items)| {
+                fit(items, tokens).map(|looseness| (looseness, opcode, proccode, items))
+            })
+            .collect();
+        // the tightest fit that also makes sense, e.g. names an existing list
+        fitting.sort_by_key(|(looseness, ..)| *looseness);
+        let mut error = None;
+        for (_, opcode, proccode, items) in fitting {
+            match self.build(opcode, *proccode, items, tokens) {
+                Ok(node) => return Ok(Some(node)),
+                Err(e) => {
+                    error.get_or_insert(e);
+                }
+            }
+        }
+        error.map_or(Ok(None), Err)
+    }
+
+    fn build(
+        &self,
+        opcode: &str,
+        proccode: Option<&str>,
+        items: &[Item],
+        tokens: &[Token],
+    ) -> Result<Node, String> {
+        let mut node = Node::new(opcode);
+        node.proccode = proccode.map(str::to_string);
+        for (item, token) in items.iter().zip(tokens) {
+            match (item, token) {
+                (Item::Input(input_name, default), token) => {
+                    if let (Some(DefaultValue::Block(menu)), Token::Menu(text)) = (default, token) {
+                        self.check_variable(menu, text)?;
+                    }
+                    node.inputs
+                        .push((input_name.to_string(), self.argument(token)?));
+                }
+                (Item::Boolean(input_name), token) => {
+                    node.inputs
+                        .push((input_name.to_string(), self.argument(token)?));
+                }
+                (Item::Field(field_name), Token::Menu(text) | Token::Square(text)) => {
+                    self.check_variable(opcode, text)?;
+                    node.fields.push((field_name.to_string(), text.clone()));
+                }
+                _ => (),
+            }
+        }
+        Ok(node)
+    }
+
+    fn check_variable(&self, opcode: &str, name: &str) -> Result<(), String> {
+        let Some(variable_type) = variable_type_of(opcode) else {
+            return Ok(());
+        };
+        if self
+            .runtime
+            .variables_of_type(variable_type)
+            .values()
+            .any(|n| n == name)
+        {
+            Ok(())
+        } else {
+            Err(format!("Unknown variable, list or broadcast `{name}`"))
+        }
+    }
+
+    fn argument(&self, token: &Token) -> Result<Arg, String> {
+        match token {
+            Token::Square(text) | Token::Menu(text) => Ok(Arg::Value(text.clone())),
+            Token::Round(inner) if inner.is_empty() => Ok(Arg::Value(String::new())),
+            Token::Round(inner) => match &inner[..] {
+                [Token::Word(number)] if number.parse::<f64>().is_ok() => {
+                    Ok(Arg::Value(number.clone()))
+                }
+                _ => match self.reporter(inner, Shape::Circle)? {
+                    Some(node) => Ok(Arg::Block(node)),
+                    // anything unrecognizable is taken literally
+                    None => Ok(Arg::Value(words(inner))),
+                },
+            },
+            Token::Boolean(inner) if inner.is_empty() => Ok(Arg::Empty),
+            Token::Boolean(inner) => match self.reporter(inner, Shape::Hexagon)? {
+                Some(node) => Ok(Arg::Block(node)),
+                None => Err(format!("Unknown block <{}>", words(inner))),
+            },
+            Token::Word(word) => Ok(Arg::Value(word.clone())),
+        }
+    }
+
+    /// the contents of round or pointy brackets
+    fn reporter(&self, inner: &[Token], shape: Shape) -> Result<Option<Node>, String> {
+        if let Some(node) = self.best_match(inner, shape)? {
+            return Ok(Some(node));
+        }
+        if !inner.iter().all(|token| matches!(token, Token::Word(_))) {
+            return Ok(None);
+        }
+        let text = words(inner);
+        let (name, annotation) = match text.rsplit_once("::") {
+            Some((name, annotation)) => (name.trim(), annotation.trim()),
+            None => (text.as_str(), ""),
+        };
+        let is_boolean = shape == Shape::Hexagon;
+        if self.arguments.contains(&(name.to_string(), is_boolean)) {
+            let opcode = if is_boolean {
+                "argument_reporter_boolean"
+            } else {
+                "argument_reporter_string_number"
+            };
+            let mut node = Node::new(opcode);
+            node.fields.push(("VALUE".into(), name.to_string()));
+            return Ok(Some(node));
+        }
+        if is_boolean {
+            return Ok(None);
+        }
+        let has = |variable_type| {
+            self.runtime
+                .variables_of_type(variable_type)
+                .values()
+                .any(|n| n == name)
+        };
+        let (opcode, field_name) = if annotation != "list" && has(VariableType::Scalar) {
+            ("data_variable", "VARIABLE")
+        } else if annotation != "variable" && has(VariableType::List) {
+            ("data_listcontents", "LIST")
+        } else {
+            return Ok(None);
+        };
+        let mut node = Node::new(opcode);
+        node.fields.push((field_name.into(), name.to_string()));
+        Ok(Some(node))
+    }
+
+    fn line(&mut self, tokens: &[Token]) -> Result<Node, String> {
+        if let Some(signature) = define_signature(tokens) {
+            let Some(parsed) = parse_signature(&signature) else {
+                return Err(format!("Invalid custom block `{signature}`"));
+            };
+            self.arguments = parsed.arguments;
+            let mut node = Node::new("procedures_definition");
+            node.proccode = Some(signature);
+            return Ok(node);
+        }
+        // lone reporters are scripts too
+        let reporter = match tokens {
+            [Token::Round(inner)] => Some((inner, Shape::Circle)),
+            [Token::Boolean(inner)] => Some((inner, Shape::Hexagon)),
+            _ => None,
+        };
+        let node = match reporter {
+            Some((inner, shape)) => self.reporter(inner, shape)?,
+            None => self.best_match(tokens, Shape::Stack)?,
+        };
+        node.ok_or_else(|| "Unknown block".into())
+    }
+
+    fn parse(&mut self, text: &str) -> Result<Vec<Vec<Node>>, String> {
+        // closes every C-block, returning the finished script
+        fn finish(stack: &mut Vec<Node>, open: &mut Vec<Open>) -> Vec<Node> {
+            while let Some(mut inner) = open.pop() {
+                inner
+                    .node
+                    .mouths
+                    .push((inner.current, std::mem::take(stack)));
+                *stack = std::mem::take(&mut inner.stack);
+                stack.push(inner.node);
+            }
+            std::mem::take(stack)
+        }
+        let mut scripts = vec![];
+        let mut stack: Vec<Node> = vec![];
+        let mut open: Vec<Open> = vec![];
+        for (number, line) in text.lines().enumerate() {
+            let error = |message: String| format!("Line {}: {message}", number + 1);
+            let line = line.trim();
+            if line.is_empty() {
+                if !stack.is_empty() || !open.is_empty() {
+                    scripts.push(finish(&mut stack, &mut open));
+                }
+                self.arguments.clear();
+                continue;
+            }
+            if line == "end" {
+                if let Some(mut inner) = open.pop() {
+                    inner
+                        .node
+                        .mouths
+                        .push((inner.current, std::mem::take(&mut stack)));
+                    stack = inner.stack;
+                    stack.push(inner.node);
+                }
+                continue;
+            }
+            if let Some(inner) = open.last_mut() {
+                // both ifs read the same up top, so it's only the else that tells them apart
+                if inner.node.opcode == "control_if" && line == "else" {
+                    inner.node.opcode = "control_if_else".into();
+                    inner.mouths.push(("else".into(), "SUBSTACK2".into()));
+                }
+                let position = inner.mouths.iter().position(|(label, _)| label == line);
+                if let Some(position) = position {
+                    let (_, next) = inner.mouths.remove(position);
+                    let current = std::mem::replace(&mut inner.current, next);
+                    inner
+                        .node
+                        .mouths
+                        .push((current, std::mem::take(&mut stack)));
+                    continue;
+                }
+            }
+            let tokens = tokenize(line).map_err(error)?;
+            let node = self.line(&tokens).map_err(error)?;
+            let spec = &BLOCKS[&node.opcode];
+            // hats always start a new script, and reporters are a script of their own
+            if (spec.is_hat || spec.shape != Shape::Stack)
+                && (!stack.is_empty() || !open.is_empty())
+            {
+                scripts.push(finish(&mut stack, &mut open));
+            }
+            if spec.is_hat && node.opcode != "procedures_definition" {
+                self.arguments.clear();
+            }
+            if spec.shape != Shape::Stack {
+                scripts.push(vec![node]);
+                continue;
+            }
+            let mut mouths = vec![];
+            let mut label = String::new();
+            for line in spec.lines.iter().skip(1) {
+                match line.iter().find(|f| matches!(f, Fragment::BlockInput(_))) {
+                    Some(Fragment::BlockInput(input_name)) => {
+                        mouths.push((std::mem::take(&mut label), input_name.clone()))
+                    }
+                    _ => {
+                        for fragment in line {
+                            if let Fragment::Text(text) = fragment {
+                                label.push_str(text.trim());
+                            }
+                        }
+                    }
+                }
+            }
+            if mouths.is_empty() {
+                stack.push(node);
+            } else {
+                let (_, current) = mouths.remove(0);
+                open.push(Open {
+                    node,
+                    mouths,
+                    current,
+                    stack: std::mem::take(&mut stack),
+                });
+            }
+        }
+        if !stack.is_empty() || !open.is_empty() {
+            scripts.push(finish(&mut stack, &mut open));
+        }
+        Ok(scripts)
+    }
+}
+
+// ==== import ====
+
+/// Scripts parsed out of text, ready to be created
+pub struct Scripts(Vec<Vec<Node>>);
+
+/// Parses scratchblocks text, or describes what couldn't be understood
+pub fn parse_scripts(runtime: &Runtime, text: &str) -> Result<Scripts, String> {
+    let mut prototypes: Vec<Mutation> = runtime
+        .blocks
+        .values()
+        .filter(|block| block.opcode == "procedures_prototype")
+        .filter_map(|block| block.mutation.clone())
+        .collect();
+    // custom blocks defined in the text can be called from it as well
+    for line in text.lines() {
+        // anything that doesn't tokenize gets reported once the lines are parsed for real
+        let Some(signature) = tokenize(line.trim())
+            .ok()
+            .and_then(|t| define_signature(&t))
+        else {
+            continue;
+        };
+        let Some(parsed) = parse_signature(&signature) else {
+            continue;
+        };
+        if let Some(error) = runtime.signature_error(&signature) {
+            return Err(error.into());
+        }
+        if prototypes.iter().any(|p| p.proccode == parsed.proccode) {
+            return Err("A custom block with that text is defined twice".into());
+        }
+        prototypes.push(Mutation {
+            proccode: parsed.proccode,
+            argument_ids: (0..parsed.arguments.len()).map(|i| i.to_string()).collect(),
+            ..Default::default()
+        });
+    }
+    let mut parser = Parser {
+        runtime,
+        prototypes,
+        arguments: vec![],
+    };
+    parser.parse(text).map(Scripts)
+}
+
+/// Creates the scripts one below the other from the given position, returning their top blocks
+pub fn create_scripts(
+    runtime: &mut Runtime,
+    scripts: &Scripts,
+    x: i32,
+    y: i32,
+) -> ViiruResult<Vec<String>> {
+    let scripts = &scripts.0;
+    let mut offsets = vec![];
+    let mut dy = 0;
+    for script in scripts {
+        offsets.push(dy);
+        dy += script_height(script) + 1;
+    }
+    // definitions go first, so that calls can find their prototypes
+    let mut order: Vec<_> = (0..scripts.len()).collect();
+    order.sort_by_key(|&i| scripts[i][0].opcode != "procedures_definition");
+    let mut top_ids = vec![];
+    for i in order {
+        let top_id = create_stack(runtime, &scripts[i])?;
+        runtime.slide_block_to(&top_id, x, y + offsets[i])?;
+        top_ids.push(top_id);
+    }
+    Ok(top_ids)
+}
+
+/// roughly how many rows the script takes up once drawn
+fn script_height(stack: &[Node]) -> i32 {
+    stack
+        .iter()
+        .map(|node| {
+            let spec = &BLOCKS[&node.opcode];
+            let mouths: i32 = node
+                .mouths
+                .iter()
+                .map(|(_, s)| script_height(s).max(1))
+                .sum();
+            spec.lines.len() as i32 + spec.is_hat as i32 + mouths - node.mouths.len() as i32
+        })
+        .sum()
+}
+
+fn create_stack(runtime: &mut Runtime, stack: &[Node]) -> ViiruResult<String> {
+    let mut top_id: Option<String> = None;
+    let mut previous_id: Option<String> = None;
+    for node in stack {
+        let id = create_node(runtime, node)?;
+        match &previous_id {
+            Some(previous_id) => runtime.attach_next(&id, previous_id)?,
+            None => top_id = Some(id.clone()),
+        }
+        previous_id = Some(id);
+    }
+    Ok(top_id.unwrap())
+}
+
+fn create_node(runtime: &mut Runtime, node: &Node) -> ViiruResult<String> {
+    let proccode = node.proccode.as_deref().unwrap_or_default();
+    let id = match node.opcode.as_str() {
+        // validated while parsing
+        "procedures_definition" => runtime
+            .create_custom_block(proccode)?
+            .ok_or_else(|| ViiruError::ImportError(format!("Invalid custom block `{proccode}`")))?,
+        "procedures_call" => {
+            let prototype = runtime
+                .blocks
+                .values()
+                .filter_map(|block| block.mutation.as_ref())
+                .find(|mutation| mutation.proccode == proccode)
+                .cloned()
+                .ok_or_else(|| ViiruError::ImportError(format!("No custom block `{proccode}`")))?;
+            let id = runtime.create_single_block("procedures_call")?;
+            runtime.set_call_mutation(&id, &prototype)?;
+            id
+        }
+        opcode => runtime.create_block_template(opcode)?.0,
+    };
+    for (field_name, text) in &node.fields {
+        set_by_display(runtime, &id, field_name, text)?;
+    }
+    let argument_ids = runtime.blocks[&id]
+        .mutation
+        .as_ref()
+        .map(|mutation| mutation.argument_ids.clone());
+    for (i, (input_name, arg)) in node.inputs.iter().enumerate() {
+        let input_name = match &argument_ids {
+            Some(argument_ids) => argument_ids.get(i).ok_or_else(|| {
+                ViiruError::ImportError(format!("Too many arguments for `{proccode}`"))
+            })?,
+            None => input_name,
+        };
+        match arg {
+            Arg::Value(text) => {
+                let shadow_id = runtime.blocks[&id].inputs[input_name].shadow_id.clone();
+                if let Some(shadow_id) = shadow_id {
+                    if let Some(field_name) = runtime.dropdown_field(&shadow_id) {
+                        set_by_display(runtime, &shadow_id, &field_name, text)?;
+                    }
+                }
+            }
+            Arg::Block(child) => {
+                let child_id = create_node(runtime, child)?;
+                runtime.attach_input(&child_id, &id, input_name, false)?;
+            }
+            Arg::Empty => (),
+        }
+    }
+    for (input_name, stack) in &node.mouths {
+        if !stack.is_empty() {
+            let child_id = create_stack(runtime, stack)?;
+            runtime.attach_input(&child_id, &id, input_name, false)?;
+        }
+    }
+    Ok(id)
+}
+
+/// dropdowns are written the way they're shown, so look up the actual value
+fn set_by_display(
+    runtime: &mut Runtime,
+    block_id: &str,
+    field_name: &str,
+    text: &str,
+) -> ViiruResult<()> {
+    let opcode = runtime.blocks[block_id].opcode.clone();
+    let option = dropdown_options(runtime, block_id, &opcode)
+        .into_iter()
+        .find(|option| option.display == text || option.value == text);
+    match option {
+        Some(option) => {
+            runtime.set_field(block_id, field_name, &option.value, option.id.as_deref())
+        }
+        None => runtime.set_field(block_id, field_name, text, None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::MemoryBackend;
+
+    /// an empty project with `n` as a variable for all sprites
+    fn new_runtime() -> Runtime<'static> {
+        let mut runtime = Runtime::new(Box::new(MemoryBackend::default()));
+        runtime.new_project().unwrap();
+        runtime
+            .create_data(VariableType::Scalar, "n", false, false)
+            .unwrap();
+        runtime
+    }
+
+    /// creates the scripts and writes them back out, in the order they were created
+    fn round_trip(text: &str) -> String {
+        let mut runtime = new_runtime();
+        let scripts = parse_scripts(&runtime, text).unwrap();
+        let top_ids = create_scripts(&mut runtime, &scripts, 0, 0).unwrap();
+        top_ids
+            .iter()
+            .map(|id| export_script(&runtime, id))
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+
+    #[test]
+    fn c_blocks_keep_their_mouths() {
+        let text = "\
+when @greenFlag clicked
+forever
+  if <(n) > (50)> then
+    turn @turnRight (15) degrees
+  else
+    change [n v] by (1)
+  end
+  repeat (10)
+    move (10) steps
+  end
+end";
+        assert_eq!(round_trip(text), text);
+    }
+
+    #[test]
+    fn empty_mouths_stay_empty() {
+        let text = "\
+when @greenFlag clicked
+if <> then
+else
+  say [hi]
+end
+repeat (10)
+end
+forever
+end";
+        assert_eq!(round_trip(text), text);
+    }
+
+    #[test]
+    fn custom_blocks_are_defined_before_they_are_called() {
+        let text = "\
+when @greenFlag clicked
+jump [10] <>
+
+define jump (height) <fast>
+if <fast> then
+  change y by (height)
+end";
+        let definition_first = "\
+define jump (height) <fast>
+if <fast> then
+  change y by (height)
+end
+
+when @greenFlag clicked
+jump [10] <>";
+        assert_eq!(round_trip(text), definition_first);
+    }
+
+    #[test]
+    fn lone_reporters_are_scripts_of_their_own() {
+        let text = "(n)\n\n<(n) = (50)>";
+        assert_eq!(round_trip(text), text);
+    }
+
+    #[test]
+    fn unknown_opcodes_are_written_out_but_not_read_back() {
+        let mut runtime = new_runtime();
+        let block_id = runtime
+            .create_single_block("someextension_frobnicate")
+            .unwrap();
+        let text = export_script(&runtime, &block_id);
+        assert_eq!(text, "someextension_frobnicate :: grey");
+        let error = parse_scripts(&runtime, &text).err().unwrap();
+        assert_eq!(error, "Line 1: Unknown block");
+    }
+
+    #[test]
+    fn unknown_text_is_reported_by_line() {
+        let runtime = new_runtime();
+        let error = parse_scripts(&runtime, "when @greenFlag clicked\nfly to the moon")
+            .err()
+            .unwrap();
+        assert_eq!(error, "Line 2: Unknown block");
+    }
+}
@@ -1,11 +1,12 @@
 use core::str;
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     io::{stdout, Write},
 };
 
 use crossterm::{
     cursor::{Hide, MoveTo, MoveToNextLine, SetCursorStyle, Show},
+    event::{DisableBracketedPaste, EnableBracketedPaste},
     execute, queue,
     style::{
         Attribute, Color, Colors, Print, ResetColor, SetAttribute, SetBackgroundColor,
@@ -17,36 +18,84 @@ use crossterm::{
 };
 
 use crate::{
+    actions::{Action, ACTIONS},
+    backend::VariableType,
     block::{Field, Input},
-    opcodes::BLOCKS,
+    extensions::EXTENSIONS,
+    interpreter::{STAGE_HEIGHT, STAGE_WIDTH},
+    opcodes::{dropdown_options, is_asset_menu, BLOCKS},
     result::ViiruResult,
-    runtime::{Runtime, State},
-    spec::{Fragment, Shape},
-    util::parse_rgb,
+    runtime::{DataPrompt, Runtime, State},
+    spec::{proccode_line, Fragment, Shape},
+    util::{base64, parse_rgb},
 };
 
+/// the words on a block without any of its inputs, e.g. "change by" for `data_changevariableby`
+fn opcode_label(opcode: &str) -> String {
+    let Some(spec) = BLOCKS.get(opcode) else {
+        return opcode.to_string();
+    };
+    let words: Vec<_> = spec
+        .lines
+        .iter()
+        .flatten()
+        .filter_map(|fragment| match fragment {
+            Fragment::Text(text) => Some(text.split_whitespace()),
+            _ => None,
+        })
+        .flatten()
+        .collect();
+    words.join(" ")
+}
+
+/// points the way a sprite is facing, where 90 is to the right
+fn direction_arrow(direction: f64) -> char {
+    let arrows = ['↑', '↗', '→', '↘', '↓', '↙', '←', '↖'];
+    arrows[((direction + 22.5).rem_euclid(360.0) / 45.0) as usize % 8]
+}
+
 pub fn in_terminal_scope<F>(f: F) -> ViiruResult
 where
     F: FnOnce() -> ViiruResult,
 {
-    execute!(stdout(), EnterAlternateScreen, Hide, SetTitle("viiru"))?;
+    execute!(
+        stdout(),
+        EnterAlternateScreen,
+        EnableBracketedPaste,
+        Hide,
+        SetTitle("viiru")
+    )?;
     enable_raw_mode()?;
-    f()?;
+    // restore the terminal even if the editor bails out
+    let result = f();
     disable_raw_mode()?;
     execute!(
         stdout(),
         LeaveAlternateScreen,
+        DisableBracketedPaste,
         SetCursorStyle::DefaultUserShape,
         Show,
         SetTitle("")
     )?;
+    result
+}
+
+/// Hands text to the system clipboard through the terminal (OSC 52), where supported
+pub fn copy_to_system_clipboard(text: &str) -> ViiruResult {
+    execute!(
+        stdout(),
+        Print(format!("\x1b]52;c;{}\x07", base64(text.as_bytes())))
+    )?;
     Ok(())
 }
 
+#[derive(Clone)]
 pub struct DropPoint {
     pub shape: Shape,
     pub id: String,
     pub input: Option<String>,
+    /// on top of a script, rather than after the block
+    pub is_above: bool,
 }
 
 #[derive(Default)]
@@ -55,6 +104,7 @@ pub struct Accumulators {
     pub block_offsets: HashMap<String, (i32, i32)>,
     pub drop_points: HashMap<(i32, i32), DropPoint>,
     pub writable_points: HashMap<(i32, i32), String>,
+    pub dropdown_points: HashMap<(i32, i32), String>,
 }
 
 impl Accumulators {
@@ -78,10 +128,31 @@ impl Accumulators {
                 shape,
                 id: id.to_string(),
                 input: input.map(|s| s.to_string()),
+                is_above: false,
             },
         );
     }
 
+    /// the row above a script, for dropping blocks that the script should go below
+    pub fn add_drop_point_above(&mut self, x: i32, y: i32, id: &str) {
+        self.drop_points.insert(
+            (x, y - 1),
+            DropPoint {
+                shape: Shape::Stack,
+                id: id.to_string(),
+                input: None,
+                is_above: true,
+            },
+        );
+    }
+
+    pub fn add_dropdown_row(&mut self, block_id: &str, x: i32, y: i32, width: i32) {
+        for i in 0..width {
+            self.dropdown_points
+                .insert((x + i, y), block_id.to_string());
+        }
+    }
+
     pub fn add_writable_row(&mut self, block_id: &str, x: i32, y: i32, width: i32) {
         for i in 0..width {
             self.writable_points
@@ -267,7 +338,7 @@ impl Screen {
         fake: bool,
     ) -> ViiruResult<i32> {
         let block = &runtime.blocks[block_id];
-        let spec = &BLOCKS[&block.opcode];
+        let spec = runtime.spec_of(block_id);
         let mut max_width = 0;
         let mut dx = 0;
         let mut dy = 0;
@@ -308,15 +379,24 @@ impl Screen {
             Shape::Stack => Err((block.parent_id.is_some(), block.next_id.is_some())),
         };
 
+        let custom_lines;
+        let lines = if let Some(mutation) = &block.mutation {
+            let is_prototype = block.opcode == "procedures_prototype";
+            custom_lines = [proccode_line(mutation, is_prototype)];
+            &custom_lines[..]
+        } else {
+            &spec.lines[..]
+        };
+
         let mut skip_padding = false;
-        for (line_number, line) in spec.lines.iter().enumerate() {
+        for (line_number, line) in lines.iter().enumerate() {
             let is_start = line_number == 0;
-            let is_end = line_number == spec.lines.len() - 1;
+            let is_end = line_number == lines.len() - 1;
             match delimeters {
                 Ok((d, _)) => {
                     self.print_in_view(runtime, x, y + dy, d, block_colors, true, fake)?;
                     let d_count = d.chars().count();
-                    if BLOCKS[&block.opcode].is_shadow {
+                    if spec.is_shadow {
                         accumulators.add_writable_row(block_id, x, y + dy, d_count as i32);
                     } else {
                         accumulators.add_grab_row(block_id, x, y + dy, d_count as i32);
@@ -473,21 +553,48 @@ impl Screen {
                         dx += 2;
                         max_width = max_width.max(dx);
                     }
-                    Fragment::Dropdown(field, _static_options) => {
-                        // todo: dropdowns are not implemented
+                    Fragment::Dropdown(field, static_options) => {
+                        let value = block.fields.get(field).map_or("", |f| f.value.as_str());
+                        // static menus store a value that differs from what's shown
+                        let display = static_options
+                            .iter()
+                            .flatten()
+                            .find(|option| option.value == value)
+                            .map_or(value, |option| &option.display);
+                        let text = if display.is_empty() {
+                            format!("[{field}]")
+                        } else {
+                            format!("[{display} v]")
+                        };
+                        // costumes and sounds can be renamed or removed from under the menu
+                        let is_missing = is_asset_menu(&block.opcode)
+                            && !value.is_empty()
+                            && !dropdown_options(runtime, block_id, &block.opcode)
+                                .iter()
+                                .any(|option| option.value == value);
                         self.print_in_view(
                             runtime,
                             x + dx,
                             y + dy,
-                            &format!("[{field}]"),
-                            block_colors,
+                            &text,
+                            if is_missing {
+                                Colors::new(Color::White, runtime.config.colors.missing)
+                            } else {
+                                block_colors
+                            },
                             true,
                             fake,
                         )?;
-                        let count = 2 + field.chars().count() as i32;
-                        accumulators.add_grab_row(block_id, x + dx, y + dy, count);
-                        // TODO: add interactors
+                        let count = text.chars().count() as i32;
+                        accumulators.add_dropdown_row(block_id, x + dx, y + dy, count);
+                        // grabbing a menu takes the block it belongs to
+                        let owner_id = match &block.parent_id {
+                            Some(parent_id) if spec.is_shadow => parent_id,
+                            _ => block_id,
+                        };
+                        accumulators.add_grab_row(owner_id, x + dx, y + dy, count);
                         dx += count;
+                        max_width = max_width.max(dx);
                     }
                     Fragment::Expander => {
                         self.print_in_view(
@@ -596,7 +703,7 @@ impl Screen {
                     Ok((_, d)) => {
                         self.print_in_view(runtime, x + dx, y + dy, d, block_colors, true, fake)?;
                         let d_count = d.chars().count() as i32;
-                        if BLOCKS[&block.opcode].is_shadow {
+                        if spec.is_shadow {
                             accumulators.add_writable_row(block_id, x + dx, y + dy, d_count);
                         } else {
                             accumulators.add_grab_row(block_id, x + dx, y + dy, d_count);
@@ -633,6 +740,9 @@ impl Screen {
         }
 
         if let Shape::Stack = spec.shape {
+            if block.parent_id.is_none() && !spec.is_hat {
+                accumulators.add_drop_point_above(x, y, block_id);
+            }
             accumulators.add_drop_point(x, y + dy, Shape::Stack, block_id, None);
             if let Some(next_id) = &block.next_id {
                 accumulators.mark_block_offset(next_id, 0, dy);
@@ -649,12 +759,7 @@ impl Screen {
 
     pub fn draw_viewport_border(&mut self, runtime: &Runtime) -> ViiruResult<()> {
         let vp = &runtime.viewport;
-        let border_color = Color::Rgb {
-            r: 0x7f,
-            g: 0x3e,
-            b: 0xcf,
-        };
-        let colors = Some(Colors::new(border_color, Color::Reset));
+        let colors = Some(Colors::new(runtime.config.colors.border, Color::Reset));
         self.print(
             vp.x_min,
             vp.y_min - 1,
@@ -673,6 +778,9 @@ impl Screen {
             self.print(vp.x_min - 1, y, "|", false, colors);
             self.print(vp.x_max, y, "|", false, colors);
         }
+        // label the viewport with whichever sprite is being edited
+        let name = format!(" {} ", runtime.editing_target_name());
+        self.print(vp.x_min + 1, vp.y_min - 1, &name, false, colors);
         self.print(vp.x_min - 1, vp.y_min - 1, ".", false, colors);
         self.print(vp.x_max, vp.y_min - 1, ".", false, colors);
         self.print(vp.x_min - 1, vp.y_max, "'", false, colors);
@@ -710,6 +818,69 @@ impl Screen {
         Ok(())
     }
 
+    /// Marks breakpoints in the gutter, and highlights the script the project is paused in
+    pub fn draw_debug_markers(&mut self, runtime: &Runtime) -> ViiruResult<()> {
+        let vp = &runtime.viewport;
+        let pause = runtime
+            .interpreter
+            .as_ref()
+            .and_then(|interpreter| interpreter.paused.as_ref())
+            .filter(|pause| pause.target == runtime.editing_target);
+        let script = pause
+            .map(|pause| runtime.script_of(&pause.block_id))
+            .unwrap_or_default();
+        // the top row of each block is where its marker goes
+        let mut top_rows: HashMap<&String, i32> = HashMap::new();
+        for (&(x, y), ids) in &runtime.block_positions {
+            let id = ids.last().unwrap();
+            let row = top_rows.entry(id).or_insert(y);
+            *row = (*row).min(y);
+            let (screen_x, screen_y) = (x - runtime.scroll_x, y - runtime.scroll_y);
+            let is_visible = (vp.x_min..vp.x_max).contains(&screen_x)
+                && (vp.y_min..vp.y_max).contains(&screen_y);
+            if is_visible && pause.is_some_and(|pause| pause.block_id == *id) {
+                let cell = &mut self.cells[screen_y as usize][screen_x as usize];
+                cell.colors = Some(Colors::new(Color::Black, runtime.config.colors.paused));
+            }
+        }
+        let theme = &runtime.config.colors;
+        let paused_colors = Some(Colors::new(theme.paused, Color::Reset));
+        let breakpoint_colors = Some(Colors::new(theme.breakpoint, Color::Reset));
+        let lint_colors = Some(Colors::new(theme.lint, Color::Reset));
+        let diagnosed: HashSet<&String> = runtime
+            .diagnostics
+            .iter()
+            .filter_map(|diagnostic| diagnostic.block_id.as_ref())
+            .collect();
+        let mut markers = vec![];
+        for (id, y) in top_rows {
+            let screen_y = y - runtime.scroll_y;
+            if !(vp.y_min..vp.y_max).contains(&screen_y) {
+                continue;
+            }
+            if runtime
+                .breakpoints
+                .contains(&(runtime.editing_target, id.clone()))
+            {
+                markers.push((0, screen_y, "●", breakpoint_colors));
+            }
+            if pause.is_some_and(|pause| pause.block_id == *id) {
+                markers.push((2, screen_y, "▶", paused_colors));
+            } else if script.contains(id) {
+                markers.push((1, screen_y, "┃", paused_colors));
+            } else if diagnosed.contains(id) {
+                markers.push((1, screen_y, "!", lint_colors));
+            }
+        }
+        // the paused block's arrow goes over the script's line
+        markers.sort_by_key(|&(priority, ..)| priority);
+        for (priority, screen_y, marker, colors) in markers {
+            let x = vp.x_min - if priority == 0 { 3 } else { 2 };
+            self.print(x, screen_y, marker, false, colors);
+        }
+        Ok(())
+    }
+
     pub fn draw_cursor(&mut self, runtime: &Runtime) -> ViiruResult<()> {
         execute!(
             stdout(),
@@ -729,7 +900,7 @@ impl Screen {
         let x_first = runtime.scroll_x / x_spacing * x_spacing;
         let y_first = runtime.scroll_y / y_spacing * y_spacing;
 
-        let dot_color = Colors::new(Color::DarkGrey, Color::Reset);
+        let dot_color = Colors::new(runtime.config.colors.grid, Color::Reset);
 
         for dx in 0..1 + runtime.viewport.width() / x_spacing {
             for dy in 0..2 + runtime.viewport.height() / y_spacing {
@@ -811,6 +982,451 @@ impl Screen {
         Ok(())
     }
 
+    /// lists the blocks matching the toolbox search by their text, with the matched letters
+    /// highlighted
+    pub fn draw_toolbox_search(&mut self, runtime: &Runtime) -> ViiruResult<()> {
+        let Some(search) = &runtime.toolbox_search else {
+            return Ok(());
+        };
+        let x = runtime.viewport.x_max + 2;
+        let width = (runtime.window_cols as i32 - x - 1).max(4) as usize;
+        let y = runtime.viewport.y_min;
+        let matches = runtime.toolbox_matches();
+        let typing = if search.typing { "_" } else { "" };
+        let header: String = format!("/{}{typing} ({} found)", search.query, matches.len())
+            .chars()
+            .take(width)
+            .collect();
+        self.print(x, y, &header, true, None);
+        if matches.is_empty() {
+            self.print(x, y + 1, "  no matching blocks", false, None);
+        }
+        let match_colors = Colors::new(runtime.config.colors.selection, Color::Reset);
+        let height = (runtime.viewport.height() - 1).max(1) as usize;
+        let first = search.cursor.saturating_sub(height - 1);
+        for (dy, (i, block)) in matches
+            .iter()
+            .enumerate()
+            .skip(first)
+            .take(height)
+            .enumerate()
+        {
+            let marker = match (i == search.cursor, runtime.state) {
+                (true, State::Toolbox) => ">",
+                (true, _) => "=",
+                (false, _) => " ",
+            };
+            let y = y + 1 + dy as i32;
+            self.print(x, y, marker, false, None);
+            for (j, c) in block.text.chars().take(width - 2).enumerate() {
+                let is_match = block.positions.contains(&j);
+                let colors = is_match.then_some(match_colors);
+                self.print(x + 2 + j as i32, y, &c.to_string(), is_match, colors);
+            }
+        }
+        Ok(())
+    }
+
+    /// lists the stage and every sprite where the toolbox usually goes
+    pub fn draw_targets(&mut self, runtime: &Runtime) -> ViiruResult<()> {
+        let x = runtime.viewport.x_max + 2;
+        let height = runtime.viewport.height().max(1) as usize;
+        let first = runtime.target_cursor.saturating_sub(height - 1);
+        for (dy, (i, target)) in runtime
+            .targets
+            .iter()
+            .enumerate()
+            .skip(first)
+            .take(height)
+            .enumerate()
+        {
+            let marker = if i == runtime.target_cursor { ">" } else { " " };
+            let kind = if target.is_stage { "stage" } else { "sprite" };
+            self.print(
+                x,
+                runtime.viewport.y_min + dy as i32,
+                &format!("{marker} {} ({kind})", target.name),
+                i == runtime.editing_target,
+                None,
+            );
+        }
+        Ok(())
+    }
+
+    /// lists what the linter found where the toolbox usually goes
+    pub fn draw_diagnostics(&mut self, runtime: &Runtime) -> ViiruResult<()> {
+        let x = runtime.viewport.x_max + 2;
+        let width = (runtime.window_cols as i32 - x - 1).max(4) as usize;
+        let y = runtime.viewport.y_min;
+        let header = match runtime.diagnostics.len() {
+            0 => "no problems found".to_string(),
+            1 => "1 problem".to_string(),
+            n => format!("{n} problems"),
+        };
+        self.print(x, y, &header, true, None);
+        let height = (runtime.viewport.height() - 1).max(1) as usize;
+        let first = runtime.diagnostic_cursor.saturating_sub(height - 1);
+        for (dy, (i, diagnostic)) in runtime
+            .diagnostics
+            .iter()
+            .enumerate()
+            .skip(first)
+            .take(height)
+            .enumerate()
+        {
+            let marker = if i == runtime.diagnostic_cursor {
+                ">"
+            } else {
+                " "
+            };
+            let text: String = format!("{marker} {}", diagnostic.message)
+                .chars()
+                .take(width)
+                .collect();
+            self.print(x, y + 1 + dy as i32, &text, false, None);
+        }
+        Ok(())
+    }
+
+    /// lists the variables, lists and broadcasts where the toolbox usually goes
+    pub fn draw_data_list(&mut self, runtime: &Runtime) -> ViiruResult<()> {
+        let Some(list) = &runtime.data_list else {
+            return Ok(());
+        };
+        let x = runtime.viewport.x_max + 2;
+        let width = (runtime.window_cols as i32 - x - 1).max(4) as usize;
+        let y = runtime.viewport.y_min;
+        self.print(x, y, "variables, lists and broadcasts", true, None);
+        if list.entries.is_empty() {
+            self.print(x, y + 1, "  none yet", false, None);
+        }
+        let height = (runtime.viewport.height() - 1).max(1) as usize;
+        let first = list.cursor.saturating_sub(height - 1);
+        for (dy, (i, entry)) in list
+            .entries
+            .iter()
+            .enumerate()
+            .skip(first)
+            .take(height)
+            .enumerate()
+        {
+            let marker = if i == list.cursor { ">" } else { " " };
+            let kind = match entry.variable_type {
+                VariableType::Scalar => "variable",
+                VariableType::List => "list",
+                VariableType::Broadcast => "broadcast",
+            };
+            let scope = if entry.variable_type == VariableType::Broadcast {
+                ""
+            } else if entry.is_local {
+                ", this sprite only"
+            } else {
+                ", all sprites"
+            };
+            let cloud = if entry.is_cloud { ", cloud" } else { "" };
+            let text: String = format!("{marker} {} ({kind}{scope}{cloud})", entry.name)
+                .chars()
+                .take(width)
+                .collect();
+            self.print(x, y + 1 + dy as i32, &text, false, None);
+        }
+        Ok(())
+    }
+
+    /// lists the extensions that can be added to the toolbox
+    pub fn draw_extensions(&mut self, runtime: &Runtime) -> ViiruResult<()> {
+        let x = runtime.viewport.x_max + 2;
+        let y = runtime.viewport.y_min;
+        self.print(x, y, "extensions", true, None);
+        for (i, extension) in EXTENSIONS.iter().enumerate() {
+            let marker = if i == runtime.extension_cursor {
+                ">"
+            } else {
+                " "
+            };
+            let enabled = if runtime.is_extension_enabled(&extension.id) {
+                " (enabled)"
+            } else {
+                ""
+            };
+            let text = format!("{marker} {}{enabled}", extension.name);
+            self.print(x, y + 1 + i as i32, &text, false, None);
+        }
+        Ok(())
+    }
+
+    /// lists every action with its keys, scrolled to `help_scroll`
+    pub fn draw_help(&mut self, runtime: &Runtime) -> ViiruResult<()> {
+        let x = runtime.viewport.x_max + 2;
+        let y = runtime.viewport.y_min;
+        let width = (runtime.window_cols as i32 - x - 1).max(4) as usize;
+        let rows = (runtime.viewport.y_max - y).max(1) as usize;
+        self.print(x, y, "help", true, None);
+        for (i, info) in ACTIONS
+            .iter()
+            .skip(runtime.help_scroll)
+            .take(rows)
+            .enumerate()
+        {
+            let keys = runtime.config.keys_for(info.action).join(" ");
+            let text = format!("{keys:>6} {}", info.description);
+            let text: String = text.chars().take(width).collect();
+            self.print(x, y + 1 + i as i32, &text, false, None);
+        }
+        Ok(())
+    }
+
+    /// lists the uses found with `f` or `d` where the toolbox usually goes
+    pub fn draw_references(&mut self, runtime: &Runtime) -> ViiruResult<()> {
+        let Some(list) = &runtime.reference_list else {
+            return Ok(());
+        };
+        let x = runtime.viewport.x_max + 2;
+        let width = (runtime.window_cols as i32 - x - 1).max(4) as usize;
+        let y = runtime.viewport.y_min;
+        self.print(x, y, &list.title, true, None);
+        if list.references.is_empty() {
+            self.print(x, y + 1, "  nothing found", false, None);
+        }
+        let height = (runtime.viewport.height() - 1).max(1) as usize;
+        let first = list.cursor.saturating_sub(height - 1);
+        for (dy, (i, reference)) in list
+            .references
+            .iter()
+            .enumerate()
+            .skip(first)
+            .take(height)
+            .enumerate()
+        {
+            let marker = if i == list.cursor { ">" } else { " " };
+            let target_name = runtime
+                .targets
+                .get(reference.target)
+                .map_or("", |target| target.name.as_str());
+            // plain reporters like `data_variable` have no words of their own
+            let mut label = opcode_label(&reference.opcode);
+            if label.is_empty() {
+                label = list.name.clone();
+            }
+            let text: String = format!("{marker} {target_name}: {label} ({})", reference.role)
+                .chars()
+                .take(width)
+                .collect();
+            self.print(x, y + 1 + dy as i32, &text, false, None);
+        }
+        Ok(())
+    }
+
+    /// Shows the running project where the toolbox usually goes: a map of the stage with an arrow
+    /// for each sprite, then whatever is being asked, the visible monitors and the sprites' bubbles
+    pub fn draw_stage(&mut self, runtime: &Runtime) -> ViiruResult<()> {
+        let Some(interpreter) = &runtime.interpreter else {
+            return Ok(());
+        };
+        let x = runtime.viewport.x_max + 2;
+        let width = (runtime.window_cols as i32 - x - 1).max(4) as usize;
+        let y_max = runtime.viewport.y_max;
+        let mut y = runtime.viewport.y_min;
+        let mut line =
+            |screen: &mut Screen, text: &str, underline: bool, colors: Option<Colors>| {
+                if y < y_max {
+                    let text: String = text.chars().take(width).collect();
+                    screen.print(x, y, &text, underline, colors);
+                }
+                y += 1;
+            };
+
+        let stage = interpreter.stage();
+        let status = if interpreter.paused.is_some() {
+            "paused"
+        } else if interpreter.is_running() {
+            "running"
+        } else {
+            "done"
+        };
+        let header = format!(
+            "{} {:.1}s {status}",
+            stage.costumes.get(stage.costume).map_or("", String::as_str),
+            interpreter.now(),
+        );
+        line(self, &header, true, None);
+
+        // the debugger shows what the scripts are up to, instead of the stage
+        if let Some(pause) = &interpreter.paused {
+            line(self, "threads", true, None);
+            for thread in interpreter.thread_list() {
+                let marker = if thread.id == pause.thread { ">" } else { " " };
+                let clone = if thread.is_clone { " (clone)" } else { "" };
+                let text = format!(
+                    "{marker} {}{clone}: {} at {}{}",
+                    thread.sprite,
+                    opcode_label(&thread.top_opcode),
+                    thread.opcode.as_deref().map_or("end".into(), opcode_label),
+                    if thread.depth > 0 {
+                        format!(" (depth {})", thread.depth)
+                    } else {
+                        String::new()
+                    },
+                );
+                line(self, &text, false, None);
+            }
+            line(self, "variables", true, None);
+            for sprite in interpreter.sprites.iter().filter(|s| !s.is_clone) {
+                for variable in sprite.variables.values() {
+                    let text = format!("{}: {} = {}", sprite.name, variable.name, variable.value);
+                    line(self, &text, false, None);
+                }
+                for list in sprite.lists.values() {
+                    let items: Vec<_> = list.items.iter().map(ToString::to_string).collect();
+                    let text = format!("{}: {} = [{}]", sprite.name, list.name, items.join(", "));
+                    line(self, &text, false, None);
+                }
+            }
+            return Ok(());
+        }
+
+        // cells are about twice as tall as they're wide, so this keeps the stage at 4:3
+        let map_width = width.min(48) - 2;
+        let map_height = (map_width * 3 / 8).max(1);
+        let mut map = vec![vec![' '; map_width]; map_height];
+        for sprite in interpreter
+            .sprites
+            .iter()
+            .filter(|s| !s.is_stage && s.visible)
+        {
+            let column = (sprite.x + STAGE_WIDTH / 2.0) / STAGE_WIDTH * (map_width - 1) as f64;
+            let row = (STAGE_HEIGHT / 2.0 - sprite.y) / STAGE_HEIGHT * (map_height - 1) as f64;
+            let row = row.round().clamp(0.0, (map_height - 1) as f64) as usize;
+            let column = column.round().clamp(0.0, (map_width - 1) as f64) as usize;
+            map[row][column] = direction_arrow(sprite.direction);
+        }
+        let border_colors = Some(Colors::new(Color::DarkGrey, Color::Reset));
+        line(
+            self,
+            &format!(".{}.", "-".repeat(map_width)),
+            false,
+            border_colors,
+        );
+        for row in map {
+            line(self, &format!("|{}|", String::from_iter(row)), false, None);
+        }
+        line(
+            self,
+            &format!("'{}'", "-".repeat(map_width)),
+            false,
+            border_colors,
+        );
+
+        if let Some(question) = &interpreter.question {
+            line(
+                self,
+                &format!("{} asks: {}", question.sprite, question.text),
+                false,
+                None,
+            );
+            line(self, &format!("> {}_", runtime.command_buffer), true, None);
+        }
+
+        for id in &interpreter.monitors {
+            for sprite in interpreter.sprites.iter().filter(|s| !s.is_clone) {
+                // locals are labelled with their sprite, like on the stage
+                let owner = if sprite.is_stage {
+                    String::new()
+                } else {
+                    format!("{}: ", sprite.name)
+                };
+                if let Some(variable) = sprite.variables.get(id) {
+                    line(
+                        self,
+                        &format!("{owner}{} = {}", variable.name, variable.value),
+                        false,
+                        None,
+                    );
+                }
+                if let Some(list) = sprite.lists.get(id) {
+                    let items: Vec<_> = list.items.iter().map(ToString::to_string).collect();
+                    let text = format!("{owner}{} = [{}]", list.name, items.join(", "));
+                    line(self, &text, false, None);
+                }
+            }
+        }
+
+        for sprite in interpreter.sprites.iter().rev().filter(|s| !s.is_stage) {
+            let mut text = format!(
+                "{} {}{} ({}, {}) {}",
+                direction_arrow(sprite.direction),
+                sprite.name,
+                if sprite.is_clone { " (clone)" } else { "" },
+                sprite.x.round(),
+                sprite.y.round(),
+                sprite.direction.round(),
+            );
+            if !sprite.visible {
+                text.push_str(" hidden");
+            }
+            line(self, &text, false, None);
+            if let Some(bubble) = &sprite.bubble {
+                let verb = if bubble.is_think { "thinks" } else { "says" };
+                let bubble_colors = Some(Colors::new(Color::Black, Color::Grey));
+                line(
+                    self,
+                    &format!("  {verb}: {}", bubble.text),
+                    false,
+                    bubble_colors,
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// pops up below the cursor, or above it if there's no room
+    pub fn draw_dropdown_menu(&mut self, runtime: &Runtime) -> ViiruResult<()> {
+        let Some(menu) = &runtime.dropdown_menu else {
+            return Ok(());
+        };
+        let max_rows = 10;
+        let options = menu.visible_options();
+        let first = menu.cursor.saturating_sub(max_rows - 1);
+        let width = options
+            .iter()
+            .map(|option| option.display.chars().count())
+            .chain([menu.filter.chars().count() + 1, "(no matches)".len()])
+            .max()
+            .unwrap_or(0);
+        // + 1 for the filter line
+        let height = options.len().clamp(1, max_rows) as i32 + 1;
+        let x = runtime.cursor_x - runtime.scroll_x;
+        let mut y = runtime.cursor_y - runtime.scroll_y + 1;
+        if y + height > runtime.viewport.y_max {
+            y = (runtime.cursor_y - runtime.scroll_y - height).max(0);
+        }
+
+        let menu_colors = Colors::new(Color::Black, Color::Grey);
+        let selected_colors = Colors::new(Color::White, runtime.config.colors.selection);
+        let filter = format!(" /{:<width$} ", menu.filter, width = width - 1);
+        self.print(x, y, &filter, true, Some(menu_colors));
+        if options.is_empty() {
+            let text = format!(" {:<width$} ", "(no matches)");
+            self.print(x, y + 1, &text, false, Some(menu_colors));
+        }
+        for (dy, (i, option)) in options
+            .iter()
+            .enumerate()
+            .skip(first)
+            .take(max_rows)
+            .enumerate()
+        {
+            let text = format!(" {:<width$} ", option.display);
+            let colors = if i == menu.cursor {
+                selected_colors
+            } else {
+                menu_colors
+            };
+            self.print(x, y + 1 + dy as i32, &text, false, Some(colors));
+        }
+        Ok(())
+    }
+
     pub fn refresh_screen(&mut self, runtime: &mut Runtime) -> ViiruResult<()> {
         self.draw_viewport_border(runtime)?;
         self.draw_marker_dots(runtime)?;
@@ -845,10 +1461,10 @@ impl Screen {
             )?;
         }
         runtime.process_accumulators(accumulators);
-        let position = format!(
-            "{},{} {}",
-            runtime.cursor_x, runtime.cursor_y, runtime.last_command
-        );
+        runtime.relint();
+        self.draw_debug_markers(runtime)?;
+        let last_action = runtime.last_action.map_or("", |action| action.info().name);
+        let position = format!("{},{} {last_action}", runtime.cursor_x, runtime.cursor_y);
         self.print(
             runtime.viewport.x_max - position.len() as i32,
             runtime.viewport.y_max + 1,
@@ -857,9 +1473,27 @@ impl Screen {
             None,
         );
         if let State::Command = runtime.state {
-            let command_prefix = match runtime.last_command {
-                'o' => "file path: ",
-                'w' => "output path: ",
+            let command_prefix = match (runtime.last_action, &runtime.data_prompt) {
+                (Some(Action::Open), _) => "file path: ",
+                (Some(Action::Write), _) => "output path: ",
+                (Some(Action::CustomBlock), _) => "custom block: ",
+                (Some(Action::CommandLine), _) => ":",
+                (Some(Action::Data), Some(DataPrompt::Rename(_))) => "rename to: ",
+                (
+                    Some(Action::Data),
+                    Some(DataPrompt::Create {
+                        variable_type,
+                        is_local,
+                        is_cloud,
+                    }),
+                ) => match (variable_type, is_local, is_cloud) {
+                    (VariableType::Scalar, _, true) => "new cloud variable: ",
+                    (VariableType::Scalar, true, _) => "new variable for this sprite only: ",
+                    (VariableType::Scalar, false, _) => "new variable for all sprites: ",
+                    (VariableType::List, true, _) => "new list for this sprite only: ",
+                    (VariableType::List, false, _) => "new list for all sprites: ",
+                    (VariableType::Broadcast, ..) => "new broadcast: ",
+                },
                 _ => "",
             };
             self.print(
@@ -876,6 +1510,19 @@ impl Screen {
                 false,
                 None,
             );
+            // the Tab candidates go underneath, with the one filled in highlighted
+            if let Some(completion) = &runtime.completion {
+                let selected_colors = Colors::new(Color::White, runtime.config.colors.selection);
+                let mut x = runtime.viewport.x_min;
+                for (i, candidate) in completion.candidates.iter().enumerate() {
+                    if x + candidate.len() as i32 > runtime.viewport.x_max {
+                        break;
+                    }
+                    let colors = (i == completion.index).then_some(selected_colors);
+                    self.print(x, runtime.viewport.y_max + 2, candidate, false, colors);
+                    x += candidate.len() as i32 + 2;
+                }
+            }
         } else {
             self.print(
                 runtime.viewport.x_min,
@@ -885,9 +1532,28 @@ impl Screen {
                 None,
             );
         }
-        let vox = runtime.viewport_offset_x;
-        let voy = runtime.viewport_offset_y;
-        self.draw_toolbox(runtime, vox, voy, false)?;
+        if let State::Targets = runtime.state {
+            self.draw_targets(runtime)?;
+        } else if let State::Diagnostics = runtime.state {
+            self.draw_diagnostics(runtime)?;
+        } else if let State::References = runtime.state {
+            self.draw_references(runtime)?;
+        } else if runtime.state == State::Data || runtime.data_prompt.is_some() {
+            self.draw_data_list(runtime)?;
+        } else if let State::Extensions = runtime.state {
+            self.draw_extensions(runtime)?;
+        } else if let State::Help = runtime.state {
+            self.draw_help(runtime)?;
+        } else if let State::Run = runtime.state {
+            self.draw_stage(runtime)?;
+        } else if runtime.toolbox_search.is_some() {
+            self.draw_toolbox_search(runtime)?;
+        } else {
+            let vox = runtime.viewport_offset_x;
+            let voy = runtime.viewport_offset_y;
+            self.draw_toolbox(runtime, vox, voy, false)?;
+        }
+        self.draw_dropdown_menu(runtime)?;
         Ok(())
     }
 }
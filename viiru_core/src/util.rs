@@ -1,8 +1,134 @@
-pub fn assume_string(bytes: Vec<u8>) -> String {
-    String::from_utf8(bytes).unwrap()
+use std::{env, path::PathBuf};
+
+/// `~/.config/viiru`, or wherever `XDG_CONFIG_HOME` points instead
+pub fn config_dir() -> Option<PathBuf> {
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(base.join("viiru"))
+}
+
+/// `~/.local/state/viiru`, or wherever `XDG_STATE_HOME` points instead
+pub fn state_dir() -> Option<PathBuf> {
+    let base = env::var_os("XDG_STATE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state")))?;
+    Some(base.join("viiru"))
+}
+
+/// only needed for handing text to the terminal, so no point pulling in a crate for it
+pub fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::new();
+    for chunk in bytes.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, &byte)| {
+            bits | (byte as u32) << (16 - 8 * i)
+        });
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(bits >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
 }
 
 pub fn parse_rgb(s: &str) -> (u8, u8, u8) {
     let bits = u32::from_str_radix(s, 16).unwrap();
     ((bits >> 16) as u8, (bits >> 8) as u8, bits as u8)
 }
+
+/// Finds the query's characters in order in the text, ignoring case, preferring the placement
+/// with the fewest gaps and then the earliest start. Returns that score, lower being better,
+/// along with the char indices that matched.
+pub fn fuzzy_match(query: &str, text: &str) -> Option<(usize, Vec<usize>)> {
+    // lowercased a char at a time, since some chars lowercase to several and that would
+    // throw off the indices
+    let same = |a: char, b: char| a == b || a.to_lowercase().eq(b.to_lowercase());
+    let query: Vec<char> = query.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let first = *query.first()?;
+    let mut best: Option<(usize, Vec<usize>)> = None;
+    // greedy from every possible start, which is plenty for block text
+    for start in (0..text.len()).filter(|&i| same(text[i], first)) {
+        let mut positions = vec![start];
+        let mut i = start + 1;
+        for &c in &query[1..] {
+            match text[i..].iter().position(|&t| same(t, c)) {
+                Some(offset) => {
+                    positions.push(i + offset);
+                    i += offset + 1;
+                }
+                None => break,
+            }
+        }
+        if positions.len() < query.len() {
+            // later starts only have less text left to match in
+            break;
+        }
+        let gaps = positions.windows(2).filter(|w| w[1] != w[0] + 1).count();
+        let score = gaps * text.len() + start;
+        if best
+            .as_ref()
+            .is_none_or(|(best_score, _)| score < *best_score)
+        {
+            best = Some((score, positions));
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn positions(query: &str, text: &str) -> Option<Vec<usize>> {
+        fuzzy_match(query, text).map(|(_, positions)| positions)
+    }
+
+    #[test]
+    fn empty_queries_match_nothing() {
+        assert_eq!(fuzzy_match("", "move (10) steps"), None);
+        assert_eq!(fuzzy_match("a", ""), None);
+    }
+
+    #[test]
+    fn letters_match_in_order_ignoring_case() {
+        assert_eq!(positions("MOVE", "move (10) steps"), Some(vec![0, 1, 2, 3]));
+        assert_eq!(positions("mst", "move (10) steps"), Some(vec![0, 10, 11]));
+        assert_eq!(positions("ms", "Move Steps"), Some(vec![0, 5]));
+        assert_eq!(positions("evom", "move"), None);
+        assert_eq!(positions("moves", "move"), None);
+    }
+
+    #[test]
+    fn fewer_gaps_beat_an_earlier_start() {
+        // "st" right after each other in "steps", rather than the s of "says" and the next t
+        assert_eq!(positions("st", "say steps"), Some(vec![4, 5]));
+        assert_eq!(positions("aa", "a b aa"), Some(vec![4, 5]));
+        // with as many gaps, the earliest start wins
+        assert_eq!(positions("a", "banana"), Some(vec![1]));
+    }
+
+    #[test]
+    fn scores_rank_closer_matches_first() {
+        let score = |query, text| fuzzy_match(query, text).unwrap().0;
+        assert!(score("turn", "turn right") < score("turn", "return"));
+        assert!(score("turn", "return") < score("turn", "t u r n"));
+        assert!(score("say", "say hello") < score("say", "think say"));
+    }
+
+    #[test]
+    fn positions_count_chars_of_the_original_text() {
+        assert_eq!(positions("ä", "sää"), Some(vec![1]));
+        assert_eq!(positions("Ö", "pöö"), Some(vec![1]));
+        assert_eq!(positions("x", "→ x"), Some(vec![2]));
+        // İ lowercases to two chars, which mustn't shift whatever comes after it
+        assert_eq!(positions("ab", "İab"), Some(vec![1, 2]));
+        assert_eq!(positions("süß", "Süße"), Some(vec![0, 1, 2]));
+    }
+}